@@ -1,18 +1,17 @@
 use include_dir::{include_dir, Dir};
 use std::fs;
-use std::path::Path;
 use tauri::AppHandle;
+use crate::workspace;
 
 static EXAMPLES_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/examples");
+static TEMPLATES_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/templates");
 
 pub fn extract_examples(_app: &AppHandle) {
-    // In a real production app, we should use app.path().app_data_dir().
-    // For now, we match the existing behavior in commands.rs which uses "scripts" in the CWD.
-    let scripts_path = Path::new("scripts");
+    let scripts_path = workspace::scripts_dir();
     let examples_path = scripts_path.join("examples");
 
     if !scripts_path.exists() {
-        if let Err(e) = fs::create_dir_all(scripts_path) {
+        if let Err(e) = fs::create_dir_all(&scripts_path) {
             eprintln!("Failed to create scripts dir: {}", e);
             return;
         }
@@ -36,3 +35,24 @@ pub fn extract_examples(_app: &AppHandle) {
         }
     }
 }
+
+/// Writes the bundled fingerprint templates to the workspace, keeping any local edits.
+pub fn extract_templates(_app: &AppHandle) {
+    let templates_path = workspace::templates_dir();
+
+    if !templates_path.exists() {
+        if let Err(e) = fs::create_dir_all(&templates_path) {
+            eprintln!("Failed to create templates dir: {}", e);
+            return;
+        }
+    }
+
+    for file in TEMPLATES_DIR.files() {
+        let path = templates_path.join(file.path());
+        if !path.exists() {
+            if let Err(e) = fs::write(&path, file.contents()) {
+                eprintln!("Failed to write template {}: {}", path.display(), e);
+            }
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::AppHandle;
+use tokio::sync::Semaphore;
+use crate::error::PoolError;
+use crate::output::OutputSink;
+use crate::params;
+use crate::pool::{self, PoolJob};
+use crate::runs::RunSource;
+use crate::scripting::ScriptInputs;
+use crate::state::{AppState, Profile};
+
+/// Finished batches kept for the API; the oldest are dropped first.
+const MAX_BATCHES: usize = 50;
+
+/// File format of a dataset or of collected results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordFormat {
+    Csv,
+    Jsonl,
+    /// A single JSON array; results only
+    Json,
+}
+
+impl RecordFormat {
+    /// `.csv`, `.jsonl`/`.ndjson` or anything else as JSON.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("csv") => RecordFormat::Csv,
+            Some("jsonl") | Some("ndjson") => RecordFormat::Jsonl,
+            _ => RecordFormat::Json,
+        }
+    }
+}
+
+/// Parses a dataset into rows. CSV needs a header line; every CSV value is a string.
+pub fn parse_rows(content: &str, format: RecordFormat) -> Result<Vec<Map<String, Value>>, String> {
+    match format {
+        RecordFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(content.as_bytes());
+            let headers = reader.headers().map_err(|e| format!("Invalid CSV header: {}", e))?.clone();
+            reader
+                .records()
+                .enumerate()
+                .map(|(i, record)| {
+                    let record = record.map_err(|e| format!("Invalid CSV row {}: {}", i + 1, e))?;
+                    Ok(headers
+                        .iter()
+                        .zip(record.iter())
+                        .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+                        .collect())
+                })
+                .collect()
+        }
+        RecordFormat::Jsonl => content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| match serde_json::from_str(line) {
+                Ok(Value::Object(row)) => Ok(row),
+                Ok(_) => Err(format!("Line {}: each line must be a JSON object", i + 1)),
+                Err(e) => Err(format!("Line {}: {}", i + 1, e)),
+            })
+            .collect(),
+        RecordFormat::Json => match serde_json::from_str(content) {
+            Ok(Value::Array(items)) => items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| match item {
+                    Value::Object(row) => Ok(row),
+                    _ => Err(format!("Item {}: each item must be a JSON object", i + 1)),
+                })
+                .collect(),
+            Ok(_) => Err("Expected a JSON array of objects".into()),
+            Err(e) => Err(e.to_string()),
+        },
+    }
+}
+
+/// Turns a row's return value into output records: a map is one record, an array is
+/// one per item, `()` is none, and anything else becomes `{"result": value}`.
+pub fn records_from(value: Value) -> Vec<Value> {
+    let wrap = |v: Value| match v {
+        Value::Object(_) => v,
+        other => serde_json::json!({ "result": other }),
+    };
+    match value {
+        Value::Null => Vec::new(),
+        Value::Array(items) => items.into_iter().map(wrap).collect(),
+        other => vec![wrap(other)],
+    }
+}
+
+pub(crate) fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Serializes records. CSV columns are the union of all keys, in order of appearance.
+pub fn format_records(records: &[Value], format: RecordFormat) -> Result<String, String> {
+    match format {
+        RecordFormat::Json => serde_json::to_string_pretty(records).map_err(|e| e.to_string()),
+        RecordFormat::Jsonl => Ok(records.iter().map(|r| format!("{}\n", r)).collect()),
+        RecordFormat::Csv => {
+            let mut columns: Vec<&str> = Vec::new();
+            for record in records {
+                for key in record.as_object().into_iter().flat_map(|o| o.keys()) {
+                    if !columns.contains(&key.as_str()) {
+                        columns.push(key);
+                    }
+                }
+            }
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer.write_record(&columns).map_err(|e| e.to_string())?;
+            for record in records {
+                let row = columns.iter().map(|c| record.get(*c).map(csv_cell).unwrap_or_default());
+                writer.write_record(row).map_err(|e| e.to_string())?;
+            }
+            let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+            String::from_utf8(bytes).map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// One script, many input rows.
+pub struct BatchSpec {
+    pub source: RunSource,
+    pub script: String,
+    pub profile: Option<Profile>,
+    pub rows: Vec<Map<String, Value>>,
+    /// Resolved params, the same for every row
+    pub params: Map<String, Value>,
+    /// Shared by all rows, which write to it once they succeed; without one, what a
+    /// row pushes joins its results
+    pub output: Option<Arc<OutputSink>>,
+    /// Rows in flight at once; the window pool caps this too
+    pub parallel: usize,
+    /// Extra attempts for a failing row
+    pub retries: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowError {
+    /// 1-based position in the dataset
+    pub row: usize,
+    pub input: Map<String, Value>,
+    pub error: String,
+    pub attempts: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchRecord {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Collected once the batch is finished, in row order
+    pub results: Vec<Value>,
+    pub errors: Vec<RowError>,
+    /// Records of finished rows, by row
+    #[serde(skip)]
+    row_results: Vec<Option<Vec<Value>>>,
+}
+
+impl BatchRecord {
+    fn new(total: usize) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            created_at: Utc::now(),
+            finished_at: None,
+            total,
+            succeeded: 0,
+            failed: 0,
+            results: Vec::new(),
+            errors: Vec::new(),
+            row_results: vec![None; total],
+        }
+    }
+}
+
+/// Registers a batch and returns its id; `run` then works through it.
+pub fn register(state: &Arc<Mutex<AppState>>, spec: &BatchSpec) -> String {
+    let mut s = state.lock().unwrap();
+    if s.batches.len() >= MAX_BATCHES {
+        let oldest = s
+            .batches
+            .values()
+            .filter(|b| b.finished_at.is_some())
+            .min_by_key(|b| b.created_at)
+            .map(|b| b.id.clone());
+        if let Some(id) = oldest {
+            s.batches.remove(&id);
+        }
+    }
+    let batch = BatchRecord::new(spec.rows.len());
+    let id = batch.id.clone();
+    s.batches.insert(id.clone(), batch);
+    id
+}
+
+/// Writes a successful attempt's records to the batch's sink.
+fn flush(sink: &OutputSink, records: Vec<Value>) -> Result<(), String> {
+    for record in records {
+        if let Value::Object(record) = record {
+            sink.push(record).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// The records the row pushed and its return value, and the attempts it took.
+type RowOutcome = (Result<(Vec<Value>, Value), String>, u32);
+
+async fn run_row(app: &AppHandle, state: &Arc<Mutex<AppState>>, spec: &BatchSpec, row: &Map<String, Value>) -> RowOutcome {
+    let input = match rhai::serde::to_dynamic(row).map(|d| d.try_cast::<rhai::Map>()) {
+        Ok(Some(input)) => input,
+        _ => return (Err("Row cannot be converted to a script map".into()), 0),
+    };
+
+    let mut attempts = 0;
+    loop {
+        let job = PoolJob {
+            source: spec.source,
+            profile: spec.profile.clone(),
+            session: None,
+            script: spec.script.clone(),
+            // Pushed records wait in the run registry until the attempt succeeds, so a
+            // failed attempt doesn't leave them in the shared sink for a retry to repeat
+            inputs: ScriptInputs {
+                input: Some(input.clone()),
+                params: params::to_script_map(&spec.params),
+                output: None,
+                debug: None,
+            },
+            emit_logs: false,
+        };
+        let (run_id, done) = match pool::submit(app, state, job) {
+            Ok(submitted) => submitted,
+            // Other jobs filled the queue; wait for room without spending an attempt
+            Err(PoolError::QueueFull { .. }) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        attempts += 1;
+        let result = done
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()))
+            .map(|value| serde_json::to_value(&value).unwrap_or_default());
+        if result.is_ok() || attempts > spec.retries {
+            let pushed = state.lock().unwrap().runs.records(&run_id).unwrap_or_default();
+            let result = result.and_then(|value| match &spec.output {
+                Some(sink) => flush(sink, pushed).map(|_| (Vec::new(), value)),
+                None => Ok((pushed, value)),
+            });
+            return (result, attempts);
+        }
+    }
+}
+
+/// Runs the script for every row through the window pool and returns the finished batch.
+pub async fn run(app: AppHandle, state: Arc<Mutex<AppState>>, batch_id: String, spec: BatchSpec) -> BatchRecord {
+    let spec = Arc::new(spec);
+    let permits = Arc::new(Semaphore::new(spec.parallel.max(1)));
+    let mut tasks = Vec::with_capacity(spec.rows.len());
+
+    for index in 0..spec.rows.len() {
+        let (app, state, spec, permits, batch_id) = (app.clone(), state.clone(), spec.clone(), permits.clone(), batch_id.clone());
+        tasks.push(tauri::async_runtime::spawn(async move {
+            let _permit = permits.acquire().await;
+            let row = &spec.rows[index];
+            let (result, attempts) = run_row(&app, &state, &spec, row).await;
+
+            let mut s = state.lock().unwrap();
+            let Some(batch) = s.batches.get_mut(&batch_id) else { return };
+            match result {
+                Ok((mut records, value)) => {
+                    batch.succeeded += 1;
+                    records.extend(records_from(value));
+                    batch.row_results[index] = Some(records);
+                }
+                Err(error) => {
+                    batch.failed += 1;
+                    batch.errors.push(RowError { row: index + 1, input: row.clone(), error, attempts });
+                }
+            }
+        }));
+    }
+    for task in tasks {
+        let _ = task.await;
+    }
+
+    let mut s = state.lock().unwrap();
+    let batch = s.batches.get_mut(&batch_id).expect("batch registered before running");
+    batch.results = batch.row_results.drain(..).flatten().flatten().collect();
+    batch.errors.sort_by_key(|e| e.row);
+    batch.finished_at = Some(Utc::now());
+    batch.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rows(value: Value) -> Vec<Map<String, Value>> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn format_from_path() {
+        let cases = [
+            ("rows.csv", RecordFormat::Csv),
+            ("rows.CSV", RecordFormat::Csv),
+            ("rows.jsonl", RecordFormat::Jsonl),
+            ("rows.ndjson", RecordFormat::Jsonl),
+            ("rows.json", RecordFormat::Json),
+            ("rows", RecordFormat::Json),
+        ];
+        for (path, format) in cases {
+            assert_eq!(RecordFormat::from_path(Path::new(path)), format, "{path}");
+        }
+    }
+
+    #[test]
+    fn parses_rows() {
+        let expected = rows(json!([{"url": "a", "n": "1"}, {"url": "b, c", "n": "2"}]));
+        let cases = [
+            ("url,n\na,1\n\"b, c\",2\n", RecordFormat::Csv, expected.clone()),
+            ("{\"url\": \"a\", \"n\": \"1\"}\n\n{\"url\": \"b, c\", \"n\": \"2\"}\n", RecordFormat::Jsonl, expected.clone()),
+            ("[{\"url\": \"a\", \"n\": \"1\"}, {\"url\": \"b, c\", \"n\": \"2\"}]", RecordFormat::Json, expected),
+            ("url,n\n", RecordFormat::Csv, Vec::new()),
+            ("{\"n\": 1}", RecordFormat::Jsonl, rows(json!([{"n": 1}]))),
+        ];
+        for (content, format, expected) in cases {
+            assert_eq!(parse_rows(content, format).unwrap(), expected, "{content}");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_rows() {
+        let cases = [
+            ("url,n\na,1,extra\n", RecordFormat::Csv, "Invalid CSV row 1"),
+            ("{\"n\": 1}\n[1]\n", RecordFormat::Jsonl, "Line 2: each line must be a JSON object"),
+            ("{\"n\": 1}\n{oops\n", RecordFormat::Jsonl, "Line 2:"),
+            ("[{\"n\": 1}, 2]", RecordFormat::Json, "Item 2: each item must be a JSON object"),
+            ("{\"n\": 1}", RecordFormat::Json, "Expected a JSON array of objects"),
+        ];
+        for (content, format, expected) in cases {
+            let error = parse_rows(content, format).unwrap_err();
+            assert!(error.starts_with(expected), "{content}: {error}");
+        }
+    }
+
+    #[test]
+    fn records_from_return_values() {
+        let cases = [
+            (json!(null), vec![]),
+            (json!({"a": 1}), vec![json!({"a": 1})]),
+            (json!([{"a": 1}, 2]), vec![json!({"a": 1}), json!({"result": 2})]),
+            (json!("done"), vec![json!({"result": "done"})]),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(records_from(value.clone()), expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn formats_records() {
+        let records = [json!({"a": 1, "b": "x"}), json!({"c": null, "a": [1, 2]})];
+        let cases = [
+            (RecordFormat::Csv, "a,b,c\n1,x,\n\"[1,2]\",,\n"),
+            (RecordFormat::Jsonl, "{\"a\":1,\"b\":\"x\"}\n{\"a\":[1,2],\"c\":null}\n"),
+        ];
+        for (format, expected) in cases {
+            assert_eq!(format_records(&records, format).unwrap(), expected, "{format:?}");
+        }
+        let json = format_records(&records, RecordFormat::Json).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), json!(records));
+    }
+}
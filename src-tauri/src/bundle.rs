@@ -0,0 +1,510 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, State};
+use crate::commands;
+use crate::cookies::{self, CookieRecord};
+use crate::crypto::{self, SealedBox};
+use crate::error::{BundleError, ProfileError, VaultError};
+use crate::profiles;
+use crate::session::{self, SessionSnapshot};
+use crate::state::{AppState, Profile};
+use crate::storage;
+use crate::vault;
+
+/// Bumped whenever the bundle layout changes; older bundles keep importing.
+/// Version 2 can carry cookies and sessions inside the sealed secrets.
+pub const BUNDLE_FORMAT_VERSION: u32 = 2;
+
+/// A complete, portable identity: profile definition, browser state and metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileBundle {
+    pub format_version: u32,
+    pub metadata: BundleMetadata,
+    /// Secrets (the proxy password) are stripped; see `secrets`
+    pub profile: Profile,
+    /// Empty when sealed with a passphrase; they are in `secrets` then
+    pub cookies: Vec<CookieRecord>,
+    #[serde(default)]
+    pub sessions: Vec<BundledSession>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secrets: Option<BundleSecretsField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleMetadata {
+    /// Older bundles call it `created_at`
+    #[serde(alias = "created_at")]
+    pub exported_at: DateTime<Utc>,
+    pub app_version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundledSession {
+    pub name: String,
+    pub snapshot: SessionSnapshot,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BundleSecrets {
+    #[serde(default)]
+    pub proxy_password: Option<String>,
+    /// Live auth tokens, so they go in here when the bundle is sealed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cookies: Option<Vec<CookieRecord>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sessions: Option<Vec<BundledSession>>,
+}
+
+/// Without a passphrase, secrets are only included on request and in the clear. With one,
+/// they are sealed together with the cookies and sessions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "encoding", rename_all = "lowercase")]
+pub enum BundleSecretsField {
+    Plain(BundleSecrets),
+    Encrypted(SealedBox),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportOptions {
+    #[serde(default)]
+    pub include_secrets: bool,
+    /// Encrypts the cookies, the sessions and, if included, the secrets
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+/// What to do when a profile with the bundle's name already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    #[default]
+    Fail,
+    /// Replace the existing profile and discard its storage
+    Overwrite,
+    /// Import under the first free "<name> (n)"
+    Rename,
+    /// Leave the existing profile alone and import nothing
+    Skip,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ImportOptions {
+    #[serde(default)]
+    pub on_conflict: ConflictPolicy,
+    #[serde(default)]
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    /// Name the profile was imported under
+    pub profile: String,
+    pub skipped: bool,
+    pub cookies: usize,
+    pub sessions: usize,
+    pub secrets_imported: bool,
+}
+
+/// Builds a bundle for a profile. Reading cookies opens the profile's data store,
+/// so this must not run on the main thread.
+pub fn export(
+    app: &AppHandle,
+    state: &Arc<Mutex<AppState>>,
+    profile_name: &str,
+    options: &ExportOptions,
+) -> Result<ProfileBundle, BundleError> {
+    let mut profile = commands::find_profile(profile_name)
+        .ok_or_else(|| ProfileError::NotFound(profile_name.to_string()))?;
+
+    // The password is resolved through the vault, so the bundle also works where that vault doesn't exist.
+    // The secret reference stays on the profile and is reused on import when possible.
+    let proxy_password = match profile.proxy.as_mut() {
+        Some(proxy) => {
+            let password = vault::resolve_proxy_password(&state.lock().unwrap(), proxy);
+            proxy.password = None;
+            if options.include_secrets {
+                password.map_err(|e| BundleError::Storage(e.to_string()))?
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
+    let cookies = cookies::with_profile_store(app, state, profile_name, cookies::get_all).map_err(BundleError::Storage)?;
+    let sessions = session::list(profile_name)
+        .into_iter()
+        .filter_map(|info| {
+            let snapshot = session::load(profile_name, &info.name).ok()?;
+            Some(BundledSession { name: info.name, snapshot })
+        })
+        .collect();
+
+    let (cookies, sessions, secrets) = protect(
+        BundleSecrets { proxy_password, cookies: Some(cookies), sessions: Some(sessions) },
+        options.include_secrets,
+        options.passphrase.as_deref(),
+    )?;
+
+    Ok(ProfileBundle {
+        format_version: BUNDLE_FORMAT_VERSION,
+        metadata: BundleMetadata {
+            exported_at: Utc::now(),
+            app_version: app.package_info().version.to_string(),
+            notes: options.notes.clone(),
+        },
+        profile,
+        cookies,
+        sessions,
+        secrets,
+    })
+}
+
+pub fn parse(content: &str) -> Result<ProfileBundle, BundleError> {
+    // Check the version first, so a newer bundle gets a clear message instead of a field error
+    let raw: serde_json::Value = serde_json::from_str(content).map_err(|e| BundleError::Format(e.to_string()))?;
+    let version = raw.get("format_version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+    if version == 0 {
+        return Err(BundleError::Format("missing format_version".into()));
+    }
+    if version > BUNDLE_FORMAT_VERSION {
+        return Err(BundleError::UnsupportedVersion(version));
+    }
+    serde_json::from_value(raw).map_err(|e| BundleError::Format(e.to_string()))
+}
+
+/// Splits what goes into a bundle into the clear cookies, clear sessions and secrets field.
+/// With a passphrase everything is sealed and the clear parts are empty.
+fn protect(
+    mut secrets: BundleSecrets,
+    include_secrets: bool,
+    passphrase: Option<&str>,
+) -> Result<(Vec<CookieRecord>, Vec<BundledSession>, Option<BundleSecretsField>), BundleError> {
+    if let Some(passphrase) = passphrase.filter(|p| !p.is_empty()) {
+        let plaintext = serde_json::to_vec(&secrets).map_err(|e| BundleError::Format(e.to_string()))?;
+        let sealed = crypto::seal(passphrase.as_bytes(), &plaintext).map_err(BundleError::Storage)?;
+        return Ok((Vec::new(), Vec::new(), Some(BundleSecretsField::Encrypted(sealed))));
+    }
+
+    let cookies = secrets.cookies.take().unwrap_or_default();
+    let sessions = secrets.sessions.take().unwrap_or_default();
+    Ok((cookies, sessions, include_secrets.then_some(BundleSecretsField::Plain(secrets))))
+}
+
+fn open_secrets(field: Option<BundleSecretsField>, passphrase: Option<&str>) -> Result<Option<BundleSecrets>, BundleError> {
+    match field {
+        None => Ok(None),
+        Some(BundleSecretsField::Plain(secrets)) => Ok(Some(secrets)),
+        Some(BundleSecretsField::Encrypted(sealed)) => {
+            let passphrase = passphrase.filter(|p| !p.is_empty()).ok_or(BundleError::PassphraseRequired)?;
+            let plaintext = crypto::open(passphrase.as_bytes(), &sealed)
+                .map_err(BundleError::Format)?
+                .ok_or(BundleError::WrongPassphrase)?;
+            serde_json::from_slice(&plaintext)
+                .map(Some)
+                .map_err(|e| BundleError::Format(e.to_string()))
+        }
+    }
+}
+
+/// The first of `candidate(2)`, `candidate(3)`, ... that isn't taken.
+fn free_name(candidate: impl Fn(u32) -> String, taken: impl Fn(&str) -> bool) -> String {
+    (2..).map(candidate).find(|name| !taken(name)).unwrap()
+}
+
+pub async fn import(
+    app: &AppHandle,
+    state: &Arc<Mutex<AppState>>,
+    bundle: ProfileBundle,
+    options: &ImportOptions,
+) -> Result<ImportReport, BundleError> {
+    // Decrypt and validate before touching anything, so a bad bundle leaves local data as it was
+    let mut secrets = open_secrets(bundle.secrets, options.passphrase.as_deref())?;
+    let mut bundled_cookies = bundle.cookies;
+    let mut bundled_sessions = bundle.sessions;
+    if let Some(secrets) = secrets.as_mut() {
+        bundled_cookies.extend(secrets.cookies.take().unwrap_or_default());
+        bundled_sessions.extend(secrets.sessions.take().unwrap_or_default());
+    }
+
+    let mut profile = bundle.profile;
+    profiles::validate(&profile)?;
+
+    let existing = commands::load_profiles_from_disk();
+    let conflict = existing.iter().any(|p| p.name == profile.name);
+    let overwrite = conflict && options.on_conflict == ConflictPolicy::Overwrite;
+    if conflict {
+        match options.on_conflict {
+            ConflictPolicy::Fail => return Err(ProfileError::AlreadyExists(profile.name).into()),
+            ConflictPolicy::Skip => {
+                return Ok(ImportReport {
+                    profile: profile.name,
+                    skipped: true,
+                    cookies: 0,
+                    sessions: 0,
+                    secrets_imported: false,
+                })
+            }
+            ConflictPolicy::Rename => {
+                let base = profile.name.clone();
+                profile.name = free_name(|n| format!("{} ({})", base, n), |name| existing.iter().any(|p| p.name == name));
+            }
+            ConflictPolicy::Overwrite => profiles::ensure_not_open(state, &profile.name)?,
+        }
+    }
+
+    // Keep the vault reference if this machine's vault is open, else fall back to plaintext.
+    // A local secret of the same name with another value is left alone; the password gets a free name.
+    let password = secrets.and_then(|s| s.proxy_password);
+    let mut secret_to_store = None;
+    if let (Some(proxy), Some(password)) = (profile.proxy.as_mut(), password.clone()) {
+        let s = state.lock().unwrap();
+        match (proxy.password_secret.as_deref(), s.vault.as_ref()) {
+            (Some(name), Some(vault)) => {
+                let name = match vault.get(name) {
+                    None => name.to_string(),
+                    Some(value) if value == password => name.to_string(),
+                    Some(_) => free_name(|n| format!("{}-{}", name, n), |candidate| vault.get(candidate).is_some()),
+                };
+                proxy.password_secret = Some(name.clone());
+                secret_to_store = Some((name, password));
+            }
+            _ => {
+                proxy.password = Some(password);
+                proxy.password_secret = None;
+            }
+        }
+    }
+    let secrets_imported = password.is_some() && profile.proxy.is_some();
+    profiles::validate(&profile)?;
+    for bundled in bundled_sessions.iter_mut() {
+        bundled.snapshot.profile = profile.name.clone();
+    }
+
+    let name = profile.name.clone();
+    let session_count = bundled_sessions.len();
+    let cookie_count = bundled_cookies.len();
+    if overwrite {
+        replace(app, state, profile, bundled_sessions, bundled_cookies, secret_to_store).await?;
+    } else {
+        store_secret(state, secret_to_store)?;
+        profiles::create(profile.clone())?;
+        if let Err(e) = import_contents(app, &profile, bundled_sessions, bundled_cookies).await {
+            // Don't leave a half-imported profile behind
+            let _ = profiles::delete(app, state, &name).await;
+            return Err(e);
+        }
+    }
+
+    Ok(ImportReport {
+        profile: name,
+        skipped: false,
+        cookies: cookie_count,
+        sessions: session_count,
+        secrets_imported,
+    })
+}
+
+fn store_secret(state: &Arc<Mutex<AppState>>, secret: Option<(String, String)>) -> Result<(), BundleError> {
+    let Some((name, password)) = secret else { return Ok(()) };
+    let mut s = state.lock().unwrap();
+    let vault = s.vault.as_mut().ok_or_else(|| BundleError::Storage(VaultError::Locked.to_string()))?;
+    if vault.get(&name) != Some(password.as_str()) {
+        vault.set(&name, password).map_err(|e| BundleError::Storage(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Replaces an existing profile with an imported one. The new sessions and storage are
+/// written under a temporary name first and only swapped in once that worked; the old
+/// ones are kept aside until the profile itself is saved. Any failure before that leaves
+/// the existing profile as it was.
+async fn replace(
+    app: &AppHandle,
+    state: &Arc<Mutex<AppState>>,
+    profile: Profile,
+    sessions: Vec<BundledSession>,
+    cookies: Vec<CookieRecord>,
+    secret: Option<(String, String)>,
+) -> Result<(), BundleError> {
+    let name = profile.name.clone();
+    let staged = Profile { name: format!("{} (import {})", name, uuid::Uuid::new_v4().simple()), ..profile.clone() };
+    let aside = format!("{} (replaced {})", name, uuid::Uuid::new_v4().simple());
+    // WebKit on macOS keeps storage in a data store that can't be moved, so the cookies
+    // can only be written once the old store is gone
+    let movable_store = !cfg!(any(target_os = "macos", target_os = "ios"));
+    let (staged_cookies, late_cookies) = if movable_store { (cookies, Vec::new()) } else { (Vec::new(), cookies) };
+
+    if let Err(e) = import_contents(app, &staged, sessions, staged_cookies).await {
+        discard(app, &staged.name).await;
+        return Err(e);
+    }
+
+    storage::close_studio_if_using(app, state, &name);
+    let dirs: [fn(&str) -> PathBuf; 2] = [storage::profile_data_dir, session::sessions_dir];
+    let mut moves = Vec::new();
+    for dir in dirs {
+        moves.push((dir(&name), dir(&aside)));
+        moves.push((dir(&staged.name), dir(&name)));
+    }
+    let moved = match move_dirs(&moves).await {
+        Ok(moved) => moved,
+        Err(e) => {
+            discard(app, &staged.name).await;
+            return Err(BundleError::Storage(e));
+        }
+    };
+
+    let mut committed = store_secret(state, secret);
+    if committed.is_ok() {
+        committed = profiles::save(app, state, profile.clone()).await.map(|_| ()).map_err(BundleError::from);
+    }
+    if let Err(e) = committed {
+        undo_moves(&moved).await;
+        discard(app, &staged.name).await;
+        return Err(e);
+    }
+
+    discard(app, &aside).await;
+    if !movable_store {
+        storage::remove_profile_storage(app, &name).await.map_err(BundleError::Storage)?;
+        import_contents(app, &profile, Vec::new(), late_cookies).await?;
+    }
+    Ok(())
+}
+
+/// Makes the moves in order. If one fails, the ones already made are moved back.
+/// Returns the moves that were made (moving a missing directory is skipped).
+async fn move_dirs(moves: &[(PathBuf, PathBuf)]) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let mut moved = Vec::new();
+    for (from, to) in moves {
+        match storage::move_dir_with_retry(from, to).await {
+            Ok(true) => moved.push((from.clone(), to.clone())),
+            Ok(false) => {}
+            Err(e) => {
+                undo_moves(&moved).await;
+                return Err(e);
+            }
+        }
+    }
+    Ok(moved)
+}
+
+async fn undo_moves(moved: &[(PathBuf, PathBuf)]) {
+    for (from, to) in moved.iter().rev() {
+        if let Err(e) = storage::move_dir_with_retry(to, from).await {
+            eprintln!("Failed to restore profile data: {}", e);
+        }
+    }
+}
+
+/// Removes the storage and sessions kept under a temporary name.
+async fn discard(app: &AppHandle, name: &str) {
+    let _ = storage::remove_profile_storage(app, name).await;
+    let _ = session::remove_all(name);
+}
+
+/// Writes the bundled sessions and cookies into `store`'s storage.
+async fn import_contents(
+    app: &AppHandle,
+    store: &Profile,
+    sessions: Vec<BundledSession>,
+    cookies: Vec<CookieRecord>,
+) -> Result<(), BundleError> {
+    for bundled in sessions {
+        session::write(&store.name, &bundled.name, &bundled.snapshot).map_err(BundleError::Storage)?;
+    }
+    if cookies.is_empty() {
+        return Ok(());
+    }
+
+    let (app_handle, store) = (app.clone(), store.clone());
+    tauri::async_runtime::spawn_blocking(move || {
+        cookies::with_new_store(&app_handle, &store, |window| {
+            cookies.iter().try_for_each(|c| cookies::set(window, c))
+        })
+    })
+    .await
+    .map_err(|e| BundleError::Storage(e.to_string()))?
+    .map_err(BundleError::Storage)
+}
+
+/// Returns the bundle as JSON text, ready to be written to a single file.
+#[tauri::command]
+pub async fn export_profile_bundle(
+    profile_name: String,
+    options: Option<ExportOptions>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    app: AppHandle,
+) -> Result<String, BundleError> {
+    let bundle = export(&app, state.inner(), &profile_name, &options.unwrap_or_default())?;
+    serde_json::to_string_pretty(&bundle).map_err(|e| BundleError::Format(e.to_string()))
+}
+
+#[tauri::command]
+pub async fn import_profile_bundle(
+    content: String,
+    options: Option<ImportOptions>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    app: AppHandle,
+) -> Result<ImportReport, BundleError> {
+    let bundle = parse(&content)?;
+    import(&app, state.inner(), bundle, &options.unwrap_or_default()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cookie() -> CookieRecord {
+        CookieRecord {
+            name: "sid".into(),
+            value: "token-123".into(),
+            domain: ".example.com".into(),
+            path: "/".into(),
+            expires: None,
+            secure: true,
+            http_only: true,
+            same_site: None,
+        }
+    }
+
+    fn secrets() -> BundleSecrets {
+        BundleSecrets { proxy_password: None, cookies: Some(vec![cookie()]), sessions: Some(Vec::new()) }
+    }
+
+    #[test]
+    fn a_passphrase_seals_cookies_and_sessions() {
+        let (cookies, sessions, field) = protect(secrets(), false, Some("pass")).unwrap();
+        assert!(cookies.is_empty() && sessions.is_empty());
+        let Some(BundleSecretsField::Encrypted(sealed)) = &field else { panic!("not sealed: {:?}", field) };
+        assert!(!sealed.ciphertext.contains("token-123"));
+
+        let opened = open_secrets(field, Some("pass")).unwrap().unwrap();
+        assert_eq!(opened.cookies, Some(vec![cookie()]));
+        assert!(matches!(open_secrets(sealed_field(), None), Err(BundleError::PassphraseRequired)));
+    }
+
+    #[test]
+    fn without_a_passphrase_only_requested_secrets_are_kept_apart() {
+        let (cookies, _, field) = protect(secrets(), false, None).unwrap();
+        assert_eq!(cookies, vec![cookie()]);
+        assert!(field.is_none());
+
+        let with_password = BundleSecrets { proxy_password: Some("hunter2".into()), ..secrets() };
+        let (cookies, _, field) = protect(with_password, true, Some("")).unwrap();
+        assert_eq!(cookies, vec![cookie()]);
+        let Some(BundleSecretsField::Plain(plain)) = field else { panic!("expected plain secrets") };
+        assert_eq!(plain.proxy_password.as_deref(), Some("hunter2"));
+        assert!(plain.cookies.is_none());
+    }
+
+    fn sealed_field() -> Option<BundleSecretsField> {
+        protect(secrets(), false, Some("pass")).unwrap().2
+    }
+}
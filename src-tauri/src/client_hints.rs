@@ -0,0 +1,334 @@
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Brand {
+    pub brand: String,
+    pub version: String,
+}
+
+/// User-Agent Client Hints derived from a profile's `user_agent`.
+///
+/// Serializes with the same field names as `NavigatorUAData.getHighEntropyValues()`,
+/// so the injected script can use it as-is.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientHints {
+    pub brands: Vec<Brand>,
+    pub full_version_list: Vec<Brand>,
+    pub mobile: bool,
+    pub platform: String,
+    pub platform_version: String,
+    pub architecture: String,
+    pub bitness: String,
+    pub model: String,
+    pub ua_full_version: String,
+    pub wow64: bool,
+}
+
+impl ClientHints {
+    /// Parses a User-Agent string. Returns `None` for browsers that don't implement
+    /// UA-CH (Firefox, Safari, Chrome on iOS), which must expose neither
+    /// `navigator.userAgentData` nor `Sec-CH-UA*` headers.
+    pub fn from_user_agent(ua: &str) -> Option<Self> {
+        let chrome_version = token_version(ua, "Chrome/")?;
+        let chrome_major = major(&chrome_version);
+
+        // Vendor brand on top of Chromium. Edge and Opera advertise their own version.
+        let (brand_name, brand_version) = if let Some(v) = token_version(ua, "Edg/") {
+            ("Microsoft Edge", v)
+        } else if let Some(v) = token_version(ua, "OPR/") {
+            ("Opera", v)
+        } else if ua.contains("; wv)") {
+            ("Android WebView", chrome_version.clone())
+        } else {
+            ("Google Chrome", chrome_version.clone())
+        };
+
+        let brands = greased_brand_list(chrome_major, chrome_major, brand_name, major(&brand_version), false);
+        let full_version_list = greased_brand_list(chrome_major, &chrome_version, brand_name, &brand_version, true);
+
+        let platform_tokens = platform_tokens(ua);
+        let (platform, platform_version) = parse_platform(&platform_tokens);
+        let (architecture, bitness, wow64) = parse_architecture(&platform_tokens);
+        let model = if platform == "Android" { parse_android_model(&platform_tokens) } else { String::new() };
+
+        Some(Self {
+            brands,
+            full_version_list,
+            mobile: ua.contains(" Mobile"),
+            platform,
+            platform_version,
+            architecture,
+            bitness,
+            model,
+            ua_full_version: brand_version,
+            wow64,
+        })
+    }
+
+    /// Value for a `Sec-CH-UA*` request header (lowercase name), in structured-header syntax.
+    pub fn header_value(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "sec-ch-ua" => brand_list_header(&self.brands),
+            "sec-ch-ua-full-version-list" => brand_list_header(&self.full_version_list),
+            "sec-ch-ua-mobile" => sf_boolean(self.mobile),
+            "sec-ch-ua-wow64" => sf_boolean(self.wow64),
+            "sec-ch-ua-platform" => sf_string(&self.platform),
+            "sec-ch-ua-platform-version" => sf_string(&self.platform_version),
+            "sec-ch-ua-arch" => sf_string(&self.architecture),
+            "sec-ch-ua-bitness" => sf_string(&self.bitness),
+            "sec-ch-ua-model" => sf_string(&self.model),
+            "sec-ch-ua-full-version" => sf_string(&self.ua_full_version),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Low-entropy hints Chromium sends on every request.
+pub const DEFAULT_HEADERS: [&str; 3] = ["sec-ch-ua", "sec-ch-ua-mobile", "sec-ch-ua-platform"];
+
+fn token_version(ua: &str, token: &str) -> Option<String> {
+    let start = ua.find(token)? + token.len();
+    let version: String = ua[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    if version.is_empty() { None } else { Some(version) }
+}
+
+fn major(version: &str) -> &str {
+    version.split('.').next().unwrap_or(version)
+}
+
+/// Reproduces Chromium's GREASE brand (`GetGreasedUserAgentBrandVersion`): the fake
+/// brand name, its version and the list order are all derived from the major version.
+fn greased_brand_list(
+    chrome_major: &str,
+    chromium_version: &str,
+    brand_name: &str,
+    brand_version: &str,
+    full: bool,
+) -> Vec<Brand> {
+    const GREASE_CHARS: [char; 11] = [' ', '(', ':', '-', '.', '/', ')', ';', '=', '?', '_'];
+    const GREASE_VERSIONS: [&str; 3] = ["8", "99", "24"];
+    const ORDERS: [[usize; 3]; 6] = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+
+    let seed: usize = chrome_major.parse().unwrap_or(0);
+    let grease_version = GREASE_VERSIONS[seed % GREASE_VERSIONS.len()];
+    let grease = Brand {
+        brand: format!(
+            "Not{}A{}Brand",
+            GREASE_CHARS[seed % GREASE_CHARS.len()],
+            GREASE_CHARS[(seed + 1) % GREASE_CHARS.len()]
+        ),
+        version: if full { format!("{}.0.0.0", grease_version) } else { grease_version.to_string() },
+    };
+    let chromium = Brand {
+        brand: "Chromium".into(),
+        version: chromium_version.to_string(),
+    };
+    let vendor = Brand {
+        brand: brand_name.to_string(),
+        version: brand_version.to_string(),
+    };
+
+    let mut list = vec![grease.clone(); 3];
+    let order = ORDERS[seed % ORDERS.len()];
+    list[order[0]] = grease;
+    list[order[1]] = chromium;
+    list[order[2]] = vendor;
+    list
+}
+
+/// Tokens of the first parenthesized section, e.g. `["Windows NT 10.0", "Win64", "x64"]`.
+fn platform_tokens(ua: &str) -> Vec<String> {
+    let Some(start) = ua.find('(') else {
+        return Vec::new();
+    };
+    let end = ua[start..].find(')').map(|e| start + e).unwrap_or(ua.len());
+    ua[start + 1..end].split(';').map(|t| t.trim().to_string()).collect()
+}
+
+fn parse_platform(tokens: &[String]) -> (String, String) {
+    for t in tokens {
+        if let Some(v) = t.strip_prefix("Windows NT ") {
+            // Chromium reports the UniversalApiContract version; pre-10 releases map to 0.x
+            let version = match v {
+                "6.1" => "0.1.0",
+                "6.2" => "0.2.0",
+                "6.3" => "0.3.0",
+                _ => "10.0.0",
+            };
+            return ("Windows".into(), version.into());
+        }
+        if let Some(v) = t.strip_prefix("Android ") {
+            return ("Android".into(), pad_version(v));
+        }
+        if let Some(v) = t.strip_prefix("Intel Mac OS X ") {
+            return ("macOS".into(), pad_version(&v.replace('_', ".")));
+        }
+        if let Some(rest) = t.strip_prefix("CrOS ") {
+            let version = rest.split_whitespace().nth(1).unwrap_or("");
+            return ("Chrome OS".into(), version.into());
+        }
+    }
+    if tokens.iter().any(|t| t.contains("Linux")) {
+        return ("Linux".into(), String::new());
+    }
+    ("Unknown".into(), String::new())
+}
+
+fn parse_architecture(tokens: &[String]) -> (String, String, bool) {
+    let has = |needle: &str| tokens.iter().any(|t| t.contains(needle));
+
+    if has("Android") {
+        return (String::new(), String::new(), false);
+    }
+    if has("WOW64") {
+        return ("x86".into(), "64".into(), true);
+    }
+    if has("aarch64") || has("arm64") || has("armv8") {
+        return ("arm".into(), "64".into(), false);
+    }
+    if has("arm") {
+        return ("arm".into(), "32".into(), false);
+    }
+    if has("x64") || has("x86_64") || has("Win64") || has("Intel Mac") {
+        return ("x86".into(), "64".into(), false);
+    }
+    ("x86".into(), "32".into(), false)
+}
+
+fn parse_android_model(tokens: &[String]) -> String {
+    let Some(idx) = tokens.iter().position(|t| t.starts_with("Android")) else {
+        return String::new();
+    };
+    let model = tokens
+        .get(idx + 1)
+        .map(|t| t.split(" Build/").next().unwrap_or(t).to_string())
+        .unwrap_or_default();
+
+    // Reduced UA strings replace the model with "K"
+    if model == "K" { String::new() } else { model }
+}
+
+fn pad_version(v: &str) -> String {
+    let mut parts: Vec<&str> = v.split('.').collect();
+    while parts.len() < 3 {
+        parts.push("0");
+    }
+    parts.join(".")
+}
+
+fn brand_list_header(brands: &[Brand]) -> String {
+    brands
+        .iter()
+        .map(|b| format!("{};v={}", sf_string(&b.brand), sf_string(&b.version)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn sf_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn sf_boolean(b: bool) -> String {
+    if b { "?1".into() } else { "?0".into() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHROME_WIN: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+    const CHROME_MAC: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.6367.91 Safari/537.36";
+    const CHROME_ANDROID: &str = "Mozilla/5.0 (Linux; Android 10; K) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Mobile Safari/537.36";
+    const EDGE_WIN: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36 Edg/121.0.2277.83";
+
+    #[test]
+    fn browsers_without_ua_ch_get_none() {
+        let cases = [
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:121.0) Gecko/20100101 Firefox/121.0",
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.2 Safari/605.1.15",
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_2 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) CriOS/120.0.6099.119 Mobile/15E148 Safari/604.1",
+            "",
+        ];
+        for ua in cases {
+            assert_eq!(ClientHints::from_user_agent(ua), None, "{ua}");
+        }
+    }
+
+    #[test]
+    fn grease_brand_order_matches_chromium() {
+        // Header values as sent by the real browsers for these versions.
+        let cases = [
+            (CHROME_WIN, r#""Not_A Brand";v="8", "Chromium";v="120", "Google Chrome";v="120""#),
+            (CHROME_MAC, r#""Chromium";v="124", "Google Chrome";v="124", "Not-A.Brand";v="99""#),
+            (EDGE_WIN, r#""Not A(Brand";v="99", "Microsoft Edge";v="121", "Chromium";v="121""#),
+        ];
+        for (ua, expected) in cases {
+            let hints = ClientHints::from_user_agent(ua).unwrap();
+            assert_eq!(hints.header_value("sec-ch-ua").unwrap(), expected, "{ua}");
+        }
+    }
+
+    #[test]
+    fn full_version_list_carries_full_versions() {
+        let hints = ClientHints::from_user_agent(EDGE_WIN).unwrap();
+        assert_eq!(
+            hints.header_value("sec-ch-ua-full-version-list").unwrap(),
+            r#""Not A(Brand";v="99.0.0.0", "Microsoft Edge";v="121.0.2277.83", "Chromium";v="121.0.0.0""#
+        );
+        assert_eq!(hints.ua_full_version, "121.0.2277.83");
+    }
+
+    #[test]
+    fn platform_and_architecture() {
+        // (ua, platform, platform_version, architecture, bitness, mobile, model)
+        let cases = [
+            (CHROME_WIN, "Windows", "10.0.0", "x86", "64", false, ""),
+            (CHROME_MAC, "macOS", "10.15.7", "x86", "64", false, ""),
+            (CHROME_ANDROID, "Android", "10.0.0", "", "", true, ""),
+            (
+                "Mozilla/5.0 (Linux; Android 13; Pixel 7 Build/TQ3A.230805.001) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36",
+                "Android", "13.0.0", "", "", true, "Pixel 7",
+            ),
+            (
+                "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+                "Linux", "", "x86", "64", false, "",
+            ),
+        ];
+        for (ua, platform, platform_version, architecture, bitness, mobile, model) in cases {
+            let hints = ClientHints::from_user_agent(ua).unwrap();
+            assert_eq!(
+                (
+                    hints.platform.as_str(),
+                    hints.platform_version.as_str(),
+                    hints.architecture.as_str(),
+                    hints.bitness.as_str(),
+                    hints.mobile,
+                    hints.model.as_str(),
+                ),
+                (platform, platform_version, architecture, bitness, mobile, model),
+                "{ua}"
+            );
+        }
+    }
+
+    #[test]
+    fn android_webview_brand() {
+        let ua = "Mozilla/5.0 (Linux; Android 10; K; wv) AppleWebKit/537.36 (KHTML, like Gecko) Version/4.0 Chrome/120.0.0.0 Mobile Safari/537.36";
+        let hints = ClientHints::from_user_agent(ua).unwrap();
+        assert!(hints.brands.iter().any(|b| b.brand == "Android WebView" && b.version == "120"));
+    }
+
+    #[test]
+    fn header_values_are_structured_fields() {
+        let hints = ClientHints::from_user_agent(CHROME_ANDROID).unwrap();
+        assert_eq!(hints.header_value("sec-ch-ua-mobile").as_deref(), Some("?1"));
+        assert_eq!(hints.header_value("sec-ch-ua-platform").as_deref(), Some(r#""Android""#));
+        assert_eq!(hints.header_value("sec-ch-ua-model").as_deref(), Some(r#""""#));
+        assert_eq!(hints.header_value("user-agent"), None);
+    }
+}
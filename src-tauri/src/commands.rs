@@ -1,17 +1,24 @@
-use tauri::{AppHandle, Manager, State, WebviewWindow, WebviewWindowBuilder, WebviewUrl, Url};
+use tauri::{AppHandle, Manager, Runtime, State, WebviewWindow, WebviewWindowBuilder, WebviewUrl, Url};
 use crate::state::{AppState, Profile};
 use crate::scripting;
-use crate::fingerprint;
+use crate::fingerprint::{self, FINGERPRINT_VERSION};
 use crate::proxy;
+use crate::debugger::{self, DebugSession, DebugStart};
+use crate::error::ProfileError;
+use crate::modules;
+use crate::params;
+use crate::profiles;
+use crate::storage;
+use crate::windows::{self, STUDIO};
+use crate::workspace;
 use std::sync::{Arc, Mutex};
 use serde_json::Value;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
 /// Wait for a TCP port to become available (proxy ready check)
 /// Returns true if port is listening, false after timeout
-#[allow(dead_code)]
-async fn wait_for_port(port: u16) -> bool {
+pub(crate) async fn wait_for_port(port: u16) -> bool {
     let start = std::time::Instant::now();
     while start.elapsed().as_secs() < 3 {
         if std::net::TcpStream::connect(format!("127.0.0.1:{}", port)).is_ok() {
@@ -23,10 +30,10 @@ async fn wait_for_port(port: u16) -> bool {
 }
 
 fn get_profiles_path() -> PathBuf {
-    Path::new("profiles.json").to_path_buf()
+    workspace::profiles_path()
 }
 
-fn load_profiles_from_disk() -> Vec<Profile> {
+pub(crate) fn load_profiles_from_disk() -> Vec<Profile> {
     let path = get_profiles_path();
     if path.exists() {
         if let Ok(content) = fs::read_to_string(&path) {
@@ -43,31 +50,91 @@ fn load_profiles_from_disk() -> Vec<Profile> {
             user_agent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36".into(),
             seed: 12345,
             proxy: None,
+            screen: None,
+            webgl: None,
+            fingerprint_version: FINGERPRINT_VERSION,
+            human_input: false,
         },
         Profile {
             name: "Mobile iPhone".into(),
             user_agent: "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1".into(),
             seed: 67890,
             proxy: None,
+            screen: None,
+            webgl: None,
+            fingerprint_version: FINGERPRINT_VERSION,
+            human_input: false,
         },
         Profile {
              name: "Linux Firefox".into(),
              user_agent: "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/115.0".into(),
              seed: 112233,
              proxy: None,
+             screen: None,
+             webgl: None,
+             fingerprint_version: FINGERPRINT_VERSION,
+             human_input: false,
         }
     ];
 
-    save_profiles_to_disk(&defaults);
+    let _ = save_profiles_to_disk(&defaults);
     defaults
 }
 
-fn save_profiles_to_disk(profiles: &[Profile]) {
+pub(crate) fn save_profiles_to_disk(profiles: &[Profile]) -> std::io::Result<()> {
     let path = get_profiles_path();
     let json = serde_json::to_string_pretty(profiles).unwrap_or_default();
-    let _ = fs::write(path, json);
+    fs::write(path, json)
 }
 
+/// Applies a profile's identity (UA, fingerprint, storage) to a window that is about to be built.
+pub fn apply_profile<'a, R: Runtime, M: Manager<R>>(
+    builder: WebviewWindowBuilder<'a, R, M>,
+    profile: &Profile,
+) -> WebviewWindowBuilder<'a, R, M> {
+    // Own cookies / localStorage / cache, so identities can't be linked through storage
+    let builder = storage::apply_storage(builder, &profile.name);
+
+    // HTTP User-Agent, so it matches navigator.userAgent. WebKit sends no client hints, but
+    // WebView2 would pair it with the engine's real Sec-CH-UA; there only the proxy sets it
+    // (see `apply_proxy`), together with matching hints.
+    #[cfg(not(windows))]
+    let builder = builder.user_agent(&profile.user_agent);
+
+    // User Agent + Fingerprint. Registered for every frame; the script also patches
+    // runtime-created iframes and worker scopes on its own.
+    let fingerprint_script = fingerprint::generate_injection_script(profile);
+    builder.initialization_script_for_all_frames(&fingerprint_script)
+}
+
+/// Routes a window through its proxy listener on `port`, which sets the profile's
+/// User-Agent and matching `Sec-CH-UA*` headers on every request. The listener signs
+/// its TLS certificates with the workspace CA, see `proxy::init_ca`. Does nothing on
+/// macOS, where WKWebView can't be pointed at a proxy.
+pub fn apply_proxy<'a, R: Runtime, M: Manager<R>>(
+    builder: WebviewWindowBuilder<'a, R, M>,
+    port: u16,
+    profile: Option<&Profile>,
+) -> WebviewWindowBuilder<'a, R, M> {
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    {
+        let _ = (port, profile);
+        builder
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    {
+        let proxy_url = Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+        let mut builder = builder.proxy_url(proxy_url);
+        if let Some(profile) = profile {
+            builder = builder.user_agent(&profile.user_agent);
+        }
+
+        builder
+    }
+}
+
+/// Returns the window, building it with the profile bound to `label` if it isn't open.
 pub fn ensure_target_window(app: &AppHandle, label: &str) -> Option<WebviewWindow> {
     if let Some(w) = app.get_webview_window(label) {
         return Some(w);
@@ -86,31 +153,15 @@ pub fn ensure_target_window(app: &AppHandle, label: &str) -> Option<WebviewWindo
     .inner_size(1024.0, 768.0);
 
     // Apply Profile Settings
-    if let Some(profile) = &state.current_profile {
-        // 1. User Agent
-        let ua_script = format!(r#"
-            Object.defineProperty(navigator, 'userAgent', {{
-                get: function () {{ return "{}"; }}
-            }});
-        "#, profile.user_agent);
-        builder = builder.initialization_script(&ua_script);
-
-        // 2. Fingerprint
-        let fingerprint_script = fingerprint::generate_injection_script(profile.seed);
-        builder = builder.initialization_script(&fingerprint_script);
-    }
-
-    // 3. Proxy Configuration
-    // DISABLED FOR DEBUGGING: Uncomment to re-enable proxy support
-    // The blank screen issue is likely caused by the proxy not being ready
-    // or certificate rejection by Webview2
-    // if state.proxy_port > 0 {
-    //     let proxy_url = Url::parse(&format!("http://127.0.0.1:{}", state.proxy_port)).unwrap();
-    //     builder = builder.proxy_url(proxy_url);
-    //
-    //     // Ignore certificate errors
-    //     builder = builder.additional_browser_args("--ignore-certificate-errors");
-    // }
+    if let Some(profile) = state.window_profile(label) {
+        builder = apply_profile(builder, profile);
+    }
+
+    // Proxy Configuration: the window's listener rewrites UA and client hints
+    let proxy_port = state.windows.get(label).map(|c| c.proxy_port).filter(|p| *p > 0);
+    if let Some(port) = proxy_port {
+        builder = apply_proxy(builder, port, state.window_profile(label));
+    }
 
     match builder.build() {
         Ok(w) => Some(w),
@@ -121,25 +172,56 @@ pub fn ensure_target_window(app: &AppHandle, label: &str) -> Option<WebviewWindo
     }
 }
 
-#[tauri::command]
-pub async fn execute_script(script: String, state: State<'_, Arc<Mutex<AppState>>>, app: AppHandle) -> Result<(), String> {
+/// Starts the Studio window's proxy if needed and opens the window. False if the
+/// window could not be created.
+pub async fn open_studio(app: &AppHandle, state: &Arc<Mutex<AppState>>) -> bool {
     // Ensure proxy is running if not already
     let needs_start = {
         let s = state.lock().unwrap();
-        s.proxy_port == 0
+        s.windows.get(STUDIO).is_none_or(|c| c.proxy_port == 0)
     };
 
     if needs_start {
-        proxy::restart_proxy(app.clone(), state.inner().clone()).await;
-        // Wait for the TCP listener to become active before opening the window
-        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        proxy::restart_proxy(app.clone(), state.clone(), STUDIO).await;
+    }
+    // Wait for the TCP listener to become active before opening the window, which
+    // would otherwise load nothing through it
+    let port = state.lock().unwrap().windows.get(STUDIO).map(|c| c.proxy_port).unwrap_or(0);
+    if port > 0 && !wait_for_port(port).await {
+        eprintln!("Proxy on port {} did not come up", port);
+        return false;
+    }
+
+    ensure_target_window(app, STUDIO).is_some()
+}
+
+#[tauri::command]
+pub async fn execute_script(
+    script: String,
+    params: Option<serde_json::Map<String, Value>>,
+    debug: Option<DebugStart>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let params = params::resolve(&script, params.unwrap_or_default()).map_err(|e| e.to_string())?;
+    let mut inputs = scripting::ScriptInputs { params: params::to_script_map(&params), ..Default::default() };
+    if let Some(start) = debug {
+        let mut s = state.lock().unwrap();
+        if s.debug_session.is_some() {
+            return Err("A script is already being debugged".into());
+        }
+        let session = DebugSession::new(app.clone(), start);
+        s.debug_session = Some(session.clone());
+        inputs.debug = Some(session);
     }
 
-    let label = "target-studio";
-    if ensure_target_window(&app, label).is_some() {
-        scripting::run_script(script, app, state.inner().clone(), label.to_string());
+    if open_studio(&app, state.inner()).await {
+        scripting::run_script(script, app, state.inner().clone(), STUDIO.to_string(), inputs);
         Ok(())
     } else {
+        if let Some(session) = &inputs.debug {
+            debugger::finish(&app, state.inner(), session);
+        }
         Err("Could not create target window".into())
     }
 }
@@ -157,97 +239,59 @@ pub fn get_profiles() -> Vec<Profile> {
     load_profiles_from_disk()
 }
 
+pub fn find_profile(name: &str) -> Option<Profile> {
+    load_profiles_from_disk().into_iter().find(|p| p.name == name)
+}
+
 #[tauri::command]
-pub async fn set_profile(profile_name: String, state: State<'_, Arc<Mutex<AppState>>>, app: AppHandle) -> Result<(), String> {
-    let profiles = load_profiles_from_disk();
-    let profile = profiles.into_iter().find(|p| p.name == profile_name);
-
-    if let Some(p) = profile {
-        {
-            let mut state = state.lock().unwrap();
-            state.current_profile = Some(p.clone());
-        }
+pub async fn set_profile(profile_name: String, state: State<'_, Arc<Mutex<AppState>>>, app: AppHandle) -> Result<(), ProfileError> {
+    let p = find_profile(&profile_name).ok_or(ProfileError::NotFound(profile_name))?;
+    // Only the Studio window switches; runs already in progress keep their own profile
+    windows::bind(state.inner(), STUDIO, Some(p));
 
-        // Restart Proxy for new profile
-        proxy::restart_proxy(app.clone(), state.inner().clone()).await;
+    // Restart Proxy for new profile
+    proxy::restart_proxy(app.clone(), state.inner().clone(), STUDIO).await;
 
-        // Close target window if open, so it gets recreated with new UA/Proxy on next run
-        if let Some(w) = app.get_webview_window("target-studio") {
-            let _ = w.close();
-        }
+    // Close target window if open, so it gets recreated with new UA/Proxy on next run
+    if let Some(w) = app.get_webview_window(STUDIO) {
+        let _ = w.close();
     }
     Ok(())
 }
 
 #[tauri::command]
-pub async fn save_profile_config(profile: Profile, state: State<'_, Arc<Mutex<AppState>>>, app: AppHandle) -> Result<(), String> {
-    let mut profiles = load_profiles_from_disk();
-    if let Some(idx) = profiles.iter().position(|p| p.name == profile.name) {
-        profiles[idx] = profile.clone();
-    } else {
-        profiles.push(profile.clone());
-    }
-
-    save_profiles_to_disk(&profiles);
-
-    // Update current state if it matches
-    let mut is_current = false;
-    {
-        let mut state = state.lock().unwrap();
-        if let Some(current) = &state.current_profile {
-            if current.name == profile.name {
-                state.current_profile = Some(profile.clone());
-                is_current = true;
-            }
-        }
-    }
-
-    if is_current {
-        // Restart Proxy to apply new settings
-        proxy::restart_proxy(app.clone(), state.inner().clone()).await;
-
-        // Close window to force refresh
-        if let Some(w) = app.get_webview_window("target-studio") {
-            let _ = w.close();
-        }
-    }
-
-    Ok(())
+pub async fn save_profile_config(profile: Profile, state: State<'_, Arc<Mutex<AppState>>>, app: AppHandle) -> Result<(), ProfileError> {
+    profiles::save(&app, state.inner(), profile).await.map(|_| ())
 }
 
 #[tauri::command]
 pub fn list_scripts() -> Vec<String> {
-    let path = Path::new("scripts");
+    let path = workspace::scripts_dir();
     if !path.exists() {
-        let _ = fs::create_dir(path);
+        let _ = fs::create_dir(&path);
     }
 
-    let mut scripts = Vec::new();
-    if let Ok(entries) = fs::read_dir(path) {
-        for entry in entries.flatten() {
-             if let Ok(name) = entry.file_name().into_string() {
-                 scripts.push(name);
-             }
-        }
-    }
-    scripts
+    // Libraries live in subfolders, e.g. `lib/login.rhai`
+    modules::list_scripts()
 }
 
 #[tauri::command]
 pub fn save_script(filename: String, content: String) -> Result<(), String> {
-    let path = Path::new("scripts");
+    let path = workspace::scripts_dir();
     if !path.exists() {
-        let _ = fs::create_dir(path);
+        let _ = fs::create_dir(&path);
     }
 
-    let safe_filename = Path::new(&filename).file_name().ok_or("Invalid filename")?;
-    let target = path.join(safe_filename);
+    let target = modules::script_path(&filename)?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
 
     fs::write(target, content).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub fn read_script(filename: String) -> Result<String, String> {
-    let path = Path::new("scripts").join(filename);
+    let path = modules::script_path(&filename)?;
     fs::read_to_string(path).map_err(|e| e.to_string())
 }
@@ -0,0 +1,376 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tauri::webview::cookie::{time::OffsetDateTime, Cookie, SameSite};
+use tauri::{AppHandle, Manager, State, Url, WebviewUrl, WebviewWindow, WebviewWindowBuilder};
+use crate::commands;
+use crate::state::{AppState, Profile};
+
+/// A cookie in a form that scripts, JSON files and the UI can all use.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CookieRecord {
+    pub name: String,
+    pub value: String,
+    /// A leading dot means the cookie is also sent to subdomains
+    pub domain: String,
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// Unix timestamp in seconds; `None` for session cookies
+    #[serde(default)]
+    pub expires: Option<i64>,
+    #[serde(default)]
+    pub secure: bool,
+    #[serde(default)]
+    pub http_only: bool,
+    /// "Strict", "Lax" or "None"
+    #[serde(default)]
+    pub same_site: Option<String>,
+}
+
+fn default_path() -> String {
+    "/".into()
+}
+
+impl CookieRecord {
+    pub fn from_cookie(cookie: &Cookie<'_>) -> Self {
+        Self {
+            name: cookie.name().to_string(),
+            value: cookie.value().to_string(),
+            domain: cookie.domain().unwrap_or_default().to_string(),
+            path: cookie.path().map(String::from).unwrap_or_else(default_path),
+            expires: cookie.expires_datetime().map(|t| t.unix_timestamp()),
+            secure: cookie.secure().unwrap_or(false),
+            http_only: cookie.http_only().unwrap_or(false),
+            same_site: cookie.same_site().map(|s| s.to_string()),
+        }
+    }
+
+    pub fn to_cookie(&self) -> Result<Cookie<'static>, String> {
+        if self.name.is_empty() {
+            return Err("Cookie name is required".into());
+        }
+        if self.domain.is_empty() {
+            return Err(format!("Cookie '{}' has no domain", self.name));
+        }
+
+        let mut builder = Cookie::build((self.name.clone(), self.value.clone()))
+            .domain(self.domain.clone())
+            .path(self.path.clone())
+            .secure(self.secure)
+            .http_only(self.http_only);
+
+        if let Some(expires) = self.expires {
+            let at = OffsetDateTime::from_unix_timestamp(expires)
+                .map_err(|e| format!("Cookie '{}' has an invalid expiry: {}", self.name, e))?;
+            builder = builder.expires(at);
+        }
+        if let Some(same_site) = &self.same_site {
+            let same_site = match same_site.to_ascii_lowercase().as_str() {
+                "strict" => SameSite::Strict,
+                "lax" => SameSite::Lax,
+                "none" => SameSite::None,
+                other => return Err(format!("Cookie '{}' has an invalid same_site '{}'", self.name, other)),
+            };
+            builder = builder.same_site(same_site);
+        }
+        Ok(builder.build())
+    }
+
+    /// True if the cookie would be sent to `domain` (or one of its subdomains).
+    pub fn matches_domain(&self, domain: &str) -> bool {
+        let own = self.domain.trim_start_matches('.').to_ascii_lowercase();
+        let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+        own == domain || own.ends_with(&format!(".{}", domain)) || domain.ends_with(&format!(".{}", own))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookieFormat {
+    /// The `cookies.txt` format used by curl, wget and browser extensions
+    Netscape,
+    Json,
+}
+
+impl CookieFormat {
+    /// Picks the format from a file name: `.json` is JSON, anything else is cookies.txt.
+    pub fn from_path(path: &str) -> Self {
+        if path.to_ascii_lowercase().ends_with(".json") {
+            CookieFormat::Json
+        } else {
+            CookieFormat::Netscape
+        }
+    }
+
+    pub fn format(self, cookies: &[CookieRecord]) -> String {
+        match self {
+            CookieFormat::Netscape => to_netscape(cookies),
+            CookieFormat::Json => serde_json::to_string_pretty(cookies).unwrap_or_default(),
+        }
+    }
+
+    pub fn parse(self, content: &str) -> Result<Vec<CookieRecord>, String> {
+        match self {
+            CookieFormat::Netscape => parse_netscape(content),
+            CookieFormat::Json => serde_json::from_str(content).map_err(|e| format!("Invalid cookie JSON: {}", e)),
+        }
+    }
+}
+
+impl FromStr for CookieFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "netscape" | "txt" | "cookies.txt" => Ok(CookieFormat::Netscape),
+            "json" => Ok(CookieFormat::Json),
+            other => Err(format!("Unknown cookie format '{}' (expected 'netscape' or 'json')", other)),
+        }
+    }
+}
+
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// Writes cookies in Netscape format. HttpOnly cookies get curl's `#HttpOnly_` domain prefix.
+pub fn to_netscape(cookies: &[CookieRecord]) -> String {
+    let mut out = String::from("# Netscape HTTP Cookie File\n");
+    for c in cookies {
+        let flag = |b: bool| if b { "TRUE" } else { "FALSE" };
+        out.push_str(&format!(
+            "{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            if c.http_only { HTTP_ONLY_PREFIX } else { "" },
+            c.domain,
+            flag(c.domain.starts_with('.')),
+            c.path,
+            flag(c.secure),
+            c.expires.unwrap_or(0),
+            c.name,
+            c.value,
+        ));
+    }
+    out
+}
+
+pub fn parse_netscape(content: &str) -> Result<Vec<CookieRecord>, String> {
+    let mut cookies = Vec::new();
+    for (index, raw) in content.lines().enumerate() {
+        let line = raw.trim_end_matches('\r');
+        let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+            Some(rest) => (rest, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // The value is last and may itself contain tabs
+        let fields: Vec<&str> = line.splitn(7, '\t').collect();
+        if fields.len() < 6 {
+            return Err(format!("cookies.txt line {}: expected 7 tab-separated fields (6 if the value is empty)", index + 1));
+        }
+        let expires: i64 = fields[4]
+            .parse()
+            .map_err(|_| format!("cookies.txt line {}: invalid expiry '{}'", index + 1, fields[4]))?;
+
+        let mut domain = fields[0].to_string();
+        // The subdomain flag and the leading dot carry the same information
+        if fields[1].eq_ignore_ascii_case("TRUE") && !domain.starts_with('.') {
+            domain.insert(0, '.');
+        }
+
+        cookies.push(CookieRecord {
+            name: fields[5].to_string(),
+            // Some exporters drop the value column for empty cookies
+            value: fields.get(6).copied().unwrap_or("").to_string(),
+            domain,
+            path: fields[2].to_string(),
+            expires: if expires == 0 { None } else { Some(expires) },
+            secure: fields[3].eq_ignore_ascii_case("TRUE"),
+            http_only,
+            same_site: None,
+        });
+    }
+    Ok(cookies)
+}
+
+/// Every cookie in the window's data store.
+pub fn get_all(window: &WebviewWindow) -> Result<Vec<CookieRecord>, String> {
+    let cookies = window.cookies().map_err(|e| format!("Failed to read cookies: {}", e))?;
+    Ok(cookies.iter().map(CookieRecord::from_cookie).collect())
+}
+
+pub fn set(window: &WebviewWindow, record: &CookieRecord) -> Result<(), String> {
+    window
+        .set_cookie(record.to_cookie()?)
+        .map_err(|e| format!("Failed to set cookie '{}': {}", record.name, e))
+}
+
+/// Deletes every cookie and returns how many there were.
+pub fn clear(window: &WebviewWindow) -> Result<usize, String> {
+    let cookies = window.cookies().map_err(|e| format!("Failed to read cookies: {}", e))?;
+    let count = cookies.len();
+    for cookie in cookies {
+        window
+            .delete_cookie(cookie)
+            .map_err(|e| format!("Failed to delete cookie: {}", e))?;
+    }
+    Ok(count)
+}
+
+/// Sets every cookie in `content` and returns how many were imported.
+pub fn import(window: &WebviewWindow, content: &str, format: CookieFormat) -> Result<usize, String> {
+    let records = format.parse(content)?;
+    for record in &records {
+        set(window, record)?;
+    }
+    Ok(records.len())
+}
+
+/// Runs `f` against a window backed by the profile's data store: an open window running
+/// under that profile if there is one, otherwise a hidden one opened for the occasion.
+/// Callers must be async commands: reading cookies from the main thread deadlocks.
+pub(crate) fn with_profile_store<T>(
+    app: &AppHandle,
+    state: &Arc<Mutex<AppState>>,
+    profile_name: &str,
+    f: impl FnOnce(&WebviewWindow) -> Result<T, String>,
+) -> Result<T, String> {
+    let open = state.lock().unwrap().windows_using(profile_name);
+    if let Some(window) = open.iter().find_map(|label| app.get_webview_window(label)) {
+        return f(&window);
+    }
+
+    let profile = commands::find_profile(profile_name)
+        .ok_or_else(|| format!("Profile '{}' not found", profile_name))?;
+    with_new_store(app, &profile, f)
+}
+
+/// Runs `f` against a hidden window opened on `profile`'s data store, which needn't be
+/// a saved profile (bundle imports stage storage under a temporary name).
+pub(crate) fn with_new_store<T>(
+    app: &AppHandle,
+    profile: &Profile,
+    f: impl FnOnce(&WebviewWindow) -> Result<T, String>,
+) -> Result<T, String> {
+    let label = format!("cookies-{}", uuid::Uuid::new_v4());
+    let builder = WebviewWindowBuilder::new(app, &label, WebviewUrl::External(Url::parse("about:blank").unwrap()))
+        .visible(false);
+    let window = commands::apply_profile(builder, profile)
+        .build()
+        .map_err(|e| format!("Failed to open profile storage: {}", e))?;
+
+    let result = f(&window);
+    let _ = window.destroy();
+    result
+}
+
+#[tauri::command]
+pub async fn get_profile_cookies(
+    profile_name: String,
+    domain: Option<String>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    app: AppHandle,
+) -> Result<Vec<CookieRecord>, String> {
+    let cookies = with_profile_store(&app, state.inner(), &profile_name, get_all)?;
+    Ok(match domain {
+        Some(domain) => cookies.into_iter().filter(|c| c.matches_domain(&domain)).collect(),
+        None => cookies,
+    })
+}
+
+#[tauri::command]
+pub async fn clear_profile_cookies(
+    profile_name: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    app: AppHandle,
+) -> Result<usize, String> {
+    with_profile_store(&app, state.inner(), &profile_name, clear)
+}
+
+/// Returns the profile's cookies as `cookies.txt` or JSON text.
+#[tauri::command]
+pub async fn export_profile_cookies(
+    profile_name: String,
+    format: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    app: AppHandle,
+) -> Result<String, String> {
+    let format: CookieFormat = format.parse()?;
+    let cookies = with_profile_store(&app, state.inner(), &profile_name, get_all)?;
+    Ok(format.format(&cookies))
+}
+
+#[tauri::command]
+pub async fn import_profile_cookies(
+    profile_name: String,
+    content: String,
+    format: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    app: AppHandle,
+) -> Result<usize, String> {
+    let format: CookieFormat = format.parse()?;
+    with_profile_store(&app, state.inner(), &profile_name, |w| import(w, &content, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, domain: &str, expires: Option<i64>, secure: bool, http_only: bool) -> CookieRecord {
+        CookieRecord {
+            name: name.into(),
+            value: format!("{}-value", name),
+            domain: domain.into(),
+            path: "/".into(),
+            expires,
+            secure,
+            http_only,
+            same_site: None,
+        }
+    }
+
+    #[test]
+    fn netscape_round_trips() {
+        let cookies = vec![
+            record("sid", ".example.com", Some(1_900_000_000), true, true),
+            record("theme", "example.com", None, false, false),
+            record("cart", "shop.example.com", Some(1_700_000_000), true, false),
+        ];
+        let text = to_netscape(&cookies);
+        assert!(text.contains("#HttpOnly_.example.com\tTRUE\t/\tTRUE\t1900000000\tsid\tsid-value\n"), "{}", text);
+        assert!(text.contains("\nexample.com\tFALSE\t/\tFALSE\t0\ttheme\ttheme-value\n"), "{}", text);
+        assert_eq!(parse_netscape(&text).unwrap(), cookies);
+    }
+
+    #[test]
+    fn netscape_lines_from_other_exporters() {
+        for (line, expected) in [
+            ("#HttpOnly_.example.com\tTRUE\t/\tFALSE\t0\tsid\tsid-value", record("sid", ".example.com", None, false, true)),
+            // The subdomain flag alone makes a domain cookie
+            ("example.com\tTRUE\t/\tTRUE\t1700000000\tsid\tsid-value", record("sid", ".example.com", Some(1_700_000_000), true, false)),
+            ("example.com\tFALSE\t/\tFALSE\t0\tsid\tsid-value\r", record("sid", "example.com", None, false, false)),
+            ("example.com\tFALSE\t/\tFALSE\t0\tsid", CookieRecord { value: String::new(), ..record("sid", "example.com", None, false, false) }),
+            ("example.com\tFALSE\t/\tFALSE\t0\tsid\ta\tb\t", CookieRecord { value: "a\tb\t".into(), ..record("sid", "example.com", None, false, false) }),
+        ] {
+            assert_eq!(parse_netscape(&format!("# Netscape HTTP Cookie File\n\n{}\n", line)).unwrap(), vec![expected], "{}", line);
+        }
+        for (line, error) in [
+            ("example.com\tFALSE\t/\tFALSE\t0", "cookies.txt line 1: expected 7 tab-separated fields (6 if the value is empty)"),
+            ("example.com\tFALSE\t/\tFALSE\tsoon\tsid\tabc", "cookies.txt line 1: invalid expiry 'soon'"),
+        ] {
+            assert_eq!(parse_netscape(line).unwrap_err(), error, "{}", line);
+        }
+    }
+
+    #[test]
+    fn json_round_trips_and_fills_defaults() {
+        let cookies = vec![
+            CookieRecord { same_site: Some("Lax".into()), ..record("sid", ".example.com", Some(1_900_000_000), true, true) },
+            record("theme", "example.com", None, false, false),
+        ];
+        let text = CookieFormat::Json.format(&cookies);
+        assert_eq!(CookieFormat::Json.parse(&text).unwrap(), cookies);
+
+        let minimal = r#"[{"name": "theme", "value": "theme-value", "domain": "example.com"}]"#;
+        assert_eq!(CookieFormat::Json.parse(minimal).unwrap(), vec![record("theme", "example.com", None, false, false)]);
+    }
+}
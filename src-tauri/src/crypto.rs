@@ -0,0 +1,122 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use chacha20poly1305::aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+/// Data encrypted with a key derived from a passphrase (Argon2id + ChaCha20-Poly1305).
+/// The KDF parameters travel with the data so they can be raised later without
+/// breaking older files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedBox {
+    pub kdf: String,
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+    /// Base64
+    pub salt: String,
+    /// Base64
+    pub nonce: String,
+    /// Base64, including the authentication tag
+    pub ciphertext: String,
+}
+
+const KDF: &str = "argon2id";
+
+fn derive_key(passphrase: &[u8], salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Key, String> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32)).map_err(|e| e.to_string())?;
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase, salt, &mut key)
+        .map_err(|e| e.to_string())?;
+    Ok(key)
+}
+
+/// A derived key with the salt and parameters it came from. Derivation is the slow
+/// part, so a key kept in memory can seal again cheaply; every seal gets a fresh nonce.
+pub struct SealingKey {
+    key: Key,
+    salt: Vec<u8>,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+impl SealingKey {
+    /// Derives a key with a fresh salt and the default parameters.
+    pub fn new(passphrase: &[u8]) -> Result<Self, String> {
+        let params = Params::default();
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt, params.m_cost(), params.t_cost(), params.p_cost())?;
+        Ok(Self { key, salt: salt.to_vec(), m_cost: params.m_cost(), t_cost: params.t_cost(), p_cost: params.p_cost() })
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<SealedBox, String> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(&self.key)
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| "Encryption failed".to_string())?;
+
+        Ok(SealedBox {
+            kdf: KDF.into(),
+            m_cost: self.m_cost,
+            t_cost: self.t_cost,
+            p_cost: self.p_cost,
+            salt: B64.encode(&self.salt),
+            nonce: B64.encode(nonce),
+            ciphertext: B64.encode(ciphertext),
+        })
+    }
+}
+
+pub fn seal(passphrase: &[u8], plaintext: &[u8]) -> Result<SealedBox, String> {
+    SealingKey::new(passphrase)?.seal(plaintext)
+}
+
+/// Returns `None` for a wrong passphrase or tampered data; they can't be told apart.
+pub fn open(passphrase: &[u8], sealed: &SealedBox) -> Result<Option<Vec<u8>>, String> {
+    Ok(open_with_key(passphrase, sealed)?.map(|(plaintext, _)| plaintext))
+}
+
+/// Like `open`, also returning the derived key so the data can be sealed again
+/// without another derivation.
+pub fn open_with_key(passphrase: &[u8], sealed: &SealedBox) -> Result<Option<(Vec<u8>, SealingKey)>, String> {
+    if sealed.kdf != KDF {
+        return Err(format!("Unsupported key derivation '{}'", sealed.kdf));
+    }
+    let decode = |field: &str, value: &str| B64.decode(value).map_err(|e| format!("Invalid {}: {}", field, e));
+    let salt = decode("salt", &sealed.salt)?;
+    let nonce = decode("nonce", &sealed.nonce)?;
+    let ciphertext = decode("ciphertext", &sealed.ciphertext)?;
+    if nonce.len() != 12 {
+        return Err("Invalid nonce length".into());
+    }
+
+    let key = derive_key(passphrase, &salt, sealed.m_cost, sealed.t_cost, sealed.p_cost)?;
+    let plaintext = ChaCha20Poly1305::new(&key).decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice());
+    Ok(plaintext.ok().map(|plaintext| {
+        let key = SealingKey { key, salt, m_cost: sealed.m_cost, t_cost: sealed.t_cost, p_cost: sealed.p_cost };
+        (plaintext, key)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_kept_key_seals_with_fresh_nonces() {
+        let (_, key) = open_with_key(b"pass", &seal(b"pass", b"first").unwrap()).unwrap().unwrap();
+        let a = key.seal(b"second").unwrap();
+        let b = key.seal(b"second").unwrap();
+        assert_ne!(a.nonce, b.nonce);
+        assert_eq!(open(b"pass", &a).unwrap().as_deref(), Some(&b"second"[..]));
+    }
+
+    #[test]
+    fn a_wrong_passphrase_opens_nothing() {
+        let sealed = seal(b"pass", b"secret").unwrap();
+        assert_eq!(open(b"wrong", &sealed).unwrap(), None);
+    }
+}
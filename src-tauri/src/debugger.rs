@@ -0,0 +1,414 @@
+use rhai::debugger::{DebuggerCommand, DebuggerEvent};
+use rhai::{ASTNode, Array, Dynamic, Engine, EvalAltResult, EvalContext, Expr, Map, Position, Stmt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager, State};
+use crate::state::AppState;
+use crate::vault;
+
+/// What the front end asks of a paused script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DebugAction {
+    /// Run until the next breakpoint
+    Continue,
+    /// Next statement, not entering function calls
+    StepOver,
+    /// Next statement, entering function calls
+    StepInto,
+    /// Run until the current function returns
+    StepOut,
+    /// End the run
+    Stop,
+}
+
+/// How a debug run starts.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DebugStart {
+    /// Script lines to pause at
+    #[serde(default)]
+    pub breakpoints: Vec<usize>,
+    /// Pause before the first statement
+    #[serde(default)]
+    pub stop_on_entry: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Variable {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    pub value: Value,
+    pub constant: bool,
+}
+
+/// The last value a page query returned, even if the script didn't keep it.
+#[derive(Debug, Clone, Serialize)]
+pub struct LastResult {
+    pub method: String,
+    pub value: Value,
+}
+
+/// Payload of the `debug_paused` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct DebugPause {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// Module the statement is in; `None` for the script itself
+    pub source: Option<String>,
+    pub reason: &'static str,
+    /// Innermost first
+    pub call_stack: Vec<String>,
+    pub variables: Vec<Variable>,
+    pub last_result: Option<LastResult>,
+}
+
+struct Stepping {
+    action: DebugAction,
+    /// Call level where the script last paused
+    level: usize,
+    started: bool,
+    /// Module and line of the last node evaluated
+    line: Option<(Option<String>, usize)>,
+    /// Addresses of the nodes evaluated on that line so far. Meeting one of them again
+    /// means the line runs again, e.g. a loop body written on one line.
+    seen: HashSet<usize>,
+}
+
+/// Breakpoints and stepping: decides where a script pauses.
+struct Stepper {
+    breakpoints: Mutex<BTreeSet<usize>>,
+    stepping: Mutex<Stepping>,
+}
+
+impl Stepper {
+    fn new(start: DebugStart) -> Self {
+        let action = if start.stop_on_entry { DebugAction::StepInto } else { DebugAction::Continue };
+        Self {
+            breakpoints: Mutex::new(start.breakpoints.into_iter().collect()),
+            stepping: Mutex::new(Stepping { action, level: 0, started: false, line: None, seen: HashSet::new() }),
+        }
+    }
+
+    /// Pauses at most once per pass over a line: Rhai steps through every expression,
+    /// and a method call on its own line doesn't show up as a statement.
+    fn should_pause(&self, node: &ASTNode, source: Option<&str>, level: usize) -> Option<&'static str> {
+        // A block's first line is where it opens, not something that runs
+        if matches!(node, ASTNode::Stmt(Stmt::Block(..))) {
+            return None;
+        }
+        let line = node.position().line()?;
+        let mut stepping = self.stepping.lock().unwrap();
+        let at = Some((source.map(String::from), line));
+        let address = match node {
+            ASTNode::Stmt(stmt) => *stmt as *const Stmt as usize,
+            ASTNode::Expr(expr) => *expr as *const Expr as usize,
+        };
+        if stepping.line == at && stepping.seen.insert(address) {
+            return None;
+        }
+        stepping.line = at;
+        stepping.seen.clear();
+        stepping.seen.insert(address);
+        let on_breakpoint = source.is_none() && self.breakpoints.lock().unwrap().contains(&line);
+
+        let stepped = match stepping.action {
+            DebugAction::StepInto => true,
+            DebugAction::StepOver => level <= stepping.level,
+            DebugAction::StepOut => level < stepping.level,
+            DebugAction::Continue | DebugAction::Stop => false,
+        };
+        let reason = if !stepping.started {
+            stepping.started = true;
+            stepped.then_some("entry")
+        } else if stepped {
+            Some("step")
+        } else {
+            None
+        };
+        reason.or(on_breakpoint.then_some("breakpoint"))
+    }
+
+    /// Runs one debugger callback: `pause` is called where the script should stop and
+    /// returns what to do next.
+    fn on_step(
+        &self,
+        context: EvalContext,
+        event: DebuggerEvent,
+        node: ASTNode,
+        source: Option<&str>,
+        pause: impl FnOnce(&EvalContext, &ASTNode, Option<&str>, &'static str) -> DebugAction,
+    ) -> Result<DebuggerCommand, Box<EvalAltResult>> {
+        match event {
+            DebuggerEvent::End => return Ok(DebuggerCommand::Continue),
+            DebuggerEvent::Start | DebuggerEvent::Step | DebuggerEvent::BreakPoint(_) => {}
+            _ => return Ok(DebuggerCommand::StepInto),
+        }
+        let mut action = self.stepping.lock().unwrap().action;
+        if action != DebugAction::Stop {
+            if let Some(reason) = self.should_pause(&node, source, context.call_level()) {
+                action = pause(&context, &node, source, reason);
+                let mut stepping = self.stepping.lock().unwrap();
+                stepping.action = action;
+                stepping.level = context.call_level();
+            }
+        }
+        if action == DebugAction::Stop {
+            return Err(EvalAltResult::ErrorTerminated("Stopped in the debugger".into(), node.position()).into());
+        }
+        Ok(DebuggerCommand::StepInto)
+    }
+}
+
+/// A Studio run under the debugger. The script thread blocks in the debugger callback
+/// while paused, which leaves the target window on the page in question.
+pub struct DebugSession {
+    app: AppHandle,
+    stepper: Stepper,
+    actions_tx: Mutex<Sender<DebugAction>>,
+    actions_rx: Mutex<Receiver<DebugAction>>,
+    paused: AtomicBool,
+    last_result: Mutex<Option<LastResult>>,
+}
+
+impl fmt::Debug for DebugSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DebugSession").finish_non_exhaustive()
+    }
+}
+
+impl DebugSession {
+    pub fn new(app: AppHandle, start: DebugStart) -> Arc<Self> {
+        let (tx, rx) = channel();
+        Arc::new(Self {
+            app,
+            stepper: Stepper::new(start),
+            actions_tx: Mutex::new(tx),
+            actions_rx: Mutex::new(rx),
+            paused: AtomicBool::new(false),
+            last_result: Mutex::new(None),
+        })
+    }
+
+    /// Remembers what a browser query returned, for the next pause.
+    pub fn record_result(&self, method: &str, value: Value) {
+        *self.last_result.lock().unwrap() = Some(LastResult { method: method.to_string(), value });
+    }
+
+    fn pause(&self, context: &EvalContext, node: &ASTNode, source: Option<&str>, reason: &'static str) -> DebugAction {
+        // Secrets read into variables stay out of the UI, as they do in the logs
+        let state = self.app.state::<Arc<Mutex<AppState>>>();
+        let redact = |text: String| vault::redact(&state.lock().unwrap(), &text);
+        let variables = context
+            .scope()
+            .iter_raw()
+            .map(|(name, constant, value)| Variable {
+                name: name.to_string(),
+                type_name: value.type_name().to_string(),
+                value: describe(value, &redact),
+                constant,
+            })
+            .collect();
+        let call_stack = context
+            .global_runtime_state()
+            .debugger()
+            .call_stack()
+            .iter()
+            .rev()
+            .map(|frame| frame.to_string())
+            .collect();
+        let pos = node.position();
+        let pause = DebugPause {
+            line: pos.line(),
+            column: pos.position(),
+            source: source.map(String::from),
+            reason,
+            call_stack,
+            variables,
+            last_result: self.last_result.lock().unwrap().clone(),
+        };
+        self.paused.store(true, Ordering::SeqCst);
+        let _ = self.app.emit("debug_paused", &pause);
+
+        let action = self.actions_rx.lock().unwrap().recv().unwrap_or(DebugAction::Stop);
+        self.paused.store(false, Ordering::SeqCst);
+        let _ = self.app.emit("debug_resumed", action);
+        action
+    }
+
+    /// Has the engine call back on every step; whether to pause is decided here, so
+    /// breakpoints set while the script runs take effect right away.
+    pub fn attach(self: &Arc<Self>, engine: &mut Engine) {
+        let session = self.clone();
+        #[allow(deprecated)]
+        engine.register_debugger(
+            |_, debugger| debugger,
+            move |context, event, node, source, _pos: Position| {
+                session.stepper.on_step(context, event, node, source, |context, node, source, reason| {
+                    session.pause(context, node, source, reason)
+                })
+            },
+        );
+    }
+}
+
+/// A scope value for display. Values that aren't plain data show as their type.
+pub(crate) fn describe(value: &Dynamic, redact: &impl Fn(String) -> String) -> Value {
+    serde_json::to_value(redact_strings(value.clone(), redact))
+        .unwrap_or_else(|_| Value::String(format!("<{}>", value.type_name())))
+}
+
+/// `value` with `redact` applied to every string in it, map keys included.
+pub(crate) fn redact_strings(value: Dynamic, redact: &impl Fn(String) -> String) -> Dynamic {
+    let value = value.flatten();
+    if value.is_string() {
+        return redact(value.cast::<String>()).into();
+    }
+    if value.is_array() {
+        let items = value.cast::<Array>().into_iter().map(|v| redact_strings(v, redact));
+        return Dynamic::from_array(items.collect());
+    }
+    if value.is_map() {
+        let entries = value.cast::<Map>().into_iter().map(|(k, v)| (redact(k.to_string()).into(), redact_strings(v, redact)));
+        return Dynamic::from_map(entries.collect());
+    }
+    value
+}
+
+fn session(state: &Arc<Mutex<AppState>>) -> Result<Arc<DebugSession>, String> {
+    state.lock().unwrap().debug_session.clone().ok_or_else(|| "No script is being debugged".to_string())
+}
+
+/// Ends the session once its run is over.
+pub fn finish(app: &AppHandle, state: &Arc<Mutex<AppState>>, session: &Arc<DebugSession>) {
+    let mut s = state.lock().unwrap();
+    if s.debug_session.as_ref().is_some_and(|current| Arc::ptr_eq(current, session)) {
+        s.debug_session = None;
+    }
+    let _ = app.emit("debug_finished", ());
+}
+
+#[tauri::command]
+pub fn debug_action(action: DebugAction, state: State<'_, Arc<Mutex<AppState>>>) -> Result<(), String> {
+    let session = session(state.inner())?;
+    if action == DebugAction::Stop {
+        // Also ends a script that is running, at its next statement
+        session.stepper.stepping.lock().unwrap().action = DebugAction::Stop;
+    } else if !session.paused.load(Ordering::SeqCst) {
+        return Err("The script is not paused".into());
+    }
+    let sent = session.actions_tx.lock().unwrap().send(action);
+    sent.map_err(|_| "The debugged script has already finished".to_string())
+}
+
+#[tauri::command]
+pub fn set_breakpoints(lines: Vec<usize>, state: State<'_, Arc<Mutex<AppState>>>) -> Result<(), String> {
+    let session = session(state.inner())?;
+    *session.stepper.breakpoints.lock().unwrap() = lines.into_iter().collect();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `script` under the debugger, answering the pauses with `actions` and then
+    /// `Continue`. Returns the line and reason of each pause.
+    fn pauses(start: DebugStart, script: &str, actions: &[DebugAction]) -> Vec<(usize, &'static str)> {
+        let stepper = Stepper::new(start);
+        let actions = Mutex::new(actions.to_vec().into_iter());
+        let pauses = Arc::new(Mutex::new(Vec::new()));
+        let seen = pauses.clone();
+        let mut engine = Engine::new();
+        #[allow(deprecated)]
+        engine.register_debugger(
+            |_, debugger| debugger,
+            move |context, event, node, source, _pos: Position| {
+                stepper.on_step(context, event, node, source, |_, node, _, reason| {
+                    seen.lock().unwrap().push((node.position().line().unwrap(), reason));
+                    actions.lock().unwrap().next().unwrap_or(DebugAction::Continue)
+                })
+            },
+        );
+        engine.run(script).unwrap();
+        drop(engine);
+        Arc::try_unwrap(pauses).unwrap().into_inner().unwrap()
+    }
+
+    const SCRIPT: &str = "fn add(a, b) {
+    let c = a + b;
+    c
+}
+let x = add(1, 2);
+let y = x + 1;
+y";
+
+    #[test]
+    fn entry_pauses_before_the_first_statement() {
+        let entry = DebugStart { stop_on_entry: true, ..DebugStart::default() };
+        assert_eq!(pauses(entry, SCRIPT, &[]), [(5, "entry")]);
+        assert!(pauses(DebugStart::default(), SCRIPT, &[]).is_empty());
+    }
+
+    #[test]
+    fn step_over_stays_at_the_paused_call_level() {
+        let entry = DebugStart { stop_on_entry: true, ..DebugStart::default() };
+        let over = [DebugAction::StepOver, DebugAction::StepOver];
+        assert_eq!(pauses(entry, SCRIPT, &over), [(5, "entry"), (6, "step"), (7, "step")]);
+    }
+
+    #[test]
+    fn step_into_enters_calls_and_step_out_leaves_them() {
+        let entry = DebugStart { stop_on_entry: true, ..DebugStart::default() };
+        let actions = [DebugAction::StepInto, DebugAction::StepInto, DebugAction::StepOut];
+        assert_eq!(pauses(entry, SCRIPT, &actions), [(5, "entry"), (2, "step"), (3, "step"), (6, "step")]);
+    }
+
+    #[test]
+    fn breakpoints_pause_on_every_pass() {
+        let on = |lines: &[usize]| DebugStart { breakpoints: lines.to_vec(), ..DebugStart::default() };
+        assert_eq!(pauses(on(&[2, 6]), SCRIPT, &[]), [(2, "breakpoint"), (6, "breakpoint")]);
+
+        // A loop body on the loop's own line
+        let script = "let x = 0;\nfor i in 0..3 { x += i; }\nx";
+        assert_eq!(pauses(on(&[2]), script, &[]), [(2, "breakpoint"); 3]);
+        // A function called twice from one line
+        let script = "fn inc(n) {\n    n + 1\n}\nlet x = inc(inc(0));\nx";
+        assert_eq!(pauses(on(&[2]), script, &[]), [(2, "breakpoint"); 2]);
+    }
+
+    #[test]
+    fn stop_ends_the_run() {
+        let stepper = Stepper::new(DebugStart { stop_on_entry: true, ..DebugStart::default() });
+        let mut engine = Engine::new();
+        #[allow(deprecated)]
+        engine.register_debugger(
+            |_, debugger| debugger,
+            move |context, event, node, source, _pos: Position| {
+                stepper.on_step(context, event, node, source, |_, _, _, _| DebugAction::Stop)
+            },
+        );
+        let error = engine.run(SCRIPT).unwrap_err();
+        assert!(matches!(*error, EvalAltResult::ErrorTerminated(..)), "{}", error);
+    }
+
+    #[test]
+    fn describe_redacts_every_string() {
+        let engine = Engine::new();
+        let redact = |text: String| text.replace("hunter2\"x", "[secret]");
+        for (script, expected) in [
+            (r#""pw: hunter2\"x""#, r#""pw: [secret]""#),
+            (r#"["a", ["hunter2\"x"]]"#, r#"["a",["[secret]"]]"#),
+            (r#"#{"hunter2\"x": #{ pw: "hunter2\"x" }, n: 1}"#, r#"{"[secret]":{"pw":"[secret]"},"n":1}"#),
+            ("42", "42"),
+        ] {
+            let value = engine.eval::<Dynamic>(script).unwrap();
+            assert_eq!(describe(&value, &redact).to_string(), expected, "{}", script);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use serde::{Serialize, Serializer};
+
+/// Why a profile operation was refused.
+#[derive(Debug, thiserror::Error)]
+pub enum ProfileError {
+    #[error("Profile '{0}' not found")]
+    NotFound(String),
+
+    #[error("A profile named '{0}' already exists")]
+    AlreadyExists(String),
+
+    #[error("Invalid {field}: {reason}")]
+    Invalid { field: &'static str, reason: String },
+
+    #[error("Profile '{profile}' is used by scheduled job(s): {jobs}")]
+    InUse { profile: String, jobs: String },
+
+    #[error("Profile '{profile}' is open in window(s): {windows}")]
+    OpenIn { profile: String, windows: String },
+
+    #[error("Profile storage error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Storage(String),
+}
+
+/// Why a profile bundle could not be exported or imported.
+#[derive(Debug, thiserror::Error)]
+pub enum BundleError {
+    #[error(transparent)]
+    Profile(#[from] ProfileError),
+
+    #[error("Not a valid profile bundle: {0}")]
+    Format(String),
+
+    #[error("Bundle format {0} is newer than this version supports")]
+    UnsupportedVersion(u32),
+
+    #[error("This bundle's secrets are encrypted; a passphrase is required")]
+    PassphraseRequired,
+
+    #[error("Wrong passphrase, or the bundle's secrets were tampered with")]
+    WrongPassphrase,
+
+    #[error("{0}")]
+    Storage(String),
+}
+
+/// Why a vault operation failed. Messages never include secret values.
+#[derive(Debug, thiserror::Error)]
+pub enum VaultError {
+    #[error("The vault is locked")]
+    Locked,
+
+    #[error("Wrong passphrase or key file")]
+    WrongKey,
+
+    #[error("Secret '{0}' not found")]
+    NotFound(String),
+
+    #[error("A secret named '{0}' already exists")]
+    AlreadyExists(String),
+
+    #[error("Invalid secret name '{0}': use letters, digits, '_', '-' and '.'")]
+    InvalidName(String),
+
+    #[error("Vault file error: {0}")]
+    Format(String),
+
+    #[error("Vault storage error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Profile(#[from] ProfileError),
+}
+
+/// Why the window pool refused a run.
+#[derive(Debug, thiserror::Error)]
+pub enum PoolError {
+    #[error("All {max_windows} windows are busy and the queue is full ({max_queue} waiting); retry later")]
+    QueueFull { max_windows: usize, max_queue: usize },
+}
+
+/// Why a script's params were rejected before it ran.
+#[derive(Debug, thiserror::Error)]
+pub enum ParamError {
+    #[error("Missing required param '{0}'")]
+    Missing(String),
+
+    #[error("Param '{name}' must be {expected}")]
+    WrongType { name: String, expected: &'static str },
+
+    #[error("Unknown param '{0}': the script doesn't declare it")]
+    Unknown(String),
+
+    #[error("Invalid @param declaration on line {line}: {reason}")]
+    Declaration { line: usize, reason: String },
+}
+
+impl ProfileError {
+    pub fn invalid(field: &'static str, reason: impl Into<String>) -> Self {
+        ProfileError::Invalid { field, reason: reason.into() }
+    }
+}
+
+/// Why a record could not be written to a script's output sink.
+#[derive(Debug, thiserror::Error)]
+pub enum OutputError {
+    #[error("Cannot tell the output format of '{0}'; use .jsonl, .csv, .db or .sqlite")]
+    UnknownFormat(String),
+
+    #[error("Output path '{0}' must be relative to the workspace outputs folder")]
+    PathNotAllowed(String),
+
+    #[error("output.push expects a map, got {0}")]
+    NotARecord(String),
+
+    #[error("The CSV output has no column '{0}'; columns are fixed by the first record")]
+    NewColumn(String),
+
+    #[error("A run keeps at most {0} records in the run registry; use a file or SQLite sink")]
+    RegistryFull(usize),
+
+    #[error("This run has no output sink")]
+    Unavailable,
+
+    #[error("Output error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("CSV output error: {0}")]
+    Csv(#[from] csv::Error),
+
+    #[error("SQLite output error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// Why a run was stopped by its script policy.
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyError {
+    #[error("Run stopped: it ran longer than the {0}s limit")]
+    Timeout(u64),
+
+    #[error("Run stopped: more than {0} navigations")]
+    Navigations(u32),
+
+    #[error("Run stopped: navigation to '{0}' is outside the allowed domains")]
+    DomainNotAllowed(String),
+
+    #[error("Run stopped: navigation to '{0}' uses a scheme the policy doesn't allow")]
+    SchemeNotAllowed(String),
+
+    #[error("Run stopped: {0}")]
+    Limit(String),
+
+    #[error("File path '{path}' must be relative to the workspace {folder}/ folder")]
+    FileNotAllowed { path: String, folder: String },
+}
+
+/// Why a REPL request failed. Errors of the evaluated code are part of its output instead.
+#[derive(Debug, thiserror::Error)]
+pub enum ReplError {
+    #[error("REPL session '{0}' not found")]
+    NotFound(String),
+
+    #[error("At most {0} REPL sessions can be open at once")]
+    TooMany(usize),
+
+    #[error("All {0} windows of the pool are in use")]
+    PoolBusy(usize),
+
+    #[error("Could not open a window for the REPL: {0}")]
+    Window(String),
+
+    #[error("The evaluation failed: {0}")]
+    Failed(String),
+}
+
+/// Why a script could not interact with a page element.
+#[derive(Debug, thiserror::Error)]
+pub enum ActionError {
+    #[error("'{selector}' {reason} (waited {waited_ms}ms)")]
+    NotActionable { selector: String, reason: String, waited_ms: u64 },
+
+    #[error("'{selector}' {reason}")]
+    Invalid { selector: String, reason: String },
+
+    #[error("Unknown key '{key}' in '{combo}'")]
+    UnknownKey { key: String, combo: String },
+
+    #[error("Cannot upload '{path}': {reason}")]
+    File { path: String, reason: String },
+
+    #[error("The page went away while acting on '{0}'")]
+    PageGone(String),
+
+    #[error("Could not deliver input to the page: {0}")]
+    Input(String),
+}
+
+/// Commands hand errors to the frontend as plain messages, like the `String` errors elsewhere.
+macro_rules! serialize_as_message {
+    ($error:ty) => {
+        impl Serialize for $error {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.to_string())
+            }
+        }
+    };
+}
+
+serialize_as_message!(ProfileError);
+serialize_as_message!(BundleError);
+serialize_as_message!(VaultError);
+serialize_as_message!(PoolError);
+serialize_as_message!(ParamError);
+serialize_as_message!(OutputError);
+serialize_as_message!(ReplError);
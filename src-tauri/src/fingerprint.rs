@@ -1,122 +1,522 @@
-pub fn generate_injection_script(seed: u64) -> String {
-    let mut rng = Lcg::new(seed);
-
-    // Generate noise for Canvas
-    let r_noise = rng.range(-2.0, 2.0).round();
-    let g_noise = rng.range(-2.0, 2.0).round();
-    let b_noise = rng.range(-2.0, 2.0).round();
-    let a_noise = rng.range(-2.0, 2.0).round();
-
-    // Generate noise for Audio
-    let audio_noise = rng.range(-0.0001, 0.0001);
-
-    // Generate WebGL Vendor/Renderer
-    // Simple list for demonstration. In a real scenario, this would be more comprehensive.
-    let vendors = vec!["Google Inc.", "Intel Inc.", "NVIDIA Corporation"];
-    let renderers = vec![
-        "ANGLE (Intel, Intel(R) UHD Graphics 630 Direct3D11 vs_5_0 ps_5_0, D3D11)",
-        "ANGLE (NVIDIA, NVIDIA GeForce GTX 1050 Ti Direct3D11 vs_5_0 ps_5_0, D3D11)",
-        "ANGLE (AMD, AMD Radeon RX 580 Direct3D11 vs_5_0 ps_5_0, D3D11)"
-    ];
-    let vendor = vendors[rng.next() as usize % vendors.len()];
-    let renderer = renderers[rng.next() as usize % renderers.len()];
+use crate::client_hints::ClientHints;
+use crate::state::Profile;
+
+/// Builds the init script for a target window.
+///
+/// The script is registered for all frames, but it also re-applies itself to
+/// same-origin iframes created at runtime (which are reachable before their own
+/// init script runs, e.g. `about:blank` frames) and to Worker / SharedWorker
+/// global scopes, so `iframe.contentWindow.navigator` and a worker's
+/// `self.navigator` agree with the top frame.
+pub fn generate_injection_script(profile: &Profile) -> String {
+    format!("({})()(window);", generate_mask_function(profile))
+}
+
+/// Prelude for worker scripts fetched through the proxy (Service Workers can't
+/// be wrapped from the page, so the proxy prepends this to their source).
+pub fn generate_worker_prelude(profile: &Profile) -> String {
+    format!("({})()(self);\n", generate_mask_function(profile))
+}
+
+/// Current seed → fingerprint derivation. New profiles are stamped with it; profiles
+/// saved before versioning deserialize as version 1.
+///
+/// * **v1**: a single LCG stream seeded with the raw profile seed, drawn in a fixed
+///   order. Kept bit-for-bit so existing identities don't change.
+/// * **v2**: each surface draws from its own SplitMix64 stream seeded with
+///   `splitmix64(seed ^ fnv1a64(surface))`. Neighbouring seeds give unrelated
+///   values, adding a surface doesn't shift the others, and the WebGL vendor and
+///   renderer are picked as a matching pair.
+/// * **v3**: v2 with the GPU picked from the UA's OS, so a macOS or Linux UA doesn't
+///   report a Direct3D renderer.
+pub const FINGERPRINT_VERSION: u32 = 3;
+
+/// A fresh profile seed. Kept within 53 bits so it survives a round trip through
+/// JavaScript numbers (the UI, API clients) unchanged.
+pub fn random_seed() -> u64 {
+    uuid::Uuid::new_v4().as_u64_pair().0 >> 11
+}
+
+/// Per-profile values the mask spoofs, derived deterministically from the seed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    /// RGBA offsets added to `getImageData` readback
+    pub canvas_noise: [f64; 4],
+    pub audio_noise: f64,
+    pub webgl_vendor: String,
+    pub webgl_renderer: String,
+    /// Width/height offsets added to `getBoundingClientRect`
+    pub rect_noise: [f64; 2],
+}
+
+impl Fingerprint {
+    /// Unknown (future) versions fall back to the current derivation. Only v3 and
+    /// later look at the UA.
+    pub fn derive(seed: u64, version: u32, user_agent: &str) -> Self {
+        match version {
+            1 => Self::derive_v1(seed),
+            2 => Self::derive_v2(seed, &WINDOWS_GPUS),
+            _ => Self::derive_v2(seed, gpus_for(user_agent)),
+        }
+    }
+
+    fn derive_v1(seed: u64) -> Self {
+        let mut rng = Lcg::new(seed);
+
+        // Generate noise for Canvas
+        let r_noise = rng.range(-2.0, 2.0).round();
+        let g_noise = rng.range(-2.0, 2.0).round();
+        let b_noise = rng.range(-2.0, 2.0).round();
+        let a_noise = rng.range(-2.0, 2.0).round();
+
+        // Generate noise for Audio
+        let audio_noise = rng.range(-0.0001, 0.0001);
+
+        // Generate WebGL Vendor/Renderer
+        // Simple list for demonstration. In a real scenario, this would be more comprehensive.
+        let vendors = ["Google Inc.", "Intel Inc.", "NVIDIA Corporation"];
+        let renderers = [
+            "ANGLE (Intel, Intel(R) UHD Graphics 630 Direct3D11 vs_5_0 ps_5_0, D3D11)",
+            "ANGLE (NVIDIA, NVIDIA GeForce GTX 1050 Ti Direct3D11 vs_5_0 ps_5_0, D3D11)",
+            "ANGLE (AMD, AMD Radeon RX 580 Direct3D11 vs_5_0 ps_5_0, D3D11)"
+        ];
+        let vendor = vendors[rng.next() as usize % vendors.len()];
+        let renderer = renderers[rng.next() as usize % renderers.len()];
+
+        let rect_noise = [rng.range(-0.01, 0.01), rng.range(-0.01, 0.01)];
+
+        Self {
+            canvas_noise: [r_noise, g_noise, b_noise, a_noise],
+            audio_noise,
+            webgl_vendor: vendor.to_string(),
+            webgl_renderer: renderer.to_string(),
+            rect_noise,
+        }
+    }
+
+    fn derive_v2(seed: u64, gpus: &[(&str, &str)]) -> Self {
+        let mut canvas = SeedRng::for_surface(seed, "canvas");
+        let canvas_noise = [
+            canvas.range(-2.0, 2.0).round(),
+            canvas.range(-2.0, 2.0).round(),
+            canvas.range(-2.0, 2.0).round(),
+            canvas.range(-2.0, 2.0).round(),
+        ];
+
+        let audio_noise = SeedRng::for_surface(seed, "audio").range(-0.0001, 0.0001);
+
+        let (vendor, renderer) = gpus[SeedRng::for_surface(seed, "webgl").below(gpus.len())];
+
+        let mut rects = SeedRng::for_surface(seed, "rects");
+        let rect_noise = [rects.range(-0.01, 0.01), rects.range(-0.01, 0.01)];
+
+        Self {
+            canvas_noise,
+            audio_noise,
+            webgl_vendor: vendor.to_string(),
+            webgl_renderer: renderer.to_string(),
+            rect_noise,
+        }
+    }
+
+    /// Seed-derived values with the profile's explicit overrides applied.
+    pub fn for_profile(profile: &Profile) -> Self {
+        let mut fp = Self::derive(profile.seed, profile.fingerprint_version, &profile.user_agent);
+        if let Some(webgl) = &profile.webgl {
+            fp.webgl_vendor = webgl.vendor.clone();
+            fp.webgl_renderer = webgl.renderer.clone();
+        }
+        fp
+    }
+}
+
+// Vendor/renderer pairs as the browser reports them on each OS, so vendor and
+// renderer agree with each other and with the UA
+const WINDOWS_GPUS: [(&str, &str); 3] = [
+    ("Google Inc. (Intel)", "ANGLE (Intel, Intel(R) UHD Graphics 630 Direct3D11 vs_5_0 ps_5_0, D3D11)"),
+    ("Google Inc. (NVIDIA)", "ANGLE (NVIDIA, NVIDIA GeForce GTX 1050 Ti Direct3D11 vs_5_0 ps_5_0, D3D11)"),
+    ("Google Inc. (AMD)", "ANGLE (AMD, AMD Radeon RX 580 Direct3D11 vs_5_0 ps_5_0, D3D11)"),
+];
+const MAC_GPUS: [(&str, &str); 3] = [
+    ("Google Inc. (Apple)", "ANGLE (Apple, ANGLE Metal Renderer: Apple M1, Unspecified Version)"),
+    ("Google Inc. (Apple)", "ANGLE (Apple, ANGLE Metal Renderer: Apple M2, Unspecified Version)"),
+    ("Google Inc. (Intel Inc.)", "ANGLE (Intel Inc., Intel(R) Iris(TM) Plus Graphics 655, OpenGL 4.1)"),
+];
+const LINUX_GPUS: [(&str, &str); 3] = [
+    ("Google Inc. (Intel)", "ANGLE (Intel, Mesa Intel(R) UHD Graphics 630 (CFL GT2), OpenGL 4.6)"),
+    ("Google Inc. (AMD)", "ANGLE (AMD, AMD Radeon RX 580 Series (radeonsi, polaris10, LLVM 15.0.7), OpenGL 4.6)"),
+    ("Google Inc. (NVIDIA Corporation)", "ANGLE (NVIDIA Corporation, NVIDIA GeForce GTX 1050 Ti/PCIe/SSE2, OpenGL 4.5.0)"),
+];
+const ANDROID_GPUS: [(&str, &str); 2] = [
+    ("Google Inc. (Qualcomm)", "ANGLE (Qualcomm, Adreno (TM) 640, OpenGL ES 3.2)"),
+    ("Google Inc. (ARM)", "ANGLE (ARM, Mali-G78, OpenGL ES 3.2)"),
+];
+// Safari and every iOS browser hide the real GPU
+const APPLE_WEBKIT_GPUS: [(&str, &str); 1] = [("Apple Inc.", "Apple GPU")];
+
+fn gpus_for(user_agent: &str) -> &'static [(&'static str, &'static str)] {
+    let chromium = user_agent.contains("Chrome/") || user_agent.contains("Edg/");
+    if user_agent.contains("iPhone") || user_agent.contains("iPad") {
+        &APPLE_WEBKIT_GPUS
+    } else if user_agent.contains("Macintosh") {
+        if chromium { &MAC_GPUS } else { &APPLE_WEBKIT_GPUS }
+    } else if user_agent.contains("Android") {
+        &ANDROID_GPUS
+    } else if user_agent.contains("Linux") || user_agent.contains("CrOS") {
+        &LINUX_GPUS
+    } else {
+        &WINDOWS_GPUS
+    }
+}
+
+/// `navigator.platform` a real browser with this UA reports, if the UA names an OS.
+pub fn navigator_platform(user_agent: &str) -> Option<&'static str> {
+    if user_agent.contains("iPhone") {
+        Some("iPhone")
+    } else if user_agent.contains("iPad") {
+        Some("iPad")
+    } else if user_agent.contains("Windows") {
+        Some("Win32")
+    } else if user_agent.contains("Macintosh") {
+        Some("MacIntel")
+    } else if user_agent.contains("Android") {
+        Some("Linux armv8l")
+    } else if user_agent.contains("Linux") || user_agent.contains("CrOS") {
+        Some("Linux x86_64")
+    } else {
+        None
+    }
+}
+
+/// Self-contained `function(base)` returning the `function(scope)` that patches
+/// a window or worker global. `base` is the script URL of a wrapped worker.
+///
+/// Everything it needs is embedded as literals so it can be serialized with
+/// `toString()` and re-run inside another realm (frames, workers). The set of
+/// patched globals lives in the closure, so nothing is left on the page.
+fn generate_mask_function(profile: &Profile) -> String {
+    let fp = Fingerprint::for_profile(profile);
+    let [r_noise, g_noise, b_noise, a_noise] = fp.canvas_noise;
+    let [rect_w_noise, rect_h_noise] = fp.rect_noise;
+    let audio_noise = fp.audio_noise;
+    let vendor = serde_json::to_string(&fp.webgl_vendor).unwrap_or_default();
+    let renderer = serde_json::to_string(&fp.webgl_renderer).unwrap_or_default();
+    let screen = serde_json::to_string(&profile.screen).unwrap_or_else(|_| "null".into());
+
+    let user_agent = serde_json::to_string(&profile.user_agent).unwrap_or_else(|_| "\"\"".into());
+    let platform = serde_json::to_string(&navigator_platform(&profile.user_agent)).unwrap_or_else(|_| "null".into());
+    // `null` for UAs without Client Hints support (Firefox, Safari)
+    let ua_hints = serde_json::to_string(&ClientHints::from_user_agent(&profile.user_agent))
+        .unwrap_or_else(|_| "null".into());
 
     format!(r#"
-    (function() {{
-        // --- Canvas Spoofing ---
-        const originalToDataURL = HTMLCanvasElement.prototype.toDataURL;
-        const originalGetImageData = CanvasRenderingContext2D.prototype.getImageData;
-
-        const noise = {{ r: {}, g: {}, b: {}, a: {} }};
-
-        CanvasRenderingContext2D.prototype.getImageData = function(x, y, w, h) {{
-            const imageData = originalGetImageData.apply(this, arguments);
-            for (let i = 0; i < imageData.data.length; i += 4) {{
-                imageData.data[i] = Math.max(0, Math.min(255, imageData.data[i] + noise.r));
-                imageData.data[i+1] = Math.max(0, Math.min(255, imageData.data[i+1] + noise.g));
-                imageData.data[i+2] = Math.max(0, Math.min(255, imageData.data[i+2] + noise.b));
-                imageData.data[i+3] = Math.max(0, Math.min(255, imageData.data[i+3] + noise.a));
+    function __phantomMasker(base) {{
+        const patched = new WeakSet();
+        return function __phantomMask(scope) {{
+            // Frames and workers can be reached from several paths (their own init script,
+            // the parent's contentWindow hook, the proxy prelude). Patch each global once.
+            if (patched.has(scope)) return;
+            patched.add(scope);
+
+            // --- User Agent ---
+            const navProto = (scope.Navigator || scope.WorkerNavigator || {{}}).prototype;
+            // Another copy of the mask (the frame's own init script after the parent's hook)
+            // already replaced the native getter here.
+            const userAgentDesc = navProto && Object.getOwnPropertyDescriptor(navProto, 'userAgent');
+            if (userAgentDesc && userAgentDesc.get && !/\[native code\]/.test(Function.prototype.toString.call(userAgentDesc.get))) return;
+            if (base) rebase(scope, base);
+            if (navProto) {{
+                Object.defineProperty(navProto, 'userAgent', {{
+                    get: function () {{ return {user_agent}; }}
+                }});
+                const platform = {platform};
+                if (platform) {{
+                    Object.defineProperty(navProto, 'platform', {{
+                        get: function () {{ return platform; }}
+                    }});
+                }}
             }}
-            return imageData;
-        }};
 
-        HTMLCanvasElement.prototype.toDataURL = function(type, encoderOptions) {{
-            // Force a re-render or manipulation if needed, but for simple noise
-            // checking usually involves drawing to a canvas and reading it back.
-            // Since we hooked getImageData, if the user code calls getImageData it gets noise.
-            // If they use toDataURL, we might need to actually draw to a temp canvas, apply noise, and return that.
-            // For this implementation, we'll hook the context operations primarily.
-            // A more robust implementation handles toDataURL by rendering the current canvas to an offscreen one, applying noise, and exporting.
-            // But 'Consistent Noise' often just means modifying the pixel readback.
-
-            // Note: properly hooking toDataURL is complex because we can't easily "read" the pixels without getting tainted canvas errors or recursion.
-            // A common simplified approach is to override fillText/strokeText to slightly alter positions,
-            // effectively changing the hash without post-processing pixels.
-            // For now, we will leave toDataURL as is, assuming the bot detection relies on getImageData or that
-            // we accept slight risk here for the Phase 2 MVP.
-            return originalToDataURL.apply(this, arguments);
-        }};
+            // --- User-Agent Client Hints ---
+            // Must agree with the Sec-CH-UA* headers the proxy sends for this profile
+            const uaHints = {ua_hints};
+            if (navProto && uaHints) {{
+                const freezeBrands = (list) => Object.freeze(list.map(b => Object.freeze({{ ...b }})));
+                const brands = freezeBrands(uaHints.brands);
+                const fullVersionList = freezeBrands(uaHints.fullVersionList);
 
-        // --- Audio Spoofing ---
-        const originalGetFloatFrequencyData = AnalyserNode.prototype.getFloatFrequencyData;
-        const audioNoise = {};
+                // Chromium webviews already have NavigatorUAData; other engines need a stand-in
+                let UAData = scope.NavigatorUAData;
+                if (!UAData) {{
+                    UAData = function NavigatorUAData() {{ throw new TypeError("Illegal constructor"); }};
+                    scope.NavigatorUAData = UAData;
+                }}
+                const uaProto = UAData.prototype;
+                const lowEntropy = () => ({{ brands: brands, mobile: uaHints.mobile, platform: uaHints.platform }});
 
-        AnalyserNode.prototype.getFloatFrequencyData = function(array) {{
-            const ret = originalGetFloatFrequencyData.apply(this, arguments);
-            for (let i = 0; i < array.length; i++) {{
-                array[i] += audioNoise;
+                Object.defineProperty(uaProto, 'brands', {{ get: function() {{ return brands; }}, configurable: true, enumerable: true }});
+                Object.defineProperty(uaProto, 'mobile', {{ get: function() {{ return uaHints.mobile; }}, configurable: true, enumerable: true }});
+                Object.defineProperty(uaProto, 'platform', {{ get: function() {{ return uaHints.platform; }}, configurable: true, enumerable: true }});
+
+                uaProto.getHighEntropyValues = function(hints) {{
+                    if (!Array.isArray(hints)) {{
+                        return Promise.reject(new TypeError("Failed to execute 'getHighEntropyValues' on 'NavigatorUAData'"));
+                    }}
+                    const result = lowEntropy();
+                    for (const hint of hints) {{
+                        if (hint === 'fullVersionList') result.fullVersionList = fullVersionList;
+                        else if (hint !== 'brands' && Object.prototype.hasOwnProperty.call(uaHints, hint)) result[hint] = uaHints[hint];
+                    }}
+                    return Promise.resolve(result);
+                }};
+                uaProto.toJSON = function() {{ return lowEntropy(); }};
+
+                const uaData = Object.create(uaProto);
+                Object.defineProperty(navProto, 'userAgentData', {{
+                    get: function() {{ return uaData; }},
+                    configurable: true,
+                    enumerable: true
+                }});
+            }} else if (navProto) {{
+                // Hide the webview's own (Chromium) client hints behind a non-Chromium UA
+                delete navProto.userAgentData;
+                delete scope.NavigatorUAData;
             }}
-            return ret;
-        }};
 
-        // --- WebGL Spoofing ---
-        const getParameter = WebGLRenderingContext.prototype.getParameter;
-        WebGLRenderingContext.prototype.getParameter = function(parameter) {{
-            // 37445 = UNMASKED_VENDOR_WEBGL
-            // 37446 = UNMASKED_RENDERER_WEBGL
-            if (parameter === 37445) return "{}";
-            if (parameter === 37446) return "{}";
-            return getParameter.apply(this, arguments);
-        }};
+            // --- Canvas Spoofing ---
+            const noise = {{ r: {r_noise}, g: {g_noise}, b: {b_noise}, a: {a_noise} }};
 
-        const getParameter2 = WebGL2RenderingContext.prototype.getParameter;
-        WebGL2RenderingContext.prototype.getParameter = function(parameter) {{
-            if (parameter === 37445) return "{}";
-            if (parameter === 37446) return "{}";
-            return getParameter2.apply(this, arguments);
-        }};
+            const patchGetImageData = (ctxClass) => {{
+                if (!ctxClass) return;
+                const originalGetImageData = ctxClass.prototype.getImageData;
+                ctxClass.prototype.getImageData = function(x, y, w, h) {{
+                    const imageData = originalGetImageData.apply(this, arguments);
+                    for (let i = 0; i < imageData.data.length; i += 4) {{
+                        imageData.data[i] = Math.max(0, Math.min(255, imageData.data[i] + noise.r));
+                        imageData.data[i+1] = Math.max(0, Math.min(255, imageData.data[i+1] + noise.g));
+                        imageData.data[i+2] = Math.max(0, Math.min(255, imageData.data[i+2] + noise.b));
+                        imageData.data[i+3] = Math.max(0, Math.min(255, imageData.data[i+3] + noise.a));
+                    }}
+                    return imageData;
+                }};
+            }};
+            patchGetImageData(scope.CanvasRenderingContext2D);
+            // Workers only have OffscreenCanvas
+            patchGetImageData(scope.OffscreenCanvasRenderingContext2D);
+
+            if (scope.HTMLCanvasElement) {{
+                const originalToDataURL = scope.HTMLCanvasElement.prototype.toDataURL;
+                scope.HTMLCanvasElement.prototype.toDataURL = function(type, encoderOptions) {{
+                    // Force a re-render or manipulation if needed, but for simple noise
+                    // checking usually involves drawing to a canvas and reading it back.
+                    // Since we hooked getImageData, if the user code calls getImageData it gets noise.
+                    // If they use toDataURL, we might need to actually draw to a temp canvas, apply noise, and return that.
+                    // For this implementation, we'll hook the context operations primarily.
+                    // A more robust implementation handles toDataURL by rendering the current canvas to an offscreen one, applying noise, and exporting.
+                    // But 'Consistent Noise' often just means modifying the pixel readback.
+
+                    // Note: properly hooking toDataURL is complex because we can't easily "read" the pixels without getting tainted canvas errors or recursion.
+                    // A common simplified approach is to override fillText/strokeText to slightly alter positions,
+                    // effectively changing the hash without post-processing pixels.
+                    // For now, we will leave toDataURL as is, assuming the bot detection relies on getImageData or that
+                    // we accept slight risk here for the Phase 2 MVP.
+                    return originalToDataURL.apply(this, arguments);
+                }};
+            }}
+
+            // --- Audio Spoofing ---
+            if (scope.AnalyserNode) {{
+                const originalGetFloatFrequencyData = scope.AnalyserNode.prototype.getFloatFrequencyData;
+                const audioNoise = {audio_noise};
+
+                scope.AnalyserNode.prototype.getFloatFrequencyData = function(array) {{
+                    const ret = originalGetFloatFrequencyData.apply(this, arguments);
+                    for (let i = 0; i < array.length; i++) {{
+                        array[i] += audioNoise;
+                    }}
+                    return ret;
+                }};
+            }}
+
+            // --- WebGL Spoofing ---
+            // Present in windows and (via OffscreenCanvas) in dedicated workers
+            const patchGetParameter = (glClass) => {{
+                if (!glClass) return;
+                const getParameter = glClass.prototype.getParameter;
+                glClass.prototype.getParameter = function(parameter) {{
+                    // 37445 = UNMASKED_VENDOR_WEBGL
+                    // 37446 = UNMASKED_RENDERER_WEBGL
+                    if (parameter === 37445) return {vendor};
+                    if (parameter === 37446) return {renderer};
+                    return getParameter.apply(this, arguments);
+                }};
+            }};
+            patchGetParameter(scope.WebGLRenderingContext);
+            patchGetParameter(scope.WebGL2RenderingContext);
+
+            // --- Screen ---
+            const screenConfig = {screen};
+            if (screenConfig && scope.Screen) {{
+                const availHeight = screenConfig.avail_height == null ? screenConfig.height : screenConfig.avail_height;
+                const screenValues = {{
+                    width: screenConfig.width,
+                    height: screenConfig.height,
+                    availWidth: screenConfig.width,
+                    availHeight: availHeight,
+                }};
+                for (const [key, value] of Object.entries(screenValues)) {{
+                    Object.defineProperty(scope.Screen.prototype, key, {{
+                        get: function() {{ return value; }},
+                        configurable: true,
+                        enumerable: true
+                    }});
+                }}
+                Object.defineProperty(scope, 'devicePixelRatio', {{
+                    get: function() {{ return screenConfig.pixel_ratio; }},
+                    configurable: true
+                }});
+            }}
 
-        // --- Rects/Resolution Spoofing (Subpixel) ---
-        // Adding tiny noise to getBoundingClientRect
-        const originalGetBCR = Element.prototype.getBoundingClientRect;
-        Element.prototype.getBoundingClientRect = function() {{
-            const rect = originalGetBCR.apply(this, arguments);
-            // We can't modify the DOMRectReadOnly directly easily, so we proxy it.
-            // But for high-perf, we'll just return the original for now or wrap it if strictly needed.
-            // PRD mentions it, let's try a light wrapper.
-            return {{
-                x: rect.x,
-                y: rect.y,
-                width: rect.width + {},
-                height: rect.height + {},
-                top: rect.top,
-                right: rect.right,
-                bottom: rect.bottom,
-                left: rect.left
+            // --- Rects/Resolution Spoofing (Subpixel) ---
+            // Adding tiny noise to getBoundingClientRect
+            if (scope.Element) {{
+                const originalGetBCR = scope.Element.prototype.getBoundingClientRect;
+                scope.Element.prototype.getBoundingClientRect = function() {{
+                    const rect = originalGetBCR.apply(this, arguments);
+                    // We can't modify the DOMRectReadOnly directly easily, so we proxy it.
+                    // But for high-perf, we'll just return the original for now or wrap it if strictly needed.
+                    // PRD mentions it, let's try a light wrapper.
+                    return {{
+                        x: rect.x,
+                        y: rect.y,
+                        width: rect.width + {rect_w_noise},
+                        height: rect.height + {rect_h_noise},
+                        top: rect.top,
+                        right: rect.right,
+                        bottom: rect.bottom,
+                        left: rect.left
+                    }};
+                }};
+            }}
+
+            // --- Child Frames ---
+            // Same-origin frames share our origin, so detectors read `iframe.contentWindow.navigator`
+            // right after inserting the frame, before any init script of its own has run.
+            if (scope.HTMLIFrameElement) {{
+                const maskFrame = (frame) => {{
+                    try {{
+                        const w = contentWindowDesc.get.call(frame);
+                        if (w) __phantomMask(w);
+                    }} catch (e) {{
+                        // Cross-origin frame: covered by its own all-frames init script
+                    }}
+                }};
+
+                const frameProto = scope.HTMLIFrameElement.prototype;
+                const contentWindowDesc = Object.getOwnPropertyDescriptor(frameProto, 'contentWindow');
+                const contentDocumentDesc = Object.getOwnPropertyDescriptor(frameProto, 'contentDocument');
+
+                Object.defineProperty(frameProto, 'contentWindow', {{
+                    ...contentWindowDesc,
+                    get: function() {{
+                        maskFrame(this);
+                        return contentWindowDesc.get.call(this);
+                    }}
+                }});
+                Object.defineProperty(frameProto, 'contentDocument', {{
+                    ...contentDocumentDesc,
+                    get: function() {{
+                        maskFrame(this);
+                        return contentDocumentDesc.get.call(this);
+                    }}
+                }});
+
+                // `window[0]` / `window.frames` bypass the getters, so mask frames as they are attached
+                const maskInserted = (node) => {{
+                    if (!node || node.nodeType !== 1) return;
+                    if (node.tagName === 'IFRAME') maskFrame(node);
+                    else if (node.querySelectorAll) node.querySelectorAll('iframe').forEach(maskFrame);
+                }};
+
+                const nodeProto = scope.Node.prototype;
+                for (const method of ['appendChild', 'insertBefore', 'replaceChild']) {{
+                    const original = nodeProto[method];
+                    nodeProto[method] = function(node) {{
+                        const ret = original.apply(this, arguments);
+                        maskInserted(node);
+                        return ret;
+                    }};
+                }}
+
+                const elementProto = scope.Element.prototype;
+                for (const method of ['append', 'prepend', 'after', 'before', 'replaceWith']) {{
+                    const original = elementProto[method];
+                    if (!original) continue;
+                    elementProto[method] = function(...nodes) {{
+                        const ret = original.apply(this, nodes);
+                        nodes.forEach(maskInserted);
+                        return ret;
+                    }};
+                }}
+
+                // innerHTML / insertAdjacentHTML insertions are only caught asynchronously
+                const observer = new scope.MutationObserver((mutations) => {{
+                    for (const m of mutations) m.addedNodes.forEach(maskInserted);
+                }});
+                const observe = () => observer.observe(scope.document.documentElement, {{ childList: true, subtree: true }});
+                if (scope.document.documentElement) observe();
+                else scope.document.addEventListener('DOMContentLoaded', observe, {{ once: true }});
+            }}
+
+            // --- Workers ---
+            // Spawn the real worker from a blob that runs this mask first, then loads the original script.
+            const wrapWorker = (name) => {{
+                const Original = scope[name];
+                if (!Original) return;
+
+                const Wrapped = function(url, options) {{
+                    if (!new.target) return Original.apply(this, arguments);
+                    let blobUrl;
+                    try {{
+                        const absolute = new URL(url, base || scope.location.href).href;
+                        const isModule = options && typeof options === 'object' && options.type === 'module';
+                        const loader = isModule
+                            ? 'import ' + JSON.stringify(absolute) + ';'
+                            : 'importScripts(' + JSON.stringify(absolute) + ');';
+                        const prelude = '(' + __phantomMasker.toString() + ')(' + JSON.stringify(absolute) + ')(self);\n';
+                        blobUrl = URL.createObjectURL(new Blob([prelude + loader], {{ type: 'text/javascript' }}));
+                    }} catch (e) {{
+                        return new Original(url, options);
+                    }}
+                    return new Original(blobUrl, options);
+                }};
+                Wrapped.prototype = Original.prototype;
+                Object.defineProperty(Wrapped, 'name', {{ value: Original.name }});
+                Object.defineProperty(Wrapped, 'length', {{ value: Original.length }});
+                scope[name] = Wrapped;
             }};
+            wrapWorker('Worker');
+            wrapWorker('SharedWorker');
         }};
 
-        console.log("Phantom Browser: Digital Mask v2 Applied");
-    }})();
+        // A blob worker resolves relative URLs against its blob: URL. Point importScripts,
+        // fetch, XHR and nested workers back at the original script's URL.
+        function rebase(scope, base) {{
+            const resolve = (u) => (typeof u === 'string' || u instanceof scope.URL) ? new scope.URL(u, base).href : u;
+            const importScripts = scope.importScripts;
+            if (importScripts) scope.importScripts = function(...urls) {{ return importScripts.apply(this, urls.map(resolve)); }};
+            const fetch = scope.fetch;
+            if (fetch) scope.fetch = function(input, init) {{ return fetch.call(this, resolve(input), init); }};
+            const xhr = scope.XMLHttpRequest && scope.XMLHttpRequest.prototype;
+            const open = xhr && xhr.open;
+            if (open) xhr.open = function(method, url, ...rest) {{ return open.call(this, method, resolve(url), ...rest); }};
+        }}
+    }}
     "#,
-    r_noise, g_noise, b_noise, a_noise,
-    audio_noise,
-    vendor, renderer,
-    vendor, renderer,
-    rng.range(-0.01, 0.01), rng.range(-0.01, 0.01) // Rect noise
     )
 }
 
+/// Legacy generator behind fingerprint v1. Don't use for anything new.
 struct Lcg {
     state: u64,
 }
@@ -139,3 +539,191 @@ impl Lcg {
         min + (max - min) * self.next_float()
     }
 }
+
+/// SplitMix64: tiny, stable across platforms and releases, and every output goes
+/// through a full-avalanche finalizer, so nearby seeds produce unrelated streams.
+#[derive(Debug)]
+pub(crate) struct SeedRng {
+    state: u64,
+}
+
+impl SeedRng {
+    /// Independent stream for one consumer (`"canvas"`, `"webgl"`, ...) of a profile seed.
+    pub(crate) fn for_surface(seed: u64, surface: &str) -> Self {
+        Self { state: splitmix64(seed ^ fnv1a64(surface.as_bytes())) }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix64(self.state)
+    }
+
+    /// Uniform in `[0, 1)`, using the top 53 bits.
+    pub(crate) fn next_float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    pub(crate) fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next_float()
+    }
+
+    /// Uniform index in `0..n` (multiply-shift, so high bits decide).
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    mix64(x.wrapping_add(0x9E37_79B9_7F4A_7C15))
+}
+
+fn mix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn fnv1a64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Snapshots: these values are what real profiles render. If one of these
+    // changes, existing identities change with it - add a new version instead.
+
+    #[test]
+    fn v1_snapshot() {
+        assert_eq!(
+            Fingerprint::derive(12345, 1, ""),
+            Fingerprint {
+                canvas_noise: [-2.0, 0.0, -2.0, -1.0],
+                audio_noise: 4.3879544366329945e-5,
+                webgl_vendor: "Google Inc.".into(),
+                webgl_renderer: "ANGLE (NVIDIA, NVIDIA GeForce GTX 1050 Ti Direct3D11 vs_5_0 ps_5_0, D3D11)".into(),
+                rect_noise: [0.0027841105713090827, 0.0029103353430637406],
+            }
+        );
+        assert_eq!(
+            Fingerprint::derive(112233, 1, ""),
+            Fingerprint {
+                canvas_noise: [-0.0, 1.0, 1.0, -2.0],
+                audio_noise: 5.8120482725560783e-5,
+                webgl_vendor: "Intel Inc.".into(),
+                webgl_renderer: "ANGLE (NVIDIA, NVIDIA GeForce GTX 1050 Ti Direct3D11 vs_5_0 ps_5_0, D3D11)".into(),
+                rect_noise: [-0.0065695252709742046, 0.008490494044066934],
+            }
+        );
+    }
+
+    #[test]
+    fn v2_snapshot() {
+        assert_eq!(
+            Fingerprint::derive(12345, 2, ""),
+            Fingerprint {
+                canvas_noise: [0.0, 1.0, -1.0, -1.0],
+                audio_noise: 6.695936406189963e-5,
+                webgl_vendor: "Google Inc. (Intel)".into(),
+                webgl_renderer: "ANGLE (Intel, Intel(R) UHD Graphics 630 Direct3D11 vs_5_0 ps_5_0, D3D11)".into(),
+                rect_noise: [-0.002623898265871401, 0.0033397982471840577],
+            }
+        );
+        assert_eq!(
+            Fingerprint::derive(12346, 2, ""),
+            Fingerprint {
+                canvas_noise: [-2.0, -1.0, -0.0, 0.0],
+                audio_noise: 5.405406538203687e-5,
+                webgl_vendor: "Google Inc. (AMD)".into(),
+                webgl_renderer: "ANGLE (AMD, AMD Radeon RX 580 Direct3D11 vs_5_0 ps_5_0, D3D11)".into(),
+                rect_noise: [0.004528999497898924, 0.0006441274751230863],
+            }
+        );
+    }
+
+    #[test]
+    fn v3_picks_the_gpu_from_the_ua_os() {
+        let cases = [
+            ("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36", &WINDOWS_GPUS[..]),
+            ("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36", &MAC_GPUS[..]),
+            ("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Safari/605.1.15", &APPLE_WEBKIT_GPUS[..]),
+            ("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36", &LINUX_GPUS[..]),
+            ("Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36", &ANDROID_GPUS[..]),
+            ("Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1", &APPLE_WEBKIT_GPUS[..]),
+        ];
+        for (ua, pool) in cases {
+            for seed in [1, 12345, 987654321] {
+                let fp = Fingerprint::derive(seed, 3, ua);
+                assert!(pool.contains(&(fp.webgl_vendor.as_str(), fp.webgl_renderer.as_str())), "{}: {:?}", ua, fp);
+                assert_eq!(fp.webgl_renderer.contains("Direct3D"), ua.contains("Windows"), "{}", ua);
+            }
+        }
+    }
+
+    #[test]
+    fn v3_keeps_the_v2_noise() {
+        let ua = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+        let (v2, v3) = (Fingerprint::derive(12345, 2, ua), Fingerprint::derive(12345, 3, ua));
+        assert_eq!((v2.canvas_noise, v2.audio_noise, v2.rect_noise), (v3.canvas_noise, v3.audio_noise, v3.rect_noise));
+    }
+
+    #[test]
+    fn navigator_platform_follows_the_ua() {
+        for (ua, platform) in [
+            ("Mozilla/5.0 (Windows NT 10.0; Win64; x64)", Some("Win32")),
+            ("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7)", Some("MacIntel")),
+            ("Mozilla/5.0 (X11; Linux x86_64)", Some("Linux x86_64")),
+            ("Mozilla/5.0 (Linux; Android 14; Pixel 8)", Some("Linux armv8l")),
+            ("Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X)", Some("iPhone")),
+            ("curl/8.0", None),
+        ] {
+            assert_eq!(navigator_platform(ua), platform, "{}", ua);
+        }
+    }
+
+    #[test]
+    fn random_seeds_fit_in_a_js_number() {
+        for _ in 0..1000 {
+            assert!(random_seed() < 1 << 53);
+        }
+    }
+
+    #[test]
+    fn splitmix64_reference_output() {
+        // First output of the reference SplitMix64 with state 0
+        assert_eq!(splitmix64(0), 0xE220_A839_7B1D_CDAF);
+    }
+
+    #[test]
+    fn neighbouring_seeds_are_uncorrelated() {
+        let a = SeedRng::for_surface(12345, "canvas").next_u64();
+        let b = SeedRng::for_surface(12346, "canvas").next_u64();
+        let differing_bits = (a ^ b).count_ones();
+        assert!((16..=48).contains(&differing_bits), "{} bits differ", differing_bits);
+    }
+
+    #[test]
+    fn profiles_without_version_use_v1() {
+        let profile: Profile = serde_json::from_str(
+            r#"{"name": "Old", "user_agent": "UA", "seed": 12345, "proxy": null}"#,
+        )
+        .unwrap();
+        assert_eq!(profile.fingerprint_version, 1);
+        assert_eq!(Fingerprint::for_profile(&profile), Fingerprint::derive(12345, 1, "UA"));
+    }
+
+    #[test]
+    fn mask_leaves_nothing_on_the_page() {
+        let profile: Profile = serde_json::from_str(
+            r#"{"name": "P", "user_agent": "UA", "seed": 12345, "proxy": null}"#,
+        )
+        .unwrap();
+        for script in [generate_injection_script(&profile), generate_worker_prelude(&profile)] {
+            assert!(!script.contains("Symbol.for"));
+            assert!(!script.contains("console."));
+        }
+    }
+}
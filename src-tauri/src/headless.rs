@@ -1,60 +1,135 @@
-use tauri::{AppHandle, Manager, WebviewWindowBuilder, WebviewUrl, Url};
+use tauri::{AppHandle, Manager};
 use std::sync::{Arc, Mutex};
-use crate::state::AppState;
-use crate::scripting;
+use crate::batch::{self, BatchSpec, RecordFormat};
+use crate::state::{AppState, Profile};
+use crate::pool::{self, PoolJob};
+use crate::runs::RunSource;
+use crate::commands;
+use crate::output::{OutputSink, SinkConfig};
+use crate::params;
+use crate::repl;
+use crate::scripting::ScriptInputs;
+use crate::vault;
+use serde_json::{Map, Value};
 use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
 
-pub fn run_headless_script(app: AppHandle, script_path: String, output_path: Option<String>) {
-    // 1. Read script
-    let content = match fs::read_to_string(&script_path) {
-        Ok(c) => c,
+/// Options of a `--input` batch run.
+pub struct BatchArgs {
+    pub input: String,
+    pub errors: Option<String>,
+    /// Defaults to the window pool size
+    pub parallel: Option<usize>,
+    pub retries: u32,
+}
+
+fn read_script(app: &AppHandle, script_path: &str) -> Option<String> {
+    match fs::read_to_string(script_path) {
+        Ok(c) => Some(c),
         Err(e) => {
             eprintln!("Error reading script '{}': {}", script_path, e);
             app.exit(1);
-            return;
+            None
         }
-    };
+    }
+}
+
+/// `Err` means the profile doesn't exist; the app is already exiting.
+fn find_profile(app: &AppHandle, profile_name: &Option<String>) -> Result<Option<Profile>, ()> {
+    match profile_name {
+        Some(name) => match commands::find_profile(name) {
+            Some(profile) => Ok(Some(profile)),
+            None => {
+                eprintln!("Profile '{}' not found", name);
+                app.exit(1);
+                Err(())
+            }
+        },
+        None => Ok(None),
+    }
+}
+
+/// Opens `--sink`, relative to the working directory; exits on error.
+fn open_sink(app: &AppHandle, sink: Option<SinkConfig>) -> Result<Option<Arc<OutputSink>>, ()> {
+    let Some(config) = sink else { return Ok(None) };
+    OutputSink::open(&config, Path::new(".")).map_err(|e| {
+        eprintln!("{}", e);
+        app.exit(1);
+    })
+}
 
-    // 2. Create hidden window
-    let label = format!("headless-{}", uuid::Uuid::new_v4());
-    let url = Url::parse("about:blank").unwrap();
-    let builder = WebviewWindowBuilder::new(
-        &app,
-        &label,
-        WebviewUrl::External(url)
-    )
-    .inner_size(1920.0, 1080.0)
-    .visible(false); // Hidden!
-
-    let window = match builder.build() {
-        Ok(w) => w,
+/// Checks the CLI params against the script's declarations; exits on error.
+fn resolve_params(app: &AppHandle, script: &str, given: Map<String, Value>) -> Option<Map<String, Value>> {
+    match params::resolve(script, given) {
+        Ok(params) => Some(params),
         Err(e) => {
-            eprintln!("Error creating headless window: {}", e);
+            eprintln!("{}", e);
+            app.exit(1);
+            None
+        }
+    }
+}
+
+pub fn run_headless_script(
+    app: AppHandle,
+    script_path: String,
+    output_path: Option<String>,
+    profile_name: Option<String>,
+    params: Map<String, Value>,
+    sink: Option<SinkConfig>,
+) {
+    // 1. Read script and check its params
+    let Some(content) = read_script(&app, &script_path) else { return };
+    let Some(params) = resolve_params(&app, &content, params) else { return };
+    let Ok(sink) = open_sink(&app, sink) else { return };
+
+    // 2. Queue it in the window pool, under the profile given on the command line
+    let Ok(profile) = find_profile(&app, &profile_name) else { return };
+
+    let state_handle = app.state::<Arc<Mutex<AppState>>>();
+    // We don't emit logs, so prints go to stdout
+    let job = PoolJob {
+        source: RunSource::Cli,
+        profile,
+        session: None,
+        script: content,
+        inputs: ScriptInputs { params: params::to_script_map(&params), input: None, output: sink, debug: None },
+        emit_logs: false,
+    };
+    let (run_id, done) = match pool::submit(&app, state_handle.inner(), job) {
+        Ok(submitted) => submitted,
+        Err(e) => {
+            eprintln!("{}", e);
             app.exit(1);
             return;
         }
     };
 
     // 3. Execute
-    let state_handle = app.state::<Arc<Mutex<AppState>>>();
-    let state = state_handle.inner().clone();
-
     let app_handle = app.clone();
-    std::thread::spawn(move || {
-        // We pass None for app_handle so scripting::execute uses println! for logging
-        let result = scripting::execute(content, window, state, None);
+    let state = state_handle.inner().clone();
+    tauri::async_runtime::spawn(async move {
+        let result = done.await.unwrap_or_else(|e| Err(e.to_string()));
 
         match result {
             Ok(val) => {
-                // Serialize result
-                // Rhai Dynamic -> Serde Json
-                // We need to ensure Dynamic implements Serialize or convert it.
-                // rhai "serde" feature is enabled in Cargo.toml?
-                // Let's check. Yes `features = ["sync"]`. "serde" is not explicitly enabled for rhai in Cargo.toml.
-                // Wait, I saw `rhai = { version = "1.23.6", features = ["sync"] }` in the read_file output.
-                // I need to add "serde" feature to rhai.
+                // Records pushed without --sink come first, then the return value's
+                let pushed = state.lock().unwrap().runs.records(&run_id).unwrap_or_default();
+                let records = |pushed: Vec<serde_json::Value>| {
+                    let mut records = pushed;
+                    records.extend(batch::records_from(serde_json::to_value(&val).unwrap_or_default()));
+                    records
+                };
 
-                let output = serde_json::to_string_pretty(&val).unwrap_or_else(|_| format!("{:?}", val));
+                // A .csv / .jsonl output gets the result as records, anything else as JSON
+                let output = match output_path.as_deref().map(|p| RecordFormat::from_path(Path::new(p))) {
+                    Some(format @ (RecordFormat::Csv | RecordFormat::Jsonl)) => {
+                        batch::format_records(&records(pushed), format).unwrap_or_default()
+                    }
+                    _ if !pushed.is_empty() => batch::format_records(&records(pushed), RecordFormat::Json).unwrap_or_default(),
+                    _ => serde_json::to_string_pretty(&val).unwrap_or_else(|_| format!("{:?}", val)),
+                };
 
                 if let Some(path) = output_path {
                     if let Err(e) = fs::write(&path, output) {
@@ -65,7 +140,8 @@ pub fn run_headless_script(app: AppHandle, script_path: String, output_path: Opt
                          app_handle.exit(0);
                     }
                 } else {
-                    println!("{}", output);
+                    // Like the script's prints, what reaches the console has its secrets masked
+                    println!("{}", vault::redact(&state.lock().unwrap(), &output));
                     app_handle.exit(0);
                 }
             },
@@ -76,3 +152,154 @@ pub fn run_headless_script(app: AppHandle, script_path: String, output_path: Opt
         }
     });
 }
+
+/// Runs the script once per row of `--input`. Results go to `--output` (CSV, JSONL or
+/// JSON by extension; JSONL on stdout by default) and failed rows to `--errors` as JSONL.
+/// Exits with 2 if any row failed.
+pub fn run_headless_batch(
+    app: AppHandle,
+    script_path: String,
+    output_path: Option<String>,
+    profile_name: Option<String>,
+    params: Map<String, Value>,
+    sink: Option<SinkConfig>,
+    args: BatchArgs,
+) {
+    let Some(script) = read_script(&app, &script_path) else { return };
+    let Some(params) = resolve_params(&app, &script, params) else { return };
+    let Ok(output) = open_sink(&app, sink) else { return };
+    let Ok(profile) = find_profile(&app, &profile_name) else { return };
+
+    let rows = fs::read_to_string(&args.input)
+        .map_err(|e| e.to_string())
+        .and_then(|content| batch::parse_rows(&content, RecordFormat::from_path(Path::new(&args.input))));
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("Error reading input '{}': {}", args.input, e);
+            app.exit(1);
+            return;
+        }
+    };
+
+    let state = app.state::<Arc<Mutex<AppState>>>().inner().clone();
+    let parallel = args.parallel.unwrap_or_else(|| state.lock().unwrap().pool.status().max_windows);
+    let spec = BatchSpec { source: RunSource::Cli, script, profile, rows, params, output, parallel, retries: args.retries };
+    let batch_id = batch::register(&state, &spec);
+    eprintln!("Running {} rows, {} at a time", spec.rows.len(), parallel);
+
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let outcome = batch::run(app_handle.clone(), state, batch_id, spec).await;
+
+        let format = output_path.as_deref().map_or(RecordFormat::Jsonl, |p| RecordFormat::from_path(Path::new(p)));
+        let output = match batch::format_records(&outcome.results, format) {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("Error formatting output: {}", e);
+                app_handle.exit(1);
+                return;
+            }
+        };
+        match &output_path {
+            Some(path) => {
+                if let Err(e) = fs::write(path, output) {
+                    eprintln!("Error writing output to '{}': {}", path, e);
+                    app_handle.exit(1);
+                    return;
+                }
+                eprintln!("Output written to {}", path);
+            }
+            None => print!("{}", output),
+        }
+
+        if !outcome.errors.is_empty() {
+            let errors: String = outcome
+                .errors
+                .iter()
+                .map(|e| format!("{}\n", serde_json::to_string(e).unwrap_or_default()))
+                .collect();
+            match &args.errors {
+                Some(path) => {
+                    if let Err(e) = fs::write(path, errors) {
+                        eprintln!("Error writing errors to '{}': {}", path, e);
+                    }
+                }
+                None => eprint!("{}", errors),
+            }
+        }
+
+        eprintln!("{} rows: {} succeeded, {} failed", outcome.total, outcome.succeeded, outcome.failed);
+        app_handle.exit(if outcome.failed == 0 { 0 } else { 2 });
+    });
+}
+
+/// `--repl`: evaluates statements from stdin one at a time in a hidden window, printing
+/// what each prints and returns. A line ending in `\` continues on the next one;
+/// `.exit` or the end of input quits.
+pub fn run_repl(app: AppHandle, profile_name: Option<String>) {
+    let Ok(profile) = find_profile(&app, &profile_name) else { return };
+    let state = app.state::<Arc<Mutex<AppState>>>().inner().clone();
+
+    tauri::async_runtime::spawn(async move {
+        let id = match repl::start_in_new_window(&app, &state, RunSource::Cli, profile).await {
+            Ok(id) => id,
+            Err(e) => {
+                eprintln!("{}", e);
+                app.exit(1);
+                return;
+            }
+        };
+        eprintln!("Type Rhai statements; `browser` drives the page. `.exit` quits.");
+
+        let (repl_state, repl_id) = (state.clone(), id.clone());
+        let _ = tauri::async_runtime::spawn_blocking(move || read_eval_print(&repl_state, &repl_id)).await;
+        let _ = repl::close(&app, &state, &id);
+        app.exit(0);
+    });
+}
+
+fn read_eval_print(state: &Arc<Mutex<AppState>>, id: &str) {
+    let mut code = String::new();
+    loop {
+        print!("{}", if code.is_empty() { "> " } else { ".. " });
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if code.is_empty() && line.trim() == ".exit" {
+            break;
+        }
+        if let Some(start) = line.strip_suffix('\\') {
+            code.push_str(start);
+            code.push('\n');
+            continue;
+        }
+        code.push_str(line);
+        if code.trim().is_empty() {
+            code.clear();
+            continue;
+        }
+
+        match repl::eval(state, id, &std::mem::take(&mut code)) {
+            Ok(output) => {
+                for printed in output.prints {
+                    println!("{}", printed);
+                }
+                match output.error {
+                    Some(e) => eprintln!("Error: {}", e),
+                    None if !output.display.is_empty() => println!("{}", output.display),
+                    None => {}
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                break;
+            }
+        }
+    }
+}
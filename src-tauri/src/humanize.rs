@@ -0,0 +1,499 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::fingerprint::SeedRng;
+
+/// Interval of pointer and wheel events while moving, about one per frame
+const FRAME_MS: f64 = 16.0;
+
+/// An element's box and the viewport, in CSS pixels, as input.js reports them.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Target {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub viewport_width: f64,
+    pub viewport_height: f64,
+    /// Whether the element is a field that already holds text
+    #[serde(default)]
+    pub filled: bool,
+}
+
+impl Target {
+    pub fn centre(&self) -> (f64, f64) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    /// Whether the element's middle is comfortably inside the viewport.
+    pub fn in_view(&self) -> bool {
+        let middle = self.y + self.height / 2.0;
+        middle >= self.viewport_height * 0.1 && middle <= self.viewport_height * 0.9
+    }
+}
+
+/// Modifier keys held during a key press.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+/// One thing the page does; `d` is the pause before it, in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "t", rename_all = "snake_case")]
+pub enum Step {
+    /// Pointer to a viewport position, with hover events when the element under it changes
+    Move { x: f64, y: f64, d: u64 },
+    /// Left button down where the pointer is; focuses what was pressed
+    Down { d: u64 },
+    /// Left button up, then `click` if it is still over the pressed element
+    Up { d: u64 },
+    /// A key press on the focused element, `hold` ms between keydown and keyup. `text` is
+    /// what the key inserts, if anything; Tab, Enter, Backspace, Delete and select all
+    /// also get their default action.
+    Key { key: String, code: String, text: Option<String>, modifiers: Modifiers, hold: u64, d: u64 },
+    /// A wheel event under the pointer, scrolling what it is over unless cancelled
+    Wheel { dy: f64, d: u64 },
+}
+
+/// Parses a key combination such as `"Enter"`, `"Shift+Tab"` or `"Control+A"` into a
+/// key press without delays. Fails with the part it doesn't recognise.
+pub fn press(combo: &str) -> Result<Step, String> {
+    let mut modifiers = Modifiers::default();
+    let mut parts: Vec<&str> = combo.split('+').map(str::trim).collect();
+    // "+" and "Control++" press the plus key
+    if combo == "+" || combo.ends_with("++") {
+        parts.truncate(parts.len() - 2);
+        parts.push("+");
+    }
+    let (name, held) = parts.split_last().ok_or_else(|| combo.to_string())?;
+    for modifier in held {
+        match modifier.to_ascii_lowercase().as_str() {
+            "control" | "ctrl" => modifiers.ctrl = true,
+            "alt" | "option" => modifiers.alt = true,
+            "shift" => modifiers.shift = true,
+            "meta" | "cmd" | "command" => modifiers.meta = true,
+            _ => return Err(modifier.to_string()),
+        }
+    }
+
+    let shortcut = modifiers.ctrl || modifiers.alt || modifiers.meta;
+    let mut chars = name.chars();
+    let (key, code, text) = match (chars.next(), chars.next()) {
+        (Some(c), None) if !c.is_control() => {
+            // "A" is Shift+a when typed, but Control+A is the shortcut on the `a` key
+            let c = if shortcut && !modifiers.shift { c.to_ascii_lowercase() } else { c };
+            modifiers.shift |= c.is_ascii_uppercase();
+            (c.to_string(), key_code(c), (!shortcut).then(|| c.to_string()))
+        }
+        _ if name.eq_ignore_ascii_case("space") => (" ".into(), "Space".into(), (!shortcut).then(|| " ".into())),
+        _ => {
+            let key = named_key(name).ok_or_else(|| name.to_string())?;
+            let text = (key == "Enter" && !shortcut).then(|| "\n".to_string());
+            (key.clone(), key, text)
+        }
+    };
+    Ok(Step::Key { key, code, text, modifiers, hold: 0, d: 0 })
+}
+
+/// The `KeyboardEvent.key` of a named key, matched case-insensitively.
+fn named_key(name: &str) -> Option<String> {
+    const KEYS: &[&str] = &[
+        "Enter", "Tab", "Escape", "Backspace", "Delete", "Insert", "Home", "End", "PageUp", "PageDown",
+        "ArrowUp", "ArrowDown", "ArrowLeft", "ArrowRight",
+    ];
+    let lower = name.to_ascii_lowercase();
+    let key = match lower.as_str() {
+        "esc" => "Escape",
+        "return" => "Enter",
+        "del" => "Delete",
+        "up" => "ArrowUp",
+        "down" => "ArrowDown",
+        "left" => "ArrowLeft",
+        "right" => "ArrowRight",
+        _ => {
+            if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()).filter(|n| (1..=12).contains(n)) {
+                return Some(format!("F{}", n));
+            }
+            KEYS.iter().find(|k| k.eq_ignore_ascii_case(name))?
+        }
+    };
+    Some(key.to_string())
+}
+
+/// How a profile moves and types. Fixed by the seed, so a profile keeps the same habits
+/// from run to run while each movement still varies.
+#[derive(Debug, Clone)]
+pub struct Persona {
+    /// Fitts's law `a + b * log2(distance / size + 1)`, in ms
+    fitts_a: f64,
+    fitts_b: f64,
+    /// Chance of overshooting a far target and correcting
+    overshoot: f64,
+    /// Median time between keystrokes, and the spread around it (log-normal sigma)
+    key_interval: f64,
+    key_spread: f64,
+    /// Keydown to keyup, in ms
+    key_hold: (f64, f64),
+    /// Chance of hitting a neighbouring key and correcting it, per character
+    typo_rate: f64,
+    /// Pixels one wheel flick scrolls
+    flick: f64,
+    /// Per-frame decay of a flick's speed
+    inertia: f64,
+}
+
+impl Persona {
+    pub fn from_seed(seed: u64) -> Self {
+        let mut rng = SeedRng::for_surface(seed, "input");
+        let hold_low = rng.range(45.0, 85.0);
+        Self {
+            fitts_a: rng.range(80.0, 160.0),
+            fitts_b: rng.range(90.0, 170.0),
+            overshoot: rng.range(0.1, 0.4),
+            key_interval: rng.range(110.0, 220.0),
+            key_spread: rng.range(0.25, 0.45),
+            key_hold: (hold_low, hold_low + rng.range(30.0, 60.0)),
+            typo_rate: rng.range(0.01, 0.04),
+            flick: rng.range(250.0, 550.0),
+            inertia: rng.range(0.82, 0.9),
+        }
+    }
+}
+
+/// Plans input for one window: the persona of its profile, a random stream for the
+/// variation between movements, and where the pointer was left. native_input delivers the
+/// steps to the webview.
+#[derive(Debug)]
+pub struct Humanizer {
+    persona: Persona,
+    rng: Mutex<SeedRng>,
+    pointer: Mutex<Option<(f64, f64)>>,
+    /// Apple platforms use Meta (Command) where others use Control, e.g. to select all
+    apple: bool,
+}
+
+impl Humanizer {
+    pub fn new(seed: u64, apple: bool) -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or_default();
+        Self {
+            persona: Persona::from_seed(seed),
+            rng: Mutex::new(SeedRng::for_surface(seed ^ nanos, "input-variation")),
+            pointer: Mutex::new(None),
+            apple,
+        }
+    }
+
+    /// Moves the pointer to a point inside the target, usually near its middle.
+    pub fn move_to(&self, target: &Target) -> Vec<Step> {
+        let mut rng = self.rng.lock().unwrap();
+        let mut pointer = self.pointer.lock().unwrap();
+        let start = pointer.unwrap_or_else(|| {
+            (rng.range(0.2, 0.8) * target.viewport_width, rng.range(0.2, 0.8) * target.viewport_height)
+        });
+        let end = (
+            target.x + target.width * (0.5 + centred(&mut rng) * 0.35),
+            target.y + target.height * (0.5 + centred(&mut rng) * 0.35),
+        );
+        let size = target.width.min(target.height).max(1.0);
+
+        let mut steps = Vec::new();
+        let distance = (end.0 - start.0).hypot(end.1 - start.1);
+        if distance > 150.0 && rng.next_float() < self.persona.overshoot {
+            let past = rng.range(0.03, 0.08);
+            let beyond = (end.0 + (end.0 - start.0) * past, end.1 + (end.1 - start.1) * past);
+            steps.extend(self.path(&mut rng, start, beyond, size));
+            steps.extend(self.path(&mut rng, beyond, end, size));
+        } else {
+            steps.extend(self.path(&mut rng, start, end, size));
+        }
+        *pointer = Some(end);
+        steps
+    }
+
+    /// A curved, eased movement: a cubic Bézier bent to one side, timed by Fitts's law.
+    fn path(&self, rng: &mut SeedRng, from: (f64, f64), to: (f64, f64), size: f64) -> Vec<Step> {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let distance = dx.hypot(dy);
+        if distance < 1.0 {
+            return Vec::new();
+        }
+        let duration = self.persona.fitts_a + self.persona.fitts_b * (distance / size + 1.0).log2();
+        let frames = (duration / FRAME_MS).round().max(2.0) as usize;
+
+        // Control points along the line, pushed off it by up to a third of the distance
+        let normal = (-dy / distance, dx / distance);
+        let bend = (distance * 0.3).min(200.0);
+        let control = |rng: &mut SeedRng, along: f64| {
+            let off = rng.range(-bend, bend);
+            (from.0 + dx * along + normal.0 * off, from.1 + dy * along + normal.1 * off)
+        };
+        let (along1, along2) = (rng.range(0.2, 0.4), rng.range(0.6, 0.8));
+        let c1 = control(rng, along1);
+        let c2 = control(rng, along2);
+
+        (1..=frames)
+            .map(|frame| {
+                // Minimum-jerk easing: slow start, fast middle, slow landing
+                let t = frame as f64 / frames as f64;
+                let s = t * t * t * (10.0 - 15.0 * t + 6.0 * t * t);
+                let (x, y) = bezier(from, c1, c2, to, s);
+                let jitter = if frame == frames { 0.0 } else { 0.6 };
+                Step::Move {
+                    x: round(x + rng.range(-jitter, jitter)),
+                    y: round(y + rng.range(-jitter, jitter)),
+                    d: (FRAME_MS * rng.range(0.8, 1.25)).round() as u64,
+                }
+            })
+            .collect()
+    }
+
+    /// Press and release where the pointer is.
+    pub fn click(&self) -> Vec<Step> {
+        let mut rng = self.rng.lock().unwrap();
+        let (low, high) = self.persona.key_hold;
+        vec![
+            Step::Down { d: rng.range(40.0, 140.0).round() as u64 },
+            Step::Up { d: rng.range(low, high).round() as u64 },
+        ]
+    }
+
+    /// Selects everything in the focused field, so what is typed next replaces it.
+    pub fn select_all(&self) -> Vec<Step> {
+        let combo = if self.apple { "Meta+A" } else { "Control+A" };
+        vec![self.press(combo).expect("a valid key combination")]
+    }
+
+    /// A key combination (see [`press`]) pressed at a person's pace.
+    pub fn press(&self, combo: &str) -> Result<Step, String> {
+        let mut rng = self.rng.lock().unwrap();
+        let mut step = press(combo)?;
+        if let Step::Key { hold, d, .. } = &mut step {
+            *hold = self.hold(&mut rng);
+            *d = rng.range(150.0, 350.0).round() as u64;
+        }
+        Ok(step)
+    }
+
+    /// Keystrokes for `text`, with uneven rhythm, longer pauses between words and the
+    /// odd mistyped key that gets deleted and retyped.
+    pub fn type_text(&self, text: &str) -> Vec<Step> {
+        let mut rng = self.rng.lock().unwrap();
+        let persona = &self.persona;
+        let mut steps = Vec::new();
+        let mut previous = ' ';
+
+        for c in text.chars() {
+            let mut delay = persona.key_interval * (persona.key_spread * normal(&mut rng)).exp();
+            if previous == ' ' || previous.is_ascii_punctuation() {
+                delay *= rng.range(1.1, 1.8);
+            }
+            if rng.next_float() < 0.02 {
+                delay += rng.range(400.0, 1200.0);
+            }
+
+            if c.is_ascii_alphanumeric() && rng.next_float() < persona.typo_rate {
+                if let Some(wrong) = neighbour(c, &mut rng) {
+                    steps.push(self.key(&mut rng, wrong, delay));
+                    let noticed = rng.range(180.0, 450.0);
+                    steps.push(self.named_key(&mut rng, "Backspace", noticed));
+                    delay = persona.key_interval * rng.range(0.9, 1.4);
+                }
+            }
+            steps.push(self.key(&mut rng, c, delay));
+            previous = c;
+        }
+        steps
+    }
+
+    fn hold(&self, rng: &mut SeedRng) -> u64 {
+        let (low, high) = self.persona.key_hold;
+        rng.range(low, high).round() as u64
+    }
+
+    fn key(&self, rng: &mut SeedRng, c: char, delay: f64) -> Step {
+        if c == '\n' {
+            return Step::Key {
+                key: "Enter".into(),
+                code: "Enter".into(),
+                text: Some("\n".into()),
+                modifiers: Modifiers::default(),
+                hold: self.hold(rng),
+                d: delay.round() as u64,
+            };
+        }
+        Step::Key {
+            key: c.to_string(),
+            code: key_code(c),
+            text: Some(c.to_string()),
+            modifiers: Modifiers {
+                shift: c.is_uppercase() || "~!@#$%^&*()_+{}|:\"<>?".contains(c),
+                ..Modifiers::default()
+            },
+            hold: self.hold(rng),
+            d: delay.round() as u64,
+        }
+    }
+
+    fn named_key(&self, rng: &mut SeedRng, key: &str, delay: f64) -> Step {
+        Step::Key {
+            key: key.into(),
+            code: key.into(),
+            text: None,
+            modifiers: Modifiers::default(),
+            hold: self.hold(rng),
+            d: delay.round() as u64,
+        }
+    }
+
+    /// Wheel events for `dy` pixels: flicks whose speed decays frame by frame, with short
+    /// pauses between them.
+    pub fn scroll(&self, dy: f64) -> Vec<Step> {
+        let mut rng = self.rng.lock().unwrap();
+        let persona = &self.persona;
+        let direction = dy.signum();
+        let mut remaining = dy.abs();
+        let mut steps = Vec::new();
+
+        while remaining >= 1.0 {
+            let flick = remaining.min(persona.flick * rng.range(0.7, 1.3));
+            let mut speed = flick * (1.0 - persona.inertia);
+            let mut scrolled = 0.0;
+            let mut d = if steps.is_empty() { rng.range(30.0, 90.0) } else { rng.range(120.0, 380.0) };
+            while flick - scrolled >= 1.0 {
+                let delta = speed.max(1.0).min(flick - scrolled).round();
+                steps.push(Step::Wheel { dy: direction * delta, d: d.round() as u64 });
+                scrolled += delta;
+                speed *= persona.inertia;
+                d = FRAME_MS * rng.range(0.85, 1.2);
+            }
+            remaining -= scrolled;
+        }
+        steps
+    }
+
+    /// Scroll distance that brings the target to a comfortable height in the viewport.
+    pub fn scroll_into_view(&self, target: &Target) -> f64 {
+        let mut rng = self.rng.lock().unwrap();
+        let middle = target.y + target.height / 2.0;
+        middle - target.viewport_height * rng.range(0.35, 0.6)
+    }
+}
+
+/// In `(-1, 1)`, more often near 0 (the sum of two uniforms).
+fn centred(rng: &mut SeedRng) -> f64 {
+    rng.next_float() + rng.next_float() - 1.0
+}
+
+/// Standard normal, by Box-Muller.
+fn normal(rng: &mut SeedRng) -> f64 {
+    let u = rng.next_float().max(f64::MIN_POSITIVE);
+    (-2.0 * u.ln()).sqrt() * (2.0 * PI * rng.next_float()).cos()
+}
+
+fn bezier(p0: (f64, f64), p1: (f64, f64), p2: (f64, f64), p3: (f64, f64), t: f64) -> (f64, f64) {
+    let u = 1.0 - t;
+    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+    (a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0, a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1)
+}
+
+fn round(v: f64) -> f64 {
+    (v * 10.0).round() / 10.0
+}
+
+/// `KeyboardEvent.code` of a character on a US layout; empty when there's no obvious one.
+fn key_code(c: char) -> String {
+    match c {
+        'a'..='z' | 'A'..='Z' => format!("Key{}", c.to_ascii_uppercase()),
+        '0'..='9' => format!("Digit{}", c),
+        ' ' => "Space".into(),
+        '.' => "Period".into(),
+        ',' => "Comma".into(),
+        '-' => "Minus".into(),
+        '@' => "Digit2".into(),
+        _ => String::new(),
+    }
+}
+
+/// A key next to `c` on a QWERTY keyboard, in the same case.
+fn neighbour(c: char, rng: &mut SeedRng) -> Option<char> {
+    const ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+    let lower = c.to_ascii_lowercase();
+    let (row, col) = ROWS.iter().enumerate().find_map(|(r, keys)| keys.find(lower).map(|i| (r, i)))?;
+    let keys: Vec<char> = ROWS[row].chars().collect();
+    let options: Vec<char> = [col.checked_sub(1), Some(col + 1)]
+        .into_iter()
+        .flatten()
+        .filter_map(|i| keys.get(i).copied())
+        .collect();
+    let wrong = *options.get(rng.below(options.len().max(1)))?;
+    Some(if c.is_ascii_uppercase() { wrong.to_ascii_uppercase() } else { wrong })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(step: Step) -> (String, String, Option<String>, Modifiers) {
+        match step {
+            Step::Key { key, code, text, modifiers, .. } => (key, code, text, modifiers),
+            other => panic!("not a key press: {:?}", other),
+        }
+    }
+
+    const NONE: Modifiers = Modifiers { ctrl: false, alt: false, shift: false, meta: false };
+    const CTRL: Modifiers = Modifiers { ctrl: true, ..NONE };
+    const SHIFT: Modifiers = Modifiers { shift: true, ..NONE };
+
+    #[test]
+    fn parses_key_combinations() {
+        // (combo, key, code, text, modifiers)
+        let cases = [
+            ("Enter", "Enter", "Enter", Some("\n"), NONE),
+            ("return", "Enter", "Enter", Some("\n"), NONE),
+            ("Meta+Enter", "Enter", "Enter", None, Modifiers { meta: true, ..NONE }),
+            ("Shift+Tab", "Tab", "Tab", None, SHIFT),
+            ("esc", "Escape", "Escape", None, NONE),
+            ("f5", "F5", "F5", None, NONE),
+            ("a", "a", "KeyA", Some("a"), NONE),
+            ("A", "A", "KeyA", Some("A"), SHIFT),
+            ("Control+A", "a", "KeyA", None, CTRL),
+            ("ctrl + shift + a", "a", "KeyA", None, Modifiers { ctrl: true, shift: true, ..NONE }),
+            ("Control+Shift+A", "A", "KeyA", None, Modifiers { ctrl: true, shift: true, ..NONE }),
+            ("Alt+1", "1", "Digit1", None, Modifiers { alt: true, ..NONE }),
+            ("space", " ", "Space", Some(" "), NONE),
+            ("Control+Space", " ", "Space", None, CTRL),
+            ("+", "+", "", Some("+"), NONE),
+            ("Control++", "+", "", None, CTRL),
+        ];
+        for (combo, k, code, text, modifiers) in cases {
+            assert_eq!(
+                key(press(combo).unwrap()),
+                (k.to_string(), code.to_string(), text.map(String::from), modifiers),
+                "{combo}"
+            );
+        }
+    }
+
+    #[test]
+    fn selects_all_with_the_platform_shortcut() {
+        for (apple, modifiers) in [(false, CTRL), (true, Modifiers { meta: true, ..NONE })] {
+            let steps = Humanizer::new(7, apple).select_all();
+            assert_eq!(steps.len(), 1);
+            let (k, code, text, held) = key(steps[0].clone());
+            assert_eq!((k.as_str(), code.as_str(), text, held), ("a", "KeyA", None, modifiers), "apple: {apple}");
+        }
+    }
+
+    #[test]
+    fn names_the_part_it_does_not_know() {
+        let cases = [("Hyper+A", "Hyper"), ("Control+Banana", "Banana"), ("F13", "F13"), ("", "")];
+        for (combo, unknown) in cases {
+            assert_eq!(press(combo).unwrap_err(), unknown, "{combo}");
+        }
+    }
+}
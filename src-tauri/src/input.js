@@ -0,0 +1,139 @@
+// Page side of the interactions: waits until an element is actionable and runs simple
+// operations on it. Pointer, keyboard and wheel input is native (see native_input.rs).
+// Finds elements with selector.js, which is loaded before it.
+(function () {
+    const helpers = window[HELPERS];
+    if (helpers.input) return;
+
+    const sleep = (ms) => new Promise(resolve => setTimeout(resolve, ms));
+
+    function fire(target, type, Ctor, init) {
+        const event = new Ctor(type, Object.assign({ bubbles: true, cancelable: true, composed: true, view: window }, init));
+        return target.dispatchEvent(event);
+    }
+
+    const NOT_TEXT = /^(checkbox|radio|file|button|submit|reset|image|range|color|hidden)$/;
+
+    function isTextField(el) {
+        return el && (el.tagName === 'TEXTAREA' || (el.tagName === 'INPUT' && !NOT_TEXT.test(el.type)));
+    }
+
+    function visible(el) {
+        const box = el.getBoundingClientRect();
+        if (box.width === 0 || box.height === 0) return false;
+        if (typeof el.checkVisibility === 'function') return el.checkVisibility({ visibilityProperty: true });
+        return getComputedStyle(el).visibility !== 'hidden';
+    }
+
+    function disabled(el) {
+        return el.matches(':disabled') || el.closest('[aria-disabled="true"]') !== null;
+    }
+
+    function target(el) {
+        const box = el.getBoundingClientRect();
+        return {
+            x: box.left, y: box.top, width: box.width, height: box.height,
+            viewport_width: window.innerWidth, viewport_height: window.innerHeight,
+            filled: isTextField(el) ? el.value.length > 0 : el.isContentEditable && el.textContent.length > 0,
+        };
+    }
+
+    // What keeps the element from passing `checks`, or null
+    function problem(el, checks) {
+        if (checks.includes('visible') && !visible(el)) return 'is not visible';
+        if ((checks.includes('enabled') || checks.includes('editable')) && disabled(el)) return 'is disabled';
+        if (checks.includes('editable') && !el.isContentEditable && (!isTextField(el) || el.readOnly)) {
+            return isTextField(el) ? 'is read-only' : 'is not an editable field';
+        }
+        return null;
+    }
+
+    async function moving(el) {
+        const before = el.getBoundingClientRect();
+        await sleep(50);
+        const after = el.getBoundingClientRect();
+        return before.x !== after.x || before.y !== after.y || before.width !== after.width || before.height !== after.height;
+    }
+
+    // Errors thrown by an operation finish the sentence "'<selector>' ..."
+    const invalid = (reason) => new Error(reason);
+
+    function checkable(el) {
+        if (el.tagName === 'INPUT' && (el.type === 'checkbox' || el.type === 'radio')) return el;
+        throw invalid('is not a checkbox or radio button');
+    }
+
+    const OPS = {
+        locate: (el) => target(el),
+        click: (el) => { el.click(); },
+        focus: (el) => {
+            el.focus();
+            if (!el.contains(document.activeElement)) throw invalid('cannot take focus');
+        },
+        clear: (el) => {
+            if (el.isContentEditable) {
+                el.textContent = '';
+                fire(el, 'input', InputEvent, { inputType: 'deleteContent', cancelable: false });
+                return;
+            }
+            el.value = '';
+            fire(el, 'input', InputEvent, { inputType: 'deleteContent', cancelable: false });
+            fire(el, 'change', Event, { composed: false });
+        },
+        select: (el, wanted) => {
+            if (el.tagName !== 'SELECT') throw invalid('is not a <select> element');
+            const options = Array.from(el.options);
+            const option = options.find(o => o.value === wanted) || options.find(o => o.label.trim() === wanted.trim());
+            if (!option) throw invalid(`has no option with value or label "${wanted}"`);
+            if (option.disabled) throw invalid(`has option "${wanted}" disabled`);
+            for (const o of options) o.selected = o === option;
+            fire(el, 'input', Event, { cancelable: false });
+            fire(el, 'change', Event, { composed: false });
+            return option.value;
+        },
+        checked: (el) => {
+            const box = checkable(el);
+            return { checked: box.checked, radio: box.type === 'radio' };
+        },
+        scroll: (el) => { el.scrollIntoView({ block: 'center', inline: 'nearest' }); },
+        files: (el, files) => {
+            if (el.tagName !== 'INPUT' || el.type !== 'file') throw invalid('is not a file input');
+            if (files.length > 1 && !el.multiple) throw invalid('accepts a single file');
+            const transfer = new DataTransfer();
+            for (const file of files) {
+                const bytes = Uint8Array.from(atob(file.data), c => c.charCodeAt(0));
+                transfer.items.add(new File([bytes], file.name, { type: file.type, lastModified: file.modified }));
+            }
+            el.files = transfer.files;
+            fire(el, 'input', Event, { cancelable: false });
+            fire(el, 'change', Event, { composed: false });
+        },
+    };
+
+    // Polls until the element exists and passes `checks`, then runs the operation on it.
+    // Answers {ok}, {waiting: reason} when time ran out, or {invalid: reason} when the
+    // operation doesn't apply to the element.
+    async function act(selector, checks, timeout, op, arg) {
+        const until = Date.now() + timeout;
+        for (;;) {
+            // A malformed selector throws, failing the call
+            const el = helpers.selector.query(selector);
+            let reason = el ? problem(el, checks) : 'matches no element';
+            if (!reason && checks.includes('stable') && await moving(el)) reason = 'is not stable (still moving)';
+            if (!reason) {
+                try {
+                    const value = OPS[op](el, arg);
+                    return { ok: value === undefined ? null : value };
+                } catch (e) {
+                    return { invalid: e.message };
+                }
+            }
+            if (Date.now() >= until) return { waiting: reason };
+            await sleep(100);
+        }
+    }
+
+    helpers.input = {
+        act: act,
+    };
+})();
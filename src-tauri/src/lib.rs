@@ -2,12 +2,39 @@ mod state;
 mod scripting;
 mod commands;
 mod proxy;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod upstream;
 mod fingerprint;
+mod client_hints;
 mod headless;
 mod recorder;
 mod scheduler;
 mod assets;
 mod server;
+mod selftest;
+mod templates;
+mod storage;
+mod cookies;
+mod session;
+mod workspace;
+mod error;
+mod profiles;
+mod crypto;
+mod bundle;
+mod vault;
+mod windows;
+mod runs;
+mod pool;
+mod batch;
+mod params;
+mod output;
+mod modules;
+mod policy;
+mod validate;
+mod debugger;
+mod repl;
+mod humanize;
+mod native_input;
 
 use state::AppState;
 use std::sync::{Arc, Mutex};
@@ -35,6 +62,70 @@ struct Cli {
 
     #[arg(long, env = "PHANTOM_API_TOKEN")]
     api_token: Option<String>,
+
+    /// Load the local fingerprint probe under --profile and report mismatches
+    #[arg(long)]
+    selftest: bool,
+
+    /// Directory for profiles, scripts, jobs and browser data (default: the app data dir)
+    #[arg(long, env = "PHANTOM_WORKSPACE")]
+    workspace: Option<std::path::PathBuf>,
+
+    /// Unlocks the secret vault at startup
+    #[arg(long, env = "PHANTOM_VAULT_PASSPHRASE", hide_env_values = true)]
+    vault_passphrase: Option<String>,
+
+    /// Unlocks the secret vault with the contents of a key file instead of a passphrase
+    #[arg(long, env = "PHANTOM_VAULT_KEY_FILE")]
+    vault_key_file: Option<std::path::PathBuf>,
+
+    /// Hidden windows that run API, scheduled and headless jobs in parallel
+    #[arg(long, env = "PHANTOM_MAX_WINDOWS", default_value_t = 4)]
+    max_windows: usize,
+
+    /// Jobs that may wait for a free window before new ones are refused
+    #[arg(long, env = "PHANTOM_MAX_QUEUE", default_value_t = 100)]
+    max_queue: usize,
+
+    /// Run --script once per row of this CSV or JSONL file, with the row as `input`
+    #[arg(long, requires = "script")]
+    input: Option<String>,
+
+    /// Rows processed at once (default: --max-windows)
+    #[arg(long, requires = "input")]
+    parallel: Option<usize>,
+
+    /// Extra attempts for a failing row
+    #[arg(long, requires = "input", default_value_t = 0)]
+    retries: u32,
+
+    /// Write failed rows with their errors to this JSONL file
+    #[arg(long, requires = "input")]
+    errors: Option<String>,
+
+    /// Script parameter as key=value; repeatable
+    #[arg(long = "param", requires = "script")]
+    params: Vec<String>,
+
+    /// JSON object of script parameters; --param values override it
+    #[arg(long, requires = "script")]
+    params_file: Option<String>,
+
+    /// Check --script for errors without running it; exits with 1 if it has any
+    #[arg(long, requires = "script")]
+    check: bool,
+
+    /// Stream `output.push` records to this .jsonl, .csv or .db/.sqlite file (appending)
+    #[arg(long, requires = "script")]
+    sink: Option<String>,
+
+    /// Evaluate statements from stdin one at a time in a hidden window (implies --headless)
+    #[arg(long, conflicts_with_all = ["script", "selftest"])]
+    repl: bool,
+
+    /// Table for a SQLite --sink
+    #[arg(long, requires = "sink")]
+    sink_table: Option<String>,
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -44,17 +135,37 @@ pub fn run() {
     // though usually tauri doesn't.
     let cli = Cli::parse();
 
+    if cli.check {
+        let path = cli.script.clone().unwrap_or_default();
+        std::process::exit(validate::check_file(&path));
+    }
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_http::init())
         .setup(move |app| {
-            app.manage(Arc::new(Mutex::new(AppState::default())));
+            app.manage(Arc::new(Mutex::new(AppState {
+                pool: Arc::new(pool::WindowPool::new(pool::PoolConfig {
+                    max_windows: cli.max_windows,
+                    max_queue: cli.max_queue,
+                })),
+                ..AppState::default()
+            })));
+
+            workspace::init(app.handle(), cli.workspace.clone())?;
+            proxy::init_ca();
+            vault::unlock_from_cli(
+                app.state::<Arc<Mutex<AppState>>>().inner(),
+                cli.vault_passphrase.clone(),
+                cli.vault_key_file.clone(),
+            );
 
             // Extract example scripts
             assets::extract_examples(app.handle());
+            assets::extract_templates(app.handle());
 
-            if !cli.headless {
+            if !cli.headless && !cli.repl {
                 let _ = tauri::WebviewWindowBuilder::new(
                     app,
                     "main",
@@ -65,9 +176,43 @@ pub fn run() {
                 .build()?;
             } else {
                  // Headless mode
-                 if let Some(script_path) = &cli.script {
-                     println!("Running script in headless mode: {}", script_path);
-                     headless::run_headless_script(app.handle().clone(), script_path.clone(), cli.output.clone());
+                 if cli.selftest {
+                     let handle = app.handle().clone();
+                     let profile = cli.profile.clone();
+                     let output = cli.output.clone();
+                     tauri::async_runtime::spawn(async move {
+                         selftest::run_cli(handle, profile, output).await;
+                     });
+                 } else if cli.repl {
+                     headless::run_repl(app.handle().clone(), cli.profile.clone());
+                 } else if let Some(script_path) = &cli.script {
+                     let params = params::from_cli(&cli.params, cli.params_file.as_deref());
+                     let sink = cli
+                         .sink
+                         .as_deref()
+                         .map(|path| output::SinkConfig::from_path(path, cli.sink_table.clone()))
+                         .transpose()
+                         .map_err(|e| e.to_string());
+                     let (params, sink) = match (params, sink) {
+                         (Ok(params), Ok(sink)) => (params, sink),
+                         (Err(e), _) | (_, Err(e)) => {
+                             eprintln!("{}", e);
+                             app.handle().exit(1);
+                             return Ok(());
+                         }
+                     };
+                     if let Some(input) = &cli.input {
+                         let args = headless::BatchArgs {
+                             input: input.clone(),
+                             errors: cli.errors.clone(),
+                             parallel: cli.parallel,
+                             retries: cli.retries,
+                         };
+                         headless::run_headless_batch(app.handle().clone(), script_path.clone(), cli.output.clone(), cli.profile.clone(), params, sink, args);
+                     } else {
+                         println!("Running script in headless mode: {}", script_path);
+                         headless::run_headless_script(app.handle().clone(), script_path.clone(), cli.output.clone(), cli.profile.clone(), params, sink);
+                     }
                  }
             }
 
@@ -103,7 +248,46 @@ pub fn run() {
             recorder::recorder_event,
             scheduler::list_jobs,
             scheduler::save_job,
-            scheduler::delete_job
+            scheduler::delete_job,
+            selftest::run_selftest,
+            templates::list_templates,
+            templates::generate_profiles,
+            storage::wipe_profile_storage,
+            storage::clone_profile_storage,
+            cookies::get_profile_cookies,
+            cookies::clear_profile_cookies,
+            cookies::export_profile_cookies,
+            cookies::import_profile_cookies,
+            session::list_sessions,
+            session::delete_session,
+            session::save_session,
+            session::restore_session,
+            workspace::get_workspace,
+            profiles::create_profile,
+            profiles::delete_profile,
+            profiles::clone_profile,
+            profiles::rename_profile,
+            bundle::export_profile_bundle,
+            bundle::import_profile_bundle,
+            vault::vault_status,
+            vault::unlock_vault,
+            vault::lock_vault,
+            vault::set_secret,
+            vault::delete_secret,
+            vault::store_proxy_password,
+            runs::list_runs,
+            runs::get_run,
+            runs::get_run_records,
+            pool::get_pool_status,
+            params::get_script_params,
+            policy::get_script_policy,
+            policy::save_script_policy,
+            validate::validate_script,
+            debugger::debug_action,
+            debugger::set_breakpoints,
+            repl::repl_start,
+            repl::repl_eval,
+            repl::repl_close
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
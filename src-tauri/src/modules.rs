@@ -0,0 +1,208 @@
+use rhai::{Engine, EvalAltResult, Module, ModuleResolver, Position, Scope, Shared, AST};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use crate::workspace;
+
+/// Compiled modules by file, reused across runs until the file changes.
+static AST_CACHE: OnceLock<Mutex<HashMap<PathBuf, (SystemTime, AST)>>> = OnceLock::new();
+
+/// A path inside the scripts folder, refusing anything that could leave it.
+pub fn script_path(relative: &str) -> Result<PathBuf, String> {
+    let path = Path::new(relative);
+    if relative.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(format!("Invalid script path '{}'", relative));
+    }
+    Ok(workspace::scripts_dir().join(path))
+}
+
+/// Every file under the scripts folder, as `/`-separated paths relative to it.
+pub fn list_scripts() -> Vec<String> {
+    fn walk(dir: &Path, prefix: &str, out: &mut Vec<String>) {
+        let Ok(entries) = fs::read_dir(dir) else { return };
+        for entry in entries.flatten() {
+            let Ok(name) = entry.file_name().into_string() else { continue };
+            let relative = format!("{}{}", prefix, name);
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => walk(&entry.path(), &format!("{}/", relative), out),
+                Ok(_) => out.push(relative),
+                Err(_) => {}
+            }
+        }
+    }
+
+    let mut scripts = Vec::new();
+    walk(&workspace::scripts_dir(), "", &mut scripts);
+    scripts.sort();
+    scripts
+}
+
+fn compile(engine: &Engine, file: &Path, name: &str) -> Result<AST, Box<EvalAltResult>> {
+    let cache = AST_CACHE.get_or_init(Default::default);
+    let modified = fs::metadata(file).and_then(|m| m.modified()).ok();
+    if let Some(modified) = modified {
+        if let Some((at, ast)) = cache.lock().unwrap().get(file) {
+            if *at == modified {
+                return Ok(ast.clone());
+            }
+        }
+    }
+
+    let text = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let mut ast = engine.compile(&text)?;
+    ast.set_source(name);
+    if let Some(modified) = modified {
+        cache.lock().unwrap().insert(file.to_path_buf(), (modified, ast.clone()));
+    }
+    Ok(ast)
+}
+
+/// Resolves `import "lib/login" as login;` to `scripts/lib/login.rhai`. Imports can't
+/// leave the scripts folder, each module is evaluated once per run, and import cycles
+/// are reported instead of recursing.
+pub struct ScriptResolver {
+    root: PathBuf,
+    loaded: Mutex<HashMap<PathBuf, Shared<Module>>>,
+    /// Imports being evaluated, outermost first
+    loading: Mutex<Vec<(PathBuf, String)>>,
+}
+
+impl Default for ScriptResolver {
+    fn default() -> Self {
+        Self::new(workspace::scripts_dir())
+    }
+}
+
+impl ScriptResolver {
+    fn new(root: PathBuf) -> Self {
+        Self { root, loaded: Mutex::new(HashMap::new()), loading: Mutex::new(Vec::new()) }
+    }
+
+    fn locate(&self, path: &str, pos: Position) -> Result<PathBuf, Box<EvalAltResult>> {
+        let denied = |reason: &str| EvalAltResult::ErrorInModule(path.to_string(), reason.into(), pos);
+        let relative = Path::new(path);
+        if path.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(denied("imports must be relative paths inside the scripts folder").into());
+        }
+
+        let mut file = self.root.join(relative);
+        if file.extension().is_none() {
+            file.set_extension("rhai");
+        }
+        let file = file
+            .canonicalize()
+            .map_err(|_| EvalAltResult::ErrorModuleNotFound(path.to_string(), pos))?;
+        // A symlink could still point elsewhere
+        let root = self.root.canonicalize().map_err(|e| e.to_string())?;
+        if !file.starts_with(&root) {
+            return Err(denied("the module is outside the scripts folder").into());
+        }
+        Ok(file)
+    }
+}
+
+impl ModuleResolver for ScriptResolver {
+    fn resolve(&self, engine: &Engine, _source: Option<&str>, path: &str, pos: Position) -> Result<Shared<Module>, Box<EvalAltResult>> {
+        let file = self.locate(path, pos)?;
+        if let Some(module) = self.loaded.lock().unwrap().get(&file) {
+            return Ok(module.clone());
+        }
+
+        {
+            let mut loading = self.loading.lock().unwrap();
+            if loading.iter().any(|(f, _)| *f == file) {
+                let chain: Vec<&str> = loading.iter().map(|(_, name)| name.as_str()).chain([path]).collect();
+                let reason = format!("import cycle: {}", chain.join(" -> "));
+                return Err(EvalAltResult::ErrorInModule(path.to_string(), reason.into(), pos).into());
+            }
+            loading.push((file.clone(), path.to_string()));
+        }
+        let result = compile(engine, &file, path).and_then(|ast| Module::eval_ast_as_new(Scope::new(), &ast, engine));
+        self.loading.lock().unwrap().pop();
+
+        let module: Shared<Module> = result
+            .map_err(|e| EvalAltResult::ErrorInModule(path.to_string(), e, pos))?
+            .into();
+        self.loaded.lock().unwrap().insert(file, module.clone());
+        Ok(module)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A fresh `scripts` folder, with a file next to it that imports must not reach.
+    fn scripts_root(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("phantom-modules-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("scripts/lib")).unwrap();
+        fs::write(dir.join("secret.rhai"), "export const leaked = true;").unwrap();
+        dir.join("scripts")
+    }
+
+    fn run(root: &Path, script: &str) -> Result<i64, Box<EvalAltResult>> {
+        let mut engine = Engine::new();
+        engine.set_module_resolver(ScriptResolver::new(root.to_path_buf()));
+        engine.eval::<i64>(script)
+    }
+
+    #[test]
+    fn imports_cannot_leave_the_scripts_folder() {
+        let root = scripts_root("escape");
+        let outside = root.parent().unwrap().join("secret.rhai");
+        for path in ["../secret", "lib/../../secret", outside.to_str().unwrap(), ""] {
+            let error = run(&root, &format!("import {:?} as m; 1", path)).unwrap_err();
+            assert!(error.to_string().contains("relative paths inside the scripts folder"), "{}: {}", path, error);
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&outside, root.join("lib/link.rhai")).unwrap();
+            let error = run(&root, r#"import "lib/link" as m; 1"#).unwrap_err();
+            assert!(error.to_string().contains("outside the scripts folder"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn import_cycles_are_reported() {
+        let root = scripts_root("cycle");
+        fs::write(root.join("a.rhai"), r#"import "lib/b" as b; export const x = 1;"#).unwrap();
+        fs::write(root.join("lib/b.rhai"), r#"import "a" as a; export const y = 2;"#).unwrap();
+        let error = run(&root, r#"import "a" as a; a::x"#).unwrap_err();
+        assert!(error.to_string().contains("import cycle: a -> lib/b -> a"), "{}", error);
+    }
+
+    #[test]
+    fn imports_default_to_the_rhai_extension() {
+        let root = scripts_root("extension");
+        fs::write(root.join("lib/math.rhai"), "export const answer = 42; fn double(x) { x * 2 }").unwrap();
+        assert_eq!(run(&root, r#"import "lib/math" as m; m::answer"#).unwrap(), 42);
+        assert_eq!(run(&root, r#"import "lib/math.rhai" as m; m::double(m::answer)"#).unwrap(), 84);
+        let error = run(&root, r#"import "lib/missing" as m; 1"#).unwrap_err();
+        assert!(error.to_string().contains("Module not found"), "{}", error);
+    }
+
+    #[test]
+    fn edited_modules_are_reread() {
+        let root = scripts_root("cache");
+        let file = root.join("value.rhai");
+        let set = |text: &str, modified: SystemTime| {
+            fs::write(&file, text).unwrap();
+            fs::File::options().write(true).open(&file).unwrap().set_modified(modified).unwrap();
+        };
+        let import = r#"import "value" as v; v::value"#;
+        let before = SystemTime::now() - Duration::from_secs(60);
+
+        set("export const value = 1;", before);
+        assert_eq!(run(&root, import).unwrap(), 1);
+        // Compiled modules are kept while the modification time stays the same
+        set("export const value = 2;", before);
+        assert_eq!(run(&root, import).unwrap(), 1);
+        set("export const value = 2;", SystemTime::now());
+        assert_eq!(run(&root, import).unwrap(), 2);
+    }
+}
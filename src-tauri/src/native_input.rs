@@ -0,0 +1,601 @@
+use std::sync::mpsc::channel;
+use std::time::Duration;
+use tauri::webview::PlatformWebview;
+use tauri::WebviewWindow;
+use crate::humanize::{Modifiers, Step};
+
+/// How long to wait for the main thread to take an event
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One event for the webview's own input handling, in CSS pixels of the viewport.
+/// Delivered this way, the page sees it as real input (`isTrusted` is true).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    Move { x: f64, y: f64, pressed: bool },
+    /// The left button
+    Button { x: f64, y: f64, down: bool },
+    /// `modifiers` are the ones held while the key goes down or up
+    Key { key: String, code: String, text: Option<String>, modifiers: Modifiers, down: bool },
+    Wheel { x: f64, y: f64, dy: f64 },
+}
+
+/// Where the pointer is, so button and wheel input lands there.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pointer {
+    x: f64,
+    y: f64,
+    pressed: bool,
+}
+
+const MODIFIER_KEYS: [(&str, &str); 4] =
+    [("Control", "ControlLeft"), ("Alt", "AltLeft"), ("Shift", "ShiftLeft"), ("Meta", "MetaLeft")];
+
+/// The flag of a modifier key (see `MODIFIER_KEYS`).
+fn flag<'a>(modifiers: &'a mut Modifiers, key: &str) -> &'a mut bool {
+    match key {
+        "Control" => &mut modifiers.ctrl,
+        "Alt" => &mut modifiers.alt,
+        "Shift" => &mut modifiers.shift,
+        _ => &mut modifiers.meta,
+    }
+}
+
+/// Whether the event presses A with the select-all modifier of the other kind of platform:
+/// Control here on macOS, Meta elsewhere. Profiles press their own platform's shortcut,
+/// so the platform code selects all in the host webview's place.
+fn foreign_select_all(input: &Input) -> bool {
+    let Input::Key { key, modifiers, down: true, .. } = input else { return false };
+    let (own, foreign) = if cfg!(target_os = "macos") {
+        (modifiers.meta, modifiers.ctrl)
+    } else {
+        (modifiers.ctrl, modifiers.meta)
+    };
+    key.eq_ignore_ascii_case("a") && foreign && !own && !modifiers.alt
+}
+
+/// The events of a step, each with the pause before it in ms. A key press holds its
+/// modifiers down around it, the way a keyboard sends them.
+pub fn events(step: &Step, pointer: &mut Pointer) -> Vec<(u64, Input)> {
+    match step {
+        Step::Move { x, y, d } => {
+            pointer.x = *x;
+            pointer.y = *y;
+            vec![(*d, Input::Move { x: *x, y: *y, pressed: pointer.pressed })]
+        }
+        Step::Down { d } | Step::Up { d } => {
+            pointer.pressed = matches!(step, Step::Down { .. });
+            vec![(*d, Input::Button { x: pointer.x, y: pointer.y, down: pointer.pressed })]
+        }
+        Step::Wheel { dy, d } => vec![(*d, Input::Wheel { x: pointer.x, y: pointer.y, dy: *dy })],
+        Step::Key { key, code, text, modifiers, hold, d } => {
+            let modifier = |name: &str, code: &str, held: Modifiers, down: bool| Input::Key {
+                key: name.to_string(),
+                code: code.to_string(),
+                text: None,
+                modifiers: held,
+                down,
+            };
+            let mut wanted = *modifiers;
+            let keys: Vec<_> = MODIFIER_KEYS.iter().filter(|(name, _)| *flag(&mut wanted, name)).collect();
+            let mut events = Vec::new();
+            let mut held = Modifiers::default();
+            for (name, code) in &keys {
+                events.push((0, modifier(name, code, held, true)));
+                *flag(&mut held, name) = true;
+            }
+            let pressed = |down| Input::Key {
+                key: key.clone(),
+                code: code.clone(),
+                text: if down { text.clone() } else { None },
+                modifiers: *modifiers,
+                down,
+            };
+            events.push((0, pressed(true)));
+            events.push((*hold, pressed(false)));
+            for (name, code) in keys.iter().rev() {
+                events.push((0, modifier(name, code, held, false)));
+                *flag(&mut held, name) = false;
+            }
+            events[0].0 = *d;
+            events
+        }
+    }
+}
+
+/// Hands the event to the webview on the main thread and waits until it was taken.
+pub fn deliver(window: &WebviewWindow, input: Input) -> Result<(), String> {
+    let (tx, rx) = channel();
+    window
+        .with_webview(move |webview| {
+            let _ = tx.send(platform::deliver(&webview, &input));
+        })
+        .map_err(|e| e.to_string())?;
+    rx.recv_timeout(DELIVERY_TIMEOUT)
+        .map_err(|_| "the window did not take the input".to_string())?
+}
+
+/// WebKitGTK: GDK events sent to the web view widget, as the windowing system would.
+#[cfg(target_os = "linux")]
+mod platform {
+    use gtk::gdk::{self, ffi};
+    use gtk::glib::{self, translate::{ToGlibPtr, ToGlibPtrMut}};
+    use gtk::prelude::*;
+    use std::ffi::CString;
+    use webkit2gtk::WebViewExt;
+    use super::{Input, PlatformWebview};
+    use crate::humanize::Modifiers;
+
+    /// WebKitGTK scrolls this many pixels per unit of smooth scroll delta
+    const PIXELS_PER_SCROLL_UNIT: f64 = 40.0;
+
+    fn state(modifiers: &Modifiers) -> ffi::GdkModifierType {
+        let mut state = 0;
+        if modifiers.shift {
+            state |= ffi::GDK_SHIFT_MASK;
+        }
+        if modifiers.ctrl {
+            state |= ffi::GDK_CONTROL_MASK;
+        }
+        if modifiers.alt {
+            state |= ffi::GDK_MOD1_MASK;
+        }
+        if modifiers.meta {
+            state |= ffi::GDK_SUPER_MASK;
+        }
+        state
+    }
+
+    /// X keysym name of a named key.
+    fn keysym(key: &str, modifiers: &Modifiers) -> Option<String> {
+        let name = match key {
+            "Enter" => "Return",
+            "Backspace" => "BackSpace",
+            "Tab" if modifiers.shift => "ISO_Left_Tab",
+            "PageUp" => "Page_Up",
+            "PageDown" => "Page_Down",
+            "ArrowUp" => "Up",
+            "ArrowDown" => "Down",
+            "ArrowLeft" => "Left",
+            "ArrowRight" => "Right",
+            "Control" => "Control_L",
+            "Shift" => "Shift_L",
+            "Alt" => "Alt_L",
+            "Meta" => "Super_L",
+            _ => key,
+        };
+        Some(name.to_string()).filter(|n| n.len() > 1)
+    }
+
+    fn keyval(key: &str, modifiers: &Modifiers) -> u32 {
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => unsafe { ffi::gdk_unicode_to_keyval(c as u32) },
+            _ => keysym(key, modifiers)
+                .and_then(|name| CString::new(name).ok())
+                .map_or(0, |name| unsafe { ffi::gdk_keyval_from_name(name.as_ptr()) }),
+        }
+    }
+
+    pub fn deliver(webview: &PlatformWebview, input: &Input) -> Result<(), String> {
+        let view = webview.inner();
+        let window = view.window().ok_or("the webview is not shown yet")?;
+        let seat = window.display().default_seat().ok_or("there is no input seat")?;
+        let (_, origin_x, origin_y) = window.origin();
+        let time = (glib::monotonic_time() / 1000) as u32;
+
+        let mut event = match input {
+            Input::Move { .. } | Input::Button { .. } | Input::Wheel { .. } => {
+                let kind = match input {
+                    Input::Move { .. } => gdk::EventType::MotionNotify,
+                    Input::Button { down: true, .. } => gdk::EventType::ButtonPress,
+                    Input::Button { .. } => gdk::EventType::ButtonRelease,
+                    _ => gdk::EventType::Scroll,
+                };
+                let mut event = gdk::Event::new(kind);
+                event.set_device(seat.pointer().as_ref());
+                event
+            }
+            Input::Key { down, .. } => {
+                let mut event = gdk::Event::new(if *down { gdk::EventType::KeyPress } else { gdk::EventType::KeyRelease });
+                event.set_device(seat.keyboard().as_ref());
+                event
+            }
+        };
+
+        // SAFETY: each arm writes the fields of the event type it was created with
+        unsafe {
+            let raw: *mut ffi::GdkEvent = event.to_glib_none_mut().0;
+            (*raw).any.window = window.to_glib_full();
+            (*raw).any.send_event = 0;
+            match *input {
+                Input::Move { x, y, pressed } => {
+                    let motion = &mut (*raw).motion;
+                    motion.time = time;
+                    (motion.x, motion.y) = (x, y);
+                    (motion.x_root, motion.y_root) = (x + origin_x as f64, y + origin_y as f64);
+                    motion.state = if pressed { ffi::GDK_BUTTON1_MASK } else { 0 };
+                }
+                Input::Button { x, y, down } => {
+                    let button = &mut (*raw).button;
+                    button.time = time;
+                    (button.x, button.y) = (x, y);
+                    (button.x_root, button.y_root) = (x + origin_x as f64, y + origin_y as f64);
+                    button.button = 1;
+                    button.state = if down { 0 } else { ffi::GDK_BUTTON1_MASK };
+                }
+                Input::Wheel { x, y, dy } => {
+                    let scroll = &mut (*raw).scroll;
+                    scroll.time = time;
+                    (scroll.x, scroll.y) = (x, y);
+                    (scroll.x_root, scroll.y_root) = (x + origin_x as f64, y + origin_y as f64);
+                    scroll.direction = ffi::GDK_SCROLL_SMOOTH;
+                    scroll.delta_y = dy / PIXELS_PER_SCROLL_UNIT;
+                }
+                Input::Key { ref key, ref modifiers, .. } => {
+                    let keyval = keyval(key, modifiers);
+                    if keyval == 0 {
+                        return Err(format!("no key for '{}'", key));
+                    }
+                    let entry = gdk::Keymap::for_display(&window.display())
+                        .and_then(|keymap| keymap.entries_for_keyval(keyval).into_iter().next());
+                    let event_key = &mut (*raw).key;
+                    event_key.time = time;
+                    event_key.keyval = keyval;
+                    event_key.state = state(modifiers);
+                    event_key.hardware_keycode = entry.as_ref().map_or(0, |e| e.keycode() as u16);
+                    event_key.group = entry.as_ref().map_or(0, |e| e.group() as u8);
+                    event_key.is_modifier = super::MODIFIER_KEYS.iter().any(|(name, _)| name == key) as u32;
+                }
+            }
+        }
+
+        if matches!(input, Input::Key { down: true, .. }) && !view.has_focus() {
+            view.grab_focus();
+        }
+        view.event(&event);
+        if super::foreign_select_all(input) {
+            view.execute_editing_command("SelectAll");
+        }
+        Ok(())
+    }
+}
+
+/// WebView2: the DevTools protocol's input methods, which go through Chromium's input pipeline.
+#[cfg(windows)]
+mod platform {
+    use serde_json::{json, Value};
+    use webview2_com::CallDevToolsProtocolMethodCompletedHandler;
+    use windows::core::HSTRING;
+    use super::{Input, PlatformWebview};
+    use crate::humanize::Modifiers;
+
+    fn modifier_bits(modifiers: &Modifiers) -> u8 {
+        modifiers.alt as u8 | (modifiers.ctrl as u8) << 1 | (modifiers.meta as u8) << 2 | (modifiers.shift as u8) << 3
+    }
+
+    /// The Windows virtual-key code of a key, which drives Chromium's default actions.
+    fn virtual_key(key: &str) -> u16 {
+        let mut chars = key.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase() as u16,
+                ' ' => 0x20,
+                ',' => 0xBC,
+                '-' => 0xBD,
+                '.' => 0xBE,
+                _ => 0,
+            };
+        }
+        match key {
+            "Backspace" => 0x08,
+            "Tab" => 0x09,
+            "Enter" => 0x0D,
+            "Shift" => 0x10,
+            "Control" => 0x11,
+            "Alt" => 0x12,
+            "Escape" => 0x1B,
+            "PageUp" => 0x21,
+            "PageDown" => 0x22,
+            "End" => 0x23,
+            "Home" => 0x24,
+            "ArrowLeft" => 0x25,
+            "ArrowUp" => 0x26,
+            "ArrowRight" => 0x27,
+            "ArrowDown" => 0x28,
+            "Insert" => 0x2D,
+            "Delete" => 0x2E,
+            "Meta" => 0x5B,
+            _ => key.strip_prefix('F').and_then(|n| n.parse::<u16>().ok()).map_or(0, |n| 0x6F + n),
+        }
+    }
+
+    fn method(input: &Input) -> (&'static str, Value) {
+        match input {
+            Input::Move { x, y, pressed } => (
+                "Input.dispatchMouseEvent",
+                json!({ "type": "mouseMoved", "x": x, "y": y, "button": if *pressed { "left" } else { "none" }, "buttons": *pressed as u8 }),
+            ),
+            Input::Button { x, y, down } => (
+                "Input.dispatchMouseEvent",
+                json!({
+                    "type": if *down { "mousePressed" } else { "mouseReleased" },
+                    "x": x, "y": y, "button": "left", "buttons": *down as u8, "clickCount": 1,
+                }),
+            ),
+            Input::Wheel { x, y, dy } => (
+                "Input.dispatchMouseEvent",
+                json!({ "type": "mouseWheel", "x": x, "y": y, "deltaX": 0, "deltaY": dy }),
+            ),
+            Input::Key { key, code, text, modifiers, down } => {
+                let vk = virtual_key(key);
+                let kind = match (down, text) {
+                    (false, _) => "keyUp",
+                    (true, Some(_)) => "keyDown",
+                    (true, None) => "rawKeyDown",
+                };
+                let mut params = json!({
+                    "type": kind, "key": key, "code": code, "modifiers": modifier_bits(modifiers),
+                    "windowsVirtualKeyCode": vk, "nativeVirtualKeyCode": vk,
+                });
+                if let (true, Some(text)) = (down, text) {
+                    // Chromium inserts a line break for "\r", as a real Enter key sends
+                    let text = if text == "\n" { "\r" } else { text.as_str() };
+                    params["text"] = json!(text);
+                    params["unmodifiedText"] = json!(text);
+                }
+                if super::foreign_select_all(input) {
+                    params["commands"] = json!(["selectAll"]);
+                }
+                ("Input.dispatchKeyEvent", params)
+            }
+        }
+    }
+
+    pub fn deliver(webview: &PlatformWebview, input: &Input) -> Result<(), String> {
+        let (name, params) = method(input);
+        let handler = CallDevToolsProtocolMethodCompletedHandler::create(Box::new(|_, _| Ok(())));
+        // SAFETY: called on the main thread, which owns the controller
+        unsafe {
+            let core = webview.controller().CoreWebView2().map_err(|e| e.to_string())?;
+            core.CallDevToolsProtocolMethod(&HSTRING::from(name), &HSTRING::from(params.to_string()), &handler)
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// WKWebView: AppKit events sent through the window, which routes them like real ones.
+#[cfg(target_os = "macos")]
+mod platform {
+    use objc2::{msg_send, runtime::AnyObject, MainThreadMarker};
+    use objc2_app_kit::{NSEvent, NSEventModifierFlags, NSEventType, NSScreen, NSView};
+    use objc2_core_graphics::{CGEvent, CGScrollEventUnit};
+    use objc2_foundation::{NSPoint, NSProcessInfo, NSString};
+    use super::{Input, PlatformWebview};
+    use crate::humanize::Modifiers;
+
+    fn flags(modifiers: &Modifiers) -> NSEventModifierFlags {
+        let mut flags = NSEventModifierFlags::empty();
+        if modifiers.shift {
+            flags |= NSEventModifierFlags::Shift;
+        }
+        if modifiers.ctrl {
+            flags |= NSEventModifierFlags::Control;
+        }
+        if modifiers.alt {
+            flags |= NSEventModifierFlags::Option;
+        }
+        if modifiers.meta {
+            flags |= NSEventModifierFlags::Command;
+        }
+        flags
+    }
+
+    /// The virtual key code (ANSI layout) of a `KeyboardEvent.code`.
+    fn key_code(code: &str) -> u16 {
+        const LETTERS: [u16; 26] = [
+            0x00, 0x0B, 0x08, 0x02, 0x0E, 0x03, 0x05, 0x04, 0x22, 0x26, 0x28, 0x25, 0x2E, 0x2D, 0x1F, 0x23, 0x0C,
+            0x0F, 0x01, 0x11, 0x20, 0x09, 0x0D, 0x07, 0x10, 0x06,
+        ];
+        const DIGITS: [u16; 10] = [0x1D, 0x12, 0x13, 0x14, 0x15, 0x17, 0x16, 0x1A, 0x1C, 0x19];
+        const F_KEYS: [u16; 12] = [0x7A, 0x78, 0x63, 0x76, 0x60, 0x61, 0x62, 0x64, 0x65, 0x6D, 0x67, 0x6F];
+        if let Some(letter) = code.strip_prefix("Key").and_then(|l| l.bytes().next()) {
+            return LETTERS.get(letter.wrapping_sub(b'A') as usize).copied().unwrap_or(0);
+        }
+        if let Some(digit) = code.strip_prefix("Digit").and_then(|d| d.parse::<usize>().ok()) {
+            return DIGITS.get(digit).copied().unwrap_or(0);
+        }
+        if let Some(n) = code.strip_prefix('F').and_then(|n| n.parse::<usize>().ok()) {
+            return F_KEYS.get(n.wrapping_sub(1)).copied().unwrap_or(0);
+        }
+        match code {
+            "Enter" => 0x24,
+            "Tab" => 0x30,
+            "Space" => 0x31,
+            "Backspace" => 0x33,
+            "Escape" => 0x35,
+            "MetaLeft" => 0x37,
+            "ShiftLeft" => 0x38,
+            "AltLeft" => 0x3A,
+            "ControlLeft" => 0x3B,
+            "Comma" => 0x2B,
+            "Period" => 0x2F,
+            "Minus" => 0x1B,
+            "Insert" => 0x72,
+            "Home" => 0x73,
+            "PageUp" => 0x74,
+            "Delete" => 0x75,
+            "End" => 0x77,
+            "PageDown" => 0x79,
+            "ArrowLeft" => 0x7B,
+            "ArrowRight" => 0x7C,
+            "ArrowDown" => 0x7D,
+            "ArrowUp" => 0x7E,
+            _ => 0,
+        }
+    }
+
+    /// The characters AppKit reports for a key: the text, or the function-key character.
+    fn characters(key: &str, text: Option<&str>) -> String {
+        let function = match key {
+            "Enter" => '\r',
+            "Tab" => '\t',
+            "Backspace" => '\u{7f}',
+            "Escape" => '\u{1b}',
+            "ArrowUp" => '\u{f700}',
+            "ArrowDown" => '\u{f701}',
+            "ArrowLeft" => '\u{f702}',
+            "ArrowRight" => '\u{f703}',
+            "Insert" => '\u{f727}',
+            "Delete" => '\u{f728}',
+            "Home" => '\u{f729}',
+            "End" => '\u{f72b}',
+            "PageUp" => '\u{f72c}',
+            "PageDown" => '\u{f72d}',
+            _ => match key.strip_prefix('F').and_then(|n| n.parse::<u32>().ok()) {
+                Some(n) => char::from_u32(0xf703 + n).unwrap_or_default(),
+                None => return text.unwrap_or(key).to_string(),
+            },
+        };
+        function.to_string()
+    }
+
+    pub fn deliver(webview: &PlatformWebview, input: &Input) -> Result<(), String> {
+        let mtm = MainThreadMarker::new().ok_or("input must be delivered on the main thread")?;
+        // SAFETY: the platform webview is a live WKWebView, an NSView subclass
+        let view: &NSView = unsafe { &*(webview.inner() as *const NSView) };
+        let window = view.window().ok_or("the webview is not in a window")?;
+        let number = window.windowNumber();
+        let time = NSProcessInfo::processInfo().systemUptime();
+        let in_window = |x: f64, y: f64| {
+            let y = if view.isFlipped() { y } else { view.bounds().size.height - y };
+            view.convertPoint_toView(NSPoint::new(x, y), None)
+        };
+
+        let event = match input {
+            Input::Move { x, y, pressed } => {
+                let kind = if *pressed { NSEventType::LeftMouseDragged } else { NSEventType::MouseMoved };
+                NSEvent::mouseEventWithType_location_modifierFlags_timestamp_windowNumber_context_eventNumber_clickCount_pressure(
+                    kind, in_window(*x, *y), NSEventModifierFlags::empty(), time, number, None, 0, 0, 0.0,
+                )
+            }
+            Input::Button { x, y, down } => {
+                let kind = if *down { NSEventType::LeftMouseDown } else { NSEventType::LeftMouseUp };
+                NSEvent::mouseEventWithType_location_modifierFlags_timestamp_windowNumber_context_eventNumber_clickCount_pressure(
+                    kind, in_window(*x, *y), NSEventModifierFlags::empty(), time, number, None, 0, 1, if *down { 1.0 } else { 0.0 },
+                )
+            }
+            Input::Wheel { x, y, dy } => {
+                // Scroll events only come from Quartz; it places them in global, top-left based coordinates
+                let on_screen = window.convertPointToScreen(in_window(*x, *y));
+                let top = NSScreen::screens(mtm).firstObject().map_or(0.0, |s| s.frame().size.height);
+                let cg_event = CGEvent::new_scroll_wheel_event2(None, CGScrollEventUnit::Pixel, 1, -dy.round() as i32, 0, 0)
+                    .ok_or("could not create a scroll event")?;
+                CGEvent::set_location(Some(&*cg_event), NSPoint::new(on_screen.x, top - on_screen.y));
+                NSEvent::eventWithCGEvent(&cg_event)
+            }
+            Input::Key { key, code, text, modifiers, down } => {
+                let modifier = super::MODIFIER_KEYS.iter().any(|(name, _)| name == key);
+                let kind = match (modifier, down) {
+                    (true, _) => NSEventType::FlagsChanged,
+                    (false, true) => NSEventType::KeyDown,
+                    (false, false) => NSEventType::KeyUp,
+                };
+                // A modifier's own flag is set while it is down
+                let mut held = *modifiers;
+                if modifier && *down {
+                    *super::flag(&mut held, key) = true;
+                }
+                let chars = if modifier { String::new() } else { characters(key, text.as_deref()) };
+                let chars = NSString::from_str(&chars);
+                if *down && window.firstResponder().as_deref() != Some(view.as_ref()) {
+                    window.makeFirstResponder(Some(view.as_ref()));
+                }
+                NSEvent::keyEventWithType_location_modifierFlags_timestamp_windowNumber_context_characters_charactersIgnoringModifiers_isARepeat_keyCode(
+                    kind, NSPoint::new(0.0, 0.0), flags(&held), time, number, None, &chars, &chars, false, key_code(code),
+                )
+            }
+        }
+        .ok_or("could not create the event")?;
+        window.sendEvent(&event);
+        if super::foreign_select_all(input) {
+            // SAFETY: WKWebView implements the standard `selectAll:` action
+            let _: () = unsafe { msg_send![view, selectAll: std::ptr::null::<AnyObject>()] };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+mod platform {
+    use super::{Input, PlatformWebview};
+
+    pub fn deliver(_webview: &PlatformWebview, _input: &Input) -> Result<(), String> {
+        Err("native input is not available on this platform".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str, code: &str, modifiers: Modifiers, down: bool) -> Input {
+        Input::Key { key: key.into(), code: code.into(), text: None, modifiers, down }
+    }
+
+    #[test]
+    fn key_presses_hold_their_modifiers_around_them() {
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::default() };
+        let ctrl_shift = Modifiers { ctrl: true, shift: true, ..Modifiers::default() };
+        let none = Modifiers::default();
+        let cases = [
+            (
+                Step::Key { key: "a".into(), code: "KeyA".into(), text: Some("a".into()), modifiers: none, hold: 80, d: 120 },
+                vec![
+                    (120, Input::Key { key: "a".into(), code: "KeyA".into(), text: Some("a".into()), modifiers: none, down: true }),
+                    (80, key("a", "KeyA", none, false)),
+                ],
+            ),
+            (
+                Step::Key { key: "a".into(), code: "KeyA".into(), text: None, modifiers: ctrl, hold: 50, d: 30 },
+                vec![
+                    (30, key("Control", "ControlLeft", none, true)),
+                    (0, key("a", "KeyA", ctrl, true)),
+                    (50, key("a", "KeyA", ctrl, false)),
+                    (0, key("Control", "ControlLeft", ctrl, false)),
+                ],
+            ),
+            (
+                Step::Key { key: "Tab".into(), code: "Tab".into(), text: None, modifiers: ctrl_shift, hold: 40, d: 0 },
+                vec![
+                    (0, key("Control", "ControlLeft", none, true)),
+                    (0, key("Shift", "ShiftLeft", ctrl, true)),
+                    (0, key("Tab", "Tab", ctrl_shift, true)),
+                    (40, key("Tab", "Tab", ctrl_shift, false)),
+                    (0, key("Shift", "ShiftLeft", ctrl_shift, false)),
+                    (0, key("Control", "ControlLeft", ctrl, false)),
+                ],
+            ),
+        ];
+        for (step, expected) in cases {
+            assert_eq!(events(&step, &mut Pointer::default()), expected, "{step:?}");
+        }
+    }
+
+    #[test]
+    fn buttons_and_wheel_land_where_the_pointer_is() {
+        let mut pointer = Pointer::default();
+        let steps = [
+            (Step::Move { x: 10.0, y: 20.0, d: 16 }, Input::Move { x: 10.0, y: 20.0, pressed: false }),
+            (Step::Down { d: 90 }, Input::Button { x: 10.0, y: 20.0, down: true }),
+            (Step::Move { x: 15.0, y: 25.0, d: 16 }, Input::Move { x: 15.0, y: 25.0, pressed: true }),
+            (Step::Up { d: 70 }, Input::Button { x: 15.0, y: 25.0, down: false }),
+            (Step::Wheel { dy: 120.0, d: 40 }, Input::Wheel { x: 15.0, y: 25.0, dy: 120.0 }),
+        ];
+        for (step, expected) in steps {
+            let d = match step {
+                Step::Move { d, .. } | Step::Down { d } | Step::Up { d } | Step::Wheel { d, .. } | Step::Key { d, .. } => d,
+            };
+            assert_eq!(events(&step, &mut pointer), vec![(d, expected)], "{step:?}");
+        }
+    }
+}
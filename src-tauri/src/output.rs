@@ -0,0 +1,309 @@
+use rusqlite::{types::Value as SqlValue, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path};
+use std::sync::{Arc, Mutex};
+use crate::batch;
+use crate::error::OutputError;
+use crate::state::AppState;
+
+const DEFAULT_TABLE: &str = "records";
+
+fn default_table() -> String {
+    DEFAULT_TABLE.to_string()
+}
+
+/// Where the records of `output.push` go. File sinks append, so a scheduled job adds
+/// to the same file on every run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkConfig {
+    /// Kept in memory with the run, see `get_run_records`
+    #[default]
+    Registry,
+    Jsonl { path: String },
+    /// Columns come from the first record, or from the header of an existing file
+    Csv { path: String },
+    /// One column per key; columns are added as new keys show up
+    Sqlite {
+        path: String,
+        #[serde(default = "default_table")]
+        table: String,
+    },
+}
+
+impl SinkConfig {
+    /// Picks the sink by extension: `.jsonl`/`.ndjson`, `.csv`, or `.db`/`.sqlite`/`.sqlite3`.
+    pub fn from_path(path: &str, table: Option<String>) -> Result<Self, OutputError> {
+        let ext = Path::new(path).extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+        let path = path.to_string();
+        match ext.as_deref() {
+            Some("jsonl") | Some("ndjson") => Ok(SinkConfig::Jsonl { path }),
+            Some("csv") => Ok(SinkConfig::Csv { path }),
+            Some("db") | Some("sqlite") | Some("sqlite3") => {
+                Ok(SinkConfig::Sqlite { path, table: table.unwrap_or_else(default_table) })
+            }
+            _ => Err(OutputError::UnknownFormat(path)),
+        }
+    }
+
+    fn path(&self) -> Option<&str> {
+        match self {
+            SinkConfig::Registry => None,
+            SinkConfig::Jsonl { path } | SinkConfig::Csv { path } | SinkConfig::Sqlite { path, .. } => Some(path),
+        }
+    }
+
+    /// Refuses paths that could leave the base folder; for configs sent over the API.
+    pub fn check_relative(&self) -> Result<(), OutputError> {
+        match self.path() {
+            Some(path) if !Path::new(path).components().all(|c| matches!(c, Component::Normal(_))) => {
+                Err(OutputError::PathNotAllowed(path.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+enum Writer {
+    Registry { state: Arc<Mutex<AppState>>, run_id: String },
+    Jsonl(File),
+    Csv { writer: Box<csv::Writer<File>>, columns: Option<Vec<String>> },
+    Sqlite { conn: Connection, table: String, columns: Vec<String> },
+}
+
+/// An open sink. Shared by every run of a batch, so rows can push concurrently.
+pub struct OutputSink {
+    writer: Mutex<Writer>,
+}
+
+impl fmt::Debug for OutputSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutputSink").finish_non_exhaustive()
+    }
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn sql_value(value: Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+impl OutputSink {
+    /// The sink of a run without a configured one.
+    pub fn registry(state: Arc<Mutex<AppState>>, run_id: &str) -> Arc<Self> {
+        Arc::new(Self { writer: Mutex::new(Writer::Registry { state, run_id: run_id.to_string() }) })
+    }
+
+    /// Opens a file sink, with relative paths taken from `base`. `None` for the registry,
+    /// which each run sets up for itself.
+    pub fn open(config: &SinkConfig, base: &Path) -> Result<Option<Arc<Self>>, OutputError> {
+        let Some(path) = config.path() else { return Ok(None) };
+        let path = base.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let writer = match config {
+            SinkConfig::Registry => return Ok(None),
+            SinkConfig::Jsonl { .. } => Writer::Jsonl(OpenOptions::new().create(true).append(true).open(&path)?),
+            SinkConfig::Csv { .. } => {
+                let existing = fs::metadata(&path).map(|m| m.len() > 0).unwrap_or(false);
+                let columns = if existing {
+                    let mut reader = csv::Reader::from_path(&path)?;
+                    Some(reader.headers()?.iter().map(String::from).collect())
+                } else {
+                    None
+                };
+                let file = OpenOptions::new().create(true).append(true).open(&path)?;
+                Writer::Csv { writer: Box::new(csv::Writer::from_writer(file)), columns }
+            }
+            SinkConfig::Sqlite { table, .. } => {
+                let conn = Connection::open(&path)?;
+                let columns = {
+                    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", quote(table)))?;
+                    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+                    names.collect::<Result<Vec<_>, _>>()?
+                };
+                Writer::Sqlite { conn, table: table.clone(), columns }
+            }
+        };
+        Ok(Some(Arc::new(Self { writer: Mutex::new(writer) })))
+    }
+
+    /// Writes one record right away.
+    pub fn push(&self, record: Map<String, Value>) -> Result<(), OutputError> {
+        let mut writer = self.writer.lock().unwrap();
+        match &mut *writer {
+            Writer::Registry { state, run_id } => state.lock().unwrap().runs.push_record(run_id, Value::Object(record)),
+            Writer::Jsonl(file) => {
+                writeln!(file, "{}", Value::Object(record))?;
+                Ok(())
+            }
+            Writer::Csv { writer, columns } => {
+                let columns = match columns {
+                    Some(columns) => columns,
+                    None => {
+                        let keys: Vec<String> = record.keys().cloned().collect();
+                        writer.write_record(&keys)?;
+                        columns.insert(keys)
+                    }
+                };
+                if let Some(key) = record.keys().find(|k| !columns.contains(k)) {
+                    return Err(OutputError::NewColumn(key.clone()));
+                }
+                let row = columns.iter().map(|c| record.get(c).map(batch::csv_cell).unwrap_or_default());
+                writer.write_record(row)?;
+                writer.flush()?;
+                Ok(())
+            }
+            Writer::Sqlite { conn, table, columns } => {
+                if record.is_empty() {
+                    return Ok(());
+                }
+                // SQLite column names are case-insensitive
+                let known = |columns: &[String], key: &str| columns.iter().any(|c| c.eq_ignore_ascii_case(key));
+                if columns.is_empty() {
+                    let defs: Vec<String> = record.keys().map(|k| quote(k)).collect();
+                    conn.execute(&format!("CREATE TABLE {} ({})", quote(table), defs.join(", ")), [])?;
+                    columns.extend(record.keys().cloned());
+                }
+                for key in record.keys() {
+                    if !known(columns, key) {
+                        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {}", quote(table), quote(key)), [])?;
+                        columns.push(key.clone());
+                    }
+                }
+
+                let names: Vec<String> = record.keys().map(|k| quote(k)).collect();
+                let placeholders = vec!["?"; names.len()].join(", ");
+                let sql = format!("INSERT INTO {} ({}) VALUES ({})", quote(table), names.join(", "), placeholders);
+                conn.execute(&sql, rusqlite::params_from_iter(record.into_iter().map(|(_, v)| sql_value(v))))?;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn sink_from_path() {
+        let cases = [
+            ("out.jsonl", None, Some(SinkConfig::Jsonl { path: "out.jsonl".into() })),
+            ("out.NDJSON", None, Some(SinkConfig::Jsonl { path: "out.NDJSON".into() })),
+            ("dir/out.csv", None, Some(SinkConfig::Csv { path: "dir/out.csv".into() })),
+            ("out.db", None, Some(SinkConfig::Sqlite { path: "out.db".into(), table: DEFAULT_TABLE.into() })),
+            ("out.sqlite3", Some("items"), Some(SinkConfig::Sqlite { path: "out.sqlite3".into(), table: "items".into() })),
+            ("out.json", None, None),
+            ("out", None, None),
+        ];
+        for (path, table, expected) in cases {
+            let sink = SinkConfig::from_path(path, table.map(String::from));
+            match expected {
+                Some(expected) => assert_eq!(sink.unwrap(), expected, "{path}"),
+                None => assert!(matches!(sink, Err(OutputError::UnknownFormat(_))), "{path}"),
+            }
+        }
+    }
+
+    #[test]
+    fn api_paths_stay_relative() {
+        let cases = [
+            ("out.jsonl", true),
+            ("runs/today/out.csv", true),
+            ("../out.csv", false),
+            ("runs/../../out.csv", false),
+            ("./out.csv", false),
+            ("/tmp/out.csv", false),
+        ];
+        for (path, allowed) in cases {
+            let sink = SinkConfig::from_path(path, None).unwrap();
+            assert_eq!(sink.check_relative().is_ok(), allowed, "{path}");
+        }
+        assert!(SinkConfig::Registry.check_relative().is_ok());
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("phantom-output-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn record(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn csv_columns_are_fixed_by_the_first_record_or_the_file() {
+        let dir = temp_dir("csv");
+        let config = SinkConfig::Csv { path: "out.csv".into() };
+        let sink = OutputSink::open(&config, &dir).unwrap().unwrap();
+        sink.push(record(json!({ "name": "A", "price": 1 }))).unwrap();
+        sink.push(record(json!({ "name": "B" }))).unwrap();
+        assert!(matches!(sink.push(record(json!({ "name": "C", "stock": 3 }))), Err(OutputError::NewColumn(c)) if c == "stock"));
+        assert_eq!(fs::read_to_string(dir.join("out.csv")).unwrap(), "name,price\nA,1\nB,\n");
+        drop(sink);
+
+        // Reopened, e.g. by the next run of a job: the header is read back, not written again
+        fs::write(dir.join("out.csv"), "price,name\n1,A\n").unwrap();
+        let sink = OutputSink::open(&config, &dir).unwrap().unwrap();
+        sink.push(record(json!({ "name": "B", "price": 2.5 }))).unwrap();
+        assert!(matches!(sink.push(record(json!({ "Name": "C" }))), Err(OutputError::NewColumn(c)) if c == "Name"));
+        assert_eq!(fs::read_to_string(dir.join("out.csv")).unwrap(), "price,name\n1,A\n2.5,B\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sqlite_creates_the_table_and_adds_columns() {
+        let dir = temp_dir("sqlite");
+        let config = SinkConfig::Sqlite { path: "out.db".into(), table: "items".into() };
+        let sink = OutputSink::open(&config, &dir).unwrap().unwrap();
+        sink.push(record(json!({ "name": "A", "price": 1 }))).unwrap();
+        // Column names are case-insensitive, so "Price" is the existing column
+        sink.push(record(json!({ "Price": 2.5, "stock": true }))).unwrap();
+        drop(sink);
+
+        let sink = OutputSink::open(&config, &dir).unwrap().unwrap();
+        sink.push(record(json!({ "NAME": "C", "STOCK": false, "tags": ["x"] }))).unwrap();
+        drop(sink);
+
+        let conn = Connection::open(dir.join("out.db")).unwrap();
+        let mut stmt = conn.prepare("SELECT name, price, stock, tags FROM items ORDER BY rowid").unwrap();
+        type Row = (Option<String>, Option<f64>, Option<i64>, Option<String>);
+        let rows: Vec<Row> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (Some("A".into()), Some(1.0), None, None),
+                (None, Some(2.5), Some(1), None),
+                (Some("C".into()), None, Some(0), Some(r#"["x"]"#.into())),
+            ]
+        );
+        drop(stmt);
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
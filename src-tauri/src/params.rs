@@ -0,0 +1,340 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs;
+use crate::error::ParamError;
+
+/// Type of a declared parameter. Values given as strings (from the CLI) are converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamType {
+    Any,
+    String,
+    Int,
+    Float,
+    Bool,
+    Array,
+    Map,
+}
+
+impl ParamType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "any" => ParamType::Any,
+            "string" => ParamType::String,
+            "int" => ParamType::Int,
+            "float" => ParamType::Float,
+            "bool" => ParamType::Bool,
+            "array" => ParamType::Array,
+            "map" => ParamType::Map,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ParamType::Any => "any",
+            ParamType::String => "string",
+            ParamType::Int => "int",
+            ParamType::Float => "float",
+            ParamType::Bool => "bool",
+            ParamType::Array => "array",
+            ParamType::Map => "map",
+        }
+    }
+
+    fn accepts(self, value: &Value) -> bool {
+        match self {
+            ParamType::Any => true,
+            ParamType::String => value.is_string(),
+            ParamType::Int => value.is_i64(),
+            ParamType::Float => value.is_number(),
+            ParamType::Bool => value.is_boolean(),
+            ParamType::Array => value.is_array(),
+            ParamType::Map => value.is_object(),
+        }
+    }
+
+    /// Stores an accepted value in this type's form: floats given as integers (`2`) become
+    /// `2.0`, so scripts always get a float.
+    fn normalize(self, value: Value) -> Value {
+        match (self, value.as_f64()) {
+            (ParamType::Float, Some(f)) => Value::from(f),
+            _ => value,
+        }
+    }
+
+    /// Converts a string given on the command line to this type.
+    fn parse_text(self, text: &str) -> Option<Value> {
+        match self {
+            ParamType::Any | ParamType::String => Some(Value::String(text.to_string())),
+            ParamType::Int => text.trim().parse::<i64>().ok().map(Value::from),
+            ParamType::Float => text.trim().parse::<f64>().ok().map(Value::from),
+            ParamType::Bool => match text.trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "1" => Some(Value::Bool(true)),
+                "false" | "no" | "0" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            ParamType::Array | ParamType::Map => serde_json::from_str(text).ok().filter(|v| self.accepts(v)),
+        }
+    }
+}
+
+/// A parameter declared in the script header:
+/// `// @param name[: type][ = default][ -- description]`.
+/// A parameter without a default is required. Defaults are JSON (`3`, `"a b"`, `[1, 2]`)
+/// or, failing that, plain text.
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ParamType,
+    pub default: Option<Value>,
+    /// True when there is no default
+    pub required: bool,
+    pub description: Option<String>,
+}
+
+const MARKER: &str = "@param";
+
+fn parse_declaration(line_no: usize, text: &str) -> Result<ParamSpec, ParamError> {
+    let invalid = |reason: &str| ParamError::Declaration { line: line_no, reason: reason.to_string() };
+
+    let (text, description) = match text.split_once(" -- ") {
+        Some((decl, desc)) => (decl, Some(desc.trim().to_string()).filter(|d| !d.is_empty())),
+        None => (text, None),
+    };
+    let (head, default) = match text.split_once('=') {
+        Some((head, default)) => (head, Some(default.trim())),
+        None => (text, None),
+    };
+    let (name, kind) = match head.split_once(':') {
+        Some((name, kind)) => (name.trim(), ParamType::parse(kind.trim()).ok_or_else(|| invalid("unknown type"))?),
+        None => (head.trim(), ParamType::Any),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(invalid("the name must be letters, digits and '_'"));
+    }
+
+    let default = match default {
+        None => None,
+        Some("") => return Err(invalid("empty default")),
+        Some(text) => {
+            let value = match serde_json::from_str::<Value>(text) {
+                Ok(value) if kind.accepts(&value) => Some(kind.normalize(value)),
+                _ => kind.parse_text(text),
+            };
+            match value {
+                Some(v) => Some(v),
+                None => return Err(invalid(&format!("the default is not a {}", kind.name()))),
+            }
+        }
+    };
+
+    Ok(ParamSpec { name: name.to_string(), kind, required: default.is_none(), default, description })
+}
+
+/// Parameters declared with `// @param` comments anywhere in the script.
+pub fn declared(script: &str) -> Result<Vec<ParamSpec>, ParamError> {
+    let mut specs: Vec<ParamSpec> = Vec::new();
+    for (i, line) in script.lines().enumerate() {
+        let Some(comment) = line.trim_start().strip_prefix("//") else { continue };
+        let Some(decl) = comment.trim_start().strip_prefix(MARKER) else { continue };
+        let spec = parse_declaration(i + 1, decl.trim())?;
+        if specs.iter().any(|s| s.name == spec.name) {
+            return Err(ParamError::Declaration { line: i + 1, reason: format!("'{}' is declared twice", spec.name) });
+        }
+        specs.push(spec);
+    }
+    Ok(specs)
+}
+
+/// Checks the given values against the script's declarations and fills in defaults.
+/// Scripts that declare nothing accept any params as given.
+pub fn resolve(script: &str, mut given: Map<String, Value>) -> Result<Map<String, Value>, ParamError> {
+    let specs = declared(script)?;
+    if specs.is_empty() {
+        return Ok(given);
+    }
+
+    if let Some(unknown) = given.keys().find(|k| !specs.iter().any(|s| &s.name == *k)) {
+        return Err(ParamError::Unknown(unknown.clone()));
+    }
+
+    let mut resolved = Map::new();
+    for spec in specs {
+        let value = match given.remove(&spec.name) {
+            Some(value) if spec.kind.accepts(&value) => spec.kind.normalize(value),
+            Some(Value::String(text)) => spec.kind.parse_text(&text).ok_or_else(|| ParamError::WrongType {
+                name: spec.name.clone(),
+                expected: spec.kind.name(),
+            })?,
+            Some(_) => return Err(ParamError::WrongType { name: spec.name, expected: spec.kind.name() }),
+            None => spec.default.clone().ok_or_else(|| ParamError::Missing(spec.name.clone()))?,
+        };
+        resolved.insert(spec.name, value);
+    }
+    Ok(resolved)
+}
+
+/// Builds params from `--params-file` (a JSON object) and repeated `--param key=value`,
+/// which take precedence. CLI values stay strings until `resolve` converts them.
+pub fn from_cli(pairs: &[String], file: Option<&str>) -> Result<Map<String, Value>, String> {
+    let mut params = match file {
+        Some(path) => {
+            let content = fs::read_to_string(path).map_err(|e| format!("Cannot read params file '{}': {}", path, e))?;
+            match serde_json::from_str(&content) {
+                Ok(Value::Object(map)) => map,
+                Ok(_) => return Err(format!("Params file '{}' must contain a JSON object", path)),
+                Err(e) => return Err(format!("Invalid params file '{}': {}", path, e)),
+            }
+        }
+        None => Map::new(),
+    };
+    for pair in pairs {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid --param '{}': expected key=value", pair))?;
+        params.insert(key.trim().to_string(), Value::String(value.to_string()));
+    }
+    Ok(params)
+}
+
+/// The resolved params as the script's `params` map.
+pub fn to_script_map(params: &Map<String, Value>) -> rhai::Map {
+    rhai::serde::to_dynamic(params)
+        .ok()
+        .and_then(|d| d.try_cast::<rhai::Map>())
+        .unwrap_or_default()
+}
+
+/// The params a script declares, e.g. to build an input form.
+#[tauri::command]
+pub fn get_script_params(script: String) -> Result<Vec<ParamSpec>, ParamError> {
+    declared(&script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SCRIPT: &str = "// @param name: string -- who to greet
+// @param count: int = 3
+// @param ratio: float = 0.5
+// @param on: bool = false
+let x = 1;";
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parses_declarations() {
+        // (line, name, type, default, description)
+        let cases = [
+            ("// @param url", "url", ParamType::Any, None, None),
+            ("// @param count: int = 3 -- how many", "count", ParamType::Int, Some(json!(3)), Some("how many")),
+            ("  //@param tags: array = [\"a\", \"b\"]", "tags", ParamType::Array, Some(json!(["a", "b"])), None),
+            ("// @param title: string = hello world", "title", ParamType::String, Some(json!("hello world")), None),
+            ("// @param quoted: string = \"a b\"", "quoted", ParamType::String, Some(json!("a b")), None),
+            ("// @param flag: bool = yes", "flag", ParamType::Bool, Some(json!(true)), None),
+            ("// @param ratio: float = 1", "ratio", ParamType::Float, Some(json!(1.0)), None),
+            ("// @param opts: map = {\"k\": 1}", "opts", ParamType::Map, Some(json!({"k": 1})), None),
+        ];
+        for (line, name, kind, default, description) in cases {
+            let specs = declared(line).unwrap();
+            assert_eq!(specs.len(), 1, "{line}");
+            let spec = &specs[0];
+            assert_eq!(spec.name, name, "{line}");
+            assert_eq!(spec.kind, kind, "{line}");
+            assert_eq!(spec.required, default.is_none(), "{line}");
+            assert_eq!(spec.default, default, "{line}");
+            assert_eq!(spec.description.as_deref(), description, "{line}");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_declarations() {
+        let cases = [
+            ("// @param x: number", "Invalid @param declaration on line 1: unknown type"),
+            ("// @param bad-name", "Invalid @param declaration on line 1: the name must be letters, digits and '_'"),
+            ("// @param n: int = abc", "Invalid @param declaration on line 1: the default is not a int"),
+            ("// @param tags: array = {}", "Invalid @param declaration on line 1: the default is not a array"),
+            ("// @param n =", "Invalid @param declaration on line 1: empty default"),
+            ("// @param a\n// @param a: int", "Invalid @param declaration on line 2: 'a' is declared twice"),
+        ];
+        for (script, expected) in cases {
+            assert_eq!(declared(script).unwrap_err().to_string(), expected, "{script}");
+        }
+    }
+
+    #[test]
+    fn ignores_lines_that_are_not_declarations() {
+        let script = "let s = \"// @param nope\";\n/* @param nope */\n// see @param below\n// @param real";
+        let names: Vec<_> = declared(script).unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["real"]);
+    }
+
+    #[test]
+    fn resolves_and_coerces_values() {
+        // (given, resolved)
+        let cases = [
+            (json!({"name": "x"}), json!({"name": "x", "count": 3, "ratio": 0.5, "on": false})),
+            (
+                json!({"name": "x", "count": "7", "ratio": "2", "on": "YES"}),
+                json!({"name": "x", "count": 7, "ratio": 2.0, "on": true}),
+            ),
+            (
+                json!({"name": "x", "count": 7, "ratio": 2, "on": true}),
+                json!({"name": "x", "count": 7, "ratio": 2.0, "on": true}),
+            ),
+        ];
+        for (given, expected) in cases {
+            assert_eq!(Value::Object(resolve(SCRIPT, object(given.clone())).unwrap()), expected, "{given}");
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_values() {
+        let cases = [
+            (json!({}), "Missing required param 'name'"),
+            (json!({"name": "x", "other": 1}), "Unknown param 'other': the script doesn't declare it"),
+            (json!({"name": "x", "count": "seven"}), "Param 'count' must be int"),
+            (json!({"name": "x", "count": 2.5}), "Param 'count' must be int"),
+            (json!({"name": "x", "on": "maybe"}), "Param 'on' must be bool"),
+            (json!({"name": 1}), "Param 'name' must be string"),
+        ];
+        for (given, expected) in cases {
+            assert_eq!(resolve(SCRIPT, object(given.clone())).unwrap_err().to_string(), expected, "{given}");
+        }
+    }
+
+    #[test]
+    fn undeclared_scripts_take_params_as_given() {
+        let given = object(json!({"anything": [1, 2]}));
+        assert_eq!(resolve("let x = 1;", given.clone()).unwrap(), given);
+    }
+
+    #[test]
+    fn cli_pairs_override_the_params_file() {
+        let dir = std::env::temp_dir().join(format!("phantom-params-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("params.json");
+        fs::write(&file, r#"{"a": 5, "c": true}"#).unwrap();
+        let list = dir.join("list.json");
+        fs::write(&list, "[1]").unwrap();
+        let file = file.to_str().unwrap();
+
+        let pairs = ["a=1".to_string(), " b =x=y".to_string()];
+        assert_eq!(Value::Object(from_cli(&pairs, None).unwrap()), json!({"a": "1", "b": "x=y"}));
+        assert_eq!(Value::Object(from_cli(&pairs, Some(file)).unwrap()), json!({"a": "1", "b": "x=y", "c": true}));
+        assert!(from_cli(&["novalue".to_string()], None).is_err());
+        assert!(from_cli(&[], Some(list.to_str().unwrap())).unwrap_err().contains("must contain a JSON object"));
+        assert!(from_cli(&[], Some("/nonexistent/params.json")).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,388 @@
+use rhai::{Dynamic, Engine, EvalAltResult, Position};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::error::PolicyError;
+use crate::runs::RunSource;
+use crate::workspace;
+
+/// Limits on a single run. Unset limits don't apply.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptPolicy {
+    /// Rhai operations, a measure of CPU work
+    pub max_operations: Option<u64>,
+    /// Nested function calls
+    pub max_call_depth: Option<usize>,
+    pub max_string_size: Option<usize>,
+    pub max_array_size: Option<usize>,
+    pub max_map_size: Option<usize>,
+    /// Wall-clock time from the start of the script, waits included
+    pub max_runtime_secs: Option<u64>,
+    /// `browser.navigate` calls
+    pub max_navigations: Option<u32>,
+    /// Hosts `browser.navigate` may go to, subdomains included; empty allows any
+    pub allowed_domains: Vec<String>,
+    /// Let scripts read and write files anywhere on the host (uploads, cookie jars) and
+    /// navigate to `file:` URLs. Otherwise their paths are relative to a workspace folder and can't leave it.
+    pub host_files: bool,
+}
+
+/// The policy of each run source, stored in the workspace as `policy.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    pub ui: ScriptPolicy,
+    pub api: ScriptPolicy,
+    pub cron: ScriptPolicy,
+    pub cli: ScriptPolicy,
+}
+
+impl Default for PolicyConfig {
+    /// Unattended runs get a time limit, so a runaway loop can't hold a window forever.
+    /// Only scripts started on this machine may touch files outside the workspace.
+    fn default() -> Self {
+        let unattended = ScriptPolicy { max_runtime_secs: Some(600), ..ScriptPolicy::default() };
+        let local = ScriptPolicy { host_files: true, ..ScriptPolicy::default() };
+        Self {
+            ui: local.clone(),
+            api: unattended.clone(),
+            cron: unattended,
+            cli: local,
+        }
+    }
+}
+
+impl PolicyConfig {
+    pub fn for_source(&self, source: RunSource) -> ScriptPolicy {
+        match source {
+            RunSource::Ui => self.ui.clone(),
+            RunSource::Api => self.api.clone(),
+            RunSource::Cron => self.cron.clone(),
+            RunSource::Cli => self.cli.clone(),
+        }
+    }
+}
+
+/// The saved policies, or the defaults if there are none.
+pub fn load() -> PolicyConfig {
+    let path = workspace::policy_path();
+    let Ok(content) = fs::read_to_string(&path) else { return PolicyConfig::default() };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        eprintln!("Ignoring invalid {}: {}", path.display(), e);
+        PolicyConfig::default()
+    })
+}
+
+/// The policy for a run started from `source`.
+pub fn for_source(source: RunSource) -> ScriptPolicy {
+    load().for_source(source)
+}
+
+/// Ends the run; unlike a runtime error, scripts can't catch it.
+fn stop(error: PolicyError) -> Box<EvalAltResult> {
+    EvalAltResult::ErrorTerminated(error.to_string().into(), Position::NONE).into()
+}
+
+/// Enforces a policy during one run. Shared by every copy of the run's `browser`.
+#[derive(Debug)]
+pub struct RunGuard {
+    policy: ScriptPolicy,
+    deadline: Mutex<Option<Instant>>,
+    navigations: AtomicU32,
+}
+
+impl RunGuard {
+    pub fn new(policy: ScriptPolicy) -> Self {
+        let deadline = Mutex::new(Self::deadline_from_now(&policy));
+        Self { policy, deadline, navigations: AtomicU32::new(0) }
+    }
+
+    fn deadline_from_now(policy: &ScriptPolicy) -> Option<Instant> {
+        policy.max_runtime_secs.map(|secs| Instant::now() + Duration::from_secs(secs))
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        *self.deadline.lock().unwrap()
+    }
+
+    /// Starts the time and navigation limits over, for each statement of a REPL session.
+    pub fn restart(&self) {
+        *self.deadline.lock().unwrap() = Self::deadline_from_now(&self.policy);
+        self.navigations.store(0, Ordering::Relaxed);
+    }
+
+    fn timeout(&self) -> Box<EvalAltResult> {
+        stop(PolicyError::Timeout(self.policy.max_runtime_secs.unwrap_or_default()))
+    }
+
+    /// Time left before the run is stopped, `None` without a limit.
+    pub fn remaining(&self) -> Result<Option<Duration>, Box<EvalAltResult>> {
+        match self.deadline() {
+            None => Ok(None),
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => Ok(Some(left)),
+                _ => Err(self.timeout()),
+            },
+        }
+    }
+
+    /// Sleeps, but not past the deadline.
+    pub fn sleep(&self, duration: Duration) -> Result<(), Box<EvalAltResult>> {
+        match self.remaining()? {
+            Some(left) if left < duration => {
+                std::thread::sleep(left);
+                Err(self.timeout())
+            }
+            _ => {
+                std::thread::sleep(duration);
+                Ok(())
+            }
+        }
+    }
+
+    /// Waits on a page callback, but not past the deadline.
+    pub fn recv<T>(&self, rx: &std::sync::mpsc::Receiver<T>) -> Result<Option<T>, Box<EvalAltResult>> {
+        match self.remaining()? {
+            None => Ok(rx.recv().ok()),
+            Some(left) => match rx.recv_timeout(left) {
+                Ok(value) => Ok(Some(value)),
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => Err(self.timeout()),
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => Ok(None),
+            },
+        }
+    }
+
+    /// Like `recv`, but gives up with `None` after `limit`, for callbacks that may never
+    /// come because the page navigated away first.
+    pub fn recv_within<T>(&self, rx: &std::sync::mpsc::Receiver<T>, limit: Duration) -> Result<Option<T>, Box<EvalAltResult>> {
+        let left = self.remaining()?;
+        let wait = left.map_or(limit, |left| left.min(limit));
+        match rx.recv_timeout(wait) {
+            Ok(value) => Ok(Some(value)),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) if left.is_some_and(|left| left <= limit) => Err(self.timeout()),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Counts a navigation and checks its scheme (http and https, and file with
+    /// `host_files`) and its host against the allowlist. Returns the parsed URL, which is
+    /// what the window must go to: the raw string never reaches the page.
+    pub fn check_navigation(&self, url: &str) -> Result<url::Url, Box<EvalAltResult>> {
+        self.remaining()?;
+        let count = self.navigations.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(max) = self.policy.max_navigations {
+            if count > max {
+                return Err(stop(PolicyError::Navigations(max)));
+            }
+        }
+
+        let parsed = url::Url::parse(url).map_err(|e| format!("Invalid URL '{}': {}", url, e))?;
+        let scheme_allowed = match parsed.scheme() {
+            "http" | "https" => true,
+            "file" => self.policy.host_files,
+            _ => false,
+        };
+        if !scheme_allowed {
+            return Err(stop(PolicyError::SchemeNotAllowed(url.to_string())));
+        }
+        if !self.policy.allowed_domains.is_empty() {
+            let host = parsed.host_str().map(|h| h.to_ascii_lowercase()).unwrap_or_default();
+            let allowed = self.policy.allowed_domains.iter().any(|domain| {
+                let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+                host == domain || host.ends_with(&format!(".{}", domain))
+            });
+            if !allowed {
+                return Err(stop(PolicyError::DomainNotAllowed(url.to_string())));
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Sets the engine limits, and a check of the deadline while the script computes.
+    pub fn apply(self: &Arc<Self>, engine: &mut Engine) {
+        let policy = &self.policy;
+        if let Some(max) = policy.max_operations {
+            engine.set_max_operations(max);
+        }
+        if let Some(max) = policy.max_call_depth {
+            engine.set_max_call_levels(max);
+        }
+        if let Some(max) = policy.max_string_size {
+            engine.set_max_string_size(max);
+        }
+        if let Some(max) = policy.max_array_size {
+            engine.set_max_array_size(max);
+        }
+        if let Some(max) = policy.max_map_size {
+            engine.set_max_map_size(max);
+        }
+        if let Some(secs) = policy.max_runtime_secs {
+            let guard = self.clone();
+            engine.on_progress(move |ops| {
+                let expired = ops % 1024 == 0 && guard.deadline().is_some_and(|deadline| Instant::now() >= deadline);
+                expired.then(|| PolicyError::Timeout(secs).to_string().into())
+            });
+        }
+    }
+
+    /// The file a script path names. Without `host_files` it must be a relative path that
+    /// stays inside `dir` (created on demand), symlinks included.
+    pub fn resolve_file(&self, path: &str, dir: &Path) -> Result<PathBuf, Box<EvalAltResult>> {
+        if self.policy.host_files {
+            return Ok(PathBuf::from(path));
+        }
+        let folder = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let denied = || -> Box<EvalAltResult> {
+            PolicyError::FileNotAllowed { path: path.to_string(), folder: folder.clone() }.to_string().into()
+        };
+        let relative = Path::new(path);
+        if path.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(denied());
+        }
+
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let root = dir.canonicalize().map_err(|e| e.to_string())?;
+        let file = root.join(relative);
+        // A file that doesn't exist yet is checked through its folder
+        let resolved = match file.canonicalize() {
+            Ok(resolved) => resolved,
+            Err(_) => match (file.parent().and_then(|p| p.canonicalize().ok()), file.file_name()) {
+                (Some(parent), Some(name)) => parent.join(name),
+                _ => file,
+            },
+        };
+        if !resolved.starts_with(&root) {
+            return Err(denied());
+        }
+        Ok(resolved)
+    }
+
+    /// Turns a limit error into one that names the limit, so run history says why the run stopped.
+    pub fn explain(&self, error: Box<EvalAltResult>) -> Box<EvalAltResult> {
+        let policy = &self.policy;
+        // Errors raised inside functions and modules come wrapped
+        let mut cause = &*error;
+        while let EvalAltResult::ErrorInFunctionCall(_, _, inner, _) | EvalAltResult::ErrorInModule(_, inner, _) = cause {
+            cause = inner;
+        }
+        let reason = match cause {
+            EvalAltResult::ErrorTerminated(reason, _) => reason.to_string(),
+            EvalAltResult::ErrorTooManyOperations(_) => {
+                PolicyError::Limit(format!("more than {} operations", policy.max_operations.unwrap_or_default())).to_string()
+            }
+            EvalAltResult::ErrorStackOverflow(_) => {
+                PolicyError::Limit(format!("calls nested deeper than {}", policy.max_call_depth.unwrap_or_default())).to_string()
+            }
+            EvalAltResult::ErrorDataTooLarge(what, _) => PolicyError::Limit(format!("{} over the limit", what)).to_string(),
+            _ => return error,
+        };
+        EvalAltResult::ErrorRuntime(Dynamic::from(reason), error.position()).into()
+    }
+}
+
+#[tauri::command]
+pub fn get_script_policy() -> PolicyConfig {
+    load()
+}
+
+#[tauri::command]
+pub fn save_script_policy(config: PolicyConfig) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+    fs::write(workspace::policy_path(), json).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(allowed: &[&str]) -> RunGuard {
+        RunGuard::new(ScriptPolicy {
+            allowed_domains: allowed.iter().map(|d| d.to_string()).collect(),
+            ..ScriptPolicy::default()
+        })
+    }
+
+    #[test]
+    fn allowlist_checks_the_parsed_host() {
+        let guard = guard(&["allowed.com"]);
+        for (url, allowed) in [
+            ("https://allowed.com/", true),
+            ("https://www.allowed.com/path?q=1", true),
+            ("https://notallowed.com/", false),
+            ("https://allowed.com.evil.com/", false),
+            ("https://evil.com\\@allowed.com/", false),
+            ("javascript:location='https://evil.com'", false),
+            ("data:text/html,<script>location='https://evil.com'</script>", false),
+        ] {
+            assert_eq!(guard.check_navigation(url).is_ok(), allowed, "{}", url);
+        }
+    }
+
+    #[test]
+    fn navigation_is_limited_to_web_schemes() {
+        let open = guard(&[]);
+        let host = RunGuard::new(ScriptPolicy { host_files: true, ..ScriptPolicy::default() });
+        for (url, allowed, with_host_files) in [
+            ("https://example.com/", true, true),
+            ("http://example.com/", true, true),
+            ("file:///etc/passwd", false, true),
+            ("javascript:alert(1)", false, false),
+            ("data:text/html,hi", false, false),
+            ("blob:https://example.com/1", false, false),
+            ("about:blank", false, false),
+        ] {
+            assert_eq!(open.check_navigation(url).is_ok(), allowed, "{}", url);
+            assert_eq!(host.check_navigation(url).is_ok(), with_host_files, "{} with host_files", url);
+        }
+    }
+
+    #[test]
+    fn quotes_in_an_allowed_url_stay_in_its_path() {
+        // Passes the allowlist; it must not be able to run a second statement
+        let url = guard(&["allowed.com"])
+            .check_navigation("https://allowed.com/';location='https://evil.com")
+            .unwrap();
+        assert_eq!(url.host_str(), Some("allowed.com"));
+        assert!(url.path().contains("location="));
+    }
+
+    #[test]
+    fn confined_files_stay_in_their_folder() {
+        let dir = std::env::temp_dir().join(format!("phantom-policy-{}", std::process::id()));
+        let confined = RunGuard::new(ScriptPolicy::default());
+        for (path, allowed) in [
+            ("photo.png", true),
+            ("jars/shop.txt", true),
+            ("../secret.txt", false),
+            ("jars/../../secret.txt", false),
+            ("/etc/passwd", false),
+            ("", false),
+        ] {
+            let resolved = confined.resolve_file(path, &dir);
+            assert_eq!(resolved.is_ok(), allowed, "{}", path);
+            if let Ok(file) = resolved {
+                assert!(file.starts_with(dir.canonicalize().unwrap()), "{}", file.display());
+            }
+        }
+
+        let host = RunGuard::new(ScriptPolicy { host_files: true, ..ScriptPolicy::default() });
+        assert_eq!(host.resolve_file("/etc/passwd", &dir).unwrap(), PathBuf::from("/etc/passwd"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn only_local_sources_get_host_files_by_default() {
+        let config = PolicyConfig::default();
+        assert!(config.ui.host_files && config.cli.host_files);
+        assert!(!config.api.host_files && !config.cron.host_files);
+    }
+
+    #[test]
+    fn unparseable_urls_are_refused() {
+        assert!(guard(&[]).check_navigation("not a url").is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use rhai::Dynamic;
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, State, WebviewWindow};
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
+use crate::error::PoolError;
+use crate::output::OutputSink;
+use crate::policy;
+use crate::runs::RunSource;
+use crate::scripting::{self, ScriptInputs};
+use crate::session::SessionSnapshot;
+use crate::state::{AppState, Profile};
+use crate::vault;
+use crate::windows;
+
+/// Pool windows are labelled `pool-<n>`
+pub const WINDOW_PREFIX: &str = "pool-";
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolConfig {
+    /// Hidden windows open at once, i.e. runs in parallel
+    pub max_windows: usize,
+    /// Runs allowed to wait for a window before new ones are refused
+    pub max_queue: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self { max_windows: 4, max_queue: 100 }
+    }
+}
+
+/// A script to run in a pooled window.
+pub struct PoolJob {
+    pub source: RunSource,
+    pub profile: Option<Profile>,
+    /// Restored into a fresh window before the script runs
+    pub session: Option<SessionSnapshot>,
+    pub script: String,
+    pub inputs: ScriptInputs,
+    /// Send prints to the UI log (`log_output`) instead of stdout
+    pub emit_logs: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStatus {
+    pub max_windows: usize,
+    pub max_queue: usize,
+    pub running: usize,
+    pub queued: usize,
+}
+
+/// Runs scripts in at most `max_windows` hidden windows. Runs beyond that wait in a
+/// bounded queue. Each run gets a window of its own, closed when the run ends: a tab's
+/// sessionStorage and page state can only be dropped for every origin with the tab.
+/// Cookies and localStorage belong to the profile and are kept.
+pub struct WindowPool {
+    config: PoolConfig,
+    permits: Arc<Semaphore>,
+    /// Accepted runs that haven't finished, running or waiting
+    pending: Mutex<usize>,
+    next_window: AtomicUsize,
+}
+
+impl Default for WindowPool {
+    fn default() -> Self {
+        Self::new(PoolConfig::default())
+    }
+}
+
+impl WindowPool {
+    pub fn new(config: PoolConfig) -> Self {
+        let max_windows = config.max_windows.max(1);
+        Self {
+            config: PoolConfig { max_windows, ..config },
+            permits: Arc::new(Semaphore::new(max_windows)),
+            pending: Mutex::new(0),
+            next_window: AtomicUsize::new(1),
+        }
+    }
+
+    pub fn status(&self) -> PoolStatus {
+        let pending = *self.pending.lock().unwrap();
+        let running = self.config.max_windows - self.permits.available_permits();
+        PoolStatus {
+            max_windows: self.config.max_windows,
+            max_queue: self.config.max_queue,
+            running,
+            queued: pending.saturating_sub(running),
+        }
+    }
+
+    /// Takes a place in the pool, or refuses when the queue is full.
+    fn reserve(&self) -> Result<(), PoolError> {
+        let mut pending = self.pending.lock().unwrap();
+        if *pending >= self.config.max_windows + self.config.max_queue {
+            return Err(PoolError::QueueFull {
+                max_windows: self.config.max_windows,
+                max_queue: self.config.max_queue,
+            });
+        }
+        *pending += 1;
+        Ok(())
+    }
+
+    /// Opens the run's window. The permit held by the run leaves room for it.
+    async fn checkout(&self, app: &AppHandle, state: &Arc<Mutex<AppState>>, job: &PoolJob) -> Result<WebviewWindow, String> {
+        let label = format!("{}{}", WINDOW_PREFIX, self.next_window.fetch_add(1, Ordering::Relaxed));
+        windows::open_run_window(app, state, &label, job.profile.clone(), job.session.as_ref()).await
+    }
+
+    async fn run(&self, app: &AppHandle, state: &Arc<Mutex<AppState>>, run_id: &str, job: PoolJob) -> Result<Dynamic, String> {
+        let _permit = self.permits.acquire().await.map_err(|e| e.to_string())?;
+        state.lock().unwrap().runs.set_running(run_id);
+
+        let window = self.checkout(app, state, &job).await?;
+        let label = window.label().to_string();
+
+        let (script, profile, app_handle, state_handle) = (job.script.clone(), job.profile.clone(), app.clone(), state.clone());
+        let mut inputs = job.inputs.clone();
+        inputs.output.get_or_insert_with(|| OutputSink::registry(state.clone(), run_id));
+        let emit_logs = job.emit_logs;
+        let policy = policy::for_source(job.source);
+        let result = tokio::task::spawn_blocking(move || {
+            if emit_logs {
+                scripting::run_in_window(script, window, state_handle, profile, inputs, policy, app_handle)
+            } else {
+                scripting::execute(script, window, state_handle.clone(), profile, inputs, policy, None)
+                    .map_err(|e| vault::redact(&state_handle.lock().unwrap(), &e.to_string()))
+            }
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r);
+
+        windows::close(app, state, &label);
+        result
+    }
+
+    fn finish(&self) {
+        *self.pending.lock().unwrap() -= 1;
+    }
+
+    /// Takes one of the pool's windows for a window opened outside it (a REPL session).
+    /// `None` when all are busy.
+    pub fn hold_window(self: &Arc<Self>) -> Option<WindowSlot> {
+        let permit = self.permits.clone().try_acquire_owned().ok()?;
+        Some(WindowSlot { _permit: permit })
+    }
+
+    /// Closes all pool windows, which ends their runs. Each run gives its window's
+    /// place back as it finishes.
+    pub fn close_all(&self, app: &AppHandle, state: &Arc<Mutex<AppState>>) {
+        let open: Vec<String> = {
+            let s = state.lock().unwrap();
+            s.windows.keys().filter(|l| l.starts_with(WINDOW_PREFIX)).cloned().collect()
+        };
+        for label in open {
+            windows::close(app, state, &label);
+        }
+    }
+
+    pub fn max_windows(&self) -> usize {
+        self.config.max_windows
+    }
+}
+
+/// A pool window held by someone else; given back when dropped.
+pub struct WindowSlot {
+    _permit: OwnedSemaphorePermit,
+}
+
+/// Queues a run in the window pool. Returns the run id right away, and a receiver
+/// for the outcome; dropping it doesn't cancel the run.
+pub fn submit(
+    app: &AppHandle,
+    state: &Arc<Mutex<AppState>>,
+    job: PoolJob,
+) -> Result<(String, oneshot::Receiver<Result<Dynamic, String>>), PoolError> {
+    let (pool, run_id) = {
+        let mut s = state.lock().unwrap();
+        let pool = s.pool.clone();
+        pool.reserve()?;
+        let run_id = s.runs.start(job.source, job.profile.as_ref().map(|p| p.name.as_str()));
+        (pool, run_id)
+    };
+
+    let (tx, rx) = oneshot::channel();
+    let (app, state, id) = (app.clone(), state.clone(), run_id.clone());
+    tauri::async_runtime::spawn(async move {
+        let result = pool.run(&app, &state, &id, job).await;
+        pool.finish();
+
+        let outcome = match &result {
+            Ok(value) => Ok(serde_json::to_value(value).unwrap_or_default()),
+            Err(e) => Err(e.clone()),
+        };
+        state.lock().unwrap().runs.finish(&id, outcome);
+        let _ = tx.send(result);
+    });
+    Ok((run_id, rx))
+}
+
+#[tauri::command]
+pub fn get_pool_status(state: State<'_, Arc<Mutex<AppState>>>) -> PoolStatus {
+    state.lock().unwrap().pool.status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_refuses_past_windows_plus_queue() {
+        let pool = WindowPool::new(PoolConfig { max_windows: 2, max_queue: 1 });
+        for _ in 0..3 {
+            pool.reserve().unwrap();
+        }
+        assert!(matches!(pool.reserve(), Err(PoolError::QueueFull { max_windows: 2, max_queue: 1 })));
+        pool.finish();
+        assert!(pool.reserve().is_ok());
+    }
+
+    #[test]
+    fn at_least_one_window() {
+        let pool = WindowPool::new(PoolConfig { max_windows: 0, max_queue: 0 });
+        assert_eq!(pool.max_windows(), 1);
+        assert!(pool.reserve().is_ok());
+        assert!(pool.reserve().is_err());
+    }
+
+    #[test]
+    fn status_splits_accepted_runs_into_running_and_queued() {
+        let pool = WindowPool::new(PoolConfig { max_windows: 2, max_queue: 5 });
+        for _ in 0..4 {
+            pool.reserve().unwrap();
+        }
+        let status = pool.status();
+        assert_eq!((status.running, status.queued), (0, 4));
+
+        let running = pool.permits.try_acquire_many(2).unwrap();
+        let status = pool.status();
+        assert_eq!((status.max_windows, status.max_queue, status.running, status.queued), (2, 5, 2, 2));
+
+        drop(running);
+        pool.finish();
+        pool.finish();
+        let status = pool.status();
+        assert_eq!((status.running, status.queued), (0, 2));
+    }
+
+    #[test]
+    fn held_windows_count_as_running() {
+        let pool = Arc::new(WindowPool::new(PoolConfig { max_windows: 1, max_queue: 0 }));
+        let slot = pool.hold_window().unwrap();
+        assert!(pool.hold_window().is_none());
+        assert_eq!(pool.status().running, 1);
+        drop(slot);
+        assert_eq!(pool.status().running, 0);
+        assert!(pool.hold_window().is_some());
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager, State};
+use crate::commands::{load_profiles_from_disk, save_profiles_to_disk};
+use crate::error::ProfileError;
+use crate::fingerprint;
+use crate::proxy;
+use crate::scheduler;
+use crate::session;
+use crate::state::{AppState, Profile};
+use crate::storage;
+use crate::windows::{self, STUDIO};
+
+const PROXY_PROTOCOLS: [&str; 4] = ["http", "https", "socks5", "socks5h"];
+const MAX_NAME_LEN: usize = 100;
+
+fn validate_name(name: &str) -> Result<(), ProfileError> {
+    if name.trim().is_empty() {
+        return Err(ProfileError::invalid("name", "must not be empty"));
+    }
+    if name != name.trim() {
+        return Err(ProfileError::invalid("name", "must not start or end with whitespace"));
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(ProfileError::invalid("name", format!("must be at most {} characters", MAX_NAME_LEN)));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(ProfileError::invalid("name", "must not contain control characters"));
+    }
+    Ok(())
+}
+
+/// Checks a profile on its own; uniqueness against other profiles is up to the caller.
+pub fn validate(profile: &Profile) -> Result<(), ProfileError> {
+    validate_name(&profile.name)?;
+
+    let ua = &profile.user_agent;
+    if ua.trim().is_empty() {
+        return Err(ProfileError::invalid("user_agent", "must not be empty"));
+    }
+    // It becomes an HTTP header value
+    if ua.chars().any(char::is_control) {
+        return Err(ProfileError::invalid("user_agent", "must not contain control characters or line breaks"));
+    }
+
+    if let Some(proxy) = &profile.proxy {
+        if !PROXY_PROTOCOLS.contains(&proxy.protocol.as_str()) {
+            return Err(ProfileError::invalid(
+                "proxy.protocol",
+                format!("'{}' is not one of {}", proxy.protocol, PROXY_PROTOCOLS.join(", ")),
+            ));
+        }
+        if proxy.host.is_empty() || url::Host::parse(&proxy.host).is_err() {
+            return Err(ProfileError::invalid("proxy.host", format!("'{}' is not a valid host name or IP", proxy.host)));
+        }
+        if proxy.port == 0 {
+            return Err(ProfileError::invalid("proxy.port", "must be between 1 and 65535"));
+        }
+        if proxy.password.is_some() && proxy.password_secret.is_some() {
+            return Err(ProfileError::invalid("proxy.password", "set either a password or a vault secret, not both"));
+        }
+    }
+
+    if let Some(screen) = &profile.screen {
+        if screen.width == 0 || screen.height == 0 {
+            return Err(ProfileError::invalid("screen", "width and height must be positive"));
+        }
+        if screen.avail_height.is_some_and(|h| h > screen.height) {
+            return Err(ProfileError::invalid("screen.avail_height", "must not exceed the screen height"));
+        }
+        if !(screen.pixel_ratio.is_finite() && screen.pixel_ratio > 0.0) {
+            return Err(ProfileError::invalid("screen.pixel_ratio", "must be a positive number"));
+        }
+    }
+
+    if let Some(webgl) = &profile.webgl {
+        if webgl.vendor.trim().is_empty() || webgl.renderer.trim().is_empty() {
+            return Err(ProfileError::invalid("webgl", "vendor and renderer must not be empty"));
+        }
+    }
+    Ok(())
+}
+
+fn ensure_unique(profiles: &[Profile], name: &str) -> Result<(), ProfileError> {
+    if profiles.iter().any(|p| p.name == name) {
+        return Err(ProfileError::AlreadyExists(name.to_string()));
+    }
+    Ok(())
+}
+
+fn position(profiles: &[Profile], name: &str) -> Result<usize, ProfileError> {
+    profiles
+        .iter()
+        .position(|p| p.name == name)
+        .ok_or_else(|| ProfileError::NotFound(name.to_string()))
+}
+
+fn is_current(state: &Arc<Mutex<AppState>>, name: &str) -> bool {
+    let s = state.lock().unwrap();
+    s.studio_profile().is_some_and(|p| p.name == name)
+}
+
+pub fn create(profile: Profile) -> Result<Profile, ProfileError> {
+    validate(&profile)?;
+    let mut profiles = load_profiles_from_disk();
+    ensure_unique(&profiles, &profile.name)?;
+    profiles.push(profile.clone());
+    save_profiles_to_disk(&profiles)?;
+    Ok(profile)
+}
+
+/// Inserts or replaces the profile with the same name. If it is the selected one,
+/// the proxy is restarted and the Studio window closed so the change takes effect.
+pub async fn save(app: &AppHandle, state: &Arc<Mutex<AppState>>, profile: Profile) -> Result<Profile, ProfileError> {
+    validate(&profile)?;
+    let mut profiles = load_profiles_from_disk();
+    match profiles.iter().position(|p| p.name == profile.name) {
+        Some(idx) => profiles[idx] = profile.clone(),
+        None => profiles.push(profile.clone()),
+    }
+    save_profiles_to_disk(&profiles)?;
+
+    if is_current(state, &profile.name) {
+        windows::bind(state, STUDIO, Some(profile.clone()));
+        proxy::restart_proxy(app.clone(), state.clone(), STUDIO).await;
+        if let Some(w) = app.get_webview_window(STUDIO) {
+            let _ = w.close();
+        }
+    }
+    Ok(profile)
+}
+
+/// Refuses while a window other than the Studio uses the profile: runs and REPL sessions
+/// hold its data directory open, and only the Studio can be closed for them.
+pub(crate) fn ensure_not_open(state: &Arc<Mutex<AppState>>, name: &str) -> Result<(), ProfileError> {
+    let open: Vec<String> = state.lock().unwrap().windows_using(name).into_iter().filter(|l| l != STUDIO).collect();
+    if open.is_empty() {
+        Ok(())
+    } else {
+        Err(ProfileError::OpenIn { profile: name.to_string(), windows: open.join(", ") })
+    }
+}
+
+/// Removes a profile, its browser storage and its saved sessions. Refused while a scheduled job or a window
+/// other than the Studio uses it.
+pub async fn delete(app: &AppHandle, state: &Arc<Mutex<AppState>>, name: &str) -> Result<(), ProfileError> {
+    let mut profiles = load_profiles_from_disk();
+    let idx = position(&profiles, name)?;
+
+    let jobs: Vec<String> = scheduler::load_jobs_from_disk()
+        .into_iter()
+        .filter(|j| j.profile.as_deref() == Some(name))
+        .map(|j| j.id)
+        .collect();
+    if !jobs.is_empty() {
+        return Err(ProfileError::InUse { profile: name.to_string(), jobs: jobs.join(", ") });
+    }
+    ensure_not_open(state, name)?;
+
+    storage::close_studio_if_using(app, state, name);
+    if is_current(state, name) {
+        windows::bind(state, STUDIO, None);
+    }
+
+    profiles.remove(idx);
+    save_profiles_to_disk(&profiles)?;
+    // A new profile with the same name must not inherit the old cookies or sessions
+    storage::remove_profile_storage(app, name).await.map_err(ProfileError::Storage)?;
+    session::remove_all(name).map_err(ProfileError::Storage)
+}
+
+/// Copies a profile's settings under a new name. The copy gets a fresh seed, so the
+/// two don't share a fingerprint, and starts with empty storage.
+pub fn clone(name: &str, new_name: &str) -> Result<Profile, ProfileError> {
+    validate_name(new_name)?;
+    let mut profiles = load_profiles_from_disk();
+    let source = profiles[position(&profiles, name)?].clone();
+    ensure_unique(&profiles, new_name)?;
+
+    let seeds: HashSet<u64> = profiles.iter().map(|p| p.seed).collect();
+    let seed = loop {
+        let candidate = fingerprint::random_seed();
+        if !seeds.contains(&candidate) {
+            break candidate;
+        }
+    };
+
+    let copy = Profile { name: new_name.to_string(), seed, ..source };
+    profiles.push(copy.clone());
+    save_profiles_to_disk(&profiles)?;
+    Ok(copy)
+}
+
+/// Renames a profile, moving its storage and saved sessions and updating scheduled jobs that use it.
+/// Refused while a window other than the Studio uses it.
+pub async fn rename(app: &AppHandle, state: &Arc<Mutex<AppState>>, name: &str, new_name: &str) -> Result<Profile, ProfileError> {
+    validate_name(new_name)?;
+    let mut profiles = load_profiles_from_disk();
+    let idx = position(&profiles, name)?;
+    ensure_unique(&profiles, new_name)?;
+
+    ensure_not_open(state, name)?;
+
+    // The data directory is derived from the name. (On macOS the data store identifier
+    // is too, and WebKit offers no way to move it, so storage starts fresh there.)
+    storage::close_studio_if_using(app, state, name);
+    storage::move_dir_with_retry(&storage::profile_data_dir(name), &storage::profile_data_dir(new_name))
+        .await
+        .map_err(ProfileError::Storage)?;
+    session::rename_all(name, new_name)?;
+
+    profiles[idx].name = new_name.to_string();
+    let renamed = profiles[idx].clone();
+    save_profiles_to_disk(&profiles)?;
+
+    let mut jobs = scheduler::load_jobs_from_disk();
+    let mut jobs_changed = false;
+    for job in jobs.iter_mut().filter(|j| j.profile.as_deref() == Some(name)) {
+        job.profile = Some(new_name.to_string());
+        jobs_changed = true;
+    }
+    if jobs_changed {
+        scheduler::save_jobs_to_disk(&jobs);
+    }
+
+    if is_current(state, name) {
+        windows::bind(state, STUDIO, Some(renamed.clone()));
+    }
+    Ok(renamed)
+}
+
+#[tauri::command]
+pub fn create_profile(profile: Profile) -> Result<Profile, ProfileError> {
+    create(profile)
+}
+
+#[tauri::command]
+pub async fn delete_profile(profile_name: String, state: State<'_, Arc<Mutex<AppState>>>, app: AppHandle) -> Result<(), ProfileError> {
+    delete(&app, state.inner(), &profile_name).await
+}
+
+#[tauri::command]
+pub fn clone_profile(profile_name: String, new_name: String) -> Result<Profile, ProfileError> {
+    clone(&profile_name, &new_name)
+}
+
+#[tauri::command]
+pub async fn rename_profile(
+    profile_name: String,
+    new_name: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    app: AppHandle,
+) -> Result<Profile, ProfileError> {
+    rename(&app, state.inner(), &profile_name, &new_name).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{ProxyConfig, ScreenConfig, WebGlConfig};
+
+    fn profile(name: &str) -> Profile {
+        Profile {
+            name: name.into(),
+            user_agent: "Mozilla/5.0".into(),
+            seed: 1,
+            proxy: None,
+            screen: None,
+            webgl: None,
+            fingerprint_version: fingerprint::FINGERPRINT_VERSION,
+            human_input: false,
+        }
+    }
+
+    fn proxy(protocol: &str, host: &str, port: u16) -> Option<ProxyConfig> {
+        Some(ProxyConfig {
+            protocol: protocol.into(),
+            host: host.into(),
+            port,
+            username: None,
+            password: None,
+            password_secret: None,
+        })
+    }
+
+    fn invalid_field(result: Result<(), ProfileError>) -> Option<&'static str> {
+        match result {
+            Err(ProfileError::Invalid { field, .. }) => Some(field),
+            Ok(()) => None,
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn names_must_be_trimmed_printable_and_short() {
+        for (name, valid) in [
+            ("Shop 001", true),
+            ("Ünïcödé", true),
+            ("", false),
+            ("   ", false),
+            (" Shop", false),
+            ("Shop\n", false),
+            ("Sh\u{7}op", false),
+            (&"x".repeat(MAX_NAME_LEN), true),
+            (&"x".repeat(MAX_NAME_LEN + 1), false),
+        ] {
+            assert_eq!(invalid_field(validate_name(name)).is_none(), valid, "{:?}", name);
+            assert_eq!(invalid_field(validate(&profile(name))).is_none(), valid, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn validate_reports_the_offending_field() {
+        let with = |f: &dyn Fn(&mut Profile)| {
+            let mut p = profile("P");
+            f(&mut p);
+            invalid_field(validate(&p))
+        };
+        let cases: [(&dyn Fn(&mut Profile), Option<&str>); 14] = [
+            (&|_| {}, None),
+            (&|p| p.user_agent = " ".into(), Some("user_agent")),
+            (&|p| p.user_agent = "UA\r\nX-Evil: 1".into(), Some("user_agent")),
+            (&|p| p.proxy = proxy("socks5", "127.0.0.1", 1080), None),
+            (&|p| p.proxy = proxy("ftp", "proxy.example", 21), Some("proxy.protocol")),
+            (&|p| p.proxy = proxy("http", "", 8080), Some("proxy.host")),
+            (&|p| p.proxy = proxy("http", "bad host", 8080), Some("proxy.host")),
+            (&|p| p.proxy = proxy("http", "proxy.example", 0), Some("proxy.port")),
+            (
+                &|p| {
+                    p.proxy = proxy("http", "proxy.example", 8080);
+                    let proxy = p.proxy.as_mut().unwrap();
+                    proxy.password = Some("pw".into());
+                    proxy.password_secret = Some("pw".into());
+                },
+                Some("proxy.password"),
+            ),
+            (&|p| p.screen = Some(ScreenConfig { width: 0, height: 1080, avail_height: None, pixel_ratio: 1.0 }), Some("screen")),
+            (
+                &|p| p.screen = Some(ScreenConfig { width: 1920, height: 1080, avail_height: Some(1200), pixel_ratio: 1.0 }),
+                Some("screen.avail_height"),
+            ),
+            (
+                &|p| p.screen = Some(ScreenConfig { width: 1920, height: 1080, avail_height: None, pixel_ratio: f64::NAN }),
+                Some("screen.pixel_ratio"),
+            ),
+            (&|p| p.webgl = Some(WebGlConfig { vendor: "".into(), renderer: "ANGLE".into() }), Some("webgl")),
+            (&|p| p.webgl = Some(WebGlConfig { vendor: "Google Inc.".into(), renderer: "ANGLE".into() }), None),
+        ];
+        for (i, (change, field)) in cases.into_iter().enumerate() {
+            assert_eq!(with(change), field, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn names_must_be_unique() {
+        let profiles = [profile("A"), profile("B")];
+        assert!(ensure_unique(&profiles, "C").is_ok());
+        assert!(matches!(ensure_unique(&profiles, "B"), Err(ProfileError::AlreadyExists(name)) if name == "B"));
+        assert!(matches!(position(&profiles, "C"), Err(ProfileError::NotFound(_))));
+    }
+}
@@ -1,11 +1,11 @@
 use tauri::AppHandle;
 use tokio::sync::oneshot;
-use crate::state::{ProxyConfig, AppState};
+use crate::state::{ProxyConfig, AppState, Profile};
 use std::sync::{Arc, Mutex};
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 use hudsucker::{
-    certificate_authority::RcgenAuthority,
+    certificate_authority::CertificateAuthority,
     Proxy,
     HttpContext, HttpHandler, RequestOrResponse,
 };
@@ -22,13 +22,33 @@ use tauri::Emitter;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 use rcgen::{KeyPair, CertificateParams, Issuer};
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-use rustls::crypto::ring;
+use rustls::crypto::{ring, CryptoProvider};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use rustls::ServerConfig;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use std::collections::HashMap;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::fingerprint;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::upstream::UpstreamConnector;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::client_hints::{self, ClientHints};
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 #[derive(Clone)]
 struct LogHandler {
     app: AppHandle,
     state: Arc<Mutex<AppState>>,
+    /// Window this listener serves, and the profile it was started with
+    label: String,
+    profile: Option<Profile>,
+    /// Set while handling a request for a worker script, whose response gets the mask prelude.
+    /// Hudsucker passes a request and its response to the same handler instance.
+    worker_script: bool,
+    /// Whether sites are reached through the profile's upstream proxy
+    tunnelled: bool,
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -39,11 +59,27 @@ impl HttpHandler for LogHandler {
 
         let _ = self.app.emit("proxy://log", format!("REQ: {} {}", method, url));
 
+        // Hudsucker opens WebSocket connections itself, bypassing the upstream proxy, so
+        // refuse them rather than reveal the real address
+        let websocket = req
+            .headers()
+            .get(http::header::UPGRADE)
+            .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"websocket"));
+        if self.tunnelled && websocket {
+            let _ = self.app.emit("proxy://log", format!("Refused WebSocket {} (can't go through the upstream proxy)", url));
+            let mut res = Response::new(hudsucker::Body::empty());
+            *res.status_mut() = http::StatusCode::BAD_GATEWAY;
+            return RequestOrResponse::Response(res);
+        }
+
         let (mut parts, body) = req.into_parts();
 
+        self.worker_script = is_worker_script_request(&parts.headers);
+
         parts.headers.remove("X-Forwarded-For");
         parts.headers.remove("X-Real-IP");
-        parts.headers.remove("Sec-CH-UA");
+        let secure = parts.uri.scheme_str() == Some("https");
+        rewrite_identity_headers(self.profile.as_ref(), &mut parts.headers, secure);
 
         match body.collect().await {
             Ok(collected) => {
@@ -52,7 +88,9 @@ impl HttpHandler for LogHandler {
 
                 {
                     if let Ok(mut s) = self.state.lock() {
-                        s.last_request = Some(body_str);
+                        if let Some(context) = s.windows.get_mut(&self.label) {
+                            context.last_request = Some(body_str);
+                        }
                     }
                 }
 
@@ -70,52 +108,376 @@ impl HttpHandler for LogHandler {
     async fn handle_response(&mut self, _ctx: &HttpContext, res: Response<hudsucker::Body>) -> Response<hudsucker::Body> {
         let status = res.status();
         let _ = self.app.emit("proxy://log", format!("RES: {}", status));
+
+        if self.worker_script && status.is_success() {
+            return self.inject_worker_prelude(res).await;
+        }
         res
     }
 }
 
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+impl LogHandler {
+    /// Prepends the fingerprint mask to a worker script so its global scope matches the page.
+    /// Service Workers in particular can only be patched here, since they can't be spawned from a blob.
+    async fn inject_worker_prelude(&self, res: Response<hudsucker::Body>) -> Response<hudsucker::Body> {
+        let Some(profile) = &self.profile else {
+            return res;
+        };
+
+        let (parts, body) = res.into_parts();
+        let raw = match body.collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(e) => {
+                // The body is lost at this point; only the status and headers are left to forward
+                let _ = self.app.emit("proxy://log", format!("Error reading worker script: {}", e));
+                return Response::from_parts(parts, hudsucker::Body::empty());
+            }
+        };
+        // The script as it came in, for when it can't be decoded
+        let original = || Response::from_parts(parts.clone(), hudsucker::Body::from(Full::new(raw.clone())));
+
+        let decoded = match hudsucker::decode_response(original()) {
+            Ok(res) => res,
+            Err(e) => {
+                let _ = self.app.emit("proxy://log", format!("Error decoding worker script: {}", e));
+                return original();
+            }
+        };
+
+        let (mut decoded_parts, body) = decoded.into_parts();
+        match body.collect().await {
+            Ok(collected) => {
+                let mut script = fingerprint::generate_worker_prelude(profile).into_bytes();
+                script.extend_from_slice(&collected.to_bytes());
+
+                decoded_parts.headers.remove(http::header::CONTENT_LENGTH);
+                Response::from_parts(decoded_parts, hudsucker::Body::from(Full::new(bytes::Bytes::from(script))))
+            },
+            Err(e) => {
+                let _ = self.app.emit("proxy://log", format!("Error decoding worker script: {}", e));
+                original()
+            }
+        }
+    }
+}
+
+/// Sets the profile's User-Agent and replaces the webview's own `Sec-CH-UA*` headers with
+/// ones derived from it, so the two always change together. High-entropy hints are only
+/// re-emitted if the webview sent them (i.e. the site asked via Accept-CH).
+/// Chromium only sends client hints over https, so plain http requests just lose them.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn rewrite_identity_headers(profile: Option<&Profile>, headers: &mut http::HeaderMap, secure: bool) {
+    let Some(user_agent) = profile.map(|p| p.user_agent.as_str()) else {
+        headers.remove("Sec-CH-UA");
+        return;
+    };
+    if let Ok(value) = http::HeaderValue::try_from(user_agent) {
+        headers.insert(http::header::USER_AGENT, value);
+    }
+
+    let sent: Vec<http::HeaderName> = headers
+        .keys()
+        .filter(|k| k.as_str().starts_with("sec-ch-ua"))
+        .cloned()
+        .collect();
+    for name in &sent {
+        headers.remove(name);
+    }
+
+    if !secure {
+        return;
+    }
+    // Firefox / Safari UAs don't send client hints at all
+    let Some(hints) = ClientHints::from_user_agent(user_agent) else {
+        return;
+    };
+
+    let names = client_hints::DEFAULT_HEADERS
+        .iter()
+        .map(|n| n.to_string())
+        .chain(sent.iter().map(|n| n.as_str().to_string()));
+    for name in names {
+        if let Some(value) = hints.header_value(&name) {
+            if let (Ok(name), Ok(value)) = (http::HeaderName::try_from(name), http::HeaderValue::try_from(value)) {
+                headers.insert(name, value);
+            }
+        }
+    }
+}
+
+/// Chromium marks worker script fetches with `Sec-Fetch-Dest`, and Service Worker
+/// (re)registrations additionally with `Service-Worker: script`.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn is_worker_script_request(headers: &http::HeaderMap) -> bool {
+    let dest = headers
+        .get("Sec-Fetch-Dest")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    matches!(dest, "worker" | "sharedworker" | "serviceworker")
+        || headers.get("Service-Worker").is_some_and(|v| v == "script")
+}
+
+/// The CA every listener signs its per-host certificates with. It is created once and
+/// kept in `<workspace>/ca/`, so the webviews can trust this one certificate instead of
+/// ignoring certificate errors.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+struct ProxyCa {
+    cert_pem: String,
+    key_pem: String,
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+const CA_CERT_FILE: &str = "phantom-ca.pem";
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+const CA_KEY_FILE: &str = "phantom-ca.key";
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn ca_params() -> CertificateParams {
+    let mut params = CertificateParams::default();
+    params.distinguished_name.push(rcgen::DnType::CommonName, "Phantom Browser CA");
+    params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Constrained(0));
+    params
+}
+
+/// Loads the workspace CA, creating it on first use. The certificate is rebuilt from the
+/// same parameters if only the key survived, so it keeps matching what was trusted.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn proxy_ca() -> Result<Arc<ProxyCa>, String> {
+    static CA: Mutex<Option<Arc<ProxyCa>>> = Mutex::new(None);
+
+    let mut ca = CA.lock().unwrap();
+    if let Some(ca) = ca.as_ref() {
+        return Ok(ca.clone());
+    }
+
+    let dir = crate::workspace::ca_dir();
+    let (cert_path, key_path) = (dir.join(CA_CERT_FILE), dir.join(CA_KEY_FILE));
+    let key_pair = match std::fs::read_to_string(&key_path) {
+        Ok(pem) => KeyPair::from_pem(&pem).map_err(|e| format!("Invalid {}: {}", key_path.display(), e))?,
+        Err(_) => {
+            let key_pair = KeyPair::generate().map_err(|e| e.to_string())?;
+            std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+            std::fs::write(&key_path, key_pair.serialize_pem())
+                .map_err(|e| format!("Failed to write {}: {}", key_path.display(), e))?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let _ = std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o600));
+            }
+            let _ = std::fs::remove_file(&cert_path);
+            key_pair
+        }
+    };
+    let cert_pem = match std::fs::read_to_string(&cert_path) {
+        Ok(pem) => pem,
+        Err(_) => {
+            let pem = ca_params().self_signed(&key_pair).map_err(|e| e.to_string())?.pem();
+            std::fs::write(&cert_path, &pem)
+                .map_err(|e| format!("Failed to write {}: {}", cert_path.display(), e))?;
+            pem
+        }
+    };
+
+    let loaded = Arc::new(ProxyCa { cert_pem, key_pem: key_pair.serialize_pem() });
+    *ca = Some(loaded.clone());
+    Ok(loaded)
+}
+
+/// Makes sure the workspace CA exists and, on Windows, that WebView2 trusts it: it is added
+/// to the current user's Root store, which asks the user to confirm the first time.
+/// Runs at startup, before any proxied window loads.
+pub fn init_ca() {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    {
+        if let Err(e) = proxy_ca() {
+            eprintln!("Proxy CA unavailable: {}", e);
+            return;
+        }
+
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+            let cert_path = crate::workspace::ca_dir().join(CA_CERT_FILE);
+            match std::process::Command::new("certutil")
+                .args(["-user", "-addstore", "Root"])
+                .arg(&cert_path)
+                .creation_flags(CREATE_NO_WINDOW)
+                .output()
+            {
+                Ok(out) if out.status.success() => {}
+                Ok(out) => eprintln!(
+                    "Failed to trust the proxy CA: {}",
+                    String::from_utf8_lossy(&out.stdout).trim()
+                ),
+                Err(e) => eprintln!("Failed to trust the proxy CA: {}", e),
+            }
+        }
+    }
+}
+
+/// Signs the certificate a listener presents for each host with the workspace CA.
+/// WebKitGTK can't be given an extra trusted CA, so on Linux each certificate is also
+/// allowed for its host in the window's web context before it is presented.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+struct WindowAuthority {
+    issuer: Issuer<'static, KeyPair>,
+    provider: Arc<CryptoProvider>,
+    configs: Mutex<HashMap<String, Arc<ServerConfig>>>,
+    #[cfg(target_os = "linux")]
+    app: AppHandle,
+    #[cfg(target_os = "linux")]
+    label: String,
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+impl WindowAuthority {
+    fn new(app: AppHandle, label: String) -> Result<Self, String> {
+        let ca = proxy_ca()?;
+        let key_pair = KeyPair::from_pem(&ca.key_pem).map_err(|e| e.to_string())?;
+        #[cfg(not(target_os = "linux"))]
+        let _ = (app, label);
+
+        Ok(Self {
+            issuer: Issuer::new(ca_params(), key_pair),
+            provider: Arc::new(ring::default_provider()),
+            configs: Mutex::new(HashMap::new()),
+            #[cfg(target_os = "linux")]
+            app,
+            #[cfg(target_os = "linux")]
+            label,
+        })
+    }
+
+    /// A fresh leaf certificate for `host` and its PEM.
+    fn server_config(&self, host: &str) -> (Arc<ServerConfig>, String) {
+        let mut params = CertificateParams::new(vec![host.to_string()]).expect("host is a valid subject name");
+        params.distinguished_name.push(rcgen::DnType::CommonName, host);
+        let key_pair = KeyPair::generate().expect("failed to generate a certificate key");
+        let cert = params.signed_by(&key_pair, &self.issuer).expect("failed to sign a certificate");
+
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der()));
+        let mut config = ServerConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()
+            .expect("the ring provider supports the default TLS versions")
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.der().clone()], key)
+            .expect("the certificate matches its key");
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        (Arc::new(config), cert.pem())
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+impl CertificateAuthority for WindowAuthority {
+    async fn gen_server_config(&self, authority: &http::uri::Authority) -> Arc<ServerConfig> {
+        let host = authority.host().trim_start_matches('[').trim_end_matches(']');
+        let cached = self.configs.lock().unwrap().get(host).cloned();
+        if let Some(config) = cached {
+            return config;
+        }
+
+        let (config, cert_pem) = self.server_config(host);
+        #[cfg(target_os = "linux")]
+        allow_certificate(&self.app, &self.label, host, cert_pem).await;
+        #[cfg(not(target_os = "linux"))]
+        let _ = cert_pem;
+
+        self.configs.lock().unwrap().insert(host.to_string(), config.clone());
+        config
+    }
+}
+
+/// Allows `cert_pem` for `host` in the web context of window `label`, and waits until the
+/// main thread has done so (or gives up after a moment, e.g. while the window is closing).
+#[cfg(target_os = "linux")]
+async fn allow_certificate(app: &AppHandle, label: &str, host: &str, cert_pem: String) {
+    use tauri::Manager;
+
+    let Some(window) = app.get_webview_window(label) else { return };
+    let (tx, rx) = oneshot::channel();
+    let host = host.to_string();
+    let queued = window.with_webview(move |webview| {
+        use webkit2gtk::{WebContextExt, WebViewExt};
+        match (webview.inner().context(), gtk::gio::TlsCertificate::from_pem(&cert_pem)) {
+            (Some(context), Ok(cert)) => context.allow_tls_certificate_for_host(&cert, &host),
+            (_, Err(e)) => eprintln!("Invalid proxy certificate for {}: {}", host, e),
+            (None, _) => {}
+        }
+        let _ = tx.send(());
+    });
+    if queued.is_ok() {
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(2), rx).await;
+    }
+}
+
 pub async fn start_proxy(
     app: AppHandle,
     port: u16,
-    _upstream: Option<ProxyConfig>,
+    upstream: Option<ProxyConfig>,
     shutdown_rx: oneshot::Receiver<()>,
-    state: Arc<Mutex<AppState>>
+    state: Arc<Mutex<AppState>>,
+    label: String,
+    profile: Option<Profile>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     #[cfg(any(target_os = "android", target_os = "ios"))]
     {
         // Suppress unused variable warnings
         let _ = app;
         let _ = port;
-        let _ = _upstream;
+        let _ = upstream;
         let _ = shutdown_rx;
         let _ = state;
+        let _ = label;
+        let _ = profile;
         eprintln!("Proxy is not supported on mobile.");
         Ok(())
     }
 
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     {
-        let key_pair = KeyPair::generate()?;
+        let ca = WindowAuthority::new(app.clone(), label.clone())?;
 
-        let mut params = CertificateParams::default();
-        params.distinguished_name.push(rcgen::DnType::CommonName, "Phantom Browser CA");
-        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Constrained(0));
-
-        let issuer = Issuer::new(params, key_pair);
-        let provider = ring::default_provider();
-        let ca = RcgenAuthority::new(issuer, 3650, provider);
-
-        let proxy = Proxy::builder()
+        let handler = LogHandler { app, state, label, profile, worker_script: false, tunnelled: upstream.is_some() };
+        let shutdown = async move {
+            let _ = shutdown_rx.await;
+        };
+        let builder = Proxy::builder()
             .with_addr(SocketAddr::from(([127, 0, 0, 1], port)))
-            .with_ca(ca)
-            .with_rustls_connector(ring::default_provider())
-            .with_http_handler(LogHandler { app, state })
-            .with_graceful_shutdown(async move {
-                let _ = shutdown_rx.await;
-            })
-            .build()?;
+            .with_ca(ca);
 
-        if let Err(e) = proxy.start().await {
+        // Sites are reached through the profile's proxy when it has one, verified against
+        // the usual web roots either way
+        let result = match upstream {
+            Some(upstream) => {
+                let connector = hyper_rustls::HttpsConnectorBuilder::new()
+                    .with_provider_and_webpki_roots(ring::default_provider())?
+                    .https_or_http()
+                    .enable_http1()
+                    .enable_http2()
+                    .wrap_connector(UpstreamConnector::new(upstream));
+                builder
+                    .with_http_connector(connector)
+                    .with_http_handler(handler)
+                    .with_graceful_shutdown(shutdown)
+                    .build()?
+                    .start()
+                    .await
+            }
+            None => {
+                builder
+                    .with_rustls_connector(ring::default_provider())
+                    .with_http_handler(handler)
+                    .with_graceful_shutdown(shutdown)
+                    .build()?
+                    .start()
+                    .await
+            }
+        };
+        if let Err(e) = result {
             eprintln!("Proxy failed: {}", e);
         }
 
@@ -123,58 +485,171 @@ pub async fn start_proxy(
     }
 }
 
-pub async fn restart_proxy(app: AppHandle, state: Arc<Mutex<AppState>>) {
+/// (Re)starts the proxy listener of window `label` with the profile bound to it.
+/// Every window gets its own listener, so concurrent runs keep their own identity.
+pub async fn restart_proxy(app: AppHandle, state: Arc<Mutex<AppState>>, label: &str) {
     #[cfg(any(target_os = "android", target_os = "ios"))]
     {
         let _ = app;
         let _ = state;
+        let _ = label;
         eprintln!("Proxy restart skipped on mobile");
     }
 
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     {
         let mut old_tx = None;
+        let mut previous_port = 0;
         {
             if let Ok(mut s) = state.lock() {
-                old_tx = s.proxy_shutdown_tx.take();
+                if let Some(context) = s.windows.get_mut(label) {
+                    old_tx = context.proxy_shutdown_tx.take();
+                    previous_port = context.proxy_port;
+                }
             }
         }
         if let Some(tx) = old_tx {
             let _ = tx.send(());
         }
 
-        let port = {
-            match TcpListener::bind("127.0.0.1:0") {
+        // An open window stays routed through its port, so take it over once the old
+        // listener has let go of it
+        let mut port = 0;
+        if previous_port > 0 {
+            for _ in 0..20 {
+                if TcpListener::bind(("127.0.0.1", previous_port)).is_ok() {
+                    port = previous_port;
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+        }
+        if port == 0 {
+            port = match TcpListener::bind("127.0.0.1:0") {
                 Ok(l) => l.local_addr().unwrap().port(),
                 Err(_) => 0
-            }
-        };
+            };
+        }
 
         if port == 0 {
             eprintln!("Failed to find free port for proxy");
+            if let Ok(mut s) = state.lock() {
+                if let Some(context) = s.windows.get_mut(label) {
+                    context.proxy_port = 0;
+                }
+            }
             return;
         }
 
+        let profile = state.lock().unwrap().window_profile(label).cloned();
         let proxy_config = {
             let s = state.lock().unwrap();
-            s.current_profile.as_ref().and_then(|p| p.proxy.clone())
+            profile.as_ref().and_then(|p| p.proxy.clone()).map(|mut proxy| {
+                match crate::vault::resolve_proxy_password(&s, &proxy) {
+                    Ok(password) => proxy.password = password,
+                    Err(e) => eprintln!("Proxy password unavailable: {}", e),
+                }
+                proxy
+            })
         };
 
         let (tx, rx) = tokio::sync::oneshot::channel();
         let app_handle = app.clone();
         let state_clone = state.clone();
+        let label_clone = label.to_string();
 
         tokio::spawn(async move {
-            if let Err(e) = start_proxy(app_handle, port, proxy_config, rx, state_clone).await {
+            if let Err(e) = start_proxy(app_handle, port, proxy_config, rx, state_clone, label_clone, profile).await {
                 eprintln!("Proxy error: {}", e);
             }
         });
 
         {
             if let Ok(mut s) = state.lock() {
-                s.proxy_port = port;
-                s.proxy_shutdown_tx = Some(tx);
+                let context = s.windows.entry(label.to_string()).or_default();
+                context.proxy_port = port;
+                context.proxy_shutdown_tx = Some(tx);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fingerprint::FINGERPRINT_VERSION;
+
+    const CHROME_WIN: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36";
+    const FIREFOX_LINUX: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/115.0";
+    /// What WebView2 sends on its own: its real UA and hints, not the profile's
+    const WEBVIEW2_UA: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36 Edg/131.0.0.0";
+
+    fn profile(user_agent: &str) -> Profile {
+        Profile {
+            name: "Shop".into(),
+            user_agent: user_agent.into(),
+            seed: 42,
+            proxy: None,
+            screen: None,
+            webgl: None,
+            fingerprint_version: FINGERPRINT_VERSION,
+            human_input: false,
+        }
+    }
+
+    fn webview2_request() -> http::HeaderMap {
+        let mut headers = http::HeaderMap::new();
+        headers.insert("user-agent", WEBVIEW2_UA.parse().unwrap());
+        headers.insert(
+            "sec-ch-ua",
+            r#""Microsoft Edge";v="131", "Chromium";v="131", "Not_A Brand";v="24""#.parse().unwrap(),
+        );
+        headers.insert("sec-ch-ua-mobile", "?0".parse().unwrap());
+        headers.insert("sec-ch-ua-platform", r#""Windows""#.parse().unwrap());
+        headers.insert("sec-ch-ua-full-version-list", r#""Microsoft Edge";v="131.0.2903.70""#.parse().unwrap());
+        headers.insert("accept", "text/html".parse().unwrap());
+        headers
+    }
+
+    fn header<'a>(headers: &'a http::HeaderMap, name: &str) -> Option<&'a str> {
+        headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    #[test]
+    fn sent_headers_match_the_profile_ua() {
+        let profile = profile(CHROME_WIN);
+        let mut headers = webview2_request();
+        rewrite_identity_headers(Some(&profile), &mut headers, true);
+
+        let hints = ClientHints::from_user_agent(CHROME_WIN).unwrap();
+        assert_eq!(header(&headers, "user-agent"), Some(CHROME_WIN));
+        for name in ["sec-ch-ua", "sec-ch-ua-mobile", "sec-ch-ua-platform", "sec-ch-ua-full-version-list"] {
+            assert_eq!(header(&headers, name), hints.header_value(name).as_deref(), "{name}");
+        }
+        assert!(!header(&headers, "sec-ch-ua").unwrap().contains("Edge"));
+        // High-entropy hints the site didn't ask for stay out
+        assert_eq!(header(&headers, "sec-ch-ua-arch"), None);
+        assert_eq!(header(&headers, "accept"), Some("text/html"));
+    }
+
+    #[test]
+    fn no_hints_over_http_or_for_browsers_without_them() {
+        let mut headers = webview2_request();
+        rewrite_identity_headers(Some(&profile(CHROME_WIN)), &mut headers, false);
+        assert_eq!(header(&headers, "user-agent"), Some(CHROME_WIN));
+        assert!(headers.keys().all(|k| !k.as_str().starts_with("sec-ch-ua")));
+
+        let mut headers = webview2_request();
+        rewrite_identity_headers(Some(&profile(FIREFOX_LINUX)), &mut headers, true);
+        assert_eq!(header(&headers, "user-agent"), Some(FIREFOX_LINUX));
+        assert!(headers.keys().all(|k| !k.as_str().starts_with("sec-ch-ua")));
+    }
+
+    #[test]
+    fn without_a_profile_the_engine_ua_is_kept() {
+        let mut headers = webview2_request();
+        rewrite_identity_headers(None, &mut headers, true);
+        assert_eq!(header(&headers, "user-agent"), Some(WEBVIEW2_UA));
+        assert_eq!(header(&headers, "sec-ch-ua"), None);
+    }
+}
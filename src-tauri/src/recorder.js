@@ -4,45 +4,15 @@
 
     let hoveredElement = null;
 
+    // Prefers ids, test ids, role and name, then text, over CSS paths (see selector.js)
     function getSelector(el) {
-        if (!el) return "";
-        // 1. ID
-        if (el.id) return '#' + CSS.escape(el.id);
-
-        // 2. Attributes
-        if (el.hasAttribute('data-testid')) return `[data-testid="${CSS.escape(el.getAttribute('data-testid'))}"]`;
-        if (el.hasAttribute('aria-label')) return `[aria-label="${CSS.escape(el.getAttribute('aria-label'))}"]`;
-
-        // 3. Class
-        if (el.className && typeof el.className === 'string') {
-            const classes = el.className.split(/\s+/).filter(c => c);
-            if (classes.length > 0) {
-                 const classSel = '.' + classes.map(c => CSS.escape(c)).join('.');
-                 // Check uniqueness
-                 if (document.querySelectorAll(classSel).length === 1) return classSel;
-            }
-        }
+        return window[HELPERS].selector.generate(el);
+    }
 
-        // 4. Path
-        let path = [];
-        let current = el;
-        while (current && current.nodeType === Node.ELEMENT_NODE) {
-            let selector = current.nodeName.toLowerCase();
-            if (current.id) {
-                selector += '#' + CSS.escape(current.id);
-                path.unshift(selector);
-                break; // ID is usually unique enough, stop here
-            } else {
-                let sib = current, nth = 1;
-                while (sib = sib.previousElementSibling) {
-                    if (sib.nodeName.toLowerCase() === selector) nth++;
-                }
-                if (nth !== 1) selector += ":nth-of-type("+nth+")";
-            }
-            path.unshift(selector);
-            current = current.parentNode;
-        }
-        return path.join(" > ");
+    // The element really clicked or typed into, also inside open shadow roots
+    function targetOf(e) {
+        const path = e.composedPath();
+        return path.length ? path[0] : e.target;
     }
 
     // Styles for outline
@@ -76,14 +46,15 @@
         e.preventDefault();
         e.stopPropagation();
 
-        const selector = getSelector(e.target);
+        const target = targetOf(e);
+        const selector = getSelector(target);
 
         window.__TAURI__.core.invoke('recorder_event', {
             event_type: "click",
             selector: selector
         }).then(() => {
             // Re-trigger click
-            e.target.click();
+            target.click();
         });
 
     }, true);
@@ -91,11 +62,13 @@
     // Capture input changes
     document.addEventListener('change', (e) => {
          if (!e.isTrusted) return;
-         const selector = getSelector(e.target);
+         const target = targetOf(e);
+         const selector = getSelector(target);
          window.__TAURI__.core.invoke('recorder_event', {
              event_type: "type",
              selector: selector,
-             value: e.target.value
+             value: target.value,
+             sensitive: target.type === 'password'
          });
     }, true);
 
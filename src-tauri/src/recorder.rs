@@ -2,6 +2,9 @@ use tauri::{AppHandle, Manager, State, Emitter};
 use std::sync::{Arc, Mutex};
 use crate::state::AppState;
 use crate::commands::ensure_target_window;
+use crate::scripting;
+use crate::vault::{Segment, Vault};
+use crate::windows::STUDIO;
 use serde::Deserialize;
 
 #[derive(Deserialize)]
@@ -9,6 +12,52 @@ pub struct RecorderEvent {
     pub event_type: String,
     pub selector: String,
     pub value: Option<String>,
+    /// Set for password fields, whose values must not end up in the script
+    #[serde(default)]
+    pub sensitive: bool,
+}
+
+/// A Rhai string literal holding `value`; selectors like `text="Continue"` have quotes.
+fn rhai_string(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// The Rhai expression for typed text. Secrets in it, whole or in part, are recorded as
+/// vault lookups, never as literals.
+fn typed_text(vault: Option<&Vault>, value: &str, sensitive: bool) -> String {
+    let lookup = |name: &str| format!("secrets.get({})", rhai_string(name));
+    if let Some(name) = vault.and_then(|v| v.name_of(value)) {
+        return lookup(name);
+    }
+    let segments = vault.map(|v| v.split(value)).unwrap_or_default();
+    if segments.iter().any(|s| matches!(s, Segment::Secret(_))) {
+        let parts: Vec<String> = segments
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => rhai_string(text),
+                Segment::Secret(name) => lookup(name),
+            })
+            .collect();
+        return parts.join(" + ");
+    }
+    if sensitive {
+        "secrets.get(\"password\") /* add this secret to the vault */".to_string()
+    } else {
+        rhai_string(value)
+    }
 }
 
 #[tauri::command]
@@ -19,11 +68,10 @@ pub async fn start_recording(app: AppHandle, state: State<'_, Arc<Mutex<AppState
     }
 
     // 1. Ensure target window exists
-    let label = "target-studio";
-    let window = ensure_target_window(&app, label).ok_or("Could not find target window")?;
+    let window = ensure_target_window(&app, STUDIO).ok_or("Could not find target window")?;
 
-    // 2. Inject recorder.js
-    let js = include_str!("recorder.js");
+    // 2. Inject recorder.js, with the selector engine it generates selectors with
+    let js = scripting::page_script(include_str!("selector.js"), include_str!("recorder.js"));
     window.eval(js).map_err(|e| e.to_string())?;
 
     Ok(())
@@ -49,16 +97,16 @@ pub async fn recorder_event(event: RecorderEvent, state: State<'_, Arc<Mutex<App
 
     match event.event_type.as_str() {
         "click" => {
-            script_line = format!(
-                "browser.wait_for_selector(\"{}\");\nbrowser.click(\"{}\");\n",
-                event.selector, event.selector
-            );
+            let selector = rhai_string(&event.selector);
+            script_line = format!("browser.wait_for_selector({});\nbrowser.click({});\n", selector, selector);
         },
         "type" => {
             if let Some(val) = event.value {
+                 let text = typed_text(state.lock().unwrap().vault.as_ref(), &val, event.sensitive);
+                 let selector = rhai_string(&event.selector);
                  script_line = format!(
-                    "browser.wait_for_selector(\"{}\");\nbrowser.type(\"{}\", \"{}\");\n",
-                    event.selector, event.selector, val
+                    "browser.wait_for_selector({});\nbrowser.type({}, {});\n",
+                    selector, selector, text
                 );
             }
         },
@@ -76,3 +124,41 @@ pub async fn recorder_event(event: RecorderEvent, state: State<'_, Arc<Mutex<App
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_strings_are_valid_rhai_literals() {
+        let cases = [
+            ("#login", r##""#login""##),
+            ("input[name=\"q\"]", r##""input[name=\"q\"]""##),
+            ("C:\\temp", r##""C:\\temp""##),
+            ("a\nb\tc\r", r##""a\nb\tc\r""##),
+            ("`${x}` ünï", r##""`${x}` ünï""##),
+            ("", r##""""##),
+        ];
+        let engine = rhai::Engine::new();
+        for (value, literal) in cases {
+            assert_eq!(rhai_string(value), literal, "{value}");
+            assert_eq!(engine.eval::<String>(literal).unwrap(), value, "{value}");
+        }
+    }
+
+    #[test]
+    fn typed_secrets_become_vault_lookups() {
+        let vault = Vault::with_secrets(&[("api", "hunter22"), ("pin", "42")]);
+        for (value, sensitive, text) in [
+            ("hunter22", false, r#"secrets.get("api")"#),
+            ("42", true, r#"secrets.get("pin")"#),
+            ("Bearer hunter22", false, r#""Bearer " + secrets.get("api")"#),
+            ("hunter22-hunter22!", true, r#"secrets.get("api") + "-" + secrets.get("api") + "!""#),
+            ("plain 42", false, r#""plain 42""#),
+            ("unknown", true, r#"secrets.get("password") /* add this secret to the vault */"#),
+        ] {
+            assert_eq!(typed_text(Some(&vault), value, sensitive), text, "{value}");
+        }
+        assert_eq!(typed_text(None, "hunter22", false), r#""hunter22""#);
+    }
+}
@@ -0,0 +1,323 @@
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+use uuid::Uuid;
+use crate::commands;
+use crate::debugger;
+use crate::error::ReplError;
+use crate::policy::{self, RunGuard};
+use crate::pool::WindowSlot;
+use crate::runs::RunSource;
+use crate::scripting::{self, Prepared, ScriptInputs};
+use crate::state::{AppState, Profile};
+use crate::vault;
+use crate::windows::{self, STUDIO};
+
+/// Windows opened for API and CLI sessions are labelled `repl-<id>`
+pub const WINDOW_PREFIX: &str = "repl-";
+
+/// Each API or CLI session holds a window open, so their number is capped.
+const MAX_SESSIONS: usize = 8;
+
+/// API sessions nobody has used for this long are closed, window and all.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// What evaluating one piece of code produced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplOutput {
+    /// Lines printed by the code, in order
+    pub prints: Vec<String>,
+    /// The value of the last statement as JSON, `null` when it has none
+    pub value: Value,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// The value as Rhai shows it, e.g. with strings quoted; empty for `()`
+    pub display: String,
+    pub error: Option<String>,
+}
+
+/// A REPL session on one window.
+pub struct ReplSession {
+    /// The window `browser` drives
+    pub window: String,
+    /// Closing the session closes the window too; false for the Studio window
+    owns_window: bool,
+    /// Held while code is evaluated, so statements run one at a time
+    runtime: Mutex<Runtime>,
+    last_used: Mutex<Instant>,
+    /// The pool window the session's own window counts as
+    _slot: Option<WindowSlot>,
+}
+
+impl ReplSession {
+    /// Time since the last evaluation ended; zero while one is running.
+    fn idle_for(&self) -> Duration {
+        match self.runtime.try_lock() {
+            Ok(_) => self.last_used.lock().unwrap().elapsed(),
+            Err(_) => Duration::ZERO,
+        }
+    }
+}
+
+/// An engine and scope kept between evaluations, so variables, functions and the page
+/// carry over from one statement to the next.
+struct Runtime {
+    state: Arc<Mutex<AppState>>,
+    engine: Engine,
+    scope: Scope<'static>,
+    /// Functions defined so far
+    functions: AST,
+    guard: Arc<RunGuard>,
+    prints: Arc<Mutex<Vec<String>>>,
+}
+
+impl Runtime {
+    /// Collects the engine's prints, with secrets masked, for the output of each evaluation.
+    fn new(state: Arc<Mutex<AppState>>, mut engine: Engine, scope: Scope<'static>, guard: Arc<RunGuard>) -> Self {
+        // Prints belong to the output of the statement that made them
+        let prints = Arc::new(Mutex::new(Vec::new()));
+        let (sink, state_for_prints) = (prints.clone(), state.clone());
+        engine.on_print(move |s| {
+            let line = vault::redact(&state_for_prints.lock().unwrap(), s);
+            sink.lock().unwrap().push(line);
+        });
+        Self { state, engine, scope, functions: AST::empty(), guard, prints }
+    }
+
+    /// Evaluates one or more statements. Time and navigation limits apply to each call
+    /// on its own rather than to the whole session.
+    pub fn eval(&mut self, code: &str) -> ReplOutput {
+        self.prints.lock().unwrap().clear();
+        self.guard.restart();
+
+        let result = match self.engine.compile_with_scope(&self.scope, code) {
+            Ok(ast) => {
+                let ast = self.functions.merge(&ast);
+                let result = self.engine.eval_ast_with_scope::<Dynamic>(&mut self.scope, &ast);
+                self.functions = ast.clone_functions_only();
+                result.map_err(|e| self.guard.explain(e))
+            }
+            Err(e) => Err(Box::<EvalAltResult>::from(e)),
+        };
+
+        let redact = |text: String| vault::redact(&self.state.lock().unwrap(), &text);
+        let prints = std::mem::take(&mut *self.prints.lock().unwrap());
+        match result {
+            Ok(value) => ReplOutput {
+                prints,
+                value: if value.is_unit() { Value::Null } else { debugger::describe(&value, &redact) },
+                type_name: value.type_name().to_string(),
+                display: if value.is_unit() { String::new() } else { format!("{:?}", debugger::redact_strings(value.clone(), &redact)) },
+                error: None,
+            },
+            Err(e) => ReplOutput {
+                prints,
+                value: Value::Null,
+                type_name: "()".into(),
+                display: String::new(),
+                error: Some(redact(e.to_string())),
+            },
+        }
+    }
+}
+
+/// Opens a session on a window that is already open, with the policy of `source`.
+/// `slot` is the pool window a window opened for the session takes up.
+pub fn start(
+    app: &AppHandle,
+    state: &Arc<Mutex<AppState>>,
+    source: RunSource,
+    label: &str,
+    slot: Option<WindowSlot>,
+) -> Result<String, ReplError> {
+    let window = app
+        .get_webview_window(label)
+        .ok_or_else(|| ReplError::Window(format!("'{}' is not open", label)))?;
+    let profile = state.lock().unwrap().window_profile(label).cloned();
+    let policy = policy::for_source(source);
+    let Prepared { engine, scope, guard } =
+        scripting::prepare(window, state.clone(), profile, ScriptInputs::default(), policy, None);
+
+    let runtime = Runtime::new(state.clone(), engine, scope, guard);
+    let session = ReplSession {
+        window: label.to_string(),
+        owns_window: slot.is_some(),
+        runtime: Mutex::new(runtime),
+        last_used: Mutex::new(Instant::now()),
+        _slot: slot,
+    };
+    let id = Uuid::new_v4().to_string();
+    state.lock().unwrap().repl_sessions.insert(id.clone(), Arc::new(session));
+    Ok(id)
+}
+
+/// Opens a hidden window under `profile` and a session on it, for the API and `--repl`.
+/// The window takes one of the pool's, so it counts against `--max-windows`.
+pub async fn start_in_new_window(
+    app: &AppHandle,
+    state: &Arc<Mutex<AppState>>,
+    source: RunSource,
+    profile: Option<Profile>,
+) -> Result<String, ReplError> {
+    let (open, pool) = {
+        let s = state.lock().unwrap();
+        (s.windows.keys().filter(|l| l.starts_with(WINDOW_PREFIX)).count(), s.pool.clone())
+    };
+    if open >= MAX_SESSIONS {
+        return Err(ReplError::TooMany(MAX_SESSIONS));
+    }
+    let slot = pool.hold_window().ok_or(ReplError::PoolBusy(pool.max_windows()))?;
+
+    let label = format!("{}{}", WINDOW_PREFIX, Uuid::new_v4().simple());
+    windows::open_run_window(app, state, &label, profile, None).await.map_err(ReplError::Window)?;
+    let id = start(app, state, source, &label, Some(slot)).inspect_err(|_| windows::close(app, state, &label))?;
+    if source == RunSource::Api {
+        close_when_idle(app.clone(), state.clone(), id.clone());
+    }
+    Ok(id)
+}
+
+/// Closes the session once it has been idle for `IDLE_TIMEOUT`, so a client that goes
+/// away without closing it doesn't hold its window forever.
+fn close_when_idle(app: AppHandle, state: Arc<Mutex<AppState>>, id: String) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let idle = {
+                let s = state.lock().unwrap();
+                match s.repl_sessions.get(&id) {
+                    Some(session) => session.idle_for(),
+                    // Closed already
+                    None => return,
+                }
+            };
+            if idle >= IDLE_TIMEOUT {
+                eprintln!("Closing REPL session {} after {} minutes without use", id, IDLE_TIMEOUT.as_secs() / 60);
+                let _ = close(&app, &state, &id);
+                return;
+            }
+            tokio::time::sleep(IDLE_TIMEOUT - idle).await;
+        }
+    });
+}
+
+/// Evaluates code in a session. Blocks while the code waits on the page.
+pub fn eval(state: &Arc<Mutex<AppState>>, id: &str, code: &str) -> Result<ReplOutput, ReplError> {
+    let session = state
+        .lock()
+        .unwrap()
+        .repl_sessions
+        .get(id)
+        .cloned()
+        .ok_or_else(|| ReplError::NotFound(id.to_string()))?;
+    let output = session.runtime.lock().unwrap().eval(code);
+    *session.last_used.lock().unwrap() = Instant::now();
+    Ok(output)
+}
+
+/// Evaluates on a blocking thread, so the code can wait on page callbacks.
+pub async fn eval_blocking(state: Arc<Mutex<AppState>>, id: String, code: String) -> Result<ReplOutput, ReplError> {
+    tauri::async_runtime::spawn_blocking(move || eval(&state, &id, &code))
+        .await
+        .unwrap_or_else(|e| Err(ReplError::Failed(e.to_string())))
+}
+
+/// Ends a session, closing its window if the session opened it.
+pub fn close(app: &AppHandle, state: &Arc<Mutex<AppState>>, id: &str) -> Result<(), ReplError> {
+    let session = state
+        .lock()
+        .unwrap()
+        .repl_sessions
+        .remove(id)
+        .ok_or_else(|| ReplError::NotFound(id.to_string()))?;
+    if session.owns_window {
+        windows::close(app, state, &session.window);
+    }
+    Ok(())
+}
+
+/// Opens a session on the Studio window, under the profile selected in the UI.
+#[tauri::command]
+pub async fn repl_start(app: AppHandle, state: State<'_, Arc<Mutex<AppState>>>) -> Result<String, ReplError> {
+    if !commands::open_studio(&app, state.inner()).await {
+        return Err(ReplError::Window("could not create the target window".into()));
+    }
+    start(&app, state.inner(), RunSource::Ui, STUDIO, None)
+}
+
+#[tauri::command]
+pub async fn repl_eval(
+    session_id: String,
+    code: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+) -> Result<ReplOutput, ReplError> {
+    eval_blocking(state.inner().clone(), session_id, code).await
+}
+
+#[tauri::command]
+pub fn repl_close(session_id: String, app: AppHandle, state: State<'_, Arc<Mutex<AppState>>>) -> Result<(), ReplError> {
+    close(&app, state.inner(), &session_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::ScriptPolicy;
+    use crate::vault::Vault;
+
+    /// A runtime without the browser API, which none of these statements need.
+    fn runtime(policy: ScriptPolicy) -> Runtime {
+        let state = AppState { vault: Some(Vault::with_secrets(&[("api", "hunter22")])), ..AppState::default() };
+        let guard = Arc::new(RunGuard::new(policy));
+        let mut engine = Engine::new();
+        guard.apply(&mut engine);
+        Runtime::new(Arc::new(Mutex::new(state)), engine, Scope::new(), guard)
+    }
+
+    #[test]
+    fn variables_and_functions_carry_over() {
+        let mut rt = runtime(ScriptPolicy::default());
+        for code in ["let total = 40;", "fn add(a, b) { a + b }", "fn twice(x) { add(x, x) }"] {
+            let out = rt.eval(code);
+            assert_eq!(out.error, None, "{}", code);
+            assert_eq!(out.value, Value::Null, "{}", code);
+        }
+        let out = rt.eval("total = add(total, 1); twice(total) - total");
+        assert_eq!(out.error, None);
+        assert_eq!((out.value, out.type_name.as_str(), out.display.as_str()), (serde_json::json!(41), "i64", "41"));
+
+        // A failed statement keeps what came before it
+        assert!(rt.eval("let later = 1; undefined_fn()").error.is_some());
+        assert_eq!(rt.eval("total + later").value, serde_json::json!(42));
+    }
+
+    #[test]
+    fn time_limit_applies_to_each_statement() {
+        let mut rt = runtime(ScriptPolicy { max_runtime_secs: Some(1), ..ScriptPolicy::default() });
+        assert_eq!(rt.eval("let n = 0;").error, None);
+        std::thread::sleep(Duration::from_millis(1100));
+        // Past the first statement's deadline, but this one gets its own
+        let out = rt.eval("for i in 0..10000 { n += i } n");
+        assert_eq!(out.error, None);
+        assert_eq!(out.value, serde_json::json!(49995000));
+
+        let out = rt.eval("loop { n += 1 }");
+        assert!(out.error.is_some_and(|e| e.contains("1s limit")), "a runaway loop is stopped");
+        assert_eq!(rt.eval("n > 0").value, serde_json::json!(true), "the session survives it");
+    }
+
+    #[test]
+    fn secrets_are_redacted_from_the_output() {
+        let mut rt = runtime(ScriptPolicy::default());
+        let out = rt.eval(r#"print("pw: hunter22"); print("ok"); #{ token: "Bearer hunter22" }"#);
+        assert_eq!(out.prints, ["pw: ***", "ok"]);
+        assert_eq!(out.value, serde_json::json!({ "token": "Bearer ***" }));
+        assert!(!out.display.contains("hunter22") && out.display.contains("Bearer ***"), "{}", out.display);
+
+        let out = rt.eval(r#"throw "rejected hunter22""#);
+        let error = out.error.unwrap();
+        assert!(!error.contains("hunter22") && error.contains("rejected ***"), "{}", error);
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tauri::State;
+use crate::error::OutputError;
+use crate::state::AppState;
+
+/// Finished runs beyond this many are forgotten, oldest first.
+const MAX_RUNS: usize = 500;
+
+/// Records a run may `output.push` into the registry.
+const MAX_RECORDS: usize = 10_000;
+
+/// Where a run was started from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunSource {
+    Ui,
+    Api,
+    Cron,
+    Cli,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunRecord {
+    pub id: String,
+    pub source: RunSource,
+    pub profile: Option<String>,
+    pub status: RunStatus,
+    pub submitted_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    /// The script's return value
+    pub result: Option<Value>,
+    /// Records pushed to the registry sink; fetch them with `get_run_records`
+    pub records: usize,
+}
+
+/// Recent runs from every entry point, kept in memory.
+#[derive(Default)]
+pub struct RunRegistry {
+    runs: VecDeque<RunRecord>,
+    /// `output.push` records by run id
+    records: HashMap<String, Vec<Value>>,
+}
+
+impl RunRegistry {
+    /// Registers a queued run and returns its id.
+    pub fn start(&mut self, source: RunSource, profile: Option<&str>) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        if self.runs.len() >= MAX_RUNS {
+            // Drop the oldest finished run; unfinished ones are still referenced
+            if let Some(idx) = self.runs.iter().position(|r| r.finished_at.is_some()) {
+                if let Some(old) = self.runs.remove(idx) {
+                    self.records.remove(&old.id);
+                }
+            }
+        }
+        self.runs.push_back(RunRecord {
+            id: id.clone(),
+            source,
+            profile: profile.map(String::from),
+            status: RunStatus::Queued,
+            submitted_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            error: None,
+            result: None,
+            records: 0,
+        });
+        id
+    }
+
+    fn get_mut(&mut self, id: &str) -> Option<&mut RunRecord> {
+        self.runs.iter_mut().find(|r| r.id == id)
+    }
+
+    pub fn set_running(&mut self, id: &str) {
+        if let Some(run) = self.get_mut(id) {
+            run.status = RunStatus::Running;
+            run.started_at = Some(Utc::now());
+        }
+    }
+
+    pub fn finish(&mut self, id: &str, outcome: Result<Value, String>) {
+        if let Some(run) = self.get_mut(id) {
+            run.finished_at = Some(Utc::now());
+            match outcome {
+                Ok(result) => {
+                    run.status = RunStatus::Succeeded;
+                    run.result = Some(result);
+                }
+                Err(e) => {
+                    run.status = RunStatus::Failed;
+                    run.error = Some(e);
+                }
+            }
+        }
+    }
+
+    pub fn push_record(&mut self, id: &str, record: Value) -> Result<(), OutputError> {
+        let Some(run) = self.get_mut(id) else { return Err(OutputError::Unavailable) };
+        if run.records >= MAX_RECORDS {
+            return Err(OutputError::RegistryFull(MAX_RECORDS));
+        }
+        run.records += 1;
+        self.records.entry(id.to_string()).or_default().push(record);
+        Ok(())
+    }
+
+    /// Records the run pushed, `None` for an unknown run.
+    pub fn records(&self, id: &str) -> Option<Vec<Value>> {
+        self.runs.iter().find(|r| r.id == id)?;
+        Some(self.records.get(id).cloned().unwrap_or_default())
+    }
+
+    pub fn get(&self, id: &str) -> Option<RunRecord> {
+        self.runs.iter().find(|r| r.id == id).cloned()
+    }
+
+    /// Newest first.
+    pub fn list(&self) -> Vec<RunRecord> {
+        self.runs.iter().rev().cloned().collect()
+    }
+}
+
+#[tauri::command]
+pub fn list_runs(state: State<'_, Arc<Mutex<AppState>>>) -> Vec<RunRecord> {
+    state.lock().unwrap().runs.list()
+}
+
+#[tauri::command]
+pub fn get_run(run_id: String, state: State<'_, Arc<Mutex<AppState>>>) -> Option<RunRecord> {
+    state.lock().unwrap().runs.get(&run_id)
+}
+
+#[tauri::command]
+pub fn get_run_records(run_id: String, state: State<'_, Arc<Mutex<AppState>>>) -> Option<Vec<Value>> {
+    state.lock().unwrap().runs.records(&run_id)
+}
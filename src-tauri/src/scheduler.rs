@@ -1,11 +1,21 @@
 use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
-use tauri::{AppHandle, Manager, WebviewWindowBuilder, WebviewUrl, Url, State};
+use tauri::{AppHandle, Manager, State};
 use crate::state::AppState;
-use crate::scripting;
+use crate::pool::{self, PoolJob};
+use crate::output::{OutputSink, SinkConfig};
+use crate::modules;
+use crate::params;
+use crate::validate;
+use crate::runs::RunSource;
+use crate::scripting::ScriptInputs;
+use crate::commands;
+use crate::session;
+use crate::vault;
+use crate::workspace;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use chrono::Utc;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -14,15 +24,24 @@ pub struct PhantomJob {
     pub script_path: String,
     pub cron: String,
     pub profile: Option<String>,
+    /// Saved session of `profile` to restore before the script runs
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Passed to the script as `params`
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub params: serde_json::Map<String, serde_json::Value>,
+    /// Sink for `output.push`; relative paths are under the workspace outputs folder
+    #[serde(default)]
+    pub output: SinkConfig,
     pub last_run: Option<String>,
     pub status: String, // "active", "paused"
 }
 
 fn get_jobs_path() -> PathBuf {
-    Path::new("jobs.json").to_path_buf()
+    workspace::jobs_path()
 }
 
-fn load_jobs_from_disk() -> Vec<PhantomJob> {
+pub(crate) fn load_jobs_from_disk() -> Vec<PhantomJob> {
     let path = get_jobs_path();
     if path.exists() {
         if let Ok(content) = fs::read_to_string(&path) {
@@ -34,7 +53,7 @@ fn load_jobs_from_disk() -> Vec<PhantomJob> {
     Vec::new()
 }
 
-fn save_jobs_to_disk(jobs: &[PhantomJob]) {
+pub(crate) fn save_jobs_to_disk(jobs: &[PhantomJob]) {
     let path = get_jobs_path();
     let json = serde_json::to_string_pretty(jobs).unwrap_or_default();
     let _ = fs::write(path, json);
@@ -81,7 +100,8 @@ async fn schedule_job(sched: &JobScheduler, job: PhantomJob, app: AppHandle) {
 async fn run_job(app: AppHandle, job: PhantomJob) {
     println!("Executing Job: {}", job.id);
 
-    let script_content = match fs::read_to_string(Path::new("scripts").join(&job.script_path)) {
+    let script = modules::script_path(&job.script_path).and_then(|path| fs::read_to_string(path).map_err(|e| e.to_string()));
+    let script_content = match script {
         Ok(c) => c,
         Err(e) => {
             eprintln!("Job {} failed to read script: {}", job.id, e);
@@ -89,46 +109,80 @@ async fn run_job(app: AppHandle, job: PhantomJob) {
         }
     };
 
-    let run_id = uuid::Uuid::new_v4();
-    let label = format!("job-{}-{}", job.id, run_id);
-
-    let url = Url::parse("about:blank").unwrap();
-    let builder = WebviewWindowBuilder::new(
-        &app,
-        &label,
-        WebviewUrl::External(url)
-    )
-    .inner_size(1920.0, 1080.0)
-    .visible(false);
+    // The script may have changed since the job was saved
+    let params = match params::resolve(&script_content, job.params.clone()) {
+        Ok(params) => params,
+        Err(e) => {
+            eprintln!("Job {}: {}", job.id, e);
+            return;
+        }
+    };
 
-    let window = match builder.build() {
-        Ok(w) => w,
+    // jobs.json may have been edited by hand since the job was saved
+    let output = match job.output.check_relative().and_then(|_| OutputSink::open(&job.output, &workspace::outputs_dir())) {
+        Ok(output) => output,
         Err(e) => {
-             eprintln!("Job {} failed to create window: {}", job.id, e);
-             return;
+            eprintln!("Job {}: {}", job.id, e);
+            return;
         }
     };
 
-    let state_handle = app.state::<Arc<Mutex<AppState>>>();
-    let state = state_handle.inner().clone();
+    let profile = match &job.profile {
+        Some(name) => match commands::find_profile(name) {
+            Some(profile) => Some(profile),
+            None => {
+                eprintln!("Job {} profile '{}' not found", job.id, name);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let snapshot = match (&profile, &job.session) {
+        (Some(profile), Some(name)) => match session::load(&profile.name, name) {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                eprintln!("Job {}: {}", job.id, e);
+                return;
+            }
+        },
+        (None, Some(_)) => {
+            eprintln!("Job {} has a session but no profile", job.id);
+            return;
+        }
+        _ => None,
+    };
 
-    let result = tokio::task::spawn_blocking(move || {
-        scripting::execute(script_content, window, state, None)
-    }).await;
+    // Runs in a pooled window under the job's own profile, whatever the UI has selected
+    let state_handle = app.state::<Arc<Mutex<AppState>>>();
+    let pool_job = PoolJob {
+        source: RunSource::Cron,
+        profile,
+        session: snapshot,
+        script: script_content,
+        inputs: ScriptInputs { params: params::to_script_map(&params), input: None, output, debug: None },
+        emit_logs: false,
+    };
+    let done = match pool::submit(&app, state_handle.inner(), pool_job) {
+        Ok((_, done)) => done,
+        Err(e) => {
+            eprintln!("Job {} skipped: {}", job.id, e);
+            return;
+        }
+    };
 
-    match result {
+    match done.await {
         Ok(exec_res) => {
              match exec_res {
-                 Ok(val) => println!("Job {} finished: {:?}", job.id, val),
+                 Ok(val) => {
+                     let result = vault::redact(&state_handle.lock().unwrap(), &format!("{:?}", val));
+                     println!("Job {} finished: {}", job.id, result);
+                 }
                  Err(e) => println!("Job {} error: {}", job.id, e),
              }
         },
         Err(e) => println!("Job {} join error: {}", job.id, e),
     }
-
-    if let Some(w) = app.get_webview_window(&label) {
-        let _ = w.close();
-    }
 }
 
 #[tauri::command]
@@ -138,6 +192,16 @@ pub fn list_jobs() -> Vec<PhantomJob> {
 
 #[tauri::command]
 pub async fn save_job(job: PhantomJob, app: AppHandle, state: State<'_, Arc<Mutex<AppState>>>) -> Result<(), String> {
+    // Check the script, params and output now rather than when the cron fires
+    job.output.check_relative().map_err(|e| e.to_string())?;
+    let script = fs::read_to_string(modules::script_path(&job.script_path)?)
+        .map_err(|e| format!("Cannot read script '{}': {}", job.script_path, e))?;
+    params::resolve(&script, job.params.clone()).map_err(|e| e.to_string())?;
+    let validation = validate::validate(&script);
+    if !validation.valid {
+        return Err(format!("Script '{}' has errors: {}", job.script_path, validation.error_summary()));
+    }
+
     let mut jobs = load_jobs_from_disk();
     if let Some(idx) = jobs.iter().position(|j| j.id == job.id) {
         jobs[idx] = job.clone();
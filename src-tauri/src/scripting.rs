@@ -1,112 +1,368 @@
-use rhai::{Engine, Scope};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope};
 use tauri::{AppHandle, Manager, WebviewWindow, Emitter};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, LazyLock, Mutex};
 use std::thread;
 use std::sync::mpsc::channel;
+use std::time::Duration;
 use uuid::Uuid;
-use crate::state::{AppState, ProxyConfig};
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+use serde_json::{json, Value};
+use crate::cookies::{self, CookieFormat, CookieRecord};
+use crate::debugger::{self, DebugSession};
+use crate::session;
+use crate::error::{ActionError, OutputError, VaultError};
+use crate::fingerprint;
+use crate::humanize::{self, Humanizer, Step, Target};
+use crate::modules::ScriptResolver;
+use crate::native_input::{self, Pointer};
+use crate::output::OutputSink;
+use crate::policy::{self, RunGuard, ScriptPolicy};
+use crate::runs::RunSource;
+use crate::state::{AppState, Profile, ProxyConfig};
+use crate::vault;
+use crate::workspace;
 use url::Url;
 
+/// Page side of the browser API: the selector engine and the interactions
+const PAGE_HELPERS: &str = concat!(include_str!("selector.js"), include_str!("input.js"));
+
+/// Where the page helpers live on `window`, chosen per launch so pages can't look for it
+static HELPERS_KEY: LazyLock<String> = LazyLock::new(|| format!("_{}", Uuid::new_v4().simple()));
+
+/// Wraps `helpers` and `js` in a function that gets the helpers' key as `HELPERS`, so
+/// nothing but the hidden helpers lands on `window`.
+pub(crate) fn page_script(helpers: &str, js: &str) -> String {
+    format!("(function (HELPERS) {{\n{}\n{}\n}})({});", helpers, js, json!(*HELPERS_KEY))
+}
+
+/// How long interactions wait for their element to become actionable
+const ACTION_TIMEOUT_MS: u64 = 5000;
+
+/// Largest total size `set_input_files` hands to the page
+const MAX_UPLOAD_BYTES: usize = 50 * 1024 * 1024;
+
+/// What an element must be before it gets pointer input (see `problem` in input.js)
+const POINTER: &[&str] = &["visible", "enabled", "stable"];
+const EDITABLE: &[&str] = &["visible", "editable", "stable"];
+
 #[derive(Clone)]
 pub struct BrowserApi {
     window: WebviewWindow,
     state: Arc<Mutex<AppState>>,
+    /// Profile the window was opened with, if any
+    profile: Option<Profile>,
+    guard: Arc<RunGuard>,
+    /// Set when the run is debugged, to show page query results when paused
+    debug: Option<Arc<DebugSession>>,
+    /// Plans mouse, keyboard and wheel input when `human` is set
+    humanizer: Arc<Humanizer>,
+    human: bool,
+    /// Where native input last left the pointer
+    pointer: Arc<Mutex<Pointer>>,
 }
 
 impl BrowserApi {
-    pub fn new(window: WebviewWindow, state: Arc<Mutex<AppState>>) -> Self {
-        Self { window, state }
-    }
-
-    pub fn navigate(&mut self, url: &str) {
-        let js = format!("window.location.href = '{}';", url);
-        let _ = self.window.eval(&js);
+    pub fn new(window: WebviewWindow, state: Arc<Mutex<AppState>>, profile: Option<Profile>, guard: Arc<RunGuard>) -> Self {
+        // Without a profile the habits differ from run to run
+        let seed = profile.as_ref().map_or_else(|| Uuid::new_v4().as_u64_pair().0, |p| p.seed);
+        let human = profile.as_ref().is_some_and(|p| p.human_input);
+        // Without a profile the page sees the host's own platform
+        let apple = profile.as_ref().map_or(cfg!(target_os = "macos"), |p| {
+            matches!(fingerprint::navigator_platform(&p.user_agent), Some("MacIntel" | "iPhone" | "iPad"))
+        });
+        Self {
+            window,
+            state,
+            profile,
+            guard,
+            debug: None,
+            humanizer: Arc::new(Humanizer::new(seed, apple)),
+            human,
+            pointer: Arc::default(),
+        }
     }
 
-    pub fn click(&mut self, selector: &str) {
-        let js = format!("document.querySelector('{}')?.click();", selector);
-        let _ = self.window.eval(&js);
+    /// Runs `js` in the page with the selector engine and interactions installed.
+    fn eval_page(&self, js: &str) {
+        let _ = self.window.eval(page_script(PAGE_HELPERS, js));
     }
 
-    pub fn wait_for_selector(&mut self, selector: &str) {
+    /// Evaluates `body`, an expression that may be a promise, and waits for its value:
+    /// until the run's deadline, or at most `limit`. `None` if the page went away before
+    /// answering; an exception in the page fails the call with its message.
+    fn call_page(&self, body: &str, limit: Option<Duration>) -> Result<Option<Value>, Box<EvalAltResult>> {
         let (tx, rx) = channel();
         let id = Uuid::new_v4().to_string();
-
-        {
-            let mut state = self.state.lock().unwrap();
-            state.pending_callbacks.insert(id.clone(), tx);
-        }
-
-        let js = format!(r#"
-            (function() {{
-                const sel = "{}";
-                const id = "{}";
-                const check = () => {{
-                    if (document.querySelector(sel)) {{
-                        window.__TAURI__.core.invoke('script_callback', {{ id: id, data: true }});
-                        return true;
-                    }}
-                    return false;
-                }};
-                if (!check()) {{
-                    const observer = new MutationObserver(() => {{
-                        if (check()) observer.disconnect();
-                    }});
-                    observer.observe(document.body, {{ childList: true, subtree: true }});
+        self.state.lock().unwrap().pending_callbacks.insert(id.clone(), tx);
+
+        self.eval_page(&format!(r#"
+            (async function() {{
+                let data;
+                try {{
+                    data = {{ ok: await ({}) }};
+                }} catch (e) {{
+                    data = {{ error: String(e && e.message || e) }};
                 }}
+                window.__TAURI__.core.invoke('script_callback', {{ id: "{}", data: data }});
             }})()
-        "#, selector, id);
+        "#, body, id));
+
+        let received = match limit {
+            Some(limit) => self.guard.recv_within(&rx, limit),
+            None => self.guard.recv(&rx),
+        };
+        self.state.lock().unwrap().pending_callbacks.remove(&id);
+        match received? {
+            Some(mut answer) => match answer.get("error").and_then(Value::as_str) {
+                Some(error) => Err(error.into()),
+                None => Ok(answer.get_mut("ok").map(Value::take)),
+            },
+            None => Ok(None),
+        }
+    }
 
-        let _ = self.window.eval(&js);
+    /// Plays input steps through the webview's native input, with their delays, so the
+    /// page gets trusted events.
+    fn play(&self, steps: Vec<Step>) -> Result<(), Box<EvalAltResult>> {
+        for step in &steps {
+            let events = native_input::events(step, &mut self.pointer.lock().unwrap());
+            for (delay, input) in events {
+                self.guard.sleep(Duration::from_millis(delay))?;
+                native_input::deliver(&self.window, input).map_err(|e| ActionError::Input(e).to_string())?;
+            }
+        }
+        Ok(())
+    }
 
-        let _ = rx.recv();
+    /// Waits until the element passes `checks`, then runs `op` on it (see `OPS` in input.js).
+    fn act(&self, selector: &str, checks: &[&str], op: &str, arg: Value) -> Result<Value, Box<EvalAltResult>> {
+        let body = format!(
+            "window[HELPERS].input.act({}, {}, {}, {}, {})",
+            json!(selector), json!(checks), ACTION_TIMEOUT_MS, json!(op), arg
+        );
+        let limit = Duration::from_millis(ACTION_TIMEOUT_MS) + Duration::from_secs(5);
+        let Some(mut answer) = self.call_page(&body, Some(limit))? else {
+            return Err(ActionError::PageGone(selector.to_string()).to_string().into());
+        };
+        if let Some(value) = answer.get_mut("ok") {
+            return Ok(value.take());
+        }
+        let reason = |key: &str| answer.get(key).and_then(Value::as_str).map(String::from);
+        let selector = selector.to_string();
+        let error = match reason("waiting") {
+            Some(reason) => ActionError::NotActionable { selector, reason, waited_ms: ACTION_TIMEOUT_MS },
+            None => ActionError::Invalid { selector, reason: reason("invalid").unwrap_or_else(|| "could not be acted on".into()) },
+        };
+        Err(error.to_string().into())
     }
 
-    pub fn extract_text(&mut self, selector: &str) -> String {
-        let (tx, rx) = channel();
-        let id = Uuid::new_v4().to_string();
+    fn target(&self, selector: &str, checks: &[&str]) -> Result<Target, Box<EvalAltResult>> {
+        let located = self.act(selector, checks, "locate", Value::Null)?;
+        serde_json::from_value(located).map_err(|e| e.to_string().into())
+    }
 
-        {
-            let mut state = self.state.lock().unwrap();
-            state.pending_callbacks.insert(id.clone(), tx);
+    /// Waits for the element and brings it into view: with the wheel in human mode, at once
+    /// otherwise.
+    fn target_in_view(&self, selector: &str, checks: &[&str]) -> Result<Target, Box<EvalAltResult>> {
+        if !self.human {
+            self.act(selector, checks, "scroll", Value::Null)?;
+            return self.target(selector, checks);
         }
+        let target = self.target(selector, checks)?;
+        if target.in_view() {
+            return Ok(target);
+        }
+        self.play(self.humanizer.scroll(self.humanizer.scroll_into_view(&target)))?;
+        self.target(selector, checks)
+    }
 
-        let js = format!(r#"
-            (function() {{
-                const el = document.querySelector("{}");
-                const text = el ? el.innerText : "";
-                window.__TAURI__.core.invoke('script_callback', {{ id: "{}", data: text }});
-            }})()
-        "#, selector, id);
+    /// Moves to the actionable element and clicks it, the way a person would.
+    fn human_press(&self, selector: &str, checks: &[&str]) -> Result<Target, Box<EvalAltResult>> {
+        let target = self.target_in_view(selector, checks)?;
+        let mut steps = self.humanizer.move_to(&target);
+        steps.extend(self.humanizer.click());
+        self.play(steps)?;
+        Ok(target)
+    }
+
+    fn require_profile(&self) -> Result<&Profile, Box<EvalAltResult>> {
+        self.profile
+            .as_ref()
+            .ok_or_else(|| "Sessions need a profile: run the script with one selected".into())
+    }
+
+    pub fn navigate(&mut self, url: &str) -> Result<(), Box<EvalAltResult>> {
+        let url = self.guard.check_navigation(url)?;
+        self.window.navigate(url).map_err(|e| e.to_string().into())
+    }
 
-        let _ = self.window.eval(&js);
+    pub fn click(&mut self, selector: &str) -> Result<(), Box<EvalAltResult>> {
+        if self.human {
+            return self.human_press(selector, POINTER).map(|_| ());
+        }
+        self.eval_page(&format!("window[HELPERS].selector.query({})?.click()", json!(selector)));
+        Ok(())
+    }
 
-        match rx.recv() {
-            Ok(val) => val.as_str().unwrap_or("").to_string(),
-            Err(_) => "".to_string(),
+    pub fn wait_for_selector(&mut self, selector: &str) -> Result<(), Box<EvalAltResult>> {
+        let body = format!("window[HELPERS].selector.waitFor({})", json!(selector));
+        self.call_page(&body, None).map(|_| ())
+    }
+
+    pub fn extract_text(&mut self, selector: &str) -> Result<String, Box<EvalAltResult>> {
+        let body = format!("window[HELPERS].selector.query({})?.innerText ?? ''", json!(selector));
+        let received = self.call_page(&body, None)?;
+        let text = received.and_then(|val| val.as_str().map(String::from)).unwrap_or_default();
+        if let Some(debug) = &self.debug {
+            debug.record_result(&format!("extract_text({:?})", selector), serde_json::Value::String(text.clone()));
         }
+        Ok(text)
     }
 
-    pub fn type_text(&mut self, selector: &str, text: &str) {
+    pub fn type_text(&mut self, selector: &str, text: &str) -> Result<(), Box<EvalAltResult>> {
+         if self.human {
+             // Click into the field, replace what it holds, then type
+             let target = self.human_press(selector, EDITABLE)?;
+             let mut steps = if target.filled { self.humanizer.select_all() } else { Vec::new() };
+             steps.extend(self.humanizer.type_text(text));
+             return self.play(steps);
+         }
          let js = format!(r#"
-            (function() {{
-                let el = document.querySelector('{}');
+            (function(el) {{
                 if (el) {{
-                    el.value = '{}';
+                    el.value = {};
                     el.dispatchEvent(new Event('input', {{ bubbles: true }}));
                     el.dispatchEvent(new Event('change', {{ bubbles: true }}));
                 }}
-            }})()
-         "#, selector, text);
-         let _ = self.window.eval(&js);
+            }})(window[HELPERS].selector.query({}))
+         "#, json!(text), json!(selector));
+         self.eval_page(&js);
+         Ok(())
+    }
+
+    /// Scrolls the page by `dy` pixels: with wheel flicks in human mode, at once otherwise.
+    pub fn scroll_by(&mut self, dy: f64) -> Result<(), Box<EvalAltResult>> {
+        if self.human {
+            return self.play(self.humanizer.scroll(dy));
+        }
+        let _ = self.window.eval(format!("window.scrollBy(0, {});", dy));
+        Ok(())
+    }
+
+    pub fn set_human_input(&mut self, enabled: bool) {
+        self.human = enabled;
+    }
+
+    /// Moves the pointer over the element, firing the hover events along the way.
+    pub fn hover(&mut self, selector: &str) -> Result<(), Box<EvalAltResult>> {
+        let target = self.target_in_view(selector, &["visible", "stable"])?;
+        let steps = if self.human {
+            self.humanizer.move_to(&target)
+        } else {
+            let (x, y) = target.centre();
+            vec![Step::Move { x, y, d: 0 }]
+        };
+        self.play(steps)
+    }
+
+    /// Picks the `<select>` option with this value, or else this label. Returns its value.
+    pub fn select_option(&mut self, selector: &str, value: &str) -> Result<String, Box<EvalAltResult>> {
+        if self.human {
+            self.human_press(selector, POINTER)?;
+        }
+        let selected = self.act(selector, POINTER, "select", json!(value))?;
+        Ok(selected.as_str().unwrap_or_default().to_string())
+    }
+
+    /// Checks or unchecks a checkbox or radio button by clicking it, if it isn't already.
+    pub fn set_checked(&mut self, selector: &str, checked: bool) -> Result<(), Box<EvalAltResult>> {
+        let invalid = |reason: &str| -> Box<EvalAltResult> {
+            ActionError::Invalid { selector: selector.to_string(), reason: reason.to_string() }.to_string().into()
+        };
+        let current = self.act(selector, POINTER, "checked", Value::Null)?;
+        if current["checked"] == checked {
+            return Ok(());
+        }
+        if !checked && current["radio"] == true {
+            return Err(invalid("is a radio button; check another option instead"));
+        }
+
+        if self.human {
+            self.human_press(selector, POINTER)?;
+        } else {
+            self.act(selector, POINTER, "click", Value::Null)?;
+        }
+        if self.act(selector, &[], "checked", Value::Null)?["checked"] != checked {
+            return Err(invalid(if checked { "did not become checked" } else { "did not become unchecked" }));
+        }
+        Ok(())
+    }
+
+    /// Presses a key combination such as `"Enter"`, `"Shift+Tab"` or `"Control+A"` on the
+    /// focused element.
+    pub fn press(&mut self, combo: &str) -> Result<(), Box<EvalAltResult>> {
+        let step = if self.human { self.humanizer.press(combo) } else { humanize::press(combo) };
+        let step = step.map_err(|key| ActionError::UnknownKey { key, combo: combo.to_string() }.to_string())?;
+        self.play(vec![step])
+    }
+
+    pub fn focus(&mut self, selector: &str) -> Result<(), Box<EvalAltResult>> {
+        self.act(selector, &["visible", "enabled"], "focus", Value::Null).map(|_| ())
+    }
+
+    /// Empties a text field: select all and Backspace in human mode, at once otherwise.
+    pub fn clear(&mut self, selector: &str) -> Result<(), Box<EvalAltResult>> {
+        if !self.human {
+            return self.act(selector, EDITABLE, "clear", Value::Null).map(|_| ());
+        }
+        let target = self.human_press(selector, EDITABLE)?;
+        if !target.filled {
+            return Ok(());
+        }
+        let mut steps = self.humanizer.select_all();
+        steps.push(self.humanizer.press("Backspace").expect("a valid key combination"));
+        self.play(steps)
+    }
+
+    pub fn scroll_into_view(&mut self, selector: &str) -> Result<(), Box<EvalAltResult>> {
+        self.target_in_view(selector, &["visible"]).map(|_| ())
     }
 
-    pub fn sleep(&mut self, ms: i64) {
-        std::thread::sleep(std::time::Duration::from_millis(ms as u64));
+    /// Sets the files of an `<input type="file">`; an empty list clears them. File inputs
+    /// are often hidden behind a styled button, so they only need to be enabled.
+    pub fn set_input_files(&mut self, selector: &str, paths: &[String]) -> Result<(), Box<EvalAltResult>> {
+        let mut files = Vec::new();
+        let mut total = 0;
+        for path in paths {
+            let failed = |reason: String| ActionError::File { path: path.clone(), reason }.to_string();
+            let file = self.guard.resolve_file(path, &workspace::uploads_dir())?;
+            let bytes = std::fs::read(&file).map_err(|e| failed(e.to_string()))?;
+            total += bytes.len();
+            if total > MAX_UPLOAD_BYTES {
+                return Err(failed(format!("uploads are limited to {} MB in total", MAX_UPLOAD_BYTES >> 20)).into());
+            }
+            let path_ref = file.as_path();
+            let modified = std::fs::metadata(path_ref)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_millis() as u64);
+            files.push(json!({
+                "name": path_ref.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+                "type": mime_type(path_ref),
+                "data": B64.encode(&bytes),
+                "modified": modified,
+            }));
+        }
+        self.act(selector, &["enabled"], "files", Value::Array(files)).map(|_| ())
     }
 
-    pub fn wait(&mut self, ms: i64) {
-        self.sleep(ms);
+    pub fn sleep(&mut self, ms: i64) -> Result<(), Box<EvalAltResult>> {
+        self.guard.sleep(std::time::Duration::from_millis(ms.max(0) as u64))
+    }
+
+    pub fn wait(&mut self, ms: i64) -> Result<(), Box<EvalAltResult>> {
+        self.sleep(ms)
     }
 
     pub fn set_proxy(&mut self, proxy_url: &str) {
@@ -117,31 +373,150 @@ impl BrowserApi {
             let username = if url.username().is_empty() { None } else { Some(url.username().to_string()) };
             let password = url.password().map(|s| s.to_string());
 
-            let config = ProxyConfig { protocol, host, port, username, password };
+            let config = ProxyConfig { protocol, host, port, username, password, password_secret: None };
 
-            {
-                let mut state = self.state.lock().unwrap();
-                if let Some(profile) = &mut state.current_profile {
-                    profile.proxy = Some(config);
-                }
+            // Only this window's identity changes
+            if let Some(profile) = &mut self.profile {
+                profile.proxy = Some(config);
+                crate::windows::bind(&self.state, self.window.label(), Some(profile.clone()));
             }
 
             // Restart Proxy
             let app = self.window.app_handle().clone();
             let state = self.state.clone();
+            let label = self.window.label().to_string();
             tauri::async_runtime::block_on(async {
-                crate::proxy::restart_proxy(app, state).await;
+                crate::proxy::restart_proxy(app, state, &label).await;
             });
         }
     }
 
     pub fn get_last_request(&mut self) -> String {
         let state = self.state.lock().unwrap();
-        state.last_request.clone().unwrap_or_default()
+        state
+            .windows
+            .get(self.window.label())
+            .and_then(|c| c.last_request.clone())
+            .unwrap_or_default()
+    }
+
+    /// Cookies of the current profile, optionally limited to `domain` and its subdomains.
+    pub fn get_cookies(&mut self, domain: Option<&str>) -> Result<rhai::Array, Box<EvalAltResult>> {
+        let cookies = cookies::get_all(&self.window)?;
+        cookies
+            .iter()
+            .filter(|c| domain.is_none_or(|d| c.matches_domain(d)))
+            .map(rhai::serde::to_dynamic)
+            .collect()
+    }
+
+    pub fn set_cookie(&mut self, cookie: rhai::Map) -> Result<(), Box<EvalAltResult>> {
+        let record: CookieRecord = rhai::serde::from_dynamic(&Dynamic::from_map(cookie))?;
+        Ok(cookies::set(&self.window, &record)?)
+    }
+
+    pub fn clear_cookies(&mut self) -> Result<i64, Box<EvalAltResult>> {
+        Ok(cookies::clear(&self.window)? as i64)
+    }
+
+    /// Writes the cookies to `path`. Without an explicit format, `.json` files get JSON
+    /// and everything else cookies.txt.
+    pub fn export_cookies(&mut self, path: &str, format: Option<&str>) -> Result<i64, Box<EvalAltResult>> {
+        let format = match format {
+            Some(f) => f.parse::<CookieFormat>()?,
+            None => CookieFormat::from_path(path),
+        };
+        let cookies = cookies::get_all(&self.window)?;
+        let file = self.guard.resolve_file(path, &workspace::cookies_dir())?;
+        std::fs::write(&file, format.format(&cookies))
+            .map_err(|e| format!("Failed to write cookies to '{}': {}", path, e))?;
+        Ok(cookies.len() as i64)
+    }
+
+    pub fn import_cookies(&mut self, path: &str, format: Option<&str>) -> Result<i64, Box<EvalAltResult>> {
+        let format = match format {
+            Some(f) => f.parse::<CookieFormat>()?,
+            None => CookieFormat::from_path(path),
+        };
+        let file = self.guard.resolve_file(path, &workspace::cookies_dir())?;
+        let content = std::fs::read_to_string(&file)
+            .map_err(|e| format!("Failed to read cookies from '{}': {}", path, e))?;
+        Ok(cookies::import(&self.window, &content, format)? as i64)
+    }
+
+    /// Saves cookies plus the current page's storage under `name` in the profile.
+    pub fn save_session(&mut self, name: &str, options: Option<rhai::Map>) -> Result<(), Box<EvalAltResult>> {
+        let include_indexed_db = options
+            .and_then(|o| o.get("indexed_db").and_then(|v| v.as_bool().ok()))
+            .unwrap_or(false);
+        let profile = self.require_profile()?.name.clone();
+        session::save(&self.window, &self.state, &profile, name, include_indexed_db)?;
+        Ok(())
+    }
+
+    /// Reopens the window with the saved session; the next navigation sees it logged in.
+    pub fn restore_session(&mut self, name: &str) -> Result<(), Box<EvalAltResult>> {
+        let profile = self.require_profile()?.clone();
+        let snapshot = session::load(&profile.name, name)?;
+        self.window = session::reopen_with_session(&self.window, &profile, &snapshot)?;
+        Ok(())
+    }
+}
+
+/// Values put into the script's scope next to `browser`.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptInputs {
+    /// The dataset row of a batch run, as `input`
+    pub input: Option<rhai::Map>,
+    /// Resolved params (see `params::resolve`), as `params`
+    pub params: rhai::Map,
+    /// Where `output.push` writes; the run registry when unset
+    pub output: Option<Arc<OutputSink>>,
+    /// Runs the script under the Studio debugger
+    pub debug: Option<Arc<DebugSession>>,
+}
+
+/// The `secrets` object: read-only access to the unlocked vault.
+#[derive(Clone)]
+pub struct SecretsApi {
+    state: Arc<Mutex<AppState>>,
+}
+
+impl SecretsApi {
+    pub fn get(&mut self, name: &str) -> Result<String, Box<EvalAltResult>> {
+        let state = self.state.lock().unwrap();
+        let vault = state.vault.as_ref().ok_or_else(|| VaultError::Locked.to_string())?;
+        vault
+            .get(name)
+            .map(String::from)
+            .ok_or_else(|| VaultError::NotFound(name.to_string()).to_string().into())
+    }
+
+    pub fn has(&mut self, name: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state.vault.as_ref().is_some_and(|v| v.get(name).is_some())
     }
 }
 
-pub fn run_script(script: String, app_handle: AppHandle, state: Arc<Mutex<AppState>>, window_label: String) {
+/// The `output` object: streams records to the run's sink as the script goes.
+#[derive(Clone)]
+pub struct OutputApi {
+    sink: Option<Arc<OutputSink>>,
+}
+
+impl OutputApi {
+    pub fn push(&mut self, record: rhai::Map) -> Result<(), Box<EvalAltResult>> {
+        let sink = self.sink.as_ref().ok_or_else(|| OutputError::Unavailable.to_string())?;
+        let record = match serde_json::to_value(Dynamic::from_map(record)) {
+            Ok(serde_json::Value::Object(record)) => record,
+            Ok(_) => return Err(OutputError::NotARecord("map".into()).to_string().into()),
+            Err(e) => return Err(e.to_string().into()),
+        };
+        sink.push(record).map_err(|e| e.to_string().into())
+    }
+}
+
+pub fn run_script(script: String, app_handle: AppHandle, state: Arc<Mutex<AppState>>, window_label: String, inputs: ScriptInputs) {
     let app_handle = app_handle.clone();
 
     thread::spawn(move || {
@@ -153,54 +528,172 @@ pub fn run_script(script: String, app_handle: AppHandle, state: Arc<Mutex<AppSta
             Some(w) => w,
             None => {
                 let _ = app_handle.emit("log_output", format!("Error: Target window '{}' not found", window_label));
+                if let Some(debug) = &inputs.debug {
+                    debugger::finish(&app_handle, &state, debug);
+                }
                 return;
             }
         };
 
-        // We use the shared execute logic, passing the app_handle for log emission
-        match execute(script, window, state, Some(app_handle.clone())) {
-            Ok(_) => {
-                 let _ = app_handle.emit("log_output", "Script finished successfully");
-            },
-            Err(e) => {
-                 let _ = app_handle.emit("log_output", format!("Script error: {}", e));
-            },
+        let (profile, run_id) = {
+            let mut s = state.lock().unwrap();
+            let profile = s.window_profile(&window_label).cloned();
+            let run_id = s.runs.start(RunSource::Ui, profile.as_ref().map(|p| p.name.as_str()));
+            s.runs.set_running(&run_id);
+            (profile, run_id)
+        };
+        let mut inputs = inputs;
+        inputs.output.get_or_insert_with(|| OutputSink::registry(state.clone(), &run_id));
+        let debug = inputs.debug.clone();
+
+        let policy = policy::for_source(RunSource::Ui);
+        let result = run_in_window(script, window, state.clone(), profile, inputs, policy, app_handle.clone());
+        let outcome = result.map(|value| serde_json::to_value(&value).unwrap_or_default());
+        state.lock().unwrap().runs.finish(&run_id, outcome);
+        if let Some(debug) = debug {
+            debugger::finish(&app_handle, &state, &debug);
         }
     });
 }
 
-fn register_api(engine: &mut Engine) {
+/// Runs a script against an open window and reports the outcome on `log_output`.
+/// Blocks. Errors come back with secrets redacted.
+pub fn run_in_window(
+    script: String,
+    window: WebviewWindow,
+    state: Arc<Mutex<AppState>>,
+    profile: Option<Profile>,
+    inputs: ScriptInputs,
+    policy: ScriptPolicy,
+    app_handle: AppHandle,
+) -> Result<Dynamic, String> {
+    let state_for_errors = state.clone();
+
+    // We use the shared execute logic, passing the app_handle for log emission
+    match execute(script, window, state, profile, inputs, policy, Some(app_handle.clone())) {
+        Ok(value) => {
+             let _ = app_handle.emit("log_output", "Script finished successfully");
+             Ok(value)
+        },
+        Err(e) => {
+             let message = vault::redact(&state_for_errors.lock().unwrap(), &e.to_string());
+             let _ = app_handle.emit("log_output", format!("Script error: {}", message));
+             Err(message)
+        },
+    }
+}
+
+/// MIME type of an upload, from its extension.
+fn mime_type(path: &std::path::Path) -> &'static str {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+    match extension.as_str() {
+        "txt" => "text/plain",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp4" => "video/mp4",
+        "mp3" => "audio/mpeg",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        _ => "application/octet-stream",
+    }
+}
+
+pub(crate) fn register_api(engine: &mut Engine) {
     engine.register_type_with_name::<BrowserApi>("BrowserApi")
         .register_fn("navigate", |api: &mut BrowserApi, url: &str| api.navigate(url))
         .register_fn("click", |api: &mut BrowserApi, selector: &str| api.click(selector))
         .register_fn("wait_for_selector", |api: &mut BrowserApi, selector: &str| api.wait_for_selector(selector))
         .register_fn("extract_text", |api: &mut BrowserApi, selector: &str| api.extract_text(selector))
         .register_fn("type", |api: &mut BrowserApi, selector: &str, text: &str| api.type_text(selector, text))
+        .register_fn("scroll_by", |api: &mut BrowserApi, dy: i64| api.scroll_by(dy as f64))
+        .register_fn("scroll_by", |api: &mut BrowserApi, dy: f64| api.scroll_by(dy))
+        .register_fn("hover", |api: &mut BrowserApi, selector: &str| api.hover(selector))
+        .register_fn("select_option", |api: &mut BrowserApi, selector: &str, value: &str| api.select_option(selector, value))
+        .register_fn("check", |api: &mut BrowserApi, selector: &str| api.set_checked(selector, true))
+        .register_fn("uncheck", |api: &mut BrowserApi, selector: &str| api.set_checked(selector, false))
+        .register_fn("press", |api: &mut BrowserApi, combo: &str| api.press(combo))
+        .register_fn("focus", |api: &mut BrowserApi, selector: &str| api.focus(selector))
+        .register_fn("clear", |api: &mut BrowserApi, selector: &str| api.clear(selector))
+        .register_fn("scroll_into_view", |api: &mut BrowserApi, selector: &str| api.scroll_into_view(selector))
+        .register_fn("set_input_files", |api: &mut BrowserApi, selector: &str, path: &str| api.set_input_files(selector, &[path.to_string()]))
+        .register_fn("set_input_files", |api: &mut BrowserApi, selector: &str, paths: rhai::Array| {
+            let paths: Vec<String> = paths.into_iter().map(|p| p.to_string()).collect();
+            api.set_input_files(selector, &paths)
+        })
+        .register_fn("set_human_input", |api: &mut BrowserApi, enabled: bool| api.set_human_input(enabled))
         .register_fn("sleep", |api: &mut BrowserApi, ms: i64| api.sleep(ms))
         .register_fn("wait", |api: &mut BrowserApi, ms: i64| api.wait(ms))
         .register_fn("set_proxy", |api: &mut BrowserApi, url: &str| api.set_proxy(url))
-        .register_fn("get_last_request", |api: &mut BrowserApi| api.get_last_request());
+        .register_fn("get_last_request", |api: &mut BrowserApi| api.get_last_request())
+        .register_fn("get_cookies", |api: &mut BrowserApi| api.get_cookies(None))
+        .register_fn("get_cookies", |api: &mut BrowserApi, domain: &str| api.get_cookies(Some(domain)))
+        .register_fn("set_cookie", |api: &mut BrowserApi, cookie: rhai::Map| api.set_cookie(cookie))
+        .register_fn("clear_cookies", |api: &mut BrowserApi| api.clear_cookies())
+        .register_fn("export_cookies", |api: &mut BrowserApi, path: &str| api.export_cookies(path, None))
+        .register_fn("export_cookies", |api: &mut BrowserApi, path: &str, format: &str| api.export_cookies(path, Some(format)))
+        .register_fn("import_cookies", |api: &mut BrowserApi, path: &str| api.import_cookies(path, None))
+        .register_fn("import_cookies", |api: &mut BrowserApi, path: &str, format: &str| api.import_cookies(path, Some(format)))
+        .register_fn("save_session", |api: &mut BrowserApi, name: &str| api.save_session(name, None))
+        .register_fn("save_session", |api: &mut BrowserApi, name: &str, options: rhai::Map| api.save_session(name, Some(options)))
+        .register_fn("restore_session", |api: &mut BrowserApi, name: &str| api.restore_session(name));
+
+    engine.register_type_with_name::<SecretsApi>("SecretsApi")
+        .register_fn("get", |api: &mut SecretsApi, name: &str| api.get(name))
+        .register_fn("has", |api: &mut SecretsApi, name: &str| api.has(name));
+
+    engine.register_type_with_name::<OutputApi>("OutputApi")
+        .register_fn("push", |api: &mut OutputApi, record: rhai::Map| api.push(record))
+        .register_fn("push", |_: &mut OutputApi, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            Err(OutputError::NotARecord(value.type_name().to_string()).to_string().into())
+        });
 }
 
-pub fn execute(
-    script: String,
+/// An engine with the script API and policy set up, and the scope a script starts with.
+pub struct Prepared {
+    pub engine: Engine,
+    pub scope: Scope<'static>,
+    pub guard: Arc<RunGuard>,
+}
+
+pub fn prepare(
     window: WebviewWindow,
     state: Arc<Mutex<AppState>>,
+    profile: Option<Profile>,
+    inputs: ScriptInputs,
+    policy: ScriptPolicy,
     app_handle: Option<AppHandle>
-) -> Result<rhai::Dynamic, Box<rhai::EvalAltResult>> {
+) -> Prepared {
     let mut engine = Engine::new();
     let state_clone = state.clone();
-    let browser_api = BrowserApi::new(window, state);
+    let secrets_api = SecretsApi { state: state.clone() };
+    let guard = Arc::new(RunGuard::new(policy));
+    let mut browser_api = BrowserApi::new(window, state, profile, guard.clone());
 
     register_api(&mut engine);
+    engine.set_module_resolver(ScriptResolver::default());
+    guard.apply(&mut engine);
+    if let Some(debug) = inputs.debug {
+        debug.attach(&mut engine);
+        browser_api.debug = Some(debug);
+    }
 
-    // Handle print/logging
+    // Handle print/logging. Secret values never reach the logs, the UI or stdout.
     if let Some(app) = app_handle {
         engine.on_print(move |s| {
-            let _ = app.emit("log_output", s);
+            let line = vault::redact(&state_clone.lock().unwrap(), s);
+            let _ = app.emit("log_output", &line);
             // Also save to AppState
             if let Ok(mut st) = state_clone.lock() {
-                st.script_logs.push(s.to_string());
+                st.script_logs.push(line);
                 // Keep log size manageable?
                 if st.script_logs.len() > 1000 {
                     st.script_logs.remove(0);
@@ -208,13 +701,31 @@ pub fn execute(
             }
         });
     } else {
-        engine.on_print(|s| {
-            println!("{}", s);
+        engine.on_print(move |s| {
+            println!("{}", vault::redact(&state_clone.lock().unwrap(), s));
         });
     }
 
     let mut scope = Scope::new();
     scope.push("browser", browser_api);
+    scope.push("secrets", secrets_api);
+    scope.push("params", inputs.params);
+    scope.push("output", OutputApi { sink: inputs.output });
+    if let Some(input) = inputs.input {
+        scope.push("input", input);
+    }
+    Prepared { engine, scope, guard }
+}
 
-    engine.eval_with_scope::<rhai::Dynamic>(&mut scope, &script)
+pub fn execute(
+    script: String,
+    window: WebviewWindow,
+    state: Arc<Mutex<AppState>>,
+    profile: Option<Profile>,
+    inputs: ScriptInputs,
+    policy: ScriptPolicy,
+    app_handle: Option<AppHandle>
+) -> Result<Dynamic, Box<EvalAltResult>> {
+    let Prepared { engine, mut scope, guard } = prepare(window, state, profile, inputs, policy, app_handle);
+    engine.eval_with_scope::<Dynamic>(&mut scope, &script).map_err(|e| guard.explain(e))
 }
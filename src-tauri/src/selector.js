@@ -0,0 +1,396 @@
+// Selector engine behind every BrowserApi method and the recorder. Installs itself once
+// per page as `selector` on the page helpers, `window[HELPERS]` (see `page_script` in
+// scripting.rs).
+//
+//   css=form .submit        CSS, also inside open shadow roots
+//   text=Continue           elements whose text contains it, ignoring case
+//   text="Continue"         elements whose whole text is exactly it
+//   text=/contin\w+/i       elements whose text matches the regex
+//   xpath=//button[2]       XPath (doesn't enter shadow roots)
+//   role=button[name="OK"]  ARIA role, with name, checked, disabled, expanded, level, pressed, selected
+//   a >> b                  b inside each match of a
+//
+// Without a prefix, a part starting with `//` or `..` is XPath, one starting with a quote
+// is text, and anything else is CSS.
+(function () {
+    // Not enumerable, under a key the page can't guess
+    if (!Object.prototype.hasOwnProperty.call(window, HELPERS)) {
+        Object.defineProperty(window, HELPERS, { value: {}, enumerable: false });
+    }
+    const helpers = window[HELPERS];
+    if (helpers.selector) return;
+
+    const normalize = (text) => (text || '').replace(/\s+/g, ' ').trim();
+
+    // Splits `a >> b` outside quotes, brackets and parentheses
+    function split(selector) {
+        const parts = [];
+        let depth = 0;
+        let quote = null;
+        let start = 0;
+        for (let i = 0; i < selector.length; i++) {
+            const c = selector[i];
+            if (quote) {
+                if (c === '\\') i++;
+                else if (c === quote) quote = null;
+            } else if (c === '"' || c === "'") {
+                quote = c;
+            } else if (c === '[' || c === '(') {
+                depth++;
+            } else if (c === ']' || c === ')') {
+                depth--;
+            } else if (depth === 0 && c === '>' && selector[i + 1] === '>') {
+                parts.push(selector.slice(start, i));
+                start = i + 2;
+                i++;
+            }
+        }
+        parts.push(selector.slice(start));
+        return parts.map(part => part.trim()).map(part => {
+            if (!part) throw new Error('empty part in "' + selector + '"');
+            const prefix = /^(css|text|xpath|role)=/.exec(part);
+            if (prefix) return { engine: prefix[1], body: part.slice(prefix[0].length) };
+            if (part.startsWith('//') || part.startsWith('..')) return { engine: 'xpath', body: part };
+            if (part.startsWith('"') || part.startsWith("'")) return { engine: 'text', body: part };
+            return { engine: 'css', body: part };
+        });
+    }
+
+    // The root and every open shadow root below it, in document order
+    function scopes(root) {
+        const found = [root];
+        const visit = (scope) => {
+            for (const el of scope.querySelectorAll('*')) {
+                if (el.shadowRoot) {
+                    found.push(el.shadowRoot);
+                    visit(el.shadowRoot);
+                }
+            }
+        };
+        if (root.shadowRoot) {
+            found.push(root.shadowRoot);
+            visit(root.shadowRoot);
+        }
+        visit(root);
+        return found;
+    }
+
+    function descendants(root) {
+        return scopes(root).flatMap(scope => Array.from(scope.querySelectorAll('*')));
+    }
+
+    function css(root, body) {
+        return scopes(root).flatMap(scope => Array.from(scope.querySelectorAll(body)));
+    }
+
+    function xpath(root, body) {
+        // Chained parts search below the previous match
+        const expression = root !== document && body.startsWith('/') ? '.' + body : body;
+        const result = document.evaluate(expression, root, null, XPathResult.ORDERED_NODE_SNAPSHOT_TYPE, null);
+        const found = [];
+        for (let i = 0; i < result.snapshotLength; i++) {
+            const node = result.snapshotItem(i);
+            if (node.nodeType === Node.ELEMENT_NODE) found.push(node);
+        }
+        return found;
+    }
+
+    // A string matcher for `body`: "quoted" is exact, /re/flags a regex, else a substring
+    // ignoring case
+    function matcher(body) {
+        const quoted = /^(["'])(.*)\1$/s.exec(body);
+        if (quoted) {
+            const wanted = normalize(quoted[2].replace(/\\(.)/g, '$1'));
+            return (text) => normalize(text) === wanted;
+        }
+        const regex = /^\/(.*)\/([a-z]*)$/s.exec(body);
+        if (regex) {
+            const re = new RegExp(regex[1], regex[2]);
+            return (text) => re.test(normalize(text));
+        }
+        const wanted = normalize(body).toLowerCase();
+        return (text) => normalize(text).toLowerCase().includes(wanted);
+    }
+
+    const SKIPPED = new Set(['SCRIPT', 'STYLE', 'NOSCRIPT', 'TEMPLATE', 'HEAD', 'TITLE']);
+
+    function ownText(el) {
+        if (el.tagName === 'INPUT' && /^(button|submit|reset)$/.test(el.type)) return el.value;
+        return el.textContent;
+    }
+
+    // Child elements, those of an open shadow root first
+    const childrenOf = (node) => [...(node.shadowRoot ? node.shadowRoot.children : []), ...node.children];
+
+    // The innermost matching elements: an element matches unless something inside it does.
+    // One walk, deepest first, so an element is only tested when nothing inside it matched.
+    function text(root, body) {
+        const matches = matcher(body);
+        const found = [];
+        const visit = (el) => {
+            if (SKIPPED.has(el.tagName)) return false;
+            let inner = false;
+            for (const child of childrenOf(el)) inner = visit(child) || inner;
+            if (inner) return true;
+            if (!matches(ownText(el))) return false;
+            found.push(el);
+            return true;
+        };
+        for (const child of childrenOf(root)) visit(child);
+        return found;
+    }
+
+    const IMPLICIT_ROLES = {
+        A: (el) => el.hasAttribute('href') ? 'link' : null,
+        AREA: (el) => el.hasAttribute('href') ? 'link' : null,
+        ARTICLE: () => 'article',
+        ASIDE: () => 'complementary',
+        BUTTON: () => 'button',
+        DIALOG: () => 'dialog',
+        FIELDSET: () => 'group',
+        FOOTER: () => 'contentinfo',
+        FORM: () => 'form',
+        H1: () => 'heading', H2: () => 'heading', H3: () => 'heading',
+        H4: () => 'heading', H5: () => 'heading', H6: () => 'heading',
+        HEADER: () => 'banner',
+        HR: () => 'separator',
+        IMG: (el) => el.getAttribute('alt') === '' ? 'presentation' : 'img',
+        INPUT: (el) => {
+            switch (el.type) {
+                case 'button': case 'submit': case 'reset': case 'image': return 'button';
+                case 'checkbox': return 'checkbox';
+                case 'radio': return 'radio';
+                case 'range': return 'slider';
+                case 'number': return 'spinbutton';
+                case 'search': return el.hasAttribute('list') ? 'combobox' : 'searchbox';
+                case 'hidden': case 'file': case 'color': return null;
+                default: return el.hasAttribute('list') ? 'combobox' : 'textbox';
+            }
+        },
+        LI: () => 'listitem',
+        MAIN: () => 'main',
+        NAV: () => 'navigation',
+        OL: () => 'list',
+        OPTION: () => 'option',
+        PROGRESS: () => 'progressbar',
+        SECTION: (el) => el.hasAttribute('aria-label') || el.hasAttribute('aria-labelledby') ? 'region' : null,
+        SELECT: (el) => el.multiple || el.size > 1 ? 'listbox' : 'combobox',
+        TABLE: () => 'table',
+        TD: () => 'cell',
+        TH: () => 'columnheader',
+        TEXTAREA: () => 'textbox',
+        TR: () => 'row',
+        UL: () => 'list',
+    };
+
+    function roleOf(el) {
+        const explicit = (el.getAttribute('role') || '').trim().split(/\s+/)[0];
+        if (explicit) return explicit;
+        const implicit = IMPLICIT_ROLES[el.tagName];
+        return implicit ? implicit(el) : null;
+    }
+
+    // Roles whose name comes from their content when nothing else names them
+    const NAMED_BY_CONTENT = new Set([
+        'button', 'cell', 'checkbox', 'columnheader', 'heading', 'link', 'listitem', 'menuitem',
+        'menuitemcheckbox', 'menuitemradio', 'option', 'radio', 'row', 'switch', 'tab', 'tooltip', 'treeitem',
+    ]);
+
+    // A simplified accessible name: labelledby, aria-label, labels, alt, value, content, title
+    function nameOf(el) {
+        const labelledBy = el.getAttribute('aria-labelledby');
+        if (labelledBy) {
+            const root = el.getRootNode();
+            const text = labelledBy.split(/\s+/).map(id => root.getElementById ? root.getElementById(id) : document.getElementById(id))
+                .filter(Boolean).map(label => label.textContent).join(' ');
+            if (normalize(text)) return normalize(text);
+        }
+        const label = el.getAttribute('aria-label');
+        if (normalize(label)) return normalize(label);
+        if (el.labels && el.labels.length) return normalize(Array.from(el.labels).map(l => l.textContent).join(' '));
+        if (el.tagName === 'IMG' || (el.tagName === 'INPUT' && el.type === 'image')) {
+            if (normalize(el.getAttribute('alt'))) return normalize(el.getAttribute('alt'));
+        }
+        if (el.tagName === 'INPUT' && /^(button|submit|reset)$/.test(el.type)) {
+            return normalize(el.value || (el.type === 'submit' ? 'Submit' : el.type === 'reset' ? 'Reset' : ''));
+        }
+        if (NAMED_BY_CONTENT.has(roleOf(el)) && normalize(el.textContent)) return normalize(el.textContent);
+        return normalize(el.getAttribute('title') || el.getAttribute('placeholder'));
+    }
+
+    function ariaState(el, name) {
+        const attribute = el.getAttribute('aria-' + name);
+        if (attribute !== null) return attribute === 'mixed' ? 'mixed' : attribute === 'true';
+        switch (name) {
+            case 'checked': return el.tagName === 'INPUT' && (el.type === 'checkbox' || el.type === 'radio') ? el.checked : false;
+            case 'disabled': return el.matches(':disabled') || el.closest('[aria-disabled="true"]') !== null;
+            case 'selected': return el.tagName === 'OPTION' ? el.selected : false;
+            default: return false;
+        }
+    }
+
+    // role=button[name="Submit"][disabled=false]
+    function parseRole(body) {
+        const head = /^([\w-]+)/.exec(body);
+        if (!head) throw new Error('role= needs a role name, e.g. role=button');
+        const filters = [];
+        const attribute = /\[\s*([\w-]+)\s*(?:=\s*("(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*'|\/(?:[^/\\]|\\.)*\/[a-z]*|[^\]\s]+)\s*)?\]/gy;
+        attribute.lastIndex = head[0].length;
+        while (attribute.lastIndex < body.length) {
+            const m = attribute.exec(body);
+            if (!m) throw new Error('cannot read the attributes in role=' + body);
+            filters.push({ name: m[1], value: m[2] === undefined ? 'true' : m[2] });
+        }
+        return { role: head[1], filters: filters };
+    }
+
+    function role(root, body) {
+        const { role: wanted, filters } = parseRole(body);
+        const tests = filters.map(({ name, value }) => {
+            switch (name) {
+                case 'name': {
+                    const matches = matcher(value);
+                    return (el) => matches(nameOf(el));
+                }
+                case 'level': {
+                    const level = parseInt(value, 10);
+                    return (el) => parseInt(el.getAttribute('aria-level') || el.tagName.slice(1), 10) === level;
+                }
+                case 'checked': case 'disabled': case 'expanded': case 'pressed': case 'selected': {
+                    const raw = value.replace(/^(["'])(.*)\1$/, '$2');
+                    const expected = raw === 'mixed' ? 'mixed' : raw === 'true';
+                    return (el) => ariaState(el, name) === expected;
+                }
+                default:
+                    throw new Error('role= does not support [' + name + ']');
+            }
+        });
+        return descendants(root).filter(el =>
+            roleOf(el) === wanted && !el.closest('[aria-hidden="true"]') && tests.every(test => test(el)));
+    }
+
+    const ENGINES = { css: css, text: text, xpath: xpath, role: role };
+
+    function queryAll(selector, root) {
+        try {
+            let found = [root || document];
+            for (const { engine, body } of split(selector)) {
+                const next = new Set();
+                for (const scope of found) {
+                    for (const el of ENGINES[engine](scope, body)) next.add(el);
+                }
+                found = Array.from(next);
+            }
+            return found;
+        } catch (e) {
+            throw new Error("'" + selector + "' is not a valid selector: " + e.message);
+        }
+    }
+
+    function query(selector, root) {
+        return queryAll(selector, root)[0] || null;
+    }
+
+    // Resolves with true once something matches. Shadow roots aren't covered by the
+    // observer, so it also polls.
+    function waitFor(selector) {
+        return new Promise((resolve, reject) => {
+            let done = false;
+            const check = () => {
+                if (done) return true;
+                let found;
+                try {
+                    found = query(selector) !== null;
+                } catch (e) {
+                    stop();
+                    reject(e);
+                    return true;
+                }
+                if (found) {
+                    stop();
+                    resolve(true);
+                }
+                return found;
+            };
+            // A burst of mutations gets one check. A timeout rather than requestAnimationFrame,
+            // which doesn't fire in hidden windows.
+            let pending = null;
+            const observer = new MutationObserver(() => {
+                if (pending === null) pending = setTimeout(() => { pending = null; check(); }, 50);
+            });
+            const timer = setInterval(check, 250);
+            const stop = () => {
+                done = true;
+                observer.disconnect();
+                clearInterval(timer);
+                clearTimeout(pending);
+            };
+            if (!check()) observer.observe(document, { childList: true, subtree: true });
+        });
+    }
+
+    // Selector generation for the recorder: the most readable selector that finds `el` and
+    // only `el`
+    const quote = (value) => JSON.stringify(value);
+    const unique = (selector, el, root) => {
+        try {
+            const found = queryAll(selector, root);
+            return found.length === 1 && found[0] === el;
+        } catch (e) {
+            return false;
+        }
+    };
+
+    // A CSS path from the element up to its tree's root or an ancestor with an id
+    function path(el) {
+        const steps = [];
+        for (let current = el; current && current.nodeType === Node.ELEMENT_NODE; current = current.parentNode) {
+            let step = current.nodeName.toLowerCase();
+            if (current.id) {
+                steps.unshift(step + '#' + CSS.escape(current.id));
+                break;
+            }
+            let nth = 1;
+            for (let sib = current.previousElementSibling; sib; sib = sib.previousElementSibling) {
+                if (sib.nodeName === current.nodeName) nth++;
+            }
+            if (nth !== 1) step += ':nth-of-type(' + nth + ')';
+            steps.unshift(step);
+        }
+        return steps.join(' > ');
+    }
+
+    function generateIn(el, root) {
+        const candidates = [];
+        if (el.id) candidates.push('#' + CSS.escape(el.id));
+        for (const attribute of ['data-testid', 'data-test', 'data-qa']) {
+            if (el.hasAttribute(attribute)) candidates.push('[' + attribute + '=' + quote(el.getAttribute(attribute)) + ']');
+        }
+        const elRole = roleOf(el);
+        const name = nameOf(el);
+        if (elRole && name && name.length <= 80) candidates.push('role=' + elRole + '[name=' + quote(name) + ']');
+        if (el.hasAttribute('aria-label')) candidates.push('[aria-label=' + quote(el.getAttribute('aria-label')) + ']');
+        if (el.getAttribute('name')) candidates.push(el.tagName.toLowerCase() + '[name=' + quote(el.getAttribute('name')) + ']');
+        const content = normalize(ownText(el));
+        if (content && content.length <= 50 && el.children.length === 0) candidates.push('text=' + quote(content));
+        if (typeof el.className === 'string') {
+            const classes = el.className.split(/\s+/).filter(c => c && c !== 'phantom-recording-highlight');
+            if (classes.length) candidates.push('.' + classes.map(c => CSS.escape(c)).join('.'));
+        }
+        return candidates.find(candidate => unique(candidate, el, root)) || null;
+    }
+
+    function generate(el) {
+        if (!el || el.nodeType !== Node.ELEMENT_NODE) return '';
+        const tree = el.getRootNode();
+        if (tree instanceof ShadowRoot) {
+            // Find the host, then the element among what its shadow root holds
+            const host = generate(tree.host);
+            const inner = generateIn(el, tree.host) || 'css=' + path(el);
+            return host + ' >> ' + inner;
+        }
+        return generateIn(el, document) || path(el);
+    }
+
+    helpers.selector = { query: query, queryAll: queryAll, waitFor: waitFor, generate: generate };
+})();
@@ -0,0 +1,376 @@
+use axum::{
+    extract::State,
+    response::Html,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Url, WebviewUrl, WebviewWindowBuilder};
+use tokio::sync::oneshot;
+use crate::client_hints::ClientHints;
+use crate::commands;
+use crate::fingerprint::{self, Fingerprint};
+use crate::state::Profile;
+
+const PROBE_PAGE: &str = include_str!("probe.html");
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A value the probe page observed that disagrees with the profile definition
+/// (or with another surface of the same page).
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    pub check: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub profile: String,
+    pub passed: bool,
+    pub mismatches: Vec<Mismatch>,
+    /// Suspicious but not necessarily wrong (e.g. a touchscreen on a desktop UA)
+    pub warnings: Vec<String>,
+    /// Raw data collected by the probe page
+    pub probe: Value,
+}
+
+type ResultSlot = Arc<Mutex<Option<oneshot::Sender<Value>>>>;
+
+async fn receive_result(State(slot): State<ResultSlot>, Json(payload): Json<Value>) -> &'static str {
+    if let Some(tx) = slot.lock().unwrap().take() {
+        let _ = tx.send(payload);
+    }
+    "ok"
+}
+
+/// Loads the bundled probe page under `profile` in a hidden window and compares what
+/// it collected with the profile definition.
+pub async fn run(app: AppHandle, profile: Profile) -> Result<SelfTestReport, String> {
+    // Serve the probe from a throwaway local server, so no third-party site is involved
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.map_err(|e| e.to_string())?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+
+    let (result_tx, result_rx) = oneshot::channel();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let router = Router::new()
+        .route("/probe", get(|| async { Html(PROBE_PAGE) }))
+        .route("/probe/result", post(receive_result))
+        .with_state(Arc::new(Mutex::new(Some(result_tx))));
+
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, router)
+            .with_graceful_shutdown(async move {
+                let _ = shutdown_rx.await;
+            })
+            .await;
+    });
+
+    let label = format!("selftest-{}", uuid::Uuid::new_v4());
+    let url = Url::parse(&format!("http://127.0.0.1:{}/probe", port)).unwrap();
+    let builder = WebviewWindowBuilder::new(&app, &label, WebviewUrl::External(url))
+        .inner_size(1024.0, 768.0)
+        .visible(false);
+    let builder = commands::apply_profile(builder, &profile);
+
+    let window = match builder.build() {
+        Ok(w) => w,
+        Err(e) => {
+            let _ = shutdown_tx.send(());
+            return Err(format!("Failed to create probe window: {}", e));
+        }
+    };
+
+    let result = tokio::time::timeout(PROBE_TIMEOUT, result_rx).await;
+    let _ = window.close();
+    let _ = shutdown_tx.send(());
+
+    let probe = match result {
+        Ok(Ok(probe)) => probe,
+        Ok(Err(_)) => return Err("Probe page closed before reporting".into()),
+        Err(_) => return Err("Timed out waiting for the probe page".into()),
+    };
+
+    let (mismatches, warnings) = compare(&profile, &probe);
+    Ok(SelfTestReport {
+        profile: profile.name.clone(),
+        passed: mismatches.is_empty(),
+        mismatches,
+        warnings,
+        probe,
+    })
+}
+
+/// Checks the probe data against the profile and for internal coherence.
+pub fn compare(profile: &Profile, probe: &Value) -> (Vec<Mismatch>, Vec<String>) {
+    let mut mismatches = Vec::new();
+    let mut warnings = Vec::new();
+
+    let str_at = |path: &str| probe.pointer(path).and_then(Value::as_str).unwrap_or("").to_string();
+    let mut expect = |check: &str, expected: &str, actual: &str| {
+        if expected != actual {
+            mismatches.push(Mismatch {
+                check: check.into(),
+                expected: expected.into(),
+                actual: actual.into(),
+            });
+        }
+    };
+
+    for section in ["navigator", "clientHints", "screen", "canvas", "webgl", "audio", "timezone", "webrtc", "iframe", "worker"] {
+        if let Some(err) = probe.pointer(&format!("/{}/error", section)).and_then(Value::as_str) {
+            warnings.push(format!("{} probe failed: {}", section, err));
+        }
+    }
+
+    // --- User Agent vs platform ---
+    let ua = profile.user_agent.as_str();
+    let platform = str_at("/navigator/platform");
+    expect("navigator.userAgent", ua, &str_at("/navigator/userAgent"));
+    if let Some(expected) = fingerprint::navigator_platform(ua) {
+        expect("navigator.platform", expected, &platform);
+    }
+
+    // --- Client Hints ---
+    let hints = ClientHints::from_user_agent(ua);
+    let observed_hints = probe.get("clientHints").filter(|v| !v.is_null());
+    match (&hints, observed_hints) {
+        (Some(hints), Some(observed)) => {
+            expect("userAgentData.platform", &hints.platform, observed.get("platform").and_then(Value::as_str).unwrap_or(""));
+            expect("userAgentData.mobile", &hints.mobile.to_string(), &observed.get("mobile").map(|v| v.to_string()).unwrap_or_default());
+            let brands = serde_json::to_value(&hints.brands).unwrap_or_default();
+            expect("userAgentData.brands", &brands.to_string(), &observed.get("brands").cloned().unwrap_or_default().to_string());
+        }
+        (Some(_), None) => expect("navigator.userAgentData", "present", "absent"),
+        (None, Some(_)) => expect("navigator.userAgentData", "absent", "present"),
+        (None, None) => {}
+    }
+
+    // --- WebGL ---
+    let fp = Fingerprint::for_profile(profile);
+    let renderer = str_at("/webgl/renderer");
+    expect("webgl.vendor", &fp.webgl_vendor, &str_at("/webgl/vendor"));
+    expect("webgl.renderer", &fp.webgl_renderer, &renderer);
+    if renderer.contains("Direct3D") && !ua.contains("Windows") {
+        expect("webgl.renderer (OS)", "non-Direct3D renderer for a non-Windows UA", &renderer);
+    }
+
+    // --- Screen ---
+    if let Some(screen) = &profile.screen {
+        let num_at = |path: &str| probe.pointer(path).map(|v| v.to_string()).unwrap_or_default();
+        expect("screen.width", &screen.width.to_string(), &num_at("/screen/width"));
+        expect("screen.height", &screen.height.to_string(), &num_at("/screen/height"));
+        let ratio = probe.pointer("/screen/pixelRatio").and_then(Value::as_f64).unwrap_or(0.0);
+        if (ratio - screen.pixel_ratio).abs() > f64::EPSILON {
+            expect("devicePixelRatio", &screen.pixel_ratio.to_string(), &ratio.to_string());
+        }
+    }
+
+    // --- Touch vs mobile UA ---
+    let mobile = is_mobile_ua(ua);
+    let touch_points = probe.pointer("/navigator/maxTouchPoints").and_then(Value::as_u64).unwrap_or(0);
+    if mobile && touch_points == 0 {
+        expect("navigator.maxTouchPoints", "> 0 for a mobile UA", "0");
+    } else if !mobile && touch_points > 0 {
+        warnings.push(format!("Desktop UA reports {} touch points", touch_points));
+    }
+
+    // --- Child frames and workers must agree with the top frame ---
+    let top_ua = str_at("/navigator/userAgent");
+    let top_uad_platform = str_at("/clientHints/platform");
+    expect("iframe navigator.userAgent", &top_ua, &str_at("/iframe/navigator/userAgent"));
+    expect("iframe navigator.platform", &platform, &str_at("/iframe/navigator/platform"));
+    expect("iframe userAgentData.platform", &top_uad_platform, &str_at("/iframe/userAgentDataPlatform"));
+    expect("iframe webgl.renderer", &renderer, &str_at("/iframe/webgl/renderer"));
+    expect("worker navigator.userAgent", &top_ua, &str_at("/worker/userAgent"));
+    expect("worker navigator.platform", &platform, &str_at("/worker/platform"));
+    expect("worker userAgentData.platform", &top_uad_platform, &str_at("/worker/userAgentDataPlatform"));
+
+    // --- WebRTC ---
+    // Host candidates are normally mDNS-obfuscated (*.local); a raw IP is a leak.
+    let leaked: Vec<String> = probe
+        .pointer("/webrtc/candidates")
+        .and_then(Value::as_array)
+        .map(|c| {
+            c.iter()
+                .filter_map(Value::as_str)
+                .filter(|ip| ip.parse::<std::net::IpAddr>().is_ok())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+    if !leaked.is_empty() {
+        if profile.proxy.is_some() {
+            expect("webrtc candidates", "no IPs bypassing the proxy", &leaked.join(", "));
+        } else {
+            warnings.push(format!("WebRTC exposes IPs: {}", leaked.join(", ")));
+        }
+    }
+
+    (mismatches, warnings)
+}
+
+fn is_mobile_ua(ua: &str) -> bool {
+    ua.contains("Mobile") || ua.contains("iPhone") || ua.contains("iPad") || ua.contains("Android")
+}
+
+#[tauri::command]
+pub async fn run_selftest(profile_name: String, app: AppHandle) -> Result<SelfTestReport, String> {
+    let profile = commands::get_profiles()
+        .into_iter()
+        .find(|p| p.name == profile_name)
+        .ok_or_else(|| format!("Profile '{}' not found", profile_name))?;
+    run(app, profile).await
+}
+
+/// `--selftest` entry point: prints (or writes to `--output`) the report and exits
+/// non-zero if any mismatch was found.
+pub async fn run_cli(app: AppHandle, profile_name: Option<String>, output_path: Option<String>) {
+    let profiles = commands::get_profiles();
+    let profile = match &profile_name {
+        Some(name) => profiles.into_iter().find(|p| &p.name == name),
+        None => profiles.into_iter().next(),
+    };
+    let Some(profile) = profile else {
+        eprintln!("Self-test: profile '{}' not found", profile_name.unwrap_or_default());
+        app.exit(1);
+        return;
+    };
+
+    eprintln!("Running fingerprint self-test for profile: {}", profile.name);
+    let report = match run(app.clone(), profile).await {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("Self-test error: {}", e);
+            app.exit(1);
+            return;
+        }
+    };
+
+    let output = serde_json::to_string_pretty(&report).unwrap_or_default();
+    if let Some(path) = output_path {
+        if let Err(e) = std::fs::write(&path, output) {
+            eprintln!("Error writing output to '{}': {}", path, e);
+            app.exit(1);
+            return;
+        }
+        eprintln!("Report written to {}", path);
+    } else {
+        println!("{}", output);
+    }
+
+    for m in &report.mismatches {
+        eprintln!("MISMATCH {}: expected '{}', got '{}'", m.check, m.expected, m.actual);
+    }
+    app.exit(if report.passed { 0 } else { 1 });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::fingerprint::FINGERPRINT_VERSION;
+    use crate::state::{ProxyConfig, ScreenConfig, WebGlConfig};
+
+    const UA: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36";
+
+    fn profile() -> Profile {
+        Profile {
+            name: "Shop".into(),
+            user_agent: UA.into(),
+            seed: 42,
+            proxy: None,
+            screen: Some(ScreenConfig { width: 1920, height: 1080, avail_height: None, pixel_ratio: 1.0 }),
+            webgl: Some(WebGlConfig {
+                vendor: "Google Inc. (Intel)".into(),
+                renderer: "ANGLE (Intel, Intel(R) UHD Graphics 630 Direct3D11 vs_5_0 ps_5_0, D3D11)".into(),
+            }),
+            fingerprint_version: FINGERPRINT_VERSION,
+            human_input: false,
+        }
+    }
+
+    /// What the probe page reports for `profile` when every surface is masked.
+    fn matching_probe(profile: &Profile) -> Value {
+        let fp = Fingerprint::for_profile(profile);
+        let hints = ClientHints::from_user_agent(&profile.user_agent).unwrap();
+        json!({
+            "navigator": { "userAgent": UA, "platform": "Win32", "maxTouchPoints": 0 },
+            "clientHints": { "platform": hints.platform, "mobile": hints.mobile, "brands": hints.brands },
+            "webgl": { "vendor": fp.webgl_vendor, "renderer": fp.webgl_renderer },
+            "screen": { "width": 1920, "height": 1080, "pixelRatio": 1.0 },
+            "iframe": {
+                "navigator": { "userAgent": UA, "platform": "Win32" },
+                "userAgentDataPlatform": hints.platform,
+                "webgl": { "renderer": fp.webgl_renderer },
+            },
+            "worker": { "userAgent": UA, "platform": "Win32", "userAgentDataPlatform": hints.platform },
+            "webrtc": { "candidates": ["0f3c9a2e.local"] },
+        })
+    }
+
+    #[test]
+    fn compare_reports_each_surface_that_disagrees() {
+        let profile = profile();
+        let firefox = "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:125.0) Gecko/20100101 Firefox/125.0";
+        for (path, value, mismatches, warnings) in [
+            ("/navigator/maxTouchPoints", json!(0), vec![], 0),
+            (
+                "/navigator/platform",
+                json!("Win64"),
+                vec!["navigator.platform", "iframe navigator.platform", "worker navigator.platform"],
+                0,
+            ),
+            ("/iframe/navigator/platform", json!("MacIntel"), vec!["iframe navigator.platform"], 0),
+            ("/worker/userAgent", json!(firefox), vec!["worker navigator.userAgent"], 0),
+            (
+                "/navigator/userAgent",
+                json!(firefox),
+                vec!["navigator.userAgent", "iframe navigator.userAgent", "worker navigator.userAgent"],
+                0,
+            ),
+            (
+                "/clientHints/platform",
+                json!("Linux"),
+                vec!["userAgentData.platform", "iframe userAgentData.platform", "worker userAgentData.platform"],
+                0,
+            ),
+            ("/clientHints/mobile", json!(true), vec!["userAgentData.mobile"], 0),
+            ("/clientHints", Value::Null, vec!["navigator.userAgentData", "iframe userAgentData.platform", "worker userAgentData.platform"], 0),
+            ("/webgl/vendor", json!("Google Inc. (NVIDIA)"), vec!["webgl.vendor"], 0),
+            ("/webgl/renderer", json!("ANGLE (Apple, ANGLE Metal Renderer: Apple M1)"), vec!["webgl.renderer", "iframe webgl.renderer"], 0),
+            ("/screen/width", json!(1280), vec!["screen.width"], 0),
+            ("/screen/pixelRatio", json!(2.0), vec!["devicePixelRatio"], 0),
+            ("/navigator/maxTouchPoints", json!(5), vec![], 1),
+            ("/webrtc/candidates", json!(["192.168.1.20"]), vec![], 1),
+        ] {
+            let mut probe = matching_probe(&profile);
+            *probe.pointer_mut(path).unwrap() = value;
+            let (found, found_warnings) = compare(&profile, &probe);
+            let checks: Vec<&str> = found.iter().map(|m| m.check.as_str()).collect();
+            assert_eq!(checks, mismatches, "{}", path);
+            assert_eq!(found_warnings.len(), warnings, "{}: {:?}", path, found_warnings);
+        }
+    }
+
+    #[test]
+    fn a_leaked_ip_is_a_mismatch_behind_a_proxy() {
+        let mut profile = profile();
+        profile.proxy = Some(ProxyConfig {
+            protocol: "http".into(),
+            host: "proxy.example".into(),
+            port: 8080,
+            username: None,
+            password: None,
+            password_secret: None,
+        });
+        let mut probe = matching_probe(&profile);
+        probe["webrtc"]["candidates"] = json!(["203.0.113.7"]);
+        let (mismatches, _) = compare(&profile, &probe);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].actual, "203.0.113.7");
+    }
+}
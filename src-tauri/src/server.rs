@@ -1,13 +1,22 @@
 use axum::{
-    extract::{State, Request},
-    routing::{get, post},
-    Router, Json, http::StatusCode,
-    response::IntoResponse,
+    extract::{Path, Query, State, Request},
+    routing::{get, post, put},
+    Router, Json, http::{header, StatusCode},
+    response::{IntoResponse, Response},
     middleware::Next,
 };
 use tauri::{AppHandle, Manager};
 use std::sync::{Arc, Mutex};
-use crate::state::AppState;
+use crate::batch::{self, BatchSpec, RecordFormat};
+use crate::error::{OutputError, PoolError, ProfileError, ReplError};
+use crate::output::{OutputSink, SinkConfig};
+use crate::pool::{self, PoolJob, PoolStatus};
+use crate::profiles;
+use crate::repl;
+use crate::runs::RunSource;
+use crate::scripting::ScriptInputs;
+use crate::state::{AppState, Profile};
+use crate::windows::STUDIO;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone)]
@@ -22,6 +31,8 @@ struct HealthResponse {
     status: String,
     uptime: u64,
     active_jobs: usize,
+    queued_jobs: usize,
+    pool: PoolStatus,
 }
 
 #[derive(Serialize)]
@@ -33,13 +44,24 @@ struct LogsResponse {
 struct JobRequest {
     script: String,
     profile: Option<String>,
+    /// Saved session of the profile to restore before the script runs
+    session: Option<String>,
+    /// Checked against the script's `@param` declarations
+    #[serde(default)]
+    params: serde_json::Map<String, serde_json::Value>,
+    /// Sink for `output.push`; file paths are relative to the workspace outputs folder
+    #[serde(default)]
+    output: SinkConfig,
 }
 
-async fn health(State(_data): State<ServerState>) -> Json<HealthResponse> {
+async fn health(State(data): State<ServerState>) -> Json<HealthResponse> {
+    let pool = data.state.lock().unwrap().pool.status();
     Json(HealthResponse {
         status: "ok".into(),
         uptime: 0,
-        active_jobs: 0,
+        active_jobs: pool.running,
+        queued_jobs: pool.queued,
+        pool,
     })
 }
 
@@ -51,54 +73,425 @@ async fn get_logs(State(data): State<ServerState>) -> Json<LogsResponse> {
     Json(LogsResponse { logs })
 }
 
+/// Opens a sink requested over the API, which may only write inside the outputs folder.
+fn open_sink(config: &SinkConfig) -> Result<Option<Arc<OutputSink>>, OutputError> {
+    config.check_relative()?;
+    OutputSink::open(config, &crate::workspace::outputs_dir())
+}
+
+fn output_error(e: OutputError) -> Response {
+    let status = match e {
+        OutputError::PathNotAllowed(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string()).into_response()
+}
+
+#[derive(Serialize)]
+struct JobAccepted {
+    run_id: String,
+}
+
+/// Queues the script in the window pool under the requested profile. Concurrent jobs
+/// get separate windows, and the UI's selection is left alone.
 async fn submit_job(
     State(data): State<ServerState>,
     Json(payload): Json<JobRequest>
-) -> impl IntoResponse {
-    if let Some(profile_name) = payload.profile {
-        let profiles = crate::commands::get_profiles();
-        if let Some(p) = profiles.into_iter().find(|p| p.name == profile_name) {
-             {
-                let mut s = data.state.lock().unwrap();
-                s.current_profile = Some(p.clone());
-             }
-             crate::proxy::restart_proxy(data.app.clone(), data.state.clone()).await;
+) -> Response {
+    let profile = match payload.profile {
+        Some(name) => match crate::commands::find_profile(&name) {
+            Some(p) => Some(p),
+            None => return (StatusCode::NOT_FOUND, format!("Profile '{}' not found", name)).into_response(),
+        },
+        None => None,
+    };
+
+    let params = match crate::params::resolve(&payload.script, payload.params) {
+        Ok(params) => params,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let output = match open_sink(&payload.output) {
+        Ok(output) => output,
+        Err(e) => return output_error(e),
+    };
+
+    let session = match (&profile, payload.session) {
+        (Some(profile), Some(session_name)) => match crate::session::load(&profile.name, &session_name) {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        },
+        (None, Some(_)) => return (StatusCode::BAD_REQUEST, "A session needs a profile".to_string()).into_response(),
+        _ => None,
+    };
+
+    let job = PoolJob {
+        source: RunSource::Api,
+        profile,
+        session,
+        script: payload.script,
+        inputs: ScriptInputs { params: crate::params::to_script_map(&params), input: None, output, debug: None },
+        emit_logs: true,
+    };
+    match pool::submit(&data.app, &data.state, job) {
+        Ok((run_id, _)) => (StatusCode::ACCEPTED, Json(JobAccepted { run_id })).into_response(),
+        Err(e @ PoolError::QueueFull { .. }) => {
+            (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, "5")], e.to_string()).into_response()
         }
     }
+}
 
-    let needs_start = {
-        let s = data.state.lock().unwrap();
-        s.proxy_port == 0
+async fn list_runs(State(data): State<ServerState>) -> impl IntoResponse {
+    Json(data.state.lock().unwrap().runs.list())
+}
+
+async fn get_run(State(data): State<ServerState>, Path(run): Path<String>) -> Response {
+    match data.state.lock().unwrap().runs.get(&run) {
+        Some(record) => Json(record).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("Run '{}' not found", run)).into_response(),
+    }
+}
+
+/// What the run pushed to the registry sink, as JSON or `?format=csv|jsonl`.
+async fn get_run_records(
+    State(data): State<ServerState>,
+    Path(run): Path<String>,
+    Query(query): Query<BatchQuery>,
+) -> Response {
+    let Some(records) = data.state.lock().unwrap().runs.records(&run) else {
+        return (StatusCode::NOT_FOUND, format!("Run '{}' not found", run)).into_response();
+    };
+    match query.format {
+        None | Some(RecordFormat::Json) => Json(records).into_response(),
+        Some(format) => match batch::format_records(&records, format) {
+            Ok(body) => body.into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+        },
+    }
+}
+
+#[derive(Deserialize)]
+struct BatchRequest {
+    script: String,
+    profile: Option<String>,
+    /// Rows as JSON objects...
+    #[serde(default)]
+    rows: Vec<serde_json::Map<String, serde_json::Value>>,
+    /// ...or a CSV / JSONL dataset as text
+    input: Option<String>,
+    format: Option<RecordFormat>,
+    /// The same for every row
+    #[serde(default)]
+    params: serde_json::Map<String, serde_json::Value>,
+    /// One sink for all rows; by default pushed records join the batch results
+    #[serde(default)]
+    output: SinkConfig,
+    parallel: Option<usize>,
+    #[serde(default)]
+    retries: u32,
+}
+
+#[derive(Serialize)]
+struct BatchAccepted {
+    batch_id: String,
+    rows: usize,
+}
+
+async fn submit_batch(State(data): State<ServerState>, Json(payload): Json<BatchRequest>) -> Response {
+    let profile = match payload.profile {
+        Some(name) => match crate::commands::find_profile(&name) {
+            Some(p) => Some(p),
+            None => return (StatusCode::NOT_FOUND, format!("Profile '{}' not found", name)).into_response(),
+        },
+        None => None,
+    };
+
+    let params = match crate::params::resolve(&payload.script, payload.params) {
+        Ok(params) => params,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     };
-    if needs_start {
-        crate::proxy::restart_proxy(data.app.clone(), data.state.clone()).await;
+
+    let mut rows = payload.rows;
+    if let Some(input) = payload.input {
+        match batch::parse_rows(&input, payload.format.unwrap_or(RecordFormat::Jsonl)) {
+            Ok(parsed) => rows.extend(parsed),
+            Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        }
     }
+    if rows.is_empty() {
+        return (StatusCode::BAD_REQUEST, "The batch has no rows".to_string()).into_response();
+    }
+
+    let output = match open_sink(&payload.output) {
+        Ok(output) => output,
+        Err(e) => return output_error(e),
+    };
 
-    let label = "headless-job";
+    let parallel = payload
+        .parallel
+        .unwrap_or_else(|| data.state.lock().unwrap().pool.status().max_windows);
+    let spec = BatchSpec { source: RunSource::Api, script: payload.script, profile, rows, params, output, parallel, retries: payload.retries };
+    let batch_id = batch::register(&data.state, &spec);
+    let accepted = BatchAccepted { batch_id: batch_id.clone(), rows: spec.rows.len() };
+    tauri::async_runtime::spawn(batch::run(data.app.clone(), data.state.clone(), batch_id, spec));
+    (StatusCode::ACCEPTED, Json(accepted)).into_response()
+}
 
-    // We use ensure_target_window from commands.rs
-    // This assumes commands.rs is compiled and available (it is)
+#[derive(Deserialize)]
+struct BatchQuery {
+    format: Option<RecordFormat>,
+}
 
-    if let Some(_w) = crate::commands::ensure_target_window(&data.app, label) {
-         crate::scripting::run_script(
-             payload.script,
-             data.app.clone(),
-             data.state.clone(),
-             label.to_string()
-         );
-         (StatusCode::OK, "Job started")
-    } else {
-         (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create window")
+/// Progress and, once finished, results. `?format=csv|jsonl` returns just the results.
+async fn get_batch(
+    State(data): State<ServerState>,
+    Path(id): Path<String>,
+    Query(query): Query<BatchQuery>,
+) -> Response {
+    let Some(record) = data.state.lock().unwrap().batches.get(&id).cloned() else {
+        return (StatusCode::NOT_FOUND, format!("Batch '{}' not found", id)).into_response();
+    };
+    match query.format {
+        None | Some(RecordFormat::Json) => Json(record).into_response(),
+        Some(format) => match batch::format_records(&record.results, format) {
+            Ok(body) => body.into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+        },
     }
 }
 
-async fn stop_jobs(State(data): State<ServerState>) -> impl IntoResponse {
-    if let Some(w) = data.app.get_webview_window("target-studio") {
-        let _ = w.close();
+#[derive(Deserialize)]
+struct ValidateRequest {
+    script: String,
+}
+
+/// Compiles and lints a script without running it. Always 200; see `valid`.
+async fn validate_script(Json(payload): Json<ValidateRequest>) -> impl IntoResponse {
+    Json(crate::validate::validate(&payload.script))
+}
+
+#[derive(Deserialize)]
+struct ReplStartRequest {
+    profile: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ReplStarted {
+    session_id: String,
+}
+
+#[derive(Deserialize)]
+struct ReplEvalRequest {
+    code: String,
+}
+
+fn repl_error(e: ReplError) -> Response {
+    let status = match e {
+        ReplError::NotFound(_) => StatusCode::NOT_FOUND,
+        ReplError::TooMany(_) | ReplError::PoolBusy(_) => StatusCode::TOO_MANY_REQUESTS,
+        ReplError::Window(_) | ReplError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string()).into_response()
+}
+
+/// Opens a hidden window under the profile and a REPL session on it.
+async fn start_repl(State(data): State<ServerState>, Json(payload): Json<ReplStartRequest>) -> Response {
+    let profile = match payload.profile {
+        Some(name) => match crate::commands::find_profile(&name) {
+            Some(p) => Some(p),
+            None => return (StatusCode::NOT_FOUND, format!("Profile '{}' not found", name)).into_response(),
+        },
+        None => None,
+    };
+    match repl::start_in_new_window(&data.app, &data.state, RunSource::Api, profile).await {
+        Ok(session_id) => (StatusCode::CREATED, Json(ReplStarted { session_id })).into_response(),
+        Err(e) => repl_error(e),
+    }
+}
+
+/// Evaluates code in the session. Errors in the code are reported in the body, with 200.
+async fn eval_repl(
+    State(data): State<ServerState>,
+    Path(session): Path<String>,
+    Json(payload): Json<ReplEvalRequest>,
+) -> Response {
+    match repl::eval_blocking(data.state.clone(), session, payload.code).await {
+        Ok(output) => Json(output).into_response(),
+        Err(e) => repl_error(e),
+    }
+}
+
+async fn close_repl(State(data): State<ServerState>, Path(session): Path<String>) -> Response {
+    match repl::close(&data.app, &data.state, &session) {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => repl_error(e),
+    }
+}
+
+#[derive(Deserialize)]
+struct GenerateProfilesRequest {
+    template: String,
+    count: usize,
+    name_prefix: Option<String>,
+}
+
+async fn list_templates() -> impl IntoResponse {
+    Json(crate::templates::load_templates())
+}
+
+async fn generate_profiles(Json(payload): Json<GenerateProfilesRequest>) -> impl IntoResponse {
+    match crate::templates::generate_profiles(payload.template, payload.count, payload.name_prefix) {
+        Ok(profiles) => (StatusCode::OK, Json(profiles)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+fn profile_error(e: ProfileError) -> Response {
+    let status = match e {
+        ProfileError::NotFound(_) => StatusCode::NOT_FOUND,
+        ProfileError::AlreadyExists(_) | ProfileError::InUse { .. } | ProfileError::OpenIn { .. } => StatusCode::CONFLICT,
+        ProfileError::Invalid { .. } => StatusCode::BAD_REQUEST,
+        ProfileError::Io(_) | ProfileError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string()).into_response()
+}
+
+fn profile_response(result: Result<Profile, ProfileError>) -> Response {
+    match result {
+        Ok(profile) => Json(profile).into_response(),
+        Err(e) => profile_error(e),
+    }
+}
+
+#[derive(Deserialize)]
+struct NewNameRequest {
+    name: String,
+}
+
+async fn list_profiles() -> impl IntoResponse {
+    Json(crate::commands::get_profiles())
+}
+
+async fn create_profile(Json(profile): Json<Profile>) -> Response {
+    match profiles::create(profile) {
+        Ok(profile) => (StatusCode::CREATED, Json(profile)).into_response(),
+        Err(e) => profile_error(e),
+    }
+}
+
+async fn update_profile(
+    State(data): State<ServerState>,
+    Path(name): Path<String>,
+    Json(profile): Json<Profile>,
+) -> Response {
+    if profile.name != name {
+        return profile_error(ProfileError::invalid("name", "differs from the URL; use /rename to change it"));
     }
-    if let Some(w) = data.app.get_webview_window("headless-job") {
+    if crate::commands::find_profile(&name).is_none() {
+        return profile_error(ProfileError::NotFound(name));
+    }
+    profile_response(profiles::save(&data.app, &data.state, profile).await)
+}
+
+async fn delete_profile(State(data): State<ServerState>, Path(name): Path<String>) -> Response {
+    match profiles::delete(&data.app, &data.state, &name).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => profile_error(e),
+    }
+}
+
+async fn clone_profile(Path(name): Path<String>, Json(payload): Json<NewNameRequest>) -> Response {
+    match profiles::clone(&name, &payload.name) {
+        Ok(profile) => (StatusCode::CREATED, Json(profile)).into_response(),
+        Err(e) => profile_error(e),
+    }
+}
+
+async fn rename_profile(
+    State(data): State<ServerState>,
+    Path(name): Path<String>,
+    Json(payload): Json<NewNameRequest>,
+) -> Response {
+    profile_response(profiles::rename(&data.app, &data.state, &name, &payload.name).await)
+}
+
+async fn list_sessions(Path(profile): Path<String>) -> impl IntoResponse {
+    Json(crate::session::list(&profile))
+}
+
+#[derive(Deserialize, Default)]
+struct SaveSessionRequest {
+    /// Label of the window to save from; by default the Studio or another window under the profile
+    window: Option<String>,
+    #[serde(default)]
+    include_indexed_db: bool,
+}
+
+/// Saves the session of an open window running under the profile.
+async fn save_session(
+    State(data): State<ServerState>,
+    Path((profile, session)): Path<(String, String)>,
+    payload: Option<Json<SaveSessionRequest>>,
+) -> Response {
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    if let Err(e) = crate::session::validate_name(&session) {
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+    let (window, profile) = match crate::session::profile_window(&data.app, &data.state, &profile, payload.window.as_deref()) {
+        Ok(found) => found,
+        Err(e) => return (StatusCode::CONFLICT, e).into_response(),
+    };
+
+    let state = data.state.clone();
+    // Waiting for the page's answer blocks, so keep it off the async workers
+    let saved = tauri::async_runtime::spawn_blocking(move || {
+        crate::session::save(&window, &state, &profile.name, &session, payload.include_indexed_db)
+            .map(|snapshot| crate::session::SessionInfo::new(&session, &snapshot))
+    })
+    .await;
+    match saved {
+        Ok(Ok(info)) => Json(info).into_response(),
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Recreates the Studio window, which must run under the profile, with a saved session.
+async fn restore_session(State(data): State<ServerState>, Path((profile, session)): Path<(String, String)>) -> Response {
+    let (window, studio_profile) = match crate::session::studio_window(&data.app, &data.state) {
+        Ok(found) if found.1.name == profile => found,
+        Ok(_) => return (StatusCode::CONFLICT, format!("The Studio window doesn't run under profile '{}'", profile)).into_response(),
+        Err(e) => return (StatusCode::CONFLICT, e).into_response(),
+    };
+    let snapshot = match crate::session::load(&profile, &session) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return (StatusCode::NOT_FOUND, e).into_response(),
+    };
+
+    let restored = tauri::async_runtime::spawn_blocking(move || {
+        crate::session::reopen_with_session(&window, &studio_profile, &snapshot).map(|_| ())
+    })
+    .await;
+    match restored {
+        Ok(Ok(())) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn delete_session(Path((profile, session)): Path<(String, String)>) -> impl IntoResponse {
+    match crate::session::delete(&profile, &session) {
+        Ok(_) => (StatusCode::OK, "Deleted".to_string()),
+        Err(e) => (StatusCode::NOT_FOUND, e),
+    }
+}
+
+async fn stop_jobs(State(data): State<ServerState>) -> impl IntoResponse {
+    if let Some(w) = data.app.get_webview_window(STUDIO) {
         let _ = w.close();
     }
+    let pool = data.state.lock().unwrap().pool.clone();
+    pool.close_all(&data.app, &data.state);
     (StatusCode::OK, "Stopped")
 }
 
@@ -129,7 +522,24 @@ pub async fn start_server(port: u16, token: String, app: AppHandle, state: Arc<M
         .route("/health", get(health))
         .route("/logs", get(get_logs))
         .route("/jobs", post(submit_job))
+        .route("/batches", post(submit_batch))
+        .route("/batches/{batch}", get(get_batch))
+        .route("/runs", get(list_runs))
+        .route("/runs/{run}", get(get_run))
+        .route("/runs/{run}/records", get(get_run_records))
+        .route("/scripts/validate", post(validate_script))
+        .route("/repl", post(start_repl))
+        .route("/repl/{session}", post(eval_repl).delete(close_repl))
         .route("/stop", post(stop_jobs))
+        .route("/templates", get(list_templates))
+        .route("/profiles", get(list_profiles).post(create_profile))
+        .route("/profiles/generate", post(generate_profiles))
+        .route("/profiles/{profile}", put(update_profile).delete(delete_profile))
+        .route("/profiles/{profile}/clone", post(clone_profile))
+        .route("/profiles/{profile}/rename", post(rename_profile))
+        .route("/profiles/{profile}/sessions", get(list_sessions))
+        .route("/profiles/{profile}/sessions/{session}", post(save_session).delete(delete_session))
+        .route("/profiles/{profile}/sessions/{session}/restore", post(restore_session))
         .layer(axum::middleware::from_fn_with_state(server_state.clone(), auth_middleware))
         .with_state(server_state);
 
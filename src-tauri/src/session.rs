@@ -0,0 +1,468 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{Manager, State, Url, WebviewUrl, WebviewWindow, WebviewWindowBuilder};
+use crate::commands;
+use crate::cookies::{self, CookieRecord};
+use crate::state::{AppState, Profile};
+use crate::storage;
+use crate::workspace;
+
+/// Bumped whenever the snapshot layout changes; older files keep loading.
+pub const SESSION_FORMAT_VERSION: u32 = 2;
+
+const CAPTURE_SCRIPT: &str = include_str!("session_capture.js");
+const RESTORE_SCRIPT: &str = include_str!("session_restore.js");
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long restoring one origin may take, including loading a page of it
+const RESTORE_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to wait for an answer before asking the window again, as a script evaluated
+/// while a navigation commits can be lost with the old document
+const RESTORE_POLL: Duration = Duration::from_millis(500);
+
+/// Everything needed to resume a logged-in session under the same profile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub version: u32,
+    pub profile: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub cookies: Vec<CookieRecord>,
+    /// One entry per origin the session was saved on
+    pub origins: Vec<OriginStorage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OriginStorage {
+    pub origin: String,
+    #[serde(default)]
+    pub local_storage: BTreeMap<String, String>,
+    #[serde(default)]
+    pub session_storage: BTreeMap<String, String>,
+    /// Only captured on request, as it can be large
+    #[serde(default)]
+    pub indexed_db: Option<Vec<IdbDatabase>>,
+    /// Snapshot format the origin was captured with, which decides how restore reads
+    /// its IndexedDB values
+    #[serde(default = "first_format")]
+    pub format: u32,
+}
+
+fn first_format() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdbDatabase {
+    pub name: String,
+    pub version: u64,
+    pub stores: Vec<IdbStore>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdbStore {
+    pub name: String,
+    /// `null`, a string or an array of strings, as in `IDBObjectStore.keyPath`
+    pub key_path: Value,
+    pub auto_increment: bool,
+    pub indexes: Vec<IdbIndex>,
+    pub records: Vec<IdbRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdbIndex {
+    pub name: String,
+    pub key_path: Value,
+    pub unique: bool,
+    pub multi_entry: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdbRecord {
+    pub key: Value,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub name: String,
+    pub updated_at: DateTime<Utc>,
+    pub origins: Vec<String>,
+    pub cookies: usize,
+}
+
+impl SessionInfo {
+    pub(crate) fn new(name: &str, snapshot: &SessionSnapshot) -> Self {
+        Self {
+            name: name.to_string(),
+            updated_at: snapshot.updated_at,
+            origins: snapshot.origins.iter().map(|o| o.origin.clone()).collect(),
+            cookies: snapshot.cookies.len(),
+        }
+    }
+}
+
+/// Saved sessions live next to, not inside, the profile's browser data, so wiping or
+/// cloning the storage doesn't touch them.
+pub(crate) fn sessions_dir(profile_name: &str) -> PathBuf {
+    workspace::sessions_root().join(storage::profile_dir_name(profile_name))
+}
+
+pub fn validate_name(session_name: &str) -> Result<(), String> {
+    let valid = !session_name.is_empty()
+        && session_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !session_name.starts_with('.');
+    if !valid {
+        return Err(format!(
+            "Invalid session name '{}': use letters, digits, '-', '_' and '.'",
+            session_name
+        ));
+    }
+    Ok(())
+}
+
+fn session_path(profile_name: &str, session_name: &str) -> Result<PathBuf, String> {
+    validate_name(session_name)?;
+    Ok(sessions_dir(profile_name).join(format!("{}.json", session_name)))
+}
+
+pub fn load(profile_name: &str, session_name: &str) -> Result<SessionSnapshot, String> {
+    let path = session_path(profile_name, session_name)?;
+    let content = fs::read_to_string(&path)
+        .map_err(|_| format!("Session '{}' not found for profile '{}'", session_name, profile_name))?;
+    let snapshot: SessionSnapshot = serde_json::from_str(&content)
+        .map_err(|e| format!("Session '{}' is corrupt: {}", session_name, e))?;
+    if snapshot.version > SESSION_FORMAT_VERSION {
+        return Err(format!(
+            "Session '{}' was written by a newer version (format {})",
+            session_name, snapshot.version
+        ));
+    }
+    Ok(snapshot)
+}
+
+pub(crate) fn write(profile_name: &str, session_name: &str, snapshot: &SessionSnapshot) -> Result<(), String> {
+    let path = session_path(profile_name, session_name)?;
+    fs::create_dir_all(sessions_dir(profile_name)).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(snapshot).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write session '{}': {}", session_name, e))
+}
+
+pub fn list(profile_name: &str) -> Vec<SessionInfo> {
+    let mut sessions = Vec::new();
+    if let Ok(entries) = fs::read_dir(sessions_dir(profile_name)) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            if let Ok(snapshot) = load(profile_name, name) {
+                sessions.push(SessionInfo::new(name, &snapshot));
+            }
+        }
+    }
+    sessions.sort_by(|a, b| a.name.cmp(&b.name));
+    sessions
+}
+
+pub fn delete(profile_name: &str, session_name: &str) -> Result<(), String> {
+    let path = session_path(profile_name, session_name)?;
+    fs::remove_file(&path)
+        .map_err(|_| format!("Session '{}' not found for profile '{}'", session_name, profile_name))
+}
+
+/// Deletes every saved session of a profile.
+pub(crate) fn remove_all(profile_name: &str) -> Result<(), String> {
+    match fs::remove_dir_all(sessions_dir(profile_name)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Failed to remove the sessions of '{}': {}", profile_name, e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Moves a profile's saved sessions to its new name.
+pub(crate) fn rename_all(profile_name: &str, new_name: &str) -> std::io::Result<()> {
+    let dir = sessions_dir(profile_name);
+    if dir.exists() {
+        fs::rename(dir, sessions_dir(new_name))?;
+    }
+    Ok(())
+}
+
+/// Reads Web Storage (and IndexedDB if asked) of the page currently shown in `window`.
+fn capture_origin(
+    window: &WebviewWindow,
+    state: &Arc<Mutex<AppState>>,
+    include_indexed_db: bool,
+) -> Result<OriginStorage, String> {
+    let (tx, rx) = channel();
+    let id = uuid::Uuid::new_v4().to_string();
+    state.lock().unwrap().pending_callbacks.insert(id.clone(), tx);
+
+    let args = serde_json::json!({ "id": id, "indexedDb": include_indexed_db });
+    let js = format!("({})({});", CAPTURE_SCRIPT, args);
+    if let Err(e) = window.eval(&js) {
+        state.lock().unwrap().pending_callbacks.remove(&id);
+        return Err(format!("Failed to read page storage: {}", e));
+    }
+
+    let data = rx.recv_timeout(CAPTURE_TIMEOUT).map_err(|_| {
+        state.lock().unwrap().pending_callbacks.remove(&id);
+        "Timed out reading page storage".to_string()
+    })?;
+    if let Some(err) = data.get("error").and_then(Value::as_str) {
+        return Err(format!("Failed to read page storage: {}", err));
+    }
+    let mut origin: OriginStorage =
+        serde_json::from_value(data).map_err(|e| format!("Unexpected page storage data: {}", e))?;
+    origin.format = SESSION_FORMAT_VERSION;
+    Ok(origin)
+}
+
+/// Saves the window's cookies and the storage of the page it currently shows.
+/// Saving again under the same name keeps the other origins already in the snapshot,
+/// so a session spanning several sites can be built up one page at a time.
+pub fn save(
+    window: &WebviewWindow,
+    state: &Arc<Mutex<AppState>>,
+    profile_name: &str,
+    session_name: &str,
+    include_indexed_db: bool,
+) -> Result<SessionSnapshot, String> {
+    let origin = capture_origin(window, state, include_indexed_db)?;
+    let cookies = cookies::get_all(window)?;
+    let now = Utc::now();
+
+    let mut snapshot = match load(profile_name, session_name) {
+        Ok(existing) => existing,
+        Err(_) => SessionSnapshot {
+            version: SESSION_FORMAT_VERSION,
+            profile: profile_name.to_string(),
+            created_at: now,
+            updated_at: now,
+            cookies: Vec::new(),
+            origins: Vec::new(),
+        },
+    };
+    snapshot.version = SESSION_FORMAT_VERSION;
+    snapshot.updated_at = now;
+    snapshot.cookies = cookies;
+    snapshot.origins.retain(|o| o.origin != origin.origin);
+    snapshot.origins.push(origin);
+
+    write(profile_name, session_name, &snapshot)?;
+    Ok(snapshot)
+}
+
+/// Writes the snapshot's storage into the window, one origin at a time: the window opens
+/// the origin's `/favicon.ico` (a document of the origin that runs no page scripts),
+/// the storage is written there, and the window goes back to about:blank. Nothing is
+/// left in the pages' reach to tell that a session was restored, and later visits see
+/// only what the sites themselves store from then on. Blocks until done.
+pub fn restore_storage(
+    window: &WebviewWindow,
+    state: &Arc<Mutex<AppState>>,
+    snapshot: &SessionSnapshot,
+) -> Result<(), String> {
+    for origin in &snapshot.origins {
+        let url = Url::parse(&origin.origin)
+            .and_then(|u| u.join("/favicon.ico"))
+            .map_err(|e| format!("Invalid origin '{}' in session: {}", origin.origin, e))?;
+        window.navigate(url).map_err(|e| format!("Failed to open '{}': {}", origin.origin, e))?;
+        restore_origin(window, state, origin)?;
+    }
+    if !snapshot.origins.is_empty() {
+        let _ = window.navigate(Url::parse("about:blank").unwrap());
+    }
+    Ok(())
+}
+
+fn restore_origin(window: &WebviewWindow, state: &Arc<Mutex<AppState>>, origin: &OriginStorage) -> Result<(), String> {
+    let start = Instant::now();
+    while start.elapsed() < RESTORE_TIMEOUT {
+        // The page acknowledges right away, and answers again once the storage is written
+        let (ack_tx, ack_rx) = channel();
+        let (done_tx, done_rx) = channel();
+        let (ack_id, done_id) = (uuid::Uuid::new_v4().to_string(), uuid::Uuid::new_v4().to_string());
+        {
+            let mut s = state.lock().unwrap();
+            s.pending_callbacks.insert(ack_id.clone(), ack_tx);
+            s.pending_callbacks.insert(done_id.clone(), done_tx);
+        }
+        let forget = || {
+            let mut s = state.lock().unwrap();
+            s.pending_callbacks.remove(&ack_id);
+            s.pending_callbacks.remove(&done_id);
+        };
+
+        let args = serde_json::json!({ "ack": &ack_id, "id": &done_id, "origin": origin.origin, "storage": origin });
+        if let Err(e) = window.eval(format!("({})({});", RESTORE_SCRIPT, args)) {
+            forget();
+            return Err(format!("Failed to restore storage of '{}': {}", origin.origin, e));
+        }
+
+        let started = ack_rx.recv_timeout(RESTORE_POLL).is_ok_and(|ack| ack.get("started").is_some());
+        if !started {
+            // Still on the previous document, or the script went with it
+            forget();
+            std::thread::sleep(Duration::from_millis(100));
+            continue;
+        }
+
+        let answer = done_rx.recv_timeout(RESTORE_TIMEOUT.saturating_sub(start.elapsed()));
+        forget();
+        return match answer {
+            Ok(data) => match data.get("error").and_then(Value::as_str) {
+                Some(err) => Err(format!("Failed to restore storage of '{}': {}", origin.origin, err)),
+                None => Ok(()),
+            },
+            Err(_) => Err(format!("Timed out restoring storage of '{}'", origin.origin)),
+        };
+    }
+    Err(format!("Timed out opening a page of '{}' to restore its storage", origin.origin))
+}
+
+/// Replaces the window's cookies with the snapshot's.
+pub fn set_cookies(window: &WebviewWindow, snapshot: &SessionSnapshot) -> Result<(), String> {
+    cookies::clear(window)?;
+    for cookie in &snapshot.cookies {
+        cookies::set(window, cookie)?;
+    }
+    Ok(())
+}
+
+/// Restores a session into a fresh copy of the window (same label, size and visibility),
+/// so the sessionStorage its tab gathered doesn't mix with the snapshot's. The new
+/// window ends on about:blank.
+pub fn reopen_with_session(
+    window: &WebviewWindow,
+    profile: &Profile,
+    snapshot: &SessionSnapshot,
+) -> Result<WebviewWindow, String> {
+    let app = window.app_handle().clone();
+    let label = window.label().to_string();
+    let title = window.title().unwrap_or_default();
+    let visible = window.is_visible().unwrap_or(true);
+    let scale = window.scale_factor().unwrap_or(1.0);
+    let size = window.inner_size().ok().map(|s| s.to_logical::<f64>(scale));
+
+    window.destroy().map_err(|e| format!("Failed to close window '{}': {}", label, e))?;
+    let start = Instant::now();
+    while app.get_webview_window(&label).is_some() {
+        if start.elapsed() > Duration::from_secs(5) {
+            return Err(format!("Window '{}' did not close", label));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    let mut builder = WebviewWindowBuilder::new(&app, &label, WebviewUrl::External(Url::parse("about:blank").unwrap()))
+        .title(title)
+        .visible(visible);
+    if let Some(size) = size {
+        builder = builder.inner_size(size.width, size.height);
+    }
+    let mut builder = commands::apply_profile(builder, profile);
+    // Keep going through the window's proxy listener, if it has one
+    let state = app.state::<Arc<Mutex<AppState>>>().inner().clone();
+    let proxy_port = state.lock().unwrap().windows.get(&label).map(|c| c.proxy_port).filter(|p| *p > 0);
+    if let Some(port) = proxy_port {
+        builder = commands::apply_proxy(builder, port, Some(profile));
+    }
+    let new_window = builder
+        .build()
+        .map_err(|e| format!("Failed to recreate window '{}': {}", label, e))?;
+
+    set_cookies(&new_window, snapshot)?;
+    restore_storage(&new_window, &state, snapshot)?;
+    Ok(new_window)
+}
+
+pub(crate) fn studio_window(app: &tauri::AppHandle, state: &Arc<Mutex<AppState>>) -> Result<(WebviewWindow, Profile), String> {
+    let profile = state
+        .lock()
+        .unwrap()
+        .studio_profile()
+        .cloned()
+        .ok_or("No profile selected")?;
+    let window = app
+        .get_webview_window(crate::windows::STUDIO)
+        .ok_or("The target window is not open")?;
+    Ok((window, profile))
+}
+
+/// An open window running under the profile: `label` if given, else the Studio window
+/// if it uses the profile, else any other.
+pub fn profile_window(
+    app: &tauri::AppHandle,
+    state: &Arc<Mutex<AppState>>,
+    profile_name: &str,
+    label: Option<&str>,
+) -> Result<(WebviewWindow, Profile), String> {
+    let s = state.lock().unwrap();
+    let mut labels = s.windows_using(profile_name);
+    labels.sort_by_key(|l| l != crate::windows::STUDIO);
+    let label = match label {
+        Some(label) if labels.iter().any(|l| l == label) => label.to_string(),
+        Some(label) => return Err(format!("Window '{}' doesn't run under profile '{}'", label, profile_name)),
+        None => labels
+            .into_iter()
+            .find(|l| app.get_webview_window(l).is_some())
+            .ok_or_else(|| format!("No open window runs under profile '{}'", profile_name))?,
+    };
+    let profile = s.window_profile(&label).cloned().ok_or_else(|| format!("Window '{}' has no profile", label))?;
+    let window = app.get_webview_window(&label).ok_or_else(|| format!("Window '{}' is not open", label))?;
+    Ok((window, profile))
+}
+
+#[tauri::command]
+pub fn list_sessions(profile_name: String) -> Vec<SessionInfo> {
+    list(&profile_name)
+}
+
+#[tauri::command]
+pub fn delete_session(profile_name: String, session_name: String) -> Result<(), String> {
+    delete(&profile_name, &session_name)
+}
+
+/// Saves the Studio target window's session under the current profile.
+#[tauri::command]
+pub async fn save_session(
+    session_name: String,
+    include_indexed_db: Option<bool>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    app: tauri::AppHandle,
+) -> Result<SessionInfo, String> {
+    let (window, profile) = studio_window(&app, state.inner())?;
+    let state = state.inner().clone();
+    // Waiting for the page's answer blocks, so keep it off the async workers
+    tauri::async_runtime::spawn_blocking(move || {
+        save(&window, &state, &profile.name, &session_name, include_indexed_db.unwrap_or(false))
+            .map(|snapshot| SessionInfo::new(&session_name, &snapshot))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Recreates the Studio target window with a saved session of the current profile.
+#[tauri::command]
+pub async fn restore_session(
+    session_name: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    let (window, profile) = studio_window(&app, state.inner())?;
+    let snapshot = load(&profile.name, &session_name)?;
+    tauri::async_runtime::spawn_blocking(move || reopen_with_session(&window, &profile, &snapshot).map(|_| ()))
+        .await
+        .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,81 @@
+// Collects the current origin's Web Storage (and optionally IndexedDB) and reports it
+// back through `script_callback`. Called with { id, indexedDb }.
+(async function(args) {
+    const report = (data) => window.__TAURI__.core.invoke('script_callback', { id: args.id, data });
+
+    // Structured-clone values that JSON can't carry are tagged with a `$phantom` key so
+    // restore can rebuild them. The page's own objects with that key are wrapped as
+    // 'object', so they can't pass for a tag.
+    const encode = (v) => {
+        if (v === null || typeof v !== 'object') return v;
+        if (v instanceof Date) return { $phantom: 'date', v: v.getTime() };
+        if (v instanceof ArrayBuffer || ArrayBuffer.isView(v)) {
+            const bytes = v instanceof ArrayBuffer
+                ? new Uint8Array(v)
+                : new Uint8Array(v.buffer, v.byteOffset, v.byteLength);
+            let bin = '';
+            for (let i = 0; i < bytes.length; i++) bin += String.fromCharCode(bytes[i]);
+            return { $phantom: 'bytes', v: btoa(bin), type: v.constructor.name };
+        }
+        if (Array.isArray(v)) return v.map(encode);
+        const out = {};
+        for (const k of Object.keys(v)) out[k] = encode(v[k]);
+        return Object.prototype.hasOwnProperty.call(v, '$phantom') ? { $phantom: 'object', v: out } : out;
+    };
+
+    const dumpStorage = (storage) => {
+        const out = {};
+        for (let i = 0; i < storage.length; i++) {
+            const key = storage.key(i);
+            out[key] = storage.getItem(key);
+        }
+        return out;
+    };
+
+    const request = (r) => new Promise((resolve, reject) => {
+        r.onsuccess = () => resolve(r.result);
+        r.onerror = () => reject(r.error);
+    });
+
+    const dumpIndexedDb = async () => {
+        if (!indexedDB.databases) return null;
+        const databases = [];
+        for (const info of await indexedDB.databases()) {
+            const db = await request(indexedDB.open(info.name));
+            const stores = [];
+            for (const name of Array.from(db.objectStoreNames)) {
+                const store = db.transaction(name, 'readonly').objectStore(name);
+                const indexes = Array.from(store.indexNames).map((n) => {
+                    const index = store.index(n);
+                    return { name: n, key_path: index.keyPath, unique: index.unique, multi_entry: index.multiEntry };
+                });
+                // Both requests in the same tick, so the transaction is still active
+                const [keys, values] = await Promise.all([request(store.getAllKeys()), request(store.getAll())]);
+                stores.push({
+                    name,
+                    key_path: store.keyPath,
+                    auto_increment: store.autoIncrement,
+                    indexes,
+                    records: keys.map((key, i) => ({ key: encode(key), value: encode(values[i]) })),
+                });
+            }
+            databases.push({ name: db.name, version: db.version, stores });
+            db.close();
+        }
+        return databases;
+    };
+
+    try {
+        if (location.origin === 'null') {
+            throw new Error('the page has no origin (navigate to a site first)');
+        }
+        report({
+            origin: location.origin,
+            local_storage: dumpStorage(localStorage),
+            session_storage: dumpStorage(sessionStorage),
+            indexed_db: args.indexedDb ? await dumpIndexedDb() : null,
+        });
+    } catch (e) {
+        report({ error: String(e && e.message || e) });
+    }
+})
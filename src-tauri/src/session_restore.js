@@ -0,0 +1,89 @@
+// Writes a saved origin's storage into the document currently shown, which must be a
+// page of that origin. Acknowledges through `script_callback` with `ack` right away
+// (`{ started: true }`, or `{ pending: true }` while the window is still on another
+// document), then reports `{ done: true }` or `{ error }` with `id`.
+// Called with { ack, id, origin, storage: { local_storage, session_storage, indexed_db, format } }.
+(async function(args) {
+    const callback = (id, data) => window.__TAURI__.core.invoke('script_callback', { id, data });
+    const report = (data) => callback(args.id, data);
+    if (location.origin !== args.origin || document.readyState === 'loading') {
+        callback(args.ack, { pending: true });
+        return;
+    }
+    callback(args.ack, { started: true });
+    const saved = args.storage;
+
+    try {
+        localStorage.clear();
+        for (const [k, v] of Object.entries(saved.local_storage || {})) localStorage.setItem(k, v);
+        sessionStorage.clear();
+        for (const [k, v] of Object.entries(saved.session_storage || {})) sessionStorage.setItem(k, v);
+    } catch (e) {
+        report({ error: String(e && e.message || e) });
+        return;
+    }
+
+    // Values are tagged with a `$phantom` key (see session_capture.js). Format 1 used
+    // `$date` and `$bytes` keys instead, which the page's own objects could also have.
+    const legacy = (saved.format || 1) < 2;
+    const fromBase64 = (base64, type) => {
+        const bin = atob(base64);
+        const bytes = new Uint8Array(bin.length);
+        for (let i = 0; i < bin.length; i++) bytes[i] = bin.charCodeAt(i);
+        const Type = globalThis[type];
+        return type === 'ArrayBuffer' || typeof Type !== 'function' ? bytes.buffer : new Type(bytes.buffer);
+    };
+    const fields = (v) => {
+        const out = {};
+        for (const k of Object.keys(v)) out[k] = decode(v[k]);
+        return out;
+    };
+    const decode = (v) => {
+        if (v === null || typeof v !== 'object') return v;
+        if (Array.isArray(v)) return v.map(decode);
+        const has = (key) => Object.prototype.hasOwnProperty.call(v, key);
+        if (legacy && has('$date')) return new Date(v.$date);
+        if (legacy && has('$bytes')) return fromBase64(v.$bytes, v.$type);
+        if (!legacy && has('$phantom')) {
+            if (v.$phantom === 'date') return new Date(v.v);
+            if (v.$phantom === 'bytes') return fromBase64(v.v, v.type);
+            return fields(v.v);
+        }
+        return fields(v);
+    };
+
+    // Each database is recreated and filled in its upgrade transaction, which has
+    // committed once the open request succeeds.
+    const restoreDatabase = (db) => new Promise((resolve, reject) => {
+        indexedDB.deleteDatabase(db.name);
+        const open = indexedDB.open(db.name, db.version);
+        open.onupgradeneeded = () => {
+            const conn = open.result;
+            for (const s of db.stores) {
+                const options = { autoIncrement: s.auto_increment };
+                if (s.key_path !== null) options.keyPath = s.key_path;
+                const store = conn.createObjectStore(s.name, options);
+                for (const index of s.indexes) {
+                    store.createIndex(index.name, index.key_path, { unique: index.unique, multiEntry: index.multi_entry });
+                }
+                for (const record of s.records) {
+                    if (s.key_path !== null) store.put(decode(record.value));
+                    else store.put(decode(record.value), decode(record.key));
+                }
+            }
+        };
+        open.onsuccess = () => {
+            open.result.close();
+            resolve();
+        };
+        open.onerror = () => reject(open.error);
+        open.onblocked = () => reject(new Error(`database '${db.name}' is open elsewhere`));
+    });
+
+    try {
+        for (const db of saved.indexed_db || []) await restoreDatabase(db);
+        report({ done: true });
+    } catch (e) {
+        report({ error: String(e && e.message || e) });
+    }
+})
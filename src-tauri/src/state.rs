@@ -5,6 +5,12 @@ use serde_json::Value;
 use tokio::sync::oneshot;
 use tokio_cron_scheduler::JobScheduler;
 use std::sync::Arc;
+use crate::batch::BatchRecord;
+use crate::debugger::DebugSession;
+use crate::pool::WindowPool;
+use crate::repl::ReplSession;
+use crate::runs::RunRegistry;
+use crate::vault::Vault;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyConfig {
@@ -13,6 +19,30 @@ pub struct ProxyConfig {
     pub port: u16,
     pub username: Option<String>,
     pub password: Option<String>,
+    /// Name of a vault secret holding the password, used instead of `password`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScreenConfig {
+    pub width: u32,
+    pub height: u32,
+    /// Defaults to `height` (no taskbar)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avail_height: Option<u32>,
+    #[serde(default = "default_pixel_ratio")]
+    pub pixel_ratio: f64,
+}
+
+fn default_pixel_ratio() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WebGlConfig {
+    pub vendor: String,
+    pub renderer: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +51,23 @@ pub struct Profile {
     pub user_agent: String,
     pub seed: u64,
     pub proxy: Option<ProxyConfig>,
+    /// Spoofed `screen` / `devicePixelRatio`; the real values are used when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screen: Option<ScreenConfig>,
+    /// Fixed WebGL vendor/renderer pair; picked from the seed when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webgl: Option<WebGlConfig>,
+    /// Seed derivation scheme (see `fingerprint::FINGERPRINT_VERSION`). Profiles saved
+    /// before the field existed are version 1, which keeps their fingerprint stable.
+    #[serde(default = "legacy_fingerprint_version")]
+    pub fingerprint_version: u32,
+    /// Drive `click`, `type` and scrolling with human-like event sequences (see `humanize`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub human_input: bool,
+}
+
+fn legacy_fingerprint_version() -> u32 {
+    1
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -29,36 +76,77 @@ pub enum ProxyStatus {
     Connected(String),
 }
 
+/// The identity a target window runs under, and the resources bound to it.
+#[derive(Default)]
+pub struct WindowContext {
+    pub profile: Option<Profile>,
+    /// Port of the window's own proxy listener, 0 until one is started
+    pub proxy_port: u16,
+    pub proxy_shutdown_tx: Option<oneshot::Sender<()>>,
+    /// Body of the last request that went through the window's proxy
+    pub last_request: Option<String>,
+}
+
 pub struct AppState {
     pub is_running: bool,
-    pub current_profile: Option<Profile>,
     pub proxy_status: ProxyStatus,
     pub script_logs: Vec<String>,
     pub pending_callbacks: HashMap<String, Sender<Value>>,
-    pub proxy_port: u16,
     pub ca_cert: Option<String>,
-    pub proxy_shutdown_tx: Option<oneshot::Sender<()>>,
-    pub last_request: Option<String>,
+    /// Target windows by label. The Studio's entry holds the profile selected in the UI.
+    pub windows: HashMap<String, WindowContext>,
     pub recorded_script: String,
     pub scheduler: Option<Arc<JobScheduler>>,
     pub is_recording: bool,
+    pub pool: Arc<WindowPool>,
+    pub runs: RunRegistry,
+    pub batches: HashMap<String, BatchRecord>,
+    /// Decrypted secrets while the vault is unlocked
+    pub vault: Option<Vault>,
+    /// The Studio run being debugged, if any
+    pub debug_session: Option<Arc<DebugSession>>,
+    /// Open REPL sessions by id
+    pub repl_sessions: HashMap<String, Arc<ReplSession>>,
 }
 
 impl Default for AppState {
     fn default() -> Self {
         Self {
             is_running: false,
-            current_profile: None,
             proxy_status: ProxyStatus::Disconnected,
             script_logs: Vec::new(),
             pending_callbacks: HashMap::new(),
-            proxy_port: 0,
             ca_cert: None,
-            proxy_shutdown_tx: None,
-            last_request: None,
+            windows: HashMap::new(),
             recorded_script: String::new(),
             scheduler: None,
             is_recording: false,
+            pool: Arc::new(WindowPool::default()),
+            runs: RunRegistry::default(),
+            batches: HashMap::new(),
+            vault: None,
+            debug_session: None,
+            repl_sessions: HashMap::new(),
         }
     }
 }
+
+impl AppState {
+    pub fn window_profile(&self, label: &str) -> Option<&Profile> {
+        self.windows.get(label).and_then(|c| c.profile.as_ref())
+    }
+
+    /// The profile selected in the UI, i.e. the one the Studio window runs under.
+    pub fn studio_profile(&self) -> Option<&Profile> {
+        self.window_profile(crate::windows::STUDIO)
+    }
+
+    /// Labels of the windows running under the named profile.
+    pub fn windows_using(&self, profile_name: &str) -> Vec<String> {
+        self.windows
+            .iter()
+            .filter(|(_, c)| c.profile.as_ref().is_some_and(|p| p.name == profile_name))
+            .map(|(label, _)| label.clone())
+            .collect()
+    }
+}
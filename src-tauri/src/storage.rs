@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager, Runtime, State, WebviewWindowBuilder};
+use crate::profiles;
+use crate::state::AppState;
+use crate::workspace::{self, copy_dir_recursive};
+
+/// Identifies a profile's storage. Derived from the whole name, so names that slug
+/// the same ("Work 1", "Work.1") still get their own.
+fn storage_id(profile_name: &str) -> uuid::Uuid {
+    uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, profile_name.as_bytes())
+}
+
+/// Name of a profile's folders in the workspace: the name slugged for the filesystem,
+/// followed by its storage id.
+pub(crate) fn profile_dir_name(profile_name: &str) -> String {
+    let slug: String = profile_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    format!("{}-{}", slug, storage_id(profile_name).simple())
+}
+
+/// Directory holding one profile's browser storage.
+pub fn profile_data_dir(profile_name: &str) -> PathBuf {
+    workspace::profile_data_root().join(profile_dir_name(profile_name))
+}
+
+/// Points a window at the profile's own data store instead of the shared default one.
+pub fn apply_storage<'a, R: Runtime, M: Manager<R>>(
+    builder: WebviewWindowBuilder<'a, R, M>,
+    profile_name: &str,
+) -> WebviewWindowBuilder<'a, R, M> {
+    // WKWebView has no data directory; it isolates by data store identifier instead
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    {
+        builder.data_store_identifier(*storage_id(profile_name).as_bytes())
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    {
+        let dir = profile_data_dir(profile_name);
+        if let Err(e) = fs::create_dir_all(&dir) {
+            eprintln!("Failed to create profile data dir {}: {}", dir.display(), e);
+        }
+        builder.data_directory(dir)
+    }
+}
+
+/// Removes a directory, retrying briefly: the webview process of a just-closed
+/// window can hold its files for a moment.
+pub(crate) async fn remove_dir_with_retry(path: &Path) -> Result<(), String> {
+    let mut last_err = None;
+    for _ in 0..10 {
+        match fs::remove_dir_all(path) {
+            Ok(_) => return Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => last_err = Some(e),
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    }
+    Err(format!(
+        "Failed to remove {}: {}",
+        path.display(),
+        last_err.map(|e| e.to_string()).unwrap_or_default()
+    ))
+}
+
+/// Moves a directory, retrying briefly like [`remove_dir_with_retry`]. False if there
+/// was nothing to move.
+pub(crate) async fn move_dir_with_retry(from: &Path, to: &Path) -> Result<bool, String> {
+    if !from.exists() {
+        return Ok(false);
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut last_err = None;
+    for _ in 0..10 {
+        match fs::rename(from, to) {
+            Ok(_) => return Ok(true),
+            Err(e) => last_err = Some(e),
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    }
+    Err(format!(
+        "Failed to move {} to {}: {}",
+        from.display(),
+        to.display(),
+        last_err.map(|e| e.to_string()).unwrap_or_default()
+    ))
+}
+
+/// Deletes everything a profile has stored: the data directory and, on macOS, its
+/// WebKit data store.
+pub(crate) async fn remove_profile_storage(app: &AppHandle, profile_name: &str) -> Result<(), String> {
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    {
+        let id = *storage_id(profile_name).as_bytes();
+        let exists = app.fetch_data_store_identifiers().await.map_err(|e| e.to_string())?.contains(&id);
+        if exists {
+            app.remove_data_store(id)
+                .await
+                .map_err(|e| format!("Failed to remove the data store of '{}': {}", profile_name, e))?;
+        }
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    let _ = app;
+
+    remove_dir_with_retry(&profile_data_dir(profile_name)).await
+}
+
+/// The Studio window keeps its data store open, so release it before touching the files.
+pub(crate) fn close_studio_if_using(app: &AppHandle, state: &Arc<Mutex<AppState>>, profile_name: &str) {
+    let in_use = {
+        let s = state.lock().unwrap();
+        s.studio_profile().is_some_and(|p| p.name == profile_name)
+    };
+    if in_use {
+        if let Some(w) = app.get_webview_window(crate::windows::STUDIO) {
+            let _ = w.destroy();
+        }
+    }
+}
+
+/// Closes the Studio if it holds the profile's data store open. Refused while a run or
+/// REPL window uses it, since its files would change underneath a live webview.
+pub(crate) fn close_windows_using(app: &AppHandle, state: &Arc<Mutex<AppState>>, profile_name: &str) -> Result<(), String> {
+    profiles::ensure_not_open(state, profile_name).map_err(|e| e.to_string())?;
+    close_studio_if_using(app, state, profile_name);
+    Ok(())
+}
+
+/// Deletes all cookies, storage and cache of a profile.
+#[tauri::command]
+pub async fn wipe_profile_storage(profile_name: String, state: State<'_, Arc<Mutex<AppState>>>, app: AppHandle) -> Result<(), String> {
+    close_windows_using(&app, state.inner(), &profile_name)?;
+    remove_profile_storage(&app, &profile_name).await
+}
+
+/// Replaces `target_profile`'s storage with a copy of `source_profile`'s. Not available
+/// on macOS, where WebKit can't copy a data store.
+#[tauri::command]
+pub async fn clone_profile_storage(
+    source_profile: String,
+    target_profile: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+    app: AppHandle,
+) -> Result<(), String> {
+    if cfg!(any(target_os = "macos", target_os = "ios")) {
+        return Err("Cloning profile storage isn't supported on macOS".into());
+    }
+    if source_profile == target_profile {
+        return Err("Source and target profile are the same".into());
+    }
+
+    let src = profile_data_dir(&source_profile);
+    if !src.exists() {
+        return Err(format!("Profile '{}' has no stored data", source_profile));
+    }
+
+    close_windows_using(&app, state.inner(), &source_profile)?;
+    close_windows_using(&app, state.inner(), &target_profile)?;
+
+    let dst = profile_data_dir(&target_profile);
+    remove_dir_with_retry(&dst).await?;
+    copy_dir_recursive(&src, &dst).map_err(|e| format!("Failed to copy profile storage: {}", e))
+}
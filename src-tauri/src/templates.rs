@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use crate::commands;
+use crate::fingerprint::{self, SeedRng, FINGERPRINT_VERSION};
+use crate::profiles;
+use crate::state::{Profile, ScreenConfig, WebGlConfig};
+use crate::workspace;
+
+/// A family of coherent identities. Every profile generated from a template
+/// combines values from the same pools, so OS, browser, screen and GPU never contradict.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FingerprintTemplate {
+    pub name: String,
+    pub browser_versions: VersionRange,
+    /// Substituted for `{os_version}` in `user_agent`
+    #[serde(default)]
+    pub os_versions: Vec<String>,
+    /// UA pattern with `{version}` (browser major) and `{os_version}` placeholders
+    pub user_agent: String,
+    pub screens: Vec<ScreenConfig>,
+    pub gpus: Vec<WebGlConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl FingerprintTemplate {
+    fn validate(&self) -> Result<(), String> {
+        if self.browser_versions.min > self.browser_versions.max {
+            return Err(format!("Template '{}': browser_versions.min is greater than max", self.name));
+        }
+        if self.screens.is_empty() {
+            return Err(format!("Template '{}' has no screens", self.name));
+        }
+        if self.gpus.is_empty() {
+            return Err(format!("Template '{}' has no gpus", self.name));
+        }
+        if self.user_agent.contains("{os_version}") && self.os_versions.is_empty() {
+            return Err(format!("Template '{}' uses {{os_version}} but has no os_versions", self.name));
+        }
+        Ok(())
+    }
+
+    /// Builds one profile. All choices are derived from `seed`, so a profile
+    /// can be regenerated from its template and seed alone.
+    fn instantiate(&self, name: String, seed: u64) -> Profile {
+        let mut rng = SeedRng::for_surface(seed, "template");
+        let mut pick = |len: usize| rng.below(len);
+
+        let span = (self.browser_versions.max - self.browser_versions.min + 1) as usize;
+        let version = self.browser_versions.min + pick(span) as u32;
+        let os_version = if self.os_versions.is_empty() {
+            String::new()
+        } else {
+            self.os_versions[pick(self.os_versions.len())].clone()
+        };
+        let screen = self.screens[pick(self.screens.len())].clone();
+        let gpu = self.gpus[pick(self.gpus.len())].clone();
+
+        let user_agent = self
+            .user_agent
+            .replace("{version}", &version.to_string())
+            .replace("{os_version}", &os_version);
+
+        Profile {
+            name,
+            user_agent,
+            seed,
+            proxy: None,
+            screen: Some(screen),
+            webgl: Some(gpu),
+            fingerprint_version: FINGERPRINT_VERSION,
+            human_input: false,
+        }
+    }
+}
+
+fn get_templates_path() -> PathBuf {
+    workspace::templates_dir()
+}
+
+pub fn load_templates() -> Vec<FingerprintTemplate> {
+    let mut templates = Vec::new();
+    if let Ok(entries) = fs::read_dir(get_templates_path()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|c| {
+                serde_json::from_str::<FingerprintTemplate>(&c).map_err(|e| e.to_string())
+            }) {
+                Ok(t) => templates.push(t),
+                Err(e) => eprintln!("Skipping template {}: {}", path.display(), e),
+            }
+        }
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    templates
+}
+
+/// Most profiles one `generate` call creates.
+pub const MAX_GENERATE: usize = 1000;
+
+/// Generates `count` profiles from a template, with seeds and names that are unique
+/// among `existing` and within the batch. Fails if one of them isn't a valid profile,
+/// e.g. because of the prefix or the template's user agent.
+pub fn generate(
+    template: &FingerprintTemplate,
+    count: usize,
+    name_prefix: &str,
+    existing: &[Profile],
+) -> Result<Vec<Profile>, String> {
+    generate_with_seeds(template, count, name_prefix, existing, fingerprint::random_seed)
+}
+
+/// `generate`, drawing candidate seeds from `next_seed`.
+fn generate_with_seeds(
+    template: &FingerprintTemplate,
+    count: usize,
+    name_prefix: &str,
+    existing: &[Profile],
+    mut next_seed: impl FnMut() -> u64,
+) -> Result<Vec<Profile>, String> {
+    template.validate()?;
+    if count > MAX_GENERATE {
+        return Err(format!("Can't generate more than {} profiles at once", MAX_GENERATE));
+    }
+
+    let mut seeds: HashSet<u64> = existing.iter().map(|p| p.seed).collect();
+    let mut names: HashSet<String> = existing.iter().map(|p| p.name.clone()).collect();
+
+    let mut profiles = Vec::with_capacity(count);
+    let mut index = 1;
+    while profiles.len() < count {
+        let name = format!("{} {:03}", name_prefix, index);
+        index += 1;
+        if names.contains(&name) {
+            continue;
+        }
+
+        let seed = loop {
+            let candidate = next_seed();
+            if seeds.insert(candidate) {
+                break candidate;
+            }
+        };
+
+        names.insert(name.clone());
+        let profile = template.instantiate(name, seed);
+        profiles::validate(&profile)
+            .map_err(|e| format!("Template '{}' makes an invalid profile '{}': {}", template.name, profile.name, e))?;
+        profiles.push(profile);
+    }
+    Ok(profiles)
+}
+
+#[tauri::command]
+pub fn list_templates() -> Vec<FingerprintTemplate> {
+    load_templates()
+}
+
+/// Generates profiles from the named template and appends them to `profiles.json`.
+#[tauri::command]
+pub fn generate_profiles(template: String, count: usize, name_prefix: Option<String>) -> Result<Vec<Profile>, String> {
+    let template = load_templates()
+        .into_iter()
+        .find(|t| t.name == template)
+        .ok_or_else(|| format!("Template '{}' not found", template))?;
+
+    let mut profiles = commands::load_profiles_from_disk();
+    let prefix = name_prefix.unwrap_or_else(|| template.name.clone());
+    let generated = generate(&template, count, &prefix, &profiles)?;
+
+    profiles.extend(generated.iter().cloned());
+    commands::save_profiles_to_disk(&profiles).map_err(|e| format!("Failed to save profiles: {}", e))?;
+    Ok(generated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(user_agent: &str) -> FingerprintTemplate {
+        FingerprintTemplate {
+            name: "Windows Chrome".into(),
+            browser_versions: VersionRange { min: 120, max: 124 },
+            os_versions: vec!["10.0".into(), "11.0".into()],
+            user_agent: user_agent.into(),
+            screens: vec![
+                ScreenConfig { width: 1920, height: 1080, avail_height: None, pixel_ratio: 1.0 },
+                ScreenConfig { width: 2560, height: 1440, avail_height: None, pixel_ratio: 1.25 },
+            ],
+            gpus: vec![
+                WebGlConfig { vendor: "Google Inc.".into(), renderer: "ANGLE".into() },
+                WebGlConfig { vendor: "Google Inc. (Intel)".into(), renderer: "ANGLE (Intel)".into() },
+            ],
+        }
+    }
+
+    #[test]
+    fn generated_profiles_must_be_valid() {
+        let ua = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) Chrome/{version}.0.0.0 Safari/537.36";
+        for (user_agent, prefix, valid) in [
+            (ua, "Shop", true),
+            (ua, " Shop", false),
+            (ua, "Shop\n", false),
+            (ua, &"x".repeat(120), false),
+            ("Chrome/{version}\r\nX-Evil: 1", "Shop", false),
+        ] {
+            let generated = generate(&template(user_agent), 3, prefix, &[]);
+            assert_eq!(generated.is_ok(), valid, "{:?} {:?}", user_agent, prefix);
+        }
+    }
+
+    const UA: &str = "Mozilla/5.0 (Windows NT {os_version}; Win64; x64) Chrome/{version}.0.0.0 Safari/537.36";
+
+    fn existing(name: &str, seed: u64) -> Profile {
+        Profile { seed, ..template(UA).instantiate(name.into(), 0) }
+    }
+
+    #[test]
+    fn generate_skips_names_and_seeds_already_taken() {
+        let taken = [existing("Shop 001", 1), existing("Shop 003", 2)];
+        // Seed 1 belongs to a saved profile, and 3 is drawn twice
+        let mut seeds = [1, 3, 2, 3, 4, 5].into_iter();
+        let generated = generate_with_seeds(&template(UA), 3, "Shop", &taken, || seeds.next().unwrap()).unwrap();
+
+        let names: Vec<&str> = generated.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Shop 002", "Shop 004", "Shop 005"]);
+        let seeds: Vec<u64> = generated.iter().map(|p| p.seed).collect();
+        assert_eq!(seeds, [3, 4, 5]);
+    }
+
+    #[test]
+    fn generated_names_and_seeds_are_unique_within_a_batch() {
+        let generated = generate(&template(UA), 200, "Shop", &[]).unwrap();
+        let names: HashSet<&str> = generated.iter().map(|p| p.name.as_str()).collect();
+        let seeds: HashSet<u64> = generated.iter().map(|p| p.seed).collect();
+        assert_eq!((names.len(), seeds.len()), (200, 200));
+    }
+
+    #[test]
+    fn instantiate_is_deterministic_for_a_seed() {
+        let template = template(UA);
+        let json = |seed| serde_json::to_value(template.instantiate("P".into(), seed)).unwrap();
+        for seed in [0, 1, 12345, fingerprint::random_seed()] {
+            assert_eq!(json(seed), json(seed), "{}", seed);
+        }
+        // The seed picks the values, not just the noise
+        let distinct: HashSet<String> = (0..50)
+            .map(|seed| {
+                let p = template.instantiate("P".into(), seed);
+                format!("{} {:?} {:?}", p.user_agent, p.screen, p.webgl)
+            })
+            .collect();
+        assert!(distinct.len() > 1);
+    }
+}
@@ -0,0 +1,364 @@
+//! Connects a window's proxy listener to the profile's own proxy. Every connection to a
+//! site is tunnelled through it (HTTP `CONNECT` or SOCKS5), so the site sees the upstream
+//! proxy's address while the listener still rewrites the requests.
+
+use base64::Engine;
+use hyper_util::client::legacy::connect::{Connected, Connection};
+use hyper_util::rt::TokioIo;
+use rustls::pki_types::ServerName;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::state::ProxyConfig;
+
+/// Anything a tunnel can run over: a plain TCP stream or TLS to an `https` proxy.
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+/// An open tunnel to the site, as handed to hyper.
+pub struct Tunnel(TokioIo<Box<dyn Stream>>);
+
+impl hyper::rt::Read for Tunnel {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: hyper::rt::ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl hyper::rt::Write for Tunnel {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+impl Connection for Tunnel {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+/// Opens tunnels through one upstream proxy. The password is the resolved one, never the
+/// vault reference.
+#[derive(Clone)]
+pub struct UpstreamConnector {
+    proxy: Arc<ProxyConfig>,
+}
+
+impl UpstreamConnector {
+    pub fn new(proxy: ProxyConfig) -> Self {
+        Self { proxy: Arc::new(proxy) }
+    }
+}
+
+impl tower_service::Service<http::Uri> for UpstreamConnector {
+    type Response = Tunnel;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Tunnel>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, dst: http::Uri) -> Self::Future {
+        let proxy = self.proxy.clone();
+        Box::pin(async move {
+            let host = dst
+                .host()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("No host in {}", dst)))?
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string();
+            let port = dst
+                .port_u16()
+                .unwrap_or(if dst.scheme_str() == Some("https") { 443 } else { 80 });
+            open_tunnel(&proxy, &host, port).await.map(|stream| Tunnel(TokioIo::new(stream)))
+        })
+    }
+}
+
+async fn open_tunnel(proxy: &ProxyConfig, host: &str, port: u16) -> io::Result<Box<dyn Stream>> {
+    let tcp = TcpStream::connect((proxy.host.as_str(), proxy.port)).await?;
+    match proxy.protocol.as_str() {
+        "http" => http_connect(tcp, proxy, host, port).await,
+        "https" => {
+            let tls = tls_to_proxy(tcp, &proxy.host).await?;
+            http_connect(tls, proxy, host, port).await
+        }
+        "socks5" | "socks5h" => socks5_connect(tcp, proxy, host, port).await,
+        other => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Unsupported proxy protocol '{}'", other),
+        )),
+    }
+}
+
+async fn tls_to_proxy(tcp: TcpStream, proxy_host: &str) -> io::Result<impl Stream> {
+    let roots = rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from(proxy_host.to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    tokio_rustls::TlsConnector::from(Arc::new(config)).connect(name, tcp).await
+}
+
+/// The proxy's username, if credentials are to be sent at all.
+fn username(proxy: &ProxyConfig) -> Option<&str> {
+    proxy.username.as_deref().filter(|u| !u.is_empty())
+}
+
+/// Asks an HTTP proxy for a tunnel with `CONNECT`, using Basic auth when a username is set.
+async fn http_connect<S: Stream + 'static>(
+    mut stream: S,
+    proxy: &ProxyConfig,
+    host: &str,
+    port: u16,
+) -> io::Result<Box<dyn Stream>> {
+    let target = if host.contains(':') { format!("[{}]:{}", host, port) } else { format!("{}:{}", host, port) };
+    let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+    if let Some(username) = username(proxy) {
+        let credentials = format!("{}:{}", username, proxy.password.as_deref().unwrap_or(""));
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", encoded));
+    }
+    request.push_str("\r\n");
+
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    // Read the answer byte by byte, so nothing from the tunnel is swallowed with it
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > 16 * 1024 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Upstream proxy sent an oversized answer"));
+        }
+        response.push(stream.read_u8().await?);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or("");
+    let code = status.split_whitespace().nth(1).unwrap_or("");
+    if !code.starts_with('2') {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("Upstream proxy refused CONNECT {}: {}", target, status.trim()),
+        ));
+    }
+    Ok(Box::new(stream))
+}
+
+/// SOCKS5 handshake (RFC 1928), with username/password auth (RFC 1929) when a username is
+/// set. The host name is always sent as is, so the proxy resolves it (as `socks5h` would).
+async fn socks5_connect(
+    mut stream: TcpStream,
+    proxy: &ProxyConfig,
+    host: &str,
+    port: u16,
+) -> io::Result<Box<dyn Stream>> {
+    let refused = |reason: String| io::Error::new(io::ErrorKind::ConnectionRefused, reason);
+
+    let method = if username(proxy).is_some() { 0x02 } else { 0x00 };
+    stream.write_all(&[0x05, 0x01, method]).await?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply != [0x05, method] {
+        return Err(refused("SOCKS5 proxy refused the authentication method".into()));
+    }
+
+    if let Some(username) = username(proxy) {
+        let password = proxy.password.as_deref().unwrap_or("");
+        if username.len() > 255 || password.len() > 255 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "SOCKS5 credentials are too long"));
+        }
+        let mut auth = vec![0x01, username.len() as u8];
+        auth.extend_from_slice(username.as_bytes());
+        auth.push(password.len() as u8);
+        auth.extend_from_slice(password.as_bytes());
+        stream.write_all(&auth).await?;
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0x00 {
+            return Err(refused("SOCKS5 proxy rejected the credentials".into()));
+        }
+    }
+
+    let mut request = vec![0x05, 0x01, 0x00];
+    match host.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V4(ip)) => {
+            request.push(0x01);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(std::net::IpAddr::V6(ip)) => {
+            request.push(0x04);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Host name is too long for SOCKS5"));
+            }
+            request.push(0x03);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[1] != 0x00 {
+        return Err(refused(format!("SOCKS5 proxy could not connect to {}:{} (code {})", host, port, head[1])));
+    }
+    // Skip the bound address the proxy reports
+    let address_len = match head[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => stream.read_u8().await? as usize,
+        other => return Err(refused(format!("SOCKS5 proxy sent address type {}", other))),
+    };
+    let mut bound = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound).await?;
+
+    Ok(Box::new(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn proxy(protocol: &str, port: u16, username: Option<&str>, password: Option<&str>) -> ProxyConfig {
+        ProxyConfig {
+            protocol: protocol.into(),
+            host: "127.0.0.1".into(),
+            port,
+            username: username.map(Into::into),
+            password: password.map(Into::into),
+            password_secret: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn http_connect_sends_basic_auth_and_hands_over_the_tunnel() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let upstream = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                socket.read_line(&mut line).await.unwrap();
+                head.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            socket.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+            socket.write_all(b"hello").await.unwrap();
+            head
+        });
+
+        let mut tunnel = open_tunnel(&proxy("http", port, Some("user"), Some("p@ss")), "example.com", 443)
+            .await
+            .unwrap();
+        let mut greeting = [0u8; 5];
+        tunnel.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(&greeting, b"hello");
+
+        let head = upstream.await.unwrap();
+        assert!(head.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+        // base64("user:p@ss")
+        assert!(head.contains("Proxy-Authorization: Basic dXNlcjpwQHNz\r\n"));
+    }
+
+    #[tokio::test]
+    async fn http_connect_reports_a_refused_tunnel() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await;
+            socket.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").await.unwrap();
+        });
+
+        let err = open_tunnel(&proxy("http", port, None, None), "example.com", 80)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("407"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn socks5_authenticates_and_sends_the_host_name() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let upstream = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            socket.read_exact(&mut greeting).await.unwrap();
+            socket.write_all(&[0x05, 0x02]).await.unwrap();
+
+            let mut auth = [0u8; 2 + 4 + 1 + 6];
+            socket.read_exact(&mut auth).await.unwrap();
+            socket.write_all(&[0x01, 0x00]).await.unwrap();
+
+            let mut request = [0u8; 4 + 1 + 11 + 2];
+            socket.read_exact(&mut request).await.unwrap();
+            socket.write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0x1f, 0x90]).await.unwrap();
+            socket.write_all(b"hi").await.unwrap();
+            (greeting, auth, request)
+        });
+
+        let mut tunnel = open_tunnel(&proxy("socks5", port, Some("user"), Some("secret")), "example.com", 443)
+            .await
+            .unwrap();
+        let mut greeting = [0u8; 2];
+        tunnel.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(&greeting, b"hi");
+
+        let (hello, auth, request) = upstream.await.unwrap();
+        assert_eq!(hello, [0x05, 0x01, 0x02]);
+        assert_eq!(&auth, b"\x01\x04user\x06secret");
+        assert_eq!(&request, b"\x05\x01\x00\x03\x0bexample.com\x01\xbb");
+    }
+
+    #[tokio::test]
+    async fn socks5_reports_a_failed_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            socket.read_exact(&mut greeting).await.unwrap();
+            socket.write_all(&[0x05, 0x00]).await.unwrap();
+            let mut request = [0u8; 4 + 4 + 2];
+            socket.read_exact(&mut request).await.unwrap();
+            socket.write_all(&[0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await.unwrap();
+        });
+
+        let err = open_tunnel(&proxy("socks5", port, None, None), "10.0.0.1", 80)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("code 5"), "{}", err);
+    }
+}
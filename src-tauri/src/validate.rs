@@ -0,0 +1,233 @@
+use rhai::{ASTNode, Engine, Expr, Position, Stmt, AST};
+use serde::{Deserialize, Serialize};
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
+use crate::error::ParamError;
+use crate::params;
+use crate::scripting::{self, BrowserApi, OutputApi, SecretsApi};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// 1-based, when the problem has a position
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, message: String, pos: Position) -> Self {
+        Self { severity, message, line: pos.line(), column: pos.position() }
+    }
+}
+
+/// Outcome of checking a script without running it. Warnings don't make it invalid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Validation {
+    pub valid: bool,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Validation {
+    /// The errors as one message, e.g. to refuse a job.
+    pub fn error_summary(&self) -> String {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| match (d.line, d.column) {
+                (Some(line), Some(column)) => format!("line {}, column {}: {}", line, column, d.message),
+                _ => d.message.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// The objects a script starts with, and the argument counts of each of their methods.
+fn known_methods(engine: &Engine) -> HashMap<&'static str, HashMap<String, Vec<usize>>> {
+    let objects = [
+        ("browser", TypeId::of::<BrowserApi>()),
+        ("secrets", TypeId::of::<SecretsApi>()),
+        ("output", TypeId::of::<OutputApi>()),
+    ];
+    let registered = engine.collect_fn_metadata(
+        None,
+        |info| {
+            let this = *info.metadata.param_types.first()?;
+            Some((this, info.metadata.name.to_string(), info.metadata.num_params - 1))
+        },
+        false,
+    );
+
+    objects
+        .into_iter()
+        .map(|(name, type_id)| {
+            let mut methods: HashMap<String, Vec<usize>> = HashMap::new();
+            for (_, method, args) in registered.iter().filter(|(this, ..)| *this == type_id) {
+                let counts = methods.entry(method.clone()).or_default();
+                if !counts.contains(args) {
+                    counts.push(*args);
+                    counts.sort_unstable();
+                }
+            }
+            (name, methods)
+        })
+        .collect()
+}
+
+fn is_loop(stmt: &Stmt) -> bool {
+    matches!(stmt, Stmt::While(..) | Stmt::Do(..) | Stmt::For(..))
+}
+
+fn check_calls(ast: &AST, engine: &Engine, diagnostics: &mut Vec<Diagnostic>) {
+    let known = known_methods(engine);
+    // Loops (by position) with a fixed sleep and loops that wait for the page
+    let mut sleeping_loops: Vec<Position> = Vec::new();
+    let mut waiting_loops: HashSet<Position> = HashSet::new();
+
+    ast.walk(&mut |path: &[ASTNode]| {
+        let Some(ASTNode::Expr(Expr::Dot(dot, ..))) = path.last() else { return true };
+        let (Expr::Variable(var, ..), Expr::MethodCall(call, pos)) = (&dot.lhs, &dot.rhs) else { return true };
+        let object = var.1.as_str();
+        let Some(methods) = known.get(object) else { return true };
+
+        let method = call.name.as_str();
+        match methods.get(method) {
+            None => diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                format!("'{}' has no method '{}'", object, method),
+                *pos,
+            )),
+            Some(counts) if !counts.contains(&call.args.len()) => {
+                let expected = counts.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(" or ");
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    format!("{}.{} takes {} argument(s), got {}", object, method, expected, call.args.len()),
+                    *pos,
+                ));
+            }
+            Some(_) => {}
+        }
+
+        let mut loops = path.iter().filter_map(|node| match node {
+            ASTNode::Stmt(stmt) if is_loop(stmt) => Some(stmt.position()),
+            _ => None,
+        });
+        match (object, method) {
+            ("browser", "sleep" | "wait") => sleeping_loops.extend(loops.next_back()),
+            ("browser", "wait_for_selector") => waiting_loops.extend(loops),
+            _ => {}
+        }
+        true
+    });
+
+    let mut warned = HashSet::new();
+    for pos in sleeping_loops {
+        if !waiting_loops.contains(&pos) && warned.insert(pos) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                "fixed sleep in a loop that never waits for the page; prefer browser.wait_for_selector".into(),
+                pos,
+            ));
+        }
+    }
+}
+
+/// Compiles the script without running it and checks its `@param` declarations and
+/// the methods it calls on `browser`, `secrets` and `output`.
+pub fn validate(script: &str) -> Validation {
+    let mut engine = Engine::new();
+    scripting::register_api(&mut engine);
+    let mut diagnostics = Vec::new();
+
+    match engine.compile(script) {
+        Err(e) => diagnostics.push(Diagnostic::new(Severity::Error, e.err_type().to_string(), e.position())),
+        Ok(ast) => check_calls(&ast, &engine, &mut diagnostics),
+    }
+    if let Err(e) = params::declared(script) {
+        let line = match e {
+            ParamError::Declaration { line, .. } => Some(line),
+            _ => None,
+        };
+        diagnostics.push(Diagnostic { severity: Severity::Error, message: e.to_string(), line, column: None });
+    }
+
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    let valid = diagnostics.iter().all(|d| d.severity != Severity::Error);
+    Validation { valid, diagnostics }
+}
+
+/// `--check`: prints the diagnostics as `file:line:column: severity: message` and
+/// returns the exit code.
+pub fn check_file(path: &str) -> i32 {
+    let script = match std::fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("Error reading script '{}': {}", path, e);
+            return 1;
+        }
+    };
+    let validation = validate(&script);
+    for d in &validation.diagnostics {
+        let severity = match d.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match (d.line, d.column) {
+            (Some(line), Some(column)) => eprintln!("{}:{}:{}: {}: {}", path, line, column, severity, d.message),
+            _ => eprintln!("{}: {}: {}", path, severity, d.message),
+        }
+    }
+    if validation.valid {
+        println!("{}: OK", path);
+        0
+    } else {
+        1
+    }
+}
+
+#[tauri::command]
+pub fn validate_script(script: String) -> Validation {
+    validate(&script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_point_at_the_problem() {
+        let warning = "fixed sleep in a loop that never waits for the page; prefer browser.wait_for_selector";
+        for (script, expected) in [
+            ("browser.navigate(\"https://example.com\");", vec![]),
+            ("browser.fly();", vec![(Severity::Error, "'browser' has no method 'fly'", Some(1), Some(9))]),
+            (
+                "let a = 1;\nbrowser.navigate(\"a\", \"b\");",
+                vec![(Severity::Error, "browser.navigate takes 1 argument(s), got 2", Some(2), Some(9))],
+            ),
+            ("while true {\n    browser.sleep(100);\n}", vec![(Severity::Warning, warning, Some(1), Some(1))]),
+            ("loop {\n    browser.wait_for_selector(\"#x\");\n    browser.sleep(100);\n}", vec![]),
+            ("let a = 1;\nlet b = (a + ;", vec![(Severity::Error, "Unexpected ';'", Some(2), Some(14))]),
+            (
+                "// @param url\n// @param n: number\nbrowser.navigate(url);",
+                vec![(Severity::Error, "Invalid @param declaration on line 2: unknown type", Some(2), None)],
+            ),
+        ] {
+            let validation = validate(script);
+            let found: Vec<_> = validation
+                .diagnostics
+                .iter()
+                .map(|d| (d.severity, d.message.as_str(), d.line, d.column))
+                .collect();
+            assert_eq!(found, expected, "{}", script);
+            assert_eq!(validation.valid, expected.iter().all(|d| d.0 != Severity::Error), "{}", script);
+        }
+    }
+}
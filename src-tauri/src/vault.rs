@@ -0,0 +1,360 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tauri::State;
+use crate::commands::{load_profiles_from_disk, save_profiles_to_disk};
+use crate::crypto::{self, SealedBox, SealingKey};
+use crate::error::{ProfileError, VaultError};
+use crate::state::{AppState, ProxyConfig};
+use crate::workspace;
+
+/// Bumped whenever the vault file layout changes.
+pub const VAULT_FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    /// JSON map of secret name to value
+    sealed: SealedBox,
+}
+
+/// How the vault is unlocked: a passphrase, or the contents of a key file.
+pub enum VaultKey {
+    Passphrase(String),
+    KeyFile(PathBuf),
+}
+
+impl VaultKey {
+    fn bytes(&self) -> Result<Vec<u8>, VaultError> {
+        match self {
+            VaultKey::Passphrase(p) if p.is_empty() => Err(VaultError::WrongKey),
+            VaultKey::Passphrase(p) => Ok(p.as_bytes().to_vec()),
+            VaultKey::KeyFile(path) => fs::read(path)
+                .map_err(|e| VaultError::Format(format!("Cannot read key file {}: {}", path.display(), e))),
+        }
+    }
+}
+
+/// The decrypted vault. Only held in memory while unlocked.
+pub struct Vault {
+    secrets: BTreeMap<String, String>,
+    /// Derived once at unlock, so saving doesn't rerun Argon2id under the state lock
+    key: SealingKey,
+}
+
+/// A piece of text, as split by `Vault::split`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    /// The name of the secret found there
+    Secret(&'a str),
+}
+
+/// Secrets shorter than this aren't redacted: masking every "a" or "42" in the logs
+/// would garble them without hiding anything.
+const MIN_REDACTED_LEN: usize = 3;
+
+fn vault_path() -> PathBuf {
+    workspace::root().join("vault.json")
+}
+
+fn validate_name(name: &str) -> Result<(), VaultError> {
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if valid {
+        Ok(())
+    } else {
+        Err(VaultError::InvalidName(name.to_string()))
+    }
+}
+
+impl Vault {
+    /// Opens the workspace vault, creating an empty one with this key if none exists yet.
+    pub fn unlock(key: &VaultKey) -> Result<Self, VaultError> {
+        let key = key.bytes()?;
+        let path = vault_path();
+        if !path.exists() {
+            let vault = Vault { secrets: BTreeMap::new(), key: SealingKey::new(&key).map_err(VaultError::Format)? };
+            vault.save()?;
+            return Ok(vault);
+        }
+
+        let file: VaultFile = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| VaultError::Format(e.to_string()))?;
+        if file.version > VAULT_FORMAT_VERSION {
+            return Err(VaultError::Format(format!("vault format {} is newer than this version supports", file.version)));
+        }
+        let (plaintext, key) = crypto::open_with_key(&key, &file.sealed)
+            .map_err(VaultError::Format)?
+            .ok_or(VaultError::WrongKey)?;
+        let secrets = serde_json::from_slice(&plaintext).map_err(|e| VaultError::Format(e.to_string()))?;
+        Ok(Vault { secrets, key })
+    }
+
+    fn save(&self) -> Result<(), VaultError> {
+        let plaintext = serde_json::to_vec(&self.secrets).map_err(|e| VaultError::Format(e.to_string()))?;
+        let file = VaultFile {
+            version: VAULT_FORMAT_VERSION,
+            sealed: self.key.seal(&plaintext).map_err(VaultError::Format)?,
+        };
+        let json = serde_json::to_string_pretty(&file).map_err(|e| VaultError::Format(e.to_string()))?;
+        // Written beside the vault and renamed over it, so a failed write leaves the old one
+        let path = vault_path();
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, json)?;
+        fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.secrets.get(name).map(String::as_str)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.secrets.keys().cloned().collect()
+    }
+
+    pub fn set(&mut self, name: &str, value: String) -> Result<(), VaultError> {
+        validate_name(name)?;
+        self.secrets.insert(name.to_string(), value);
+        self.save()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), VaultError> {
+        self.secrets.remove(name).ok_or_else(|| VaultError::NotFound(name.to_string()))?;
+        self.save()
+    }
+
+    /// Name of the secret whose value is exactly `value`, if any.
+    pub fn name_of(&self, value: &str) -> Option<&str> {
+        self.secrets
+            .iter()
+            .find(|(_, v)| !v.is_empty() && v.as_str() == value)
+            .map(|(k, _)| k.as_str())
+    }
+
+    /// Secret values of at least `MIN_REDACTED_LEN` characters, longest first, so a
+    /// secret that contains another is matched whole.
+    fn redactable(&self) -> Vec<(&str, &str)> {
+        let mut secrets: Vec<(&str, &str)> = self
+            .secrets
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .filter(|(_, v)| v.chars().count() >= MIN_REDACTED_LEN)
+            .collect();
+        secrets.sort_by_key(|(_, v)| std::cmp::Reverse(v.len()));
+        secrets
+    }
+
+    /// Replaces every secret value of at least `MIN_REDACTED_LEN` characters in `text` with `***`.
+    pub fn redact(&self, text: &str) -> String {
+        self.redactable().into_iter().fold(text.to_string(), |acc, (_, v)| acc.replace(v, "***"))
+    }
+
+    /// `text` split into the runs between secrets and the names of the secrets, in order.
+    /// Finds the same secrets `redact` masks.
+    pub fn split<'a>(&'a self, text: &'a str) -> Vec<Segment<'a>> {
+        let secrets = self.redactable();
+        let mut segments = Vec::new();
+        let mut rest = text;
+        loop {
+            // Earliest match; at the same position the longest wins, since it comes first
+            let next = secrets
+                .iter()
+                .filter_map(|&(name, value)| rest.find(value).map(|at| (at, name, value.len())))
+                .min_by_key(|&(at, _, _)| at);
+            let Some((at, name, len)) = next else { break };
+            if at > 0 {
+                segments.push(Segment::Text(&rest[..at]));
+            }
+            segments.push(Segment::Secret(name));
+            rest = &rest[at + len..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest));
+        }
+        segments
+    }
+}
+
+#[cfg(test)]
+impl Vault {
+    /// An unlocked vault holding `secrets`, with nothing on disk.
+    pub(crate) fn with_secrets(secrets: &[(&str, &str)]) -> Self {
+        Self {
+            secrets: secrets.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            key: SealingKey::new(b"test").unwrap(),
+        }
+    }
+}
+
+/// Masks vault secrets in text headed for logs or the UI. A no-op while the vault is locked.
+pub fn redact(state: &AppState, text: &str) -> String {
+    match &state.vault {
+        Some(vault) => vault.redact(text),
+        None => text.to_string(),
+    }
+}
+
+/// The proxy password, looked up in the vault when the profile references a secret.
+pub fn resolve_proxy_password(state: &AppState, proxy: &ProxyConfig) -> Result<Option<String>, VaultError> {
+    match &proxy.password_secret {
+        Some(name) => {
+            let vault = state.vault.as_ref().ok_or(VaultError::Locked)?;
+            vault
+                .get(name)
+                .map(|v| Some(v.to_string()))
+                .ok_or_else(|| VaultError::NotFound(name.clone()))
+        }
+        None => Ok(proxy.password.clone()),
+    }
+}
+
+/// Unlocks the vault at startup from `--vault-passphrase` / `--vault-key-file`.
+pub fn unlock_from_cli(state: &Arc<Mutex<AppState>>, passphrase: Option<String>, key_file: Option<PathBuf>) {
+    let key = match (passphrase, key_file) {
+        (_, Some(path)) => VaultKey::KeyFile(path),
+        (Some(p), None) => VaultKey::Passphrase(p),
+        (None, None) => return,
+    };
+    match Vault::unlock(&key) {
+        Ok(vault) => {
+            eprintln!("Vault unlocked ({} secrets)", vault.secrets.len());
+            state.lock().unwrap().vault = Some(vault);
+        }
+        Err(e) => eprintln!("Failed to unlock vault: {}", e),
+    }
+}
+
+fn with_vault<T>(state: &Arc<Mutex<AppState>>, f: impl FnOnce(&mut Vault) -> Result<T, VaultError>) -> Result<T, VaultError> {
+    let mut s = state.lock().unwrap();
+    let vault = s.vault.as_mut().ok_or(VaultError::Locked)?;
+    f(vault)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultStatus {
+    pub exists: bool,
+    pub unlocked: bool,
+    /// Secret names (never values); empty while locked
+    pub names: Vec<String>,
+}
+
+#[tauri::command]
+pub fn vault_status(state: State<'_, Arc<Mutex<AppState>>>) -> VaultStatus {
+    let s = state.lock().unwrap();
+    VaultStatus {
+        exists: vault_path().exists(),
+        unlocked: s.vault.is_some(),
+        names: s.vault.as_ref().map(Vault::names).unwrap_or_default(),
+    }
+}
+
+/// Unlocks (or creates) the vault with a passphrase or a key file.
+#[tauri::command]
+pub async fn unlock_vault(
+    passphrase: Option<String>,
+    key_file: Option<String>,
+    state: State<'_, Arc<Mutex<AppState>>>,
+) -> Result<(), VaultError> {
+    let key = match (passphrase, key_file) {
+        (_, Some(path)) => VaultKey::KeyFile(PathBuf::from(path)),
+        (Some(p), None) => VaultKey::Passphrase(p),
+        (None, None) => return Err(VaultError::WrongKey),
+    };
+    // Key derivation takes a moment; keep it off the async workers
+    let vault = tauri::async_runtime::spawn_blocking(move || Vault::unlock(&key))
+        .await
+        .map_err(|e| VaultError::Format(e.to_string()))??;
+    state.lock().unwrap().vault = Some(vault);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn lock_vault(state: State<'_, Arc<Mutex<AppState>>>) {
+    state.lock().unwrap().vault = None;
+}
+
+#[tauri::command]
+pub async fn set_secret(name: String, value: String, state: State<'_, Arc<Mutex<AppState>>>) -> Result<(), VaultError> {
+    with_vault(state.inner(), |v| v.set(&name, value))
+}
+
+#[tauri::command]
+pub async fn delete_secret(name: String, state: State<'_, Arc<Mutex<AppState>>>) -> Result<(), VaultError> {
+    with_vault(state.inner(), |v| v.remove(&name))
+}
+
+/// Moves a profile's plaintext proxy password into the vault under a new secret name and
+/// leaves a reference behind.
+#[tauri::command]
+pub async fn store_proxy_password(
+    profile_name: String,
+    secret_name: String,
+    state: State<'_, Arc<Mutex<AppState>>>,
+) -> Result<(), VaultError> {
+    let mut profiles = load_profiles_from_disk();
+    let profile = profiles
+        .iter_mut()
+        .find(|p| p.name == profile_name)
+        .ok_or_else(|| ProfileError::NotFound(profile_name.clone()))?;
+    let proxy = profile
+        .proxy
+        .as_mut()
+        .ok_or_else(|| ProfileError::invalid("proxy", "the profile has no proxy"))?;
+    let password = proxy
+        .password
+        .take()
+        .ok_or_else(|| ProfileError::invalid("proxy.password", "no plaintext password to move"))?;
+
+    with_vault(state.inner(), |v| match v.get(&secret_name) {
+        Some(_) => Err(VaultError::AlreadyExists(secret_name.clone())),
+        None => v.set(&secret_name, password),
+    })?;
+    proxy.password_secret = Some(secret_name);
+    let updated = profile.clone();
+    save_profiles_to_disk(&profiles).map_err(ProfileError::from)?;
+
+    let mut s = state.lock().unwrap();
+    for label in s.windows_using(&profile_name) {
+        if let Some(context) = s.windows.get_mut(&label) {
+            context.profile = Some(updated.clone());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_masks_secrets_but_not_very_short_ones() {
+        let vault = Vault::with_secrets(&[("api", "hunter22"), ("api_prefix", "hunter"), ("pin", "42"), ("flag", "y")]);
+        for (text, redacted) in [
+            ("token hunter22", "token ***"),
+            ("token hunter", "token ***"),
+            ("answer 42, say y", "answer 42, say y"),
+            ("nothing here", "nothing here"),
+        ] {
+            assert_eq!(vault.redact(text), redacted, "{}", text);
+        }
+    }
+
+    #[test]
+    fn split_finds_the_secrets_redact_masks() {
+        use Segment::{Secret, Text};
+        let vault = Vault::with_secrets(&[("api", "hunter22"), ("api_prefix", "hunter"), ("pin", "42")]);
+        for (text, segments) in [
+            ("hunter22", vec![Secret("api")]),
+            ("user:hunter22@host", vec![Text("user:"), Secret("api"), Text("@host")]),
+            ("hunterhunter22 42", vec![Secret("api_prefix"), Secret("api"), Text(" 42")]),
+            ("nothing here", vec![Text("nothing here")]),
+            ("", vec![]),
+        ] {
+            assert_eq!(vault.split(text), segments, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder, Url};
+use crate::commands;
+use crate::proxy;
+use crate::session::{self, SessionSnapshot};
+use crate::state::{AppState, Profile};
+
+/// Label of the target window driven from the UI.
+pub const STUDIO: &str = "target-studio";
+
+/// Binds a profile to a window label. The window's fingerprint, proxy listener and
+/// scripts all take their identity from here, so concurrent runs don't mix.
+pub fn bind(state: &Arc<Mutex<AppState>>, label: &str, profile: Option<Profile>) {
+    let mut s = state.lock().unwrap();
+    s.windows.entry(label.to_string()).or_default().profile = profile;
+}
+
+/// Forgets a window's context and stops its proxy listener, if it has one.
+pub fn release(state: &Arc<Mutex<AppState>>, label: &str) {
+    let context = state.lock().unwrap().windows.remove(label);
+    if let Some(tx) = context.and_then(|c| c.proxy_shutdown_tx) {
+        let _ = tx.send(());
+    }
+}
+
+/// Opens a hidden window for a single run, with its own profile, proxy listener and
+/// optionally a saved session. Pair with `close` once the run is over.
+pub async fn open_run_window(
+    app: &AppHandle,
+    state: &Arc<Mutex<AppState>>,
+    label: &str,
+    profile: Option<Profile>,
+    snapshot: Option<&SessionSnapshot>,
+) -> Result<WebviewWindow, String> {
+    bind(state, label, profile.clone());
+    // No proxy on mobile (see `proxy::restart_proxy`)
+    let port = if cfg!(any(target_os = "android", target_os = "ios")) {
+        None
+    } else {
+        proxy::restart_proxy(app.clone(), state.clone(), label).await;
+        let port = state.lock().unwrap().windows.get(label).map(|c| c.proxy_port).unwrap_or(0);
+        // Without its listener the run would go out with the engine's own identity
+        if port == 0 || !commands::wait_for_port(port).await {
+            release(state, label);
+            return Err(format!("The proxy for window '{}' did not start", label));
+        }
+        Some(port)
+    };
+
+    let url = Url::parse("about:blank").unwrap();
+    let mut builder = WebviewWindowBuilder::new(app, label, WebviewUrl::External(url))
+        .inner_size(1920.0, 1080.0)
+        .visible(false);
+    if let Some(profile) = &profile {
+        builder = commands::apply_profile(builder, profile);
+    }
+    if let Some(port) = port {
+        builder = commands::apply_proxy(builder, port, profile.as_ref());
+    }
+
+    let window = match builder.build() {
+        Ok(w) => w,
+        Err(e) => {
+            release(state, label);
+            return Err(format!("Failed to create window '{}': {}", label, e));
+        }
+    };
+
+    if let Some(snapshot) = snapshot {
+        let (restored_window, state_handle, snapshot) = (window.clone(), state.clone(), snapshot.clone());
+        // Waiting for the pages' answers blocks, so keep it off the async workers
+        let restored = tauri::async_runtime::spawn_blocking(move || {
+            session::set_cookies(&restored_window, &snapshot)?;
+            session::restore_storage(&restored_window, &state_handle, &snapshot)
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r);
+        if let Err(e) = restored {
+            close(app, state, label);
+            return Err(e);
+        }
+    }
+    Ok(window)
+}
+
+pub fn close(app: &AppHandle, state: &Arc<Mutex<AppState>>, label: &str) {
+    if let Some(w) = app.get_webview_window(label) {
+        let _ = w.destroy();
+    }
+    release(state, label);
+}
@@ -0,0 +1,224 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tauri::{AppHandle, Manager};
+
+/// Layout version of the workspace directory. Each bump adds a step to [`migrate`].
+pub const WORKSPACE_SCHEMA_VERSION: u32 = 2;
+
+const MANIFEST_FILE: &str = "workspace.json";
+
+/// Files and folders older versions kept in the current working directory.
+const LEGACY_ENTRIES: [&str; 5] = ["profiles.json", "jobs.json", "scripts", "templates", "profile_data"];
+
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WorkspaceManifest {
+    schema_version: u32,
+    /// Directory legacy data was imported from, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    migrated_from: Option<String>,
+}
+
+/// Resolves the workspace root (`--workspace` / `PHANTOM_WORKSPACE`, else the app data
+/// dir), brings its layout up to date and makes it available through [`root`].
+/// Must run before anything reads profiles, jobs or scripts.
+pub fn init(app: &AppHandle, override_dir: Option<PathBuf>) -> Result<PathBuf, String> {
+    let dir = match override_dir {
+        Some(dir) if dir.is_absolute() => dir,
+        Some(dir) => std::env::current_dir().map_err(|e| e.to_string())?.join(dir),
+        None => app
+            .path()
+            .app_data_dir()
+            .map_err(|e| format!("Failed to resolve the app data dir: {}", e))?,
+    };
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create workspace {}: {}", dir.display(), e))?;
+
+    migrate(&dir)?;
+
+    let root = ROOT.get_or_init(|| dir);
+    eprintln!("Workspace: {}", root.display());
+    Ok(root.clone())
+}
+
+/// Root directory holding all persistent data.
+pub fn root() -> &'static Path {
+    ROOT.get().expect("workspace::init must run before the workspace is used")
+}
+
+pub fn profiles_path() -> PathBuf {
+    root().join("profiles.json")
+}
+
+pub fn jobs_path() -> PathBuf {
+    root().join("jobs.json")
+}
+
+/// Script limits per run source, see `policy::PolicyConfig`.
+pub fn policy_path() -> PathBuf {
+    root().join("policy.json")
+}
+
+pub fn scripts_dir() -> PathBuf {
+    root().join("scripts")
+}
+
+pub fn templates_dir() -> PathBuf {
+    root().join("templates")
+}
+
+/// Default place for script output files (`output.push` sinks).
+pub fn outputs_dir() -> PathBuf {
+    root().join("outputs")
+}
+
+/// Files scripts may upload when their policy keeps them in the workspace.
+pub fn uploads_dir() -> PathBuf {
+    root().join("uploads")
+}
+
+/// Cookie jars scripts export and import when their policy keeps them in the workspace.
+pub fn cookies_dir() -> PathBuf {
+    root().join("cookies")
+}
+
+/// Root of every profile's webview data (cookies, localStorage, IndexedDB, cache).
+pub fn profile_data_root() -> PathBuf {
+    root().join("profile_data")
+}
+
+/// Root of every profile's saved sessions, kept apart from the webview data so wiping
+/// or cloning a profile's storage leaves them alone.
+pub fn sessions_root() -> PathBuf {
+    root().join("sessions")
+}
+
+/// The CA the proxy listeners sign their TLS certificates with.
+pub fn ca_dir() -> PathBuf {
+    root().join("ca")
+}
+
+fn read_manifest(dir: &Path) -> Result<WorkspaceManifest, String> {
+    let path = dir.join(MANIFEST_FILE);
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+        // No manifest: a new workspace, or one that predates versioning
+        Err(_) => Ok(WorkspaceManifest { schema_version: 0, migrated_from: None }),
+    }
+}
+
+fn write_manifest(dir: &Path, manifest: &WorkspaceManifest) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(manifest).map_err(|e| e.to_string())?;
+    fs::write(dir.join(MANIFEST_FILE), json).map_err(|e| format!("Failed to write workspace manifest: {}", e))
+}
+
+/// Runs every migration step between the workspace's schema version and the current one.
+fn migrate(dir: &Path) -> Result<(), String> {
+    let mut manifest = read_manifest(dir)?;
+    if manifest.schema_version > WORKSPACE_SCHEMA_VERSION {
+        return Err(format!(
+            "Workspace {} uses schema {}, newer than this build supports ({})",
+            dir.display(),
+            manifest.schema_version,
+            WORKSPACE_SCHEMA_VERSION
+        ));
+    }
+
+    while manifest.schema_version < WORKSPACE_SCHEMA_VERSION {
+        match manifest.schema_version {
+            0 => import_legacy_cwd(dir, &mut manifest)?,
+            1 => move_sessions_out_of_profile_data(dir)?,
+            v => unreachable!("no migration from workspace schema {}", v),
+        }
+        manifest.schema_version += 1;
+        write_manifest(dir, &manifest)?;
+    }
+    Ok(())
+}
+
+/// v0 -> v1: copies data that earlier versions kept in the working directory. The
+/// originals are left alone (in development the working directory is the source tree),
+/// and anything already present in the workspace wins.
+fn import_legacy_cwd(dir: &Path, manifest: &mut WorkspaceManifest) -> Result<(), String> {
+    let Ok(cwd) = std::env::current_dir() else { return Ok(()) };
+    let same_dir = match (cwd.canonicalize(), dir.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    };
+    if same_dir {
+        return Ok(());
+    }
+
+    let mut imported = Vec::new();
+    for name in LEGACY_ENTRIES {
+        let src = cwd.join(name);
+        let dst = dir.join(name);
+        if !src.exists() || dst.exists() {
+            continue;
+        }
+        let result = if src.is_dir() {
+            copy_dir_recursive(&src, &dst)
+        } else {
+            fs::copy(&src, &dst).map(|_| ())
+        };
+        result.map_err(|e| format!("Failed to import {} into the workspace: {}", src.display(), e))?;
+        imported.push(name);
+    }
+
+    if !imported.is_empty() {
+        eprintln!(
+            "Imported {} from {} into the workspace; the originals can be deleted.",
+            imported.join(", "),
+            cwd.display()
+        );
+        manifest.migrated_from = Some(cwd.display().to_string());
+    }
+    Ok(())
+}
+
+/// v1 -> v2: saved sessions lived in `profile_data/<profile>/sessions`; they move to
+/// `sessions/<profile>`, under the same folder name.
+fn move_sessions_out_of_profile_data(dir: &Path) -> Result<(), String> {
+    let Ok(entries) = fs::read_dir(dir.join("profile_data")) else { return Ok(()) };
+    for entry in entries.flatten() {
+        let src = entry.path().join("sessions");
+        if !src.is_dir() {
+            continue;
+        }
+        let dst = dir.join("sessions").join(entry.file_name());
+        fs::create_dir_all(dir.join("sessions")).map_err(|e| e.to_string())?;
+        fs::rename(&src, &dst)
+            .map_err(|e| format!("Failed to move {} to {}: {}", src.display(), dst.display(), e))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn copy_dir_recursive(src: &Path, dst: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_recursive(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceInfo {
+    pub root: String,
+    pub schema_version: u32,
+}
+
+#[tauri::command]
+pub fn get_workspace() -> WorkspaceInfo {
+    WorkspaceInfo {
+        root: root().display().to_string(),
+        schema_version: WORKSPACE_SCHEMA_VERSION,
+    }
+}
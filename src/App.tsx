@@ -5,6 +5,8 @@ import "./App.css";
 import { NetworkTab } from "./components/NetworkTab";
 import { SettingsTab, Profile } from "./components/SettingsTab";
 import { JobsTab } from "./components/JobsTab";
+import { DebugPanel } from "./components/DebugPanel";
+import { ReplPanel } from "./components/ReplPanel";
 
 function App() {
   const [profiles, setProfiles] = useState<Profile[]>([]);
@@ -18,6 +20,8 @@ function App() {
   const [isRecording, setIsRecording] = useState(false);
   const [isConsoleOpen, setIsConsoleOpen] = useState(false); // Mobile console toggle
   const [isScriptListOpen, setIsScriptListOpen] = useState(false); // Mobile script list toggle
+  const [isDebugOpen, setIsDebugOpen] = useState(false);
+  const [isReplOpen, setIsReplOpen] = useState(false);
 
   const logsEndRef = useRef<HTMLDivElement>(null);
 
@@ -220,6 +224,18 @@ function App() {
                         </button>
                         <button onClick={saveScript} className="hidden md:block px-3 py-1 bg-blue-600 hover:bg-blue-500 rounded text-sm">Save</button>
                         <button onClick={runScript} className="px-3 py-1 bg-green-600 hover:bg-green-500 rounded text-sm font-bold">RUN</button>
+                        <button
+                            onClick={() => setIsDebugOpen(!isDebugOpen)}
+                            className={`hidden md:block px-3 py-1 rounded text-sm ${isDebugOpen ? "bg-yellow-600" : "bg-gray-700 hover:bg-gray-600"}`}
+                        >
+                            Debug
+                        </button>
+                        <button
+                            onClick={() => setIsReplOpen(!isReplOpen)}
+                            className={`hidden md:block px-3 py-1 rounded text-sm ${isReplOpen ? "bg-purple-600" : "bg-gray-700 hover:bg-gray-600"}`}
+                        >
+                            REPL
+                        </button>
 
                         <button
                             onClick={() => setIsConsoleOpen(!isConsoleOpen)}
@@ -244,6 +260,13 @@ function App() {
                                 spellCheck={false}
                             />
                         </div>
+                        {isDebugOpen && (
+                            <DebugPanel
+                                script={scriptContent}
+                                onLog={(line) => setLogs(prev => [...prev, line])}
+                            />
+                        )}
+                        {isReplOpen && <ReplPanel />}
                         {/* Console */}
                         <div className={`
                             ${isConsoleOpen ? 'flex' : 'hidden'}
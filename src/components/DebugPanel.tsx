@@ -0,0 +1,137 @@
+import { useState, useEffect } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+
+interface Variable {
+  name: string;
+  type: string;
+  value: unknown;
+  constant: boolean;
+}
+
+interface DebugPause {
+  line: number | null;
+  column: number | null;
+  source: string | null;
+  reason: string;
+  call_stack: string[];
+  variables: Variable[];
+  last_result: { method: string; value: unknown } | null;
+}
+
+type DebugAction = "continue" | "step_over" | "step_into" | "step_out" | "stop";
+
+function parseLines(text: string): number[] {
+  return text
+    .split(/[\s,]+/)
+    .map(s => parseInt(s, 10))
+    .filter(n => !isNaN(n) && n > 0);
+}
+
+export function DebugPanel({ script, onLog }: { script: string; onLog: (line: string) => void }) {
+  const [breakpoints, setBreakpoints] = useState("");
+  const [stopOnEntry, setStopOnEntry] = useState(true);
+  const [running, setRunning] = useState(false);
+  const [paused, setPaused] = useState<DebugPause | null>(null);
+
+  useEffect(() => {
+    const unlistenPaused = listen<DebugPause>("debug_paused", (event) => setPaused(event.payload));
+    const unlistenResumed = listen("debug_resumed", () => setPaused(null));
+    const unlistenFinished = listen("debug_finished", () => {
+      setPaused(null);
+      setRunning(false);
+    });
+    return () => {
+      unlistenPaused.then(f => f());
+      unlistenResumed.then(f => f());
+      unlistenFinished.then(f => f());
+    };
+  }, []);
+
+  const start = async () => {
+    onLog("> Debugging script...");
+    try {
+      await invoke("execute_script", {
+        script,
+        debug: { breakpoints: parseLines(breakpoints), stop_on_entry: stopOnEntry },
+      });
+      setRunning(true);
+    } catch (e) {
+      onLog(`Error starting debugger: ${e}`);
+    }
+  };
+
+  const send = async (action: DebugAction) => {
+    try {
+      await invoke("debug_action", { action });
+    } catch (e) {
+      onLog(`Debugger: ${e}`);
+    }
+  };
+
+  const updateBreakpoints = async (text: string) => {
+    setBreakpoints(text);
+    if (running) {
+      await invoke("set_breakpoints", { lines: parseLines(text) }).catch(() => {});
+    }
+  };
+
+  const button = "px-2 py-1 bg-gray-700 hover:bg-gray-600 rounded disabled:opacity-40";
+
+  return (
+    <div className="bg-gray-900 border-t border-gray-700 text-xs flex flex-col max-h-64">
+      <div className="bg-gray-800 px-2 py-1 flex flex-wrap items-center gap-2">
+        <span className="text-gray-400">Breakpoints</span>
+        <input
+          className="bg-gray-900 px-2 py-0.5 rounded w-32 font-mono"
+          value={breakpoints}
+          onChange={(e) => updateBreakpoints(e.target.value)}
+          placeholder="e.g. 3, 12"
+        />
+        <label className="flex items-center gap-1 text-gray-400">
+          <input type="checkbox" checked={stopOnEntry} onChange={(e) => setStopOnEntry(e.target.checked)} />
+          Stop on entry
+        </label>
+        <button onClick={start} disabled={running} className="px-2 py-1 bg-yellow-600 hover:bg-yellow-500 rounded font-bold disabled:opacity-40">Debug</button>
+        <button onClick={() => send("continue")} disabled={!paused} className={button}>Continue</button>
+        <button onClick={() => send("step_over")} disabled={!paused} className={button}>Step Over</button>
+        <button onClick={() => send("step_into")} disabled={!paused} className={button}>Step Into</button>
+        <button onClick={() => send("step_out")} disabled={!paused} className={button}>Step Out</button>
+        <button onClick={() => send("stop")} disabled={!running} className={button}>Stop</button>
+        <span className="text-gray-400 ml-auto">
+          {paused
+            ? `Paused (${paused.reason}) at ${paused.source ?? "script"}:${paused.line ?? "?"}`
+            : running ? "Running" : "Idle"}
+        </span>
+      </div>
+      {paused && (
+        <div className="flex-1 overflow-y-auto p-2 font-mono grid grid-cols-1 md:grid-cols-2 gap-2">
+          <div>
+            <div className="text-gray-500 mb-1">Variables</div>
+            {paused.variables.map(v => (
+              <div key={v.name} className="truncate">
+                <span className="text-blue-400">{v.constant ? "const " : ""}{v.name}</span>
+                <span className="text-gray-500">: {v.type} = </span>
+                <span className="text-gray-200">{JSON.stringify(v.value)}</span>
+              </div>
+            ))}
+          </div>
+          <div>
+            <div className="text-gray-500 mb-1">Call stack</div>
+            {paused.call_stack.length === 0 && <div className="text-gray-600">(top level)</div>}
+            {paused.call_stack.map((frame, i) => <div key={i} className="truncate">{frame}</div>)}
+            {paused.last_result && (
+              <>
+                <div className="text-gray-500 mt-2 mb-1">Last result</div>
+                <div className="truncate">
+                  <span className="text-blue-400">{paused.last_result.method}</span>
+                  <span className="text-gray-200"> = {JSON.stringify(paused.last_result.value)}</span>
+                </div>
+              </>
+            )}
+          </div>
+        </div>
+      )}
+    </div>
+  );
+}
@@ -6,6 +6,9 @@ interface Job {
     script_path: string;
     cron: string;
     profile?: string;
+    session?: string;
+    params?: Record<string, unknown>;
+    output?: { type: "registry" | "jsonl" | "csv" | "sqlite"; path?: string; table?: string };
     last_run?: string;
     status: string;
 }
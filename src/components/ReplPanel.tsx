@@ -0,0 +1,119 @@
+import { useState, useEffect, useRef } from "react";
+import { invoke } from "@tauri-apps/api/core";
+
+interface ReplOutput {
+  prints: string[];
+  value: unknown;
+  type: string;
+  display: string;
+  error: string | null;
+}
+
+interface Entry {
+  id: number;
+  code: string;
+  output: ReplOutput | null;
+}
+
+export function ReplPanel() {
+  const [sessionId, setSessionId] = useState<string | null>(null);
+  const [startError, setStartError] = useState<string | null>(null);
+  const [entries, setEntries] = useState<Entry[]>([]);
+  const [code, setCode] = useState("");
+  const [busy, setBusy] = useState(false);
+  const [historyIndex, setHistoryIndex] = useState<number | null>(null);
+  const endRef = useRef<HTMLDivElement>(null);
+
+  useEffect(() => {
+    let id: string | null = null;
+    let closed = false;
+    invoke<string>("repl_start")
+      .then(started => {
+        id = started;
+        if (closed) {
+          invoke("repl_close", { sessionId: started }).catch(() => {});
+        } else {
+          setSessionId(started);
+        }
+      })
+      .catch(e => setStartError(String(e)));
+    return () => {
+      closed = true;
+      if (id) invoke("repl_close", { sessionId: id }).catch(() => {});
+    };
+  }, []);
+
+  useEffect(() => {
+    endRef.current?.scrollIntoView({ behavior: "smooth" });
+  }, [entries]);
+
+  const evaluate = async () => {
+    if (!sessionId || !code.trim() || busy) return;
+    const entry: Entry = { id: Date.now(), code, output: null };
+    setEntries(prev => [...prev, entry]);
+    setCode("");
+    setHistoryIndex(null);
+    setBusy(true);
+    try {
+      const output = await invoke<ReplOutput>("repl_eval", { sessionId, code: entry.code });
+      setEntries(prev => prev.map(e => e.id === entry.id ? { ...e, output } : e));
+    } catch (e) {
+      const output = { prints: [], value: null, type: "()", display: "", error: String(e) };
+      setEntries(prev => prev.map(e => e.id === entry.id ? { ...e, output } : e));
+    } finally {
+      setBusy(false);
+    }
+  };
+
+  // Enter evaluates, Shift+Enter adds a line, Up/Down recall earlier input
+  const onKeyDown = (e: React.KeyboardEvent<HTMLTextAreaElement>) => {
+    if (e.key === "Enter" && !e.shiftKey) {
+      e.preventDefault();
+      evaluate();
+    } else if ((e.key === "ArrowUp" || e.key === "ArrowDown") && !code.includes("\n") && entries.length > 0) {
+      e.preventDefault();
+      const last = entries.length - 1;
+      const next = e.key === "ArrowUp"
+        ? Math.max(0, (historyIndex ?? entries.length) - 1)
+        : Math.min(last + 1, (historyIndex ?? last) + 1);
+      setHistoryIndex(next > last ? null : next);
+      setCode(next > last ? "" : entries[next].code);
+    }
+  };
+
+  return (
+    <div className="bg-black border-t border-gray-700 text-xs flex flex-col h-64">
+      <div className="bg-gray-800 px-2 py-1 text-gray-400 flex justify-between">
+        <span>REPL {sessionId ? "(Studio window)" : startError ? "" : "(starting...)"}</span>
+        <button onClick={() => setEntries([])} className="text-gray-300 hover:text-white">Clear</button>
+      </div>
+      <div className="flex-1 overflow-y-auto p-2 font-mono">
+        {startError && <div className="text-red-400">Could not start the REPL: {startError}</div>}
+        {entries.map(entry => (
+          <div key={entry.id} className="mb-1">
+            <div className="text-blue-400 whitespace-pre-wrap">&gt; {entry.code}</div>
+            {entry.output === null && <div className="text-gray-500">...</div>}
+            {entry.output?.prints.map((line, i) => <div key={i} className="text-gray-300 whitespace-pre-wrap">{line}</div>)}
+            {entry.output?.error && <div className="text-red-400 whitespace-pre-wrap">{entry.output.error}</div>}
+            {entry.output && !entry.output.error && entry.output.display && (
+              <div className="text-green-400 whitespace-pre-wrap">
+                {entry.output.display} <span className="text-gray-600">: {entry.output.type}</span>
+              </div>
+            )}
+          </div>
+        ))}
+        <div ref={endRef} />
+      </div>
+      <textarea
+        className="bg-gray-900 p-2 outline-none resize-none font-mono border-t border-gray-700"
+        rows={2}
+        value={code}
+        onChange={(e) => setCode(e.target.value)}
+        onKeyDown={onKeyDown}
+        disabled={!sessionId}
+        placeholder='browser.extract_text("h1")'
+        spellCheck={false}
+      />
+    </div>
+  );
+}
@@ -7,6 +7,20 @@ export interface ProxyConfig {
     port: number;
     username?: string;
     password?: string;
+    /** Name of a vault secret holding the password */
+    password_secret?: string;
+}
+
+export interface ScreenConfig {
+    width: number;
+    height: number;
+    avail_height?: number;
+    pixel_ratio: number;
+}
+
+export interface WebGlConfig {
+    vendor: string;
+    renderer: string;
 }
 
 export interface Profile {
@@ -14,6 +28,10 @@ export interface Profile {
     user_agent: string;
     seed: number;
     proxy?: ProxyConfig;
+    screen?: ScreenConfig;
+    webgl?: WebGlConfig;
+    fingerprint_version?: number;
+    human_input?: boolean;
 }
 
 interface Props {
@@ -75,6 +93,17 @@ export function SettingsTab({ profile, onUpdate }: Props) {
                 />
             </div>
 
+            <div className="mb-6">
+                <label className="flex items-center gap-2 text-sm text-gray-400">
+                    <input
+                        type="checkbox"
+                        checked={!!localProfile.human_input}
+                        onChange={e => setLocalProfile({...localProfile, human_input: e.target.checked})}
+                    />
+                    Human-like input (mouse paths, typing rhythm, wheel scrolling)
+                </label>
+            </div>
+
             <div className="border-t border-gray-700 pt-4">
                 <h3 className="text-lg font-bold mb-4 text-blue-400">Upstream Proxy</h3>
 